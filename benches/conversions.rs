@@ -0,0 +1,141 @@
+//! Benchmarks of the per scan conversions
+//!
+//! Run with `cargo bench`, criterion keeps the previous run in `target/criterion` to
+//! compare against
+
+use std::{hint::black_box, time::SystemTime};
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use prost::Message;
+use rplidar_driver::ScanPoint;
+
+use rplidar_zenoh_driver::{
+    config::MountingPose,
+    encoding::{ScanEncoder, ScanFormat},
+    foxglove,
+    projection::project,
+    rp_lidar_projected_points_to_foxglove_point_cloud, RpLidarProjectedPoint,
+};
+
+/// Points of one scan of an A2 in its densest mode
+const SCAN_POINTS: u32 = 1600;
+
+fn scan() -> Vec<ScanPoint> {
+    (0..SCAN_POINTS)
+        .map(|index| ScanPoint {
+            angle_z_q14: (index * 65536 / SCAN_POINTS) as u16,
+            // a room with a gap every few degrees
+            dist_mm_q2: if index % 37 == 0 {
+                0
+            } else {
+                (2000 + (index * 7) % 500) * 4
+            },
+            quality: (index % 64) as u8,
+            flag: 0,
+        })
+        .collect()
+}
+
+fn projected(scan: &[ScanPoint]) -> Vec<RpLidarProjectedPoint> {
+    scan.iter()
+        .filter(|point| point.is_valid())
+        .map(|point| {
+            let (x, y) = project(point.angle(), point.distance());
+            RpLidarProjectedPoint::new(x, y, point.distance(), point.angle(), point.quality)
+        })
+        .collect()
+}
+
+fn pose() -> foxglove::Pose {
+    MountingPose::default().to_foxglove_pose()
+}
+
+fn projection(c: &mut Criterion) {
+    let scan = scan();
+    let mut group = c.benchmark_group("projection");
+    group.throughput(Throughput::Elements(scan.len() as u64));
+    group.bench_function("project", |b| b.iter(|| projected(black_box(&scan))));
+    group.finish();
+}
+
+fn blob_encoding(c: &mut Criterion) {
+    let points = projected(&scan());
+    let mut group = c.benchmark_group("blob");
+    group.throughput(Throughput::Elements(points.len() as u64));
+    group.bench_function("to_foxglove_blob", |b| {
+        let mut data = Vec::with_capacity(points.len() * 17);
+        b.iter(|| {
+            data.clear();
+            for point in black_box(&points) {
+                data.extend_from_slice(&point.to_foxglove_blob());
+            }
+        })
+    });
+    group.finish();
+}
+
+fn point_cloud_parsing(c: &mut Criterion) {
+    let points = projected(&scan());
+    let point_cloud = rp_lidar_projected_points_to_foxglove_point_cloud(
+        &SystemTime::now(),
+        "laser",
+        &pose(),
+        &points,
+    );
+    let payload = point_cloud.encode_to_vec();
+    let mut group = c.benchmark_group("point_cloud_parsing");
+    group.throughput(Throughput::Elements(points.len() as u64));
+    group.bench_function("from_foxglove_point_cloud", |b| {
+        b.iter(|| RpLidarProjectedPoint::from_foxglove_point_cloud(black_box(&point_cloud)))
+    });
+    group.bench_function("decode_and_parse", |b| {
+        b.iter(|| {
+            let point_cloud = foxglove::PointCloud::decode(black_box(payload.as_slice())).unwrap();
+            RpLidarProjectedPoint::from_foxglove_point_cloud(&point_cloud).unwrap()
+        })
+    });
+    group.finish();
+}
+
+fn protobuf_encoding(c: &mut Criterion) {
+    let scan = scan();
+    let pose = pose();
+    let timestamp = SystemTime::now();
+    let mut group = c.benchmark_group("protobuf_encoding");
+    group.throughput(Throughput::Elements(scan.len() as u64));
+
+    // a fresh encoder per scan shows what reusing buffers saves
+    group.bench_function("point_cloud/fresh_encoder", |b| {
+        b.iter_batched(
+            ScanEncoder::new,
+            |mut encoder| encoder.encode_point_cloud(&timestamp, "laser", &pose, &scan),
+            BatchSize::SmallInput,
+        )
+    });
+    let mut encoder = ScanEncoder::new();
+    group.bench_function("point_cloud/reused_encoder", |b| {
+        b.iter(|| encoder.encode_point_cloud(&timestamp, "laser", &pose, black_box(&scan)))
+    });
+
+    for (name, format) in [
+        ("foxglove", ScanFormat::Foxglove),
+        ("f32", ScanFormat::F32),
+        ("millimeter", ScanFormat::Millimeter),
+        ("delta", ScanFormat::Delta),
+    ] {
+        let mut encoder = ScanEncoder::with_format(format, true);
+        group.bench_function(format!("laser_scan/{}", name), |b| {
+            b.iter(|| encoder.encode_laser_scan(&timestamp, "laser", &pose, black_box(&scan)))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    projection,
+    blob_encoding,
+    point_cloud_parsing,
+    protobuf_encoding
+);
+criterion_main!(benches);
@@ -1,7 +1,8 @@
 extern crate prost_reflect_build;
 
 fn main() {
-    let proto_files = get_proto_files("proto/foxglove").unwrap();
+    let mut proto_files = get_proto_files("proto/foxglove").unwrap();
+    proto_files.extend(get_proto_files("proto/rplidar").unwrap());
 
     prost_reflect_build::Builder::new()
         .descriptor_pool("crate::DESCRIPTOR_POOL")
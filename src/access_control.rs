@@ -0,0 +1,53 @@
+use serde_json::json;
+use zenoh::config::Config;
+
+use crate::topics::TopicBuilder;
+
+/// zenoh operations covered by the access control rules
+const ACL_ACTIONS: &[&str] = &["put", "get", "declare_subscriber", "declare_queryable"];
+
+/// Restrict the zenoh session to key expressions owned by this process
+///
+/// Meant for robot networks shared between teams where other tooling shouldn't reach the lidar
+#[derive(clap::Args, Debug, Clone, Default)]
+pub struct AccessControlArgs {
+    /// Deny publications, subscriptions and queries outside of the topic prefix
+    ///
+    /// Uses zenoh access control so messages are dropped by the session before reaching the driver
+    #[clap(long)]
+    pub restrict_to_prefix: bool,
+}
+
+impl AccessControlArgs {
+    /// Configure access control allowing only `<prefix>/**` and `extra_key_exprs`
+    pub fn apply(
+        &self,
+        zenoh_config: &mut Config,
+        topics: &TopicBuilder,
+        extra_key_exprs: &[String],
+    ) -> anyhow::Result<()> {
+        if !self.restrict_to_prefix {
+            return Ok(());
+        }
+        if topics.prefix().is_empty() {
+            anyhow::bail!("Restricting access to the prefix requires a non empty prefix");
+        }
+        let key_exprs: Vec<String> = std::iter::once(topics.topic("**")?)
+            .chain(extra_key_exprs.iter().cloned())
+            .collect();
+        let access_control = json!({
+            "enabled": true,
+            "default_permission": "deny",
+            "rules": [{
+                "actions": ACL_ACTIONS,
+                "flows": ["egress", "ingress"],
+                "permission": "allow",
+                "key_exprs": key_exprs,
+            }],
+        });
+        zenoh_config
+            .insert_json5("access_control", &access_control.to_string())
+            .map_err(|err| anyhow::anyhow!("Failed to configure access control: {:?}", err))?;
+        Ok(())
+    }
+}
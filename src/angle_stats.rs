@@ -0,0 +1,242 @@
+use std::{
+    collections::VecDeque,
+    f32::consts::TAU,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
+
+use prost::Message;
+use rplidar_driver::ScanPoint;
+use tracing::{error, info};
+use zenoh::{prelude::r#async::*, Session};
+
+use crate::{rplidar, scan_broadcast::ScanBroadcast, system_time_to_proto_time, ErrorWrapper};
+
+pub const ANGLE_STATS_TOPIC: &str = "angle_stats";
+
+/// One bin per degree
+const ANGLE_BINS: usize = 360;
+
+/// Per degree range statistics for rule based monitoring
+///
+/// Consumers can check a handful of degrees against thresholds without decoding scans
+#[derive(clap::Args, Debug, Clone, Copy)]
+pub struct AngleStatsArgs {
+    /// Publish per degree min, mean and max range on <prefix>/angle_stats
+    #[clap(long)]
+    pub angle_stats: bool,
+
+    /// Length of the rolling window in seconds
+    #[clap(long, default_value_t = 10.0)]
+    pub angle_stats_window: f64,
+
+    /// Seconds between published statistics
+    #[clap(long, default_value_t = 1.0)]
+    pub angle_stats_interval: f64,
+}
+
+impl AngleStatsArgs {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.angle_stats_window.is_nan() || self.angle_stats_window <= 0.0 {
+            anyhow::bail!("Angle stats window must be positive");
+        }
+        if self.angle_stats_interval.is_nan() || self.angle_stats_interval <= 0.0 {
+            anyhow::bail!("Angle stats interval must be positive");
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct BinStats {
+    min: f32,
+    max: f32,
+    sum: f64,
+    count: u32,
+}
+
+impl Default for BinStats {
+    fn default() -> Self {
+        Self {
+            min: f32::INFINITY,
+            max: 0.0,
+            sum: 0.0,
+            count: 0,
+        }
+    }
+}
+
+impl BinStats {
+    fn add(&mut self, distance: f32) {
+        self.min = self.min.min(distance);
+        self.max = self.max.max(distance);
+        self.sum += distance as f64;
+        self.count += 1;
+    }
+
+    fn merge(&mut self, other: &BinStats) {
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+        self.sum += other.sum;
+        self.count += other.count;
+    }
+}
+
+/// Rolling per degree statistics
+///
+/// Min and max can't be subtracted when scans leave the window so each scan keeps
+/// its own bins and they are merged when publishing
+pub struct AngleStatsTracker {
+    window: Duration,
+    scans: VecDeque<(Instant, Vec<BinStats>)>,
+}
+
+impl AngleStatsTracker {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            scans: VecDeque::new(),
+        }
+    }
+
+    fn bin(angle: f32) -> usize {
+        ((angle.rem_euclid(TAU) / TAU * ANGLE_BINS as f32) as usize).min(ANGLE_BINS - 1)
+    }
+
+    pub fn record(&mut self, now: Instant, scan: &[ScanPoint]) {
+        let mut bins = vec![BinStats::default(); ANGLE_BINS];
+        for point in scan.iter().filter(|point| point.is_valid()) {
+            bins[Self::bin(point.angle())].add(point.distance());
+        }
+        self.scans.push_back((now, bins));
+        self.expire(now);
+    }
+
+    fn expire(&mut self, now: Instant) {
+        while let Some((time, _)) = self.scans.front() {
+            if now.duration_since(*time) <= self.window {
+                break;
+            }
+            self.scans.pop_front();
+        }
+    }
+
+    pub fn stats(&self, timestamp: &SystemTime, frame_id: &str) -> rplidar::AngleStats {
+        let mut totals = vec![BinStats::default(); ANGLE_BINS];
+        for (_, bins) in &self.scans {
+            for (total, bin) in totals.iter_mut().zip(bins) {
+                total.merge(bin);
+            }
+        }
+        let mut stats = rplidar::AngleStats {
+            timestamp: Some(system_time_to_proto_time(timestamp)),
+            frame_id: frame_id.to_owned(),
+            window_seconds: self.window.as_secs_f64(),
+            ..Default::default()
+        };
+        for total in totals {
+            if total.count == 0 {
+                stats.min_range.push(0.0);
+                stats.mean_range.push(0.0);
+                stats.max_range.push(0.0);
+            } else {
+                stats.min_range.push(total.min);
+                stats
+                    .mean_range
+                    .push((total.sum / total.count as f64) as f32);
+                stats.max_range.push(total.max);
+            }
+            stats.counts.push(total.count);
+        }
+        stats
+    }
+}
+
+/// Track scans from `scan_broadcast` and periodically publish the per degree statistics
+pub async fn start_angle_stats_publisher(
+    zenoh_session: Arc<Session>,
+    topic: String,
+    args: AngleStatsArgs,
+    scan_broadcast: ScanBroadcast,
+) -> anyhow::Result<()> {
+    args.validate()?;
+    let publisher = zenoh_session
+        .declare_publisher(topic.clone())
+        .res()
+        .await
+        .map_err(ErrorWrapper::ZenohError)?;
+    info!(topic, "Publishing angle stats");
+
+    let mut tracker = AngleStatsTracker::new(Duration::from_secs_f64(args.angle_stats_window));
+    let mut scan_subscriber = scan_broadcast.subscribe("angle_stats");
+    let mut interval = tokio::time::interval(Duration::from_secs_f64(args.angle_stats_interval));
+    tokio::spawn(async move {
+        let mut frame_id = None;
+        loop {
+            tokio::select! {
+                frame = scan_subscriber.recv() => {
+                    let Some(frame) = frame else {
+                        break;
+                    };
+                    tracker.record(frame.capture_instant, &frame.points);
+                    frame_id.get_or_insert_with(|| frame.frame_id.clone());
+                }
+                _ = interval.tick() => {
+                    let Some(frame_id) = &frame_id else {
+                        continue;
+                    };
+                    tracker.expire(Instant::now());
+                    let stats = tracker.stats(&SystemTime::now(), frame_id);
+                    if let Err(err) = publisher.put(stats.encode_to_vec()).res().await {
+                        error!(?err, "Failed to publish angle stats");
+                    }
+                }
+            }
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_angles_to_degrees() {
+        assert_eq!(AngleStatsTracker::bin(0.0), 0);
+        assert_eq!(AngleStatsTracker::bin(1.5f32.to_radians()), 1);
+        assert_eq!(AngleStatsTracker::bin(359.5f32.to_radians()), 359);
+        assert_eq!(AngleStatsTracker::bin(TAU), 0);
+    }
+
+    #[test]
+    fn aggregates_window() {
+        let mut tracker = AngleStatsTracker::new(Duration::from_secs(10));
+        let start = Instant::now();
+        let scan = |distance: f32| {
+            let mut bins = vec![BinStats::default(); ANGLE_BINS];
+            bins[90].add(distance);
+            bins
+        };
+        tracker.scans.push_back((start, scan(1.0)));
+        tracker
+            .scans
+            .push_back((start + Duration::from_secs(5), scan(3.0)));
+        tracker
+            .scans
+            .push_back((start + Duration::from_secs(12), scan(2.0)));
+
+        let stats = tracker.stats(&SystemTime::now(), "laser");
+        assert_eq!(stats.counts[90], 3);
+        assert_eq!(stats.min_range[90], 1.0);
+        assert_eq!(stats.mean_range[90], 2.0);
+        assert_eq!(stats.max_range[90], 3.0);
+        assert_eq!(stats.counts[0], 0);
+        assert_eq!(stats.min_range[0], 0.0);
+
+        tracker.expire(start + Duration::from_secs(12));
+        let stats = tracker.stats(&SystemTime::now(), "laser");
+        assert_eq!(stats.counts[90], 2);
+        assert_eq!(stats.min_range[90], 2.0);
+    }
+}
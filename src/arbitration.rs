@@ -0,0 +1,182 @@
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use tokio::sync::watch;
+use tracing::{info, warn};
+use zenoh::{liveliness::LivelinessToken, prelude::r#async::*, Session};
+
+use crate::{device::LidarDeviceInfo, ErrorWrapper};
+
+/// Liveliness tokens of drivers publishing for a lidar, `<topic>/<serial>/<candidate>`
+pub const PUBLISHER_TOPIC: &str = "publisher";
+
+const ARBITRATION_INTERVAL: Duration = Duration::from_secs(1);
+const ARBITRATION_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Only one driver publishes scans of a lidar
+///
+/// Every driver declares a liveliness token keyed on the lidar serial once connected.
+/// The oldest token wins, others stop publishing scans until it disappears but keep
+/// feeding in-process consumers such as snapshots and zones
+#[derive(clap::Args, Debug, Clone)]
+pub struct ArbitrationArgs {
+    /// Publish even when another driver already publishes scans of the same lidar
+    #[clap(long)]
+    pub no_arbitration: bool,
+}
+
+/// Identifies a driver in the election, ordered by start time so the oldest sorts first
+fn candidate_id(started: SystemTime, zid: &str) -> String {
+    let millis = started
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+    format!("{:020}-{}", millis, zid)
+}
+
+/// Whether `candidate` is the leader among the `alive` candidates
+///
+/// Our own token may not be visible yet, so the candidate counts as alive
+fn is_leader<'a>(candidate: &str, alive: impl Iterator<Item = &'a str>) -> bool {
+    alive
+        .filter(|other| !other.is_empty())
+        .all(|other| other >= candidate)
+}
+
+/// Take part in the election for the lidar reported on `device_info`
+///
+/// The returned receiver is true while this driver should publish scans, which includes
+/// the time before a lidar is connected
+pub async fn start_publisher_arbitration(
+    zenoh_session: Arc<Session>,
+    topic: String,
+    args: &ArbitrationArgs,
+    mut device_info: watch::Receiver<Option<LidarDeviceInfo>>,
+) -> anyhow::Result<watch::Receiver<bool>> {
+    let (leader_sender, leader_receiver) = watch::channel(true);
+    if args.no_arbitration {
+        return Ok(leader_receiver);
+    }
+    let zid = zenoh_session.info().zid().res().await.to_string();
+    let candidate = candidate_id(SystemTime::now(), &zid);
+    info!(topic, candidate, "Arbitrating scan publishing");
+
+    tokio::spawn(async move {
+        let mut serial_number: Option<String> = None;
+        let mut _token: Option<LivelinessToken<'static>> = None;
+        let mut interval = tokio::time::interval(ARBITRATION_INTERVAL);
+        loop {
+            tokio::select! {
+                changed = device_info.changed() => {
+                    if changed.is_err() {
+                        break;
+                    }
+                    let connected = device_info
+                        .borrow_and_update()
+                        .as_ref()
+                        .map(|device_info| device_info.serial_number.clone());
+                    // reconnects report the same lidar, only a different lidar restarts the election
+                    let Some(connected) = connected else {
+                        continue;
+                    };
+                    if serial_number.as_ref() == Some(&connected) {
+                        continue;
+                    }
+                    _token = None;
+                    let key_expr = format!("{}/{}/{}", topic, connected, candidate);
+                    match zenoh_session
+                        .liveliness()
+                        .declare_token(key_expr)
+                        .res()
+                        .await
+                        .map_err(ErrorWrapper::ZenohError)
+                    {
+                        Ok(token) => _token = Some(token),
+                        Err(err) => warn!(?err, "Failed to declare publisher token"),
+                    }
+                    serial_number = Some(connected);
+                }
+                _ = interval.tick() => {}
+            }
+            let Some(serial_number) = &serial_number else {
+                continue;
+            };
+
+            let key_expr = format!("{}/{}/*", topic, serial_number);
+            let replies = match zenoh_session
+                .liveliness()
+                .get(&key_expr)
+                .timeout(ARBITRATION_TIMEOUT)
+                .res()
+                .await
+            {
+                Ok(replies) => replies,
+                Err(err) => {
+                    warn!(?err, "Failed to query publisher tokens");
+                    continue;
+                }
+            };
+            let mut alive = vec![];
+            while let Ok(reply) = replies.recv_async().await {
+                if let Ok(sample) = reply.sample {
+                    let key_expr = sample.key_expr.as_str();
+                    alive.push(key_expr.rsplit('/').next().unwrap_or_default().to_owned());
+                }
+            }
+
+            let leader = is_leader(&candidate, alive.iter().map(String::as_str));
+            if leader != *leader_sender.borrow() {
+                if leader {
+                    info!(
+                        serial_number,
+                        "No other driver publishes this lidar, publishing"
+                    );
+                } else {
+                    warn!(
+                        serial_number,
+                        ?alive,
+                        "Another driver already publishes this lidar, not publishing scans"
+                    );
+                }
+                leader_sender.send_replace(leader);
+            }
+        }
+    });
+    Ok(leader_receiver)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn oldest_candidate_leads() {
+        let started = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let first = candidate_id(started, "aa");
+        let second = candidate_id(started + Duration::from_millis(1), "00");
+        // ties go to the lower zenoh id
+        let tied = candidate_id(started, "bb");
+
+        assert!(is_leader(&first, [first.as_str()].into_iter()));
+        assert!(is_leader(
+            &first,
+            [second.as_str(), tied.as_str()].into_iter()
+        ));
+        assert!(!is_leader(
+            &second,
+            [first.as_str(), second.as_str()].into_iter()
+        ));
+        assert!(!is_leader(&tied, [first.as_str()].into_iter()));
+        // alone before our own token shows up
+        assert!(is_leader(&second, std::iter::empty()));
+    }
+
+    #[test]
+    fn candidate_ids_sort_by_start_time() {
+        let early = candidate_id(UNIX_EPOCH + Duration::from_millis(999), "ff");
+        let late = candidate_id(UNIX_EPOCH + Duration::from_secs(1_000_000_000), "00");
+        assert!(early < late);
+    }
+}
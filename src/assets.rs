@@ -0,0 +1,86 @@
+use std::{
+    net::SocketAddr,
+    path::{Component, Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::Result;
+use axum::{
+    extract::{Path as UrlPath, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use tracing::{error, info, warn};
+
+/// Serve files below `dir` on `/assets/<path>`
+///
+/// Lets scene entities reference meshes and URDFs by URL without running a separate web server.
+/// Responses allow any origin so Studio running in a browser can fetch them
+pub async fn start_asset_server(bind: SocketAddr, dir: PathBuf) -> Result<()> {
+    if !dir.is_dir() {
+        anyhow::bail!("Asset directory {:?} doesn't exist", dir);
+    }
+    let app = Router::new()
+        .route("/assets/*path", get(serve_asset))
+        .with_state(Arc::new(dir.clone()));
+
+    let listener = tokio::net::TcpListener::bind(bind).await?;
+    info!(%bind, ?dir, "Serving assets");
+    tokio::spawn(async move {
+        if let Err(err) = axum::serve(listener, app).await {
+            error!(?err, "Asset http server failed");
+        }
+    });
+    Ok(())
+}
+
+async fn serve_asset(State(dir): State<Arc<PathBuf>>, UrlPath(path): UrlPath<String>) -> Response {
+    let Some(file) = asset_file(&dir, &path) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    match tokio::fs::read(&file).await {
+        Ok(contents) => (
+            [
+                (header::CONTENT_TYPE, content_type(&file)),
+                (header::ACCESS_CONTROL_ALLOW_ORIGIN, "*"),
+            ],
+            contents,
+        )
+            .into_response(),
+        Err(err) => {
+            warn!(?err, ?file, "Failed to read asset");
+            StatusCode::NOT_FOUND.into_response()
+        }
+    }
+}
+
+/// Resolve a request path inside `dir`, `None` if it would escape it
+fn asset_file(dir: &Path, path: &str) -> Option<PathBuf> {
+    let path = Path::new(path);
+    if !path
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)))
+    {
+        return None;
+    }
+    Some(dir.join(path))
+}
+
+fn content_type(file: &Path) -> &'static str {
+    let extension = file
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.to_ascii_lowercase());
+    match extension.as_deref() {
+        Some("stl") => "model/stl",
+        Some("dae") => "model/vnd.collada+xml",
+        Some("glb") => "model/gltf-binary",
+        Some("gltf") => "model/gltf+json",
+        Some("urdf" | "xacro" | "xml") => "application/xml",
+        Some("png") => "image/png",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        _ => "application/octet-stream",
+    }
+}
@@ -1,23 +1,87 @@
+use anyhow::Context;
 use clap::Parser;
 use prost::Message;
-use rplidar_driver::{utils::sort_scan, RplidarDevice, RposError, ScanOptions, ScanPoint};
+use rplidar_driver::{utils::sort_scan, RposError};
+use serde::Serialize;
 use std::{
+    net::SocketAddr,
+    path::{Path, PathBuf},
     sync::{
-        atomic::{AtomicBool, Ordering},
-        Arc,
+        atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering},
+        Arc, Mutex,
     },
     thread,
-    time::{Duration, SystemTime},
+    time::{Duration, Instant},
 };
-use tokio::sync::mpsc::{channel, Receiver, Sender};
-use tracing::{error, info, log::warn};
-use zenoh::{config::Config, prelude::r#async::*};
+use tokio::{
+    signal,
+    sync::{
+        mpsc::{channel, error::TrySendError, Receiver, Sender},
+        oneshot, watch,
+    },
+};
+use tracing::{error, info, info_span, log::warn};
+use zenoh::{config::Config, prelude::r#async::*, publication::CongestionControl};
 
 use rplidar_zenoh_driver::{
-    foxglove, rp_lidar_projected_points_to_foxglove_point_cloud, setup_tracing,
-    system_time_to_proto_time, RpLidarProjectedPoint,
+    access_control::AccessControlArgs,
+    angle_stats::{self, start_angle_stats_publisher, AngleStatsArgs},
+    arbitration::{self, start_publisher_arbitration, ArbitrationArgs},
+    compression::CompressionArgs,
+    config::{self, start_config_file_watcher, start_config_update_subscriber, DriverConfig},
+    daemon::DaemonArgs,
+    device::{
+        connect_lidar, format_firmware_version, ConnectedLidar, FirmwareSupport, LidarDeviceInfo,
+        LidarModel, MotorControl, ReconnectBackoff, ScanModeInfo,
+    },
+    diagnostics::{self, start_diagnostics_publisher},
+    encoding::{
+        EncodePipeline, LaserScanBatchEncoder, ScanEncoder, ScanFormat, BATCH_TOPIC_SUFFIX,
+    },
+    events::{self, start_event_publisher, EventPublisher},
+    failure::{ErrorAction, Failure, FailureClass, FailurePolicyArgs, FailureSlot},
+    foxglove,
+    latency::{self, start_latency_publisher, start_probe_sender, LatencyTracker},
+    liveliness::{self, declare_alive_token},
+    log_level::{self, start_log_level_subscriber},
+    logging::RepeatThrottle,
+    map_deviation::{self, start_map_deviation_monitor, MapDeviationArgs},
+    monitoring::{self, HealthCheck, HealthReport},
+    peers::StaticPeersArgs,
+    people::{self, start_person_detector, PersonDetectorArgs},
+    power_saving::{ActivityTracker, PowerSavingArgs},
+    process_stats::{self, start_process_stats_publisher},
+    profiling::{self, start_profile_reporter, StageProfiler},
+    progress::{wait_for_deadline, CaptureLimitArgs, ProgressArgs},
+    qos::PublisherPriorityArgs,
+    reflectors::{self, start_reflector_detector, ReflectorArgs},
+    remote::{start_remote_publisher, RemoteArgs, RemoteTopics},
+    ros::Ros2Args,
+    rplidar,
+    runtime::RuntimeArgs,
+    scan_broadcast::{ScanBroadcast, ScanFrame, ScanSubscriber, SCAN_BROADCAST_CAPACITY},
+    scan_publisher::{EncodeJob, EncodedScan, ScanPublishers},
+    scan_queue::{
+        scan_queue, QueueCounters, QueueReceiver, QueueSender, QueueStatsHandle, ScanQueueArgs,
+    },
+    scan_rate::{self, RotationRateAlert, RotationRateMonitor, ScanRateAlert, ScanRateMonitor},
+    sector_quality::{self, start_sector_quality_publisher, SectorQualityArgs},
+    setup_tracing_with_args, snapshot,
+    stuck_scan::StuckScanDetector,
+    subscribers::watch_subscribers,
+    systemd::SystemdNotifier,
+    temporal_filter::{start_velocity_subscriber, TemporalFilter, TemporalFilterArgs},
+    timestamping::{enable_timestamping, CaptureClock},
+    topics::TopicBuilder,
+    zones::{self, start_zone_monitor, ZoneArgs},
+    ErrorWrapper, TracingArgs,
 };
 
+/// Reported ROS 2 range_max when no max range filter is configured
+///
+/// Covers the longest range rplidar models
+const ROS2_DEFAULT_RANGE_MAX: f32 = 40.0;
+
 #[derive(Parser, Debug)]
 #[command()]
 struct Args {
@@ -25,10 +89,38 @@ struct Args {
     #[clap(long)]
     lidar_off: bool,
 
+    /// File used to remember the last commanded motor state
+    ///
+    /// When the file exists its state takes precedence over --lidar-off
+    #[clap(long)]
+    state_file: Option<PathBuf>,
+
     /// serial port for lidar
     #[clap(long)]
     serial_port: String,
 
+    /// Serial baud rate
+    ///
+    /// Detected by trying the baud rates of all supported models when not set
+    #[clap(long)]
+    baud_rate: Option<u32>,
+
+    /// Scan mode index
+    ///
+    /// Defaults to the best mode for the detected model
+    #[clap(long)]
+    scan_mode: Option<u16>,
+
+    /// Scan even when the lidar firmware is older than the known good version for its model
+    #[clap(long)]
+    allow_unsupported_firmware: bool,
+
+    /// How the motor is switched on and off
+    ///
+    /// A1 adapter boards ignore motor commands and need dtr
+    #[clap(long, value_enum, default_value_t = MotorControl::Command)]
+    motor_control: MotorControl,
+
     /// zenoh prefix
     ///
     /// Prefix for all topics
@@ -36,6 +128,8 @@ struct Args {
     prefix: String,
 
     /// publish topic
+    ///
+    /// Per scan rplidar.ScanMetadata is published on <prefix>/<scan_topic>/metadata
     #[clap(long, default_value = "laser_scan")]
     scan_topic: String,
 
@@ -43,225 +137,1665 @@ struct Args {
     #[clap(long, default_value = "point_cloud")]
     cloud_topic: String,
 
+    /// Don't publish laser scans
+    #[clap(long)]
+    no_laser_scan: bool,
+
+    /// Don't publish point clouds
+    ///
+    /// Skips projection and serialization of the point cloud
+    #[clap(long)]
+    no_point_cloud: bool,
+
+    /// Send this many consecutive laser scans as a single message
+    ///
+    /// Batches are published as rplidar.LaserScanBatch on <prefix>/<scan_topic>/batch
+    /// instead of individual scans. Useful on high latency links
+    #[clap(long, default_value_t = 1, value_parser = clap::value_parser!(u16).range(1..))]
+    scan_batch_size: u16,
+
+    /// Laser scan payload format
+    ///
+    /// Compact formats are published as rplidar.CompactLaserScan on <prefix>/<scan_topic>/compact
+    #[clap(long, value_enum, default_value_t = ScanFormat::Foxglove)]
+    scan_format: ScanFormat,
+
+    /// Range quantization step in millimeters of --scan-format delta
+    ///
+    /// Coarser steps make smaller differences between points and fewer bytes per point
+    #[clap(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..=1000))]
+    range_resolution_mm: u32,
+
+    /// Leave intensities out of laser scans
+    #[clap(long)]
+    no_intensities: bool,
+
+    /// Number of scans encoded concurrently on the blocking thread pool
+    ///
+    /// Scans are still published in capture order
+    #[clap(long, default_value_t = 2, value_parser = clap::value_parser!(u16).range(1..))]
+    encode_pipeline_depth: u16,
+
+    #[clap(flatten)]
+    ros2: Ros2Args,
+
+    #[clap(flatten)]
+    priorities: PublisherPriorityArgs,
+
+    /// Also publish laser scans as rosbridge JSON for this ROS 1 topic (e.g. /scan)
+    ///
+    /// Messages are published on <prefix>/rosbridge/<topic>
+    #[clap(long)]
+    rosbridge_topic: Option<String>,
+
+    /// Serve point cloud snapshots into this directory
+    ///
+    /// Querying <prefix>/snapshot?scans=N aggregates the next N scans into a PCD file
+    /// and replies with its path
+    #[clap(long)]
+    snapshot_dir: Option<PathBuf>,
+
     /// frame_id
     #[clap(long, default_value = "lidar")]
     frame_id: String,
 
-    /// listen on
+    /// Append the lidar serial number to the frame_id
+    ///
+    /// Produces frames like lidar_<serial> so recordings from multiple lidars stay distinguishable
+    #[clap(long)]
+    frame_id_serial_suffix: bool,
+
+    /// Stamp scans with a monotonic clock anchored to the system time at startup
+    ///
+    /// Keeps scan timestamps from jumping when the system clock is set after boot,
+    /// e.g. by NTP on robots without a real time clock
+    #[clap(long)]
+    monotonic_timestamps: bool,
+
+    /// Driver config file with filters, masks and mounting pose
+    ///
+    /// The file is watched and changes are applied without restarting the scan
+    #[clap(long)]
+    config: Option<PathBuf>,
+
+    /// Write config updates received on <prefix>/config/set back to the config file
+    ///
+    /// The file is replaced atomically, comments and formatting in it are lost
+    #[clap(long, requires = "config")]
+    persist_config_updates: bool,
+
+    /// Endpoints to listen on.
+    #[clap(long)]
+    listen: Vec<zenoh_config::EndPoint>,
+
+    /// Endpoints to connect to.
+    #[clap(long)]
+    connect: Vec<zenoh_config::EndPoint>,
+
+    #[clap(flatten)]
+    static_peers: StaticPeersArgs,
+
+    #[clap(flatten)]
+    remote: RemoteArgs,
+
+    #[clap(flatten)]
+    access_control: AccessControlArgs,
+
+    /// Notify systemd when ready and pet its watchdog from the scan loop
+    ///
+    /// Requires the systemd feature
+    #[clap(long)]
+    systemd_notify: bool,
+
+    /// Serve prometheus metrics on /metrics and health checks on /healthz and /readyz
+    #[clap(long)]
+    http_listen: Option<SocketAddr>,
+
+    /// Send latency probes and publish round trip times of echoes from other components
     #[clap(long)]
-    listen: Vec<String>,
+    latency_probe: bool,
 
-    /// connect to
+    /// Warn when the scan rate stays below this many scans per second
+    #[clap(long)]
+    min_scan_rate: Option<f64>,
+
+    /// Seconds the scan rate has to stay below --min-scan-rate before warning
+    #[clap(long, default_value_t = 5.0)]
+    scan_rate_grace_period: f64,
+
+    /// Expected motor rotation rate in Hz
+    ///
+    /// Defaults to the nominal rate of the connected model. The measured rate is
+    /// published on <prefix>/rotation_rate
     #[clap(long)]
-    connect: Vec<String>,
+    rotation_rate: Option<f64>,
+
+    /// Warn when the measured rotation rate deviates from the expected rate by more than this fraction
+    #[clap(long, default_value_t = 0.1)]
+    rotation_rate_tolerance: f64,
+
+    #[clap(flatten)]
+    scan_queue: ScanQueueArgs,
+
+    #[clap(flatten)]
+    power_saving: PowerSavingArgs,
+
+    #[clap(flatten)]
+    temporal_filter: TemporalFilterArgs,
+
+    #[clap(flatten)]
+    person_detector: PersonDetectorArgs,
+
+    #[clap(flatten)]
+    sector_quality: SectorQualityArgs,
+
+    #[clap(flatten)]
+    reflectors: ReflectorArgs,
+
+    #[clap(flatten)]
+    zones: ZoneArgs,
+
+    #[clap(flatten)]
+    map_deviation: MapDeviationArgs,
+
+    #[clap(flatten)]
+    angle_stats: AngleStatsArgs,
+
+    #[clap(flatten)]
+    compression: CompressionArgs,
+
+    #[clap(flatten)]
+    failure_policy: FailurePolicyArgs,
+
+    #[clap(flatten)]
+    arbitration: ArbitrationArgs,
+
+    /// Record per stage pipeline timings
+    ///
+    /// Timings of grab, sort, project, encode and publish are recorded into histograms,
+    /// logged and published as rplidar.Latency on <prefix>/profile every 10 seconds
+    #[clap(long)]
+    profile: bool,
+
+    #[clap(flatten)]
+    tracing: TracingArgs,
+
+    #[clap(flatten)]
+    runtime: RuntimeArgs,
+
+    #[clap(flatten)]
+    daemon: DaemonArgs,
+
+    #[clap(flatten)]
+    progress: ProgressArgs,
+
+    #[clap(flatten)]
+    capture_limits: CaptureLimitArgs,
+
+    /// Run connectivity checks and exit
+    ///
+    /// Verifies that the lidar responds on the serial port and that the zenoh session
+    /// can be opened, prints a JSON report and exits with a nonzero code on failure
+    #[clap(long)]
+    check: bool,
 }
 
-#[tokio::main]
-async fn main() -> anyhow::Result<()> {
+fn main() -> anyhow::Result<()> {
     let args: Args = Args::parse();
-    setup_tracing()?;
+    let pid_file = args.daemon.start()?;
+    let result = args.runtime.build()?.block_on(run(args));
+    // exiting with a failure code skips destructors
+    drop(pid_file);
+    if let Err(err) = &result {
+        if let Some(failure) = err.downcast_ref::<Failure>() {
+            eprintln!("Error: {}", failure);
+            std::process::exit(failure.exit_code());
+        }
+    }
+    result
+}
 
-    let (mut scan_receiver, should_lidar_run) =
-        start_lidar_driver(&args.serial_port, !args.lidar_off)?;
+async fn run(args: Args) -> anyhow::Result<()> {
+    setup_tracing_with_args("driver", &args.tracing)?;
+    let topics = TopicBuilder::new(&args.prefix)?;
 
     let mut zenoh_config = Config::default();
+    enable_timestamping(&mut zenoh_config)?;
     if !args.listen.is_empty() {
-        zenoh_config.listen.endpoints = args
-            .listen
-            .iter()
-            .map(|endpoint| endpoint.parse().unwrap())
-            .collect();
+        zenoh_config.listen.endpoints.clone_from(&args.listen);
+        info!(listen_endpoints= ?zenoh_config.listen.endpoints, "Configured listening endpoints");
     }
 
     if !args.connect.is_empty() {
-        zenoh_config.connect.endpoints = args
-            .connect
-            .iter()
-            .map(|endpoint| endpoint.parse().unwrap())
-            .collect();
+        zenoh_config.connect.endpoints.clone_from(&args.connect);
+        info!(connect_endpoints= ?zenoh_config.connect.endpoints, "Configured connect endpoints");
+    }
+    args.static_peers.apply(&mut zenoh_config)?;
+
+    if args.access_control.restrict_to_prefix {
+        // ROS 2 key expressions live outside of the prefix
+        let mut extra_key_exprs = vec![];
+        if args.ros2.enabled {
+            extra_key_exprs.push(args.ros2.scan_key_expr()?);
+            extra_key_exprs.push(args.ros2.cloud_key_expr()?);
+        }
+        if let Some(velocity_topic) = &args.temporal_filter.velocity_topic {
+            extra_key_exprs.push(velocity_topic.clone());
+        }
+        args.access_control
+            .apply(&mut zenoh_config, &topics, &extra_key_exprs)?;
+        info!(
+            prefix = topics.prefix(),
+            ?extra_key_exprs,
+            "Restricted zenoh access to prefix"
+        );
     }
 
-    let zenoh_session = zenoh::open(zenoh_config).res().await.unwrap().into_arc();
+    if args.check {
+        let report = run_connectivity_check(
+            &args.serial_port,
+            args.baud_rate,
+            args.motor_control,
+            zenoh_config,
+        )
+        .await;
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        if !report.success() {
+            // returned so main removes the pid file before exiting
+            anyhow::bail!("Connectivity check failed");
+        }
+        return Ok(());
+    }
 
-    let state_topic = format!("{}/state", args.prefix)
-        .trim_matches('/')
-        .to_owned();
-    let subscriber = zenoh_session
-        .declare_subscriber(&state_topic)
+    args.capture_limits.validate()?;
+    let scan_rate_grace_period = Duration::try_from_secs_f64(args.scan_rate_grace_period)
+        .context("Invalid scan rate grace period")?;
+
+    if args.no_laser_scan && args.no_point_cloud {
+        anyhow::bail!("Both laser scan and point cloud outputs are disabled");
+    }
+
+    if args.scan_batch_size > 1 && args.scan_format != ScanFormat::Foxglove {
+        anyhow::bail!("Laser scan batching only supports the foxglove scan format");
+    }
+
+    // everything given on the command line or in the config file is checked before the
+    // session and lidar start so mistakes fail right away
+    args.temporal_filter.validate()?;
+    let velocity_timeout = Duration::try_from_secs_f64(args.temporal_filter.velocity_timeout)
+        .context("Invalid velocity timeout")?;
+    if args.sector_quality.sector_quality {
+        args.sector_quality.validate()?;
+    }
+    if args.angle_stats.angle_stats {
+        args.angle_stats.validate()?;
+    }
+    if args.remote.is_enabled() {
+        args.remote.validate()?;
+    }
+    let driver_config = match &args.config {
+        Some(path) => DriverConfig::load(path)?,
+        None => DriverConfig::default(),
+    };
+    info!(?driver_config, "Loaded driver config");
+
+    let mut start_with_lidar_running = !args.lidar_off;
+    if let Some(state_file) = &args.state_file {
+        if let Some(lidar_on) = load_motor_state(state_file).await {
+            info!(?state_file, lidar_on, "Restored motor state");
+            start_with_lidar_running = lidar_on;
+        }
+    }
+
+    let zenoh_session = zenoh::open(zenoh_config)
         .res()
         .await
-        .unwrap();
+        .map_err(ErrorWrapper::ZenohError)?
+        .into_arc();
+    let _alive_token = declare_alive_token(
+        &zenoh_session,
+        topics.topic(liveliness::DRIVER_ALIVE_TOPIC)?,
+    )
+    .await?;
+
+    let events_topic = topics.topic(events::EVENTS_TOPIC)?;
+    let event_publisher = start_event_publisher(zenoh_session.clone(), events_topic).await?;
+    let profiler = StageProfiler::new(args.profile);
+    let activity = ActivityTracker::new();
 
-    let laser_scan_topic = format!("{}/{}", args.prefix, args.scan_topic)
-        .trim_matches('/')
-        .to_owned();
-    let laser_scan_publisher = zenoh_session
-        .declare_publisher(laser_scan_topic)
+    let LidarDriverHandle {
+        mut scan_receiver,
+        should_lidar_run,
+        motor_idle,
+        connection_attempts,
+        mut device_info_receiver,
+        scan_mode_receiver,
+        shutdown: lidar_shutdown,
+        thread: lidar_thread,
+        scan_queue,
+        failure: lidar_failure,
+    } = start_lidar_driver(
+        &args.serial_port,
+        start_with_lidar_running,
+        args.baud_rate,
+        args.scan_mode,
+        args.motor_control,
+        args.allow_unsupported_firmware,
+        event_publisher.clone(),
+        &args.scan_queue,
+        profiler.clone(),
+        if args.monotonic_timestamps {
+            CaptureClock::monotonic()
+        } else {
+            CaptureClock::System
+        },
+        args.failure_policy,
+    )?;
+    let mut frame_id = args.frame_id.clone();
+
+    let publisher_leader = start_publisher_arbitration(
+        zenoh_session.clone(),
+        topics.topic(arbitration::PUBLISHER_TOPIC)?,
+        &args.arbitration,
+        device_info_receiver.clone(),
+    )
+    .await?;
+
+    let state_topic = topics.topic("state")?;
+    let subscriber = zenoh_session
+        .declare_subscriber(&state_topic)
         .res()
         .await
         .unwrap();
 
-    let point_cloud_topic = format!("{}/{}", args.prefix, args.cloud_topic)
-        .trim_matches('/')
-        .to_owned();
-    let point_cloud_publisher = zenoh_session
-        .declare_publisher(point_cloud_topic)
+    let laser_scan_publisher = if args.no_laser_scan {
+        info!("Laser scan output disabled");
+        None
+    } else {
+        let mut laser_scan_topic = topics.topic(&args.scan_topic)?;
+        if let Some(suffix) = args.scan_format.topic_suffix() {
+            laser_scan_topic = topics.join(&[&args.scan_topic, suffix])?;
+            info!(laser_scan_topic, scan_format = ?args.scan_format, "Publishing compact laser scans");
+        }
+        if args.scan_batch_size > 1 {
+            laser_scan_topic = topics.join(&[&args.scan_topic, BATCH_TOPIC_SUFFIX])?;
+            info!(
+                laser_scan_topic,
+                batch_size = args.scan_batch_size,
+                "Batching laser scans"
+            );
+        }
+        let publisher = zenoh_session
+            .declare_publisher(laser_scan_topic)
+            .priority(args.priorities.laser_scan())
+            .res()
+            .await
+            .unwrap();
+        Some(publisher)
+    };
+
+    let point_cloud_publisher = if args.no_point_cloud {
+        info!("Point cloud output disabled");
+        None
+    } else {
+        let point_cloud_topic = topics.topic(&args.cloud_topic)?;
+        let publisher = zenoh_session
+            .declare_publisher(point_cloud_topic)
+            .priority(args.priorities.point_cloud())
+            .congestion_control(CongestionControl::Drop)
+            .res()
+            .await
+            .unwrap();
+        Some(publisher)
+    };
+
+    let ros2_laser_scan_publisher = if args.ros2.enabled && !args.no_laser_scan {
+        let topic = args.ros2.scan_key_expr()?;
+        info!(topic, "Publishing ROS 2 laser scans");
+        let publisher = zenoh_session
+            .declare_publisher(topic)
+            .priority(args.priorities.laser_scan())
+            .res()
+            .await
+            .unwrap();
+        Some(publisher)
+    } else {
+        None
+    };
+
+    let scan_broadcast = ScanBroadcast::new(SCAN_BROADCAST_CAPACITY);
+
+    if args.remote.is_enabled() {
+        // remote consumers get single scans even when batching locally
+        let laser_scan = match args.scan_format.topic_suffix() {
+            Some(suffix) => topics.join(&[&args.scan_topic, suffix])?,
+            None => topics.topic(&args.scan_topic)?,
+        };
+        let point_cloud = if args.remote.remote_point_cloud {
+            Some(topics.topic(&args.cloud_topic)?)
+        } else {
+            None
+        };
+        start_remote_publisher(
+            &args.remote,
+            RemoteTopics {
+                laser_scan,
+                point_cloud,
+            },
+            ScanEncoder::with_format(args.scan_format, !args.no_intensities)
+                .with_range_resolution(args.range_resolution_mm),
+            scan_broadcast.clone(),
+        )?;
+    }
+
+    if args.person_detector.detect_people {
+        start_person_detector(
+            zenoh_session.clone(),
+            topics.topic(people::PEOPLE_TOPIC)?,
+            args.person_detector,
+            scan_broadcast.clone(),
+        )
+        .await?;
+    }
+
+    if args.sector_quality.sector_quality {
+        start_sector_quality_publisher(
+            zenoh_session.clone(),
+            topics.topic(sector_quality::SECTOR_QUALITY_TOPIC)?,
+            topics.topic(sector_quality::SECTOR_QUALITY_GRID_TOPIC)?,
+            args.sector_quality,
+            scan_broadcast.clone(),
+        )
+        .await?;
+    }
+
+    if args.reflectors.detect_reflectors {
+        start_reflector_detector(
+            zenoh_session.clone(),
+            topics.topic(reflectors::REFLECTORS_TOPIC)?,
+            args.reflectors,
+            scan_broadcast.clone(),
+        )
+        .await?;
+    }
+
+    if !args.zones.zone.is_empty() {
+        start_zone_monitor(
+            zenoh_session.clone(),
+            topics.topic(zones::ZONE_SCENE_TOPIC)?,
+            args.zones.clone(),
+            event_publisher.clone(),
+            scan_broadcast.clone(),
+        )
+        .await?;
+    }
+
+    if args.map_deviation.reference_map.is_some() {
+        start_map_deviation_monitor(
+            zenoh_session.clone(),
+            topics.topic(map_deviation::MAP_DEVIATION_TOPIC)?,
+            topics.topic(map_deviation::MAP_DEVIATION_POINTS_TOPIC)?,
+            args.map_deviation.clone(),
+            scan_broadcast.clone(),
+        )
+        .await?;
+    }
+
+    if args.angle_stats.angle_stats {
+        start_angle_stats_publisher(
+            zenoh_session.clone(),
+            topics.topic(angle_stats::ANGLE_STATS_TOPIC)?,
+            args.angle_stats,
+            scan_broadcast.clone(),
+        )
+        .await?;
+    }
+
+    let (snapshot_sender, snapshot_receiver) =
+        channel::<SnapshotRequest>(SNAPSHOT_REQUEST_QUEUE_SIZE);
+    let snapshot_counters = QueueCounters::new(SNAPSHOT_REQUEST_QUEUE_SIZE);
+    if let Some(snapshot_dir) = &args.snapshot_dir {
+        tokio::spawn(run_snapshots(
+            scan_broadcast.clone(),
+            snapshot_receiver,
+            snapshot_counters.clone(),
+            snapshot_dir.clone(),
+        ));
+        let snapshot_topic = topics.topic(snapshot::SNAPSHOT_TOPIC)?;
+        info!(snapshot_topic, ?snapshot_dir, "Serving snapshots");
+        let queryable = zenoh_session
+            .declare_queryable(&snapshot_topic)
+            .res()
+            .await
+            .unwrap();
+        let snapshot_counters = snapshot_counters.clone();
+        let activity = activity.clone();
+        tokio::spawn(async move {
+            while let Ok(query) = queryable.recv_async().await {
+                activity.record();
+                let snapshot_sender = snapshot_sender.clone();
+                let snapshot_counters = snapshot_counters.clone();
+                tokio::spawn(async move {
+                    let reply = match request_snapshot(
+                        &snapshot_sender,
+                        &snapshot_counters,
+                        query.parameters(),
+                    )
+                    .await
+                    {
+                        Ok(path) => {
+                            info!(?path, "Snapshot written");
+                            Ok(Sample::new(
+                                query.key_expr().clone(),
+                                path.display().to_string(),
+                            ))
+                        }
+                        Err(err) => {
+                            error!(?err, "Snapshot failed");
+                            Err(Value::from(err.to_string()))
+                        }
+                    };
+                    if let Err(err) = query.reply(reply).res().await {
+                        error!(?err, "Failed to reply to snapshot query");
+                    }
+                });
+            }
+        });
+    } else {
+        drop(snapshot_sender);
+        drop(snapshot_receiver);
+    }
+
+    let rosbridge_publisher = match &args.rosbridge_topic {
+        Some(rosbridge_topic) => {
+            let key_expr = topics.join(&["rosbridge", rosbridge_topic])?;
+            info!(
+                key_expr,
+                rosbridge_topic, "Publishing rosbridge laser scans"
+            );
+            let publisher = zenoh_session
+                .declare_publisher(key_expr)
+                .res()
+                .await
+                .unwrap();
+            Some((rosbridge_topic.clone(), publisher))
+        }
+        None => None,
+    };
+
+    let scan_metadata_topic = topics.join(&[&args.scan_topic, "metadata"])?;
+    let scan_metadata_publisher = zenoh_session
+        .declare_publisher(scan_metadata_topic)
         .res()
         .await
         .unwrap();
 
-    let pose = foxglove::Pose {
-        position: Some(foxglove::Vector3 {
-            x: 0.0,
-            y: 0.0,
-            z: 0.0,
-        }),
-        orientation: Some(foxglove::Quaternion {
-            x: 0.0,
-            y: 0.0,
-            z: 0.0,
-            w: 0.0,
-        }),
+    let ros2_point_cloud_publisher = if args.ros2.enabled && !args.no_point_cloud {
+        let topic = args.ros2.cloud_key_expr()?;
+        info!(topic, "Publishing ROS 2 point clouds");
+        let publisher = zenoh_session
+            .declare_publisher(topic)
+            .priority(args.priorities.point_cloud())
+            .congestion_control(CongestionControl::Drop)
+            .res()
+            .await
+            .unwrap();
+        Some(publisher)
+    } else {
+        None
+    };
+
+    let (config_sender, mut config_receiver) = watch::channel(driver_config);
+    let config_sender = Arc::new(config_sender);
+    if let Some(path) = &args.config {
+        start_config_file_watcher(path.clone(), config_sender.clone());
+    }
+    start_config_update_subscriber(
+        zenoh_session.clone(),
+        topics.topic(config::CONFIG_SET_TOPIC)?,
+        topics.topic(config::CONFIG_ACK_TOPIC)?,
+        config_sender,
+        args.config.clone().filter(|_| args.persist_config_updates),
+    )
+    .await?;
+    start_log_level_subscriber(
+        zenoh_session.clone(),
+        topics.topic(log_level::LOG_LEVEL_TOPIC)?,
+    )
+    .await?;
+    // valid range of the connected model, applied when the config has no max range
+    let mut model_max_range: Option<f32> = None;
+    let mut driver_config = config_receiver.borrow_and_update().clone();
+    let mut pose = driver_config.mounting_pose.to_foxglove_pose();
+
+    let mut temporal_filter = (args.temporal_filter.temporal_smoothing > 0.0)
+        .then(|| TemporalFilter::new(args.temporal_filter.smoothing_max_jump));
+    let velocity = match &args.temporal_filter.velocity_topic {
+        Some(velocity_topic) if temporal_filter.is_some() => Some(
+            start_velocity_subscriber(zenoh_session.clone(), velocity_topic, velocity_timeout)
+                .await?,
+        ),
+        _ => None,
     };
 
+    let config_reporter = ConfigReporter {
+        startup: StartupConfig {
+            serial_port: args.serial_port.clone(),
+            baud_rate: args.baud_rate,
+            scan_mode: args.scan_mode,
+            motor_control: args.motor_control,
+            scan_format: args.scan_format,
+            range_resolution_mm: args.range_resolution_mm,
+            intensities: !args.no_intensities,
+            monotonic_timestamps: args.monotonic_timestamps,
+            laser_scan: !args.no_laser_scan,
+            point_cloud: !args.no_point_cloud,
+            ros2: args.ros2.enabled,
+            scan_batch_size: args.scan_batch_size,
+            compression: args.compression,
+            min_scan_rate: args.min_scan_rate,
+            rotation_rate_tolerance: args.rotation_rate_tolerance,
+            power_saving: args.power_saving.clone(),
+            priorities: args.priorities,
+            failure_policy: args.failure_policy,
+        },
+        frame_id: args.frame_id.clone(),
+        frame_id_serial_suffix: args.frame_id_serial_suffix,
+        rotation_rate: args.rotation_rate,
+        should_lidar_run: should_lidar_run.clone(),
+        motor_idle: motor_idle.clone(),
+        device_info_receiver: device_info_receiver.clone(),
+        scan_mode_receiver: scan_mode_receiver.clone(),
+        config_receiver: config_receiver.clone(),
+    };
+    let config_topic = topics.topic(CONFIG_TOPIC)?;
+    let config_queryable = zenoh_session
+        .declare_queryable(&config_topic)
+        .res()
+        .await
+        .unwrap();
     tokio::spawn(async move {
-        loop {
-            if let Ok(sample) = subscriber.recv_async().await {
-                info!("Received message: {}", sample);
-                if let Ok(message) = TryInto::<String>::try_into(&sample.value) {
-                    info!("Message: {}", message);
-                    let lidar_command_on = message.to_lowercase().ends_with("on");
-                    if lidar_command_on {
-                        info!("Starting scan");
-                        should_lidar_run.store(true, Ordering::Relaxed);
+        while let Ok(query) = config_queryable.recv_async().await {
+            let reply = match serde_json::to_string(&config_reporter.report()) {
+                Ok(json) => Ok(Sample::new(
+                    query.key_expr().clone(),
+                    Value::from(json).encoding(Encoding::APP_JSON),
+                )),
+                Err(err) => {
+                    error!(?err, "Failed to serialize config");
+                    Err(Value::from(err.to_string()))
+                }
+            };
+            if let Err(err) = query.reply(reply).res().await {
+                error!(?err, "Failed to reply to config query");
+            }
+        }
+    });
+
+    tokio::spawn({
+        let state_file = args.state_file.clone();
+        let should_lidar_run = should_lidar_run.clone();
+        let event_publisher = event_publisher.clone();
+        let activity = activity.clone();
+        async move {
+            loop {
+                if let Ok(sample) = subscriber.recv_async().await {
+                    info!("Received message: {}", sample);
+                    if let Ok(message) = TryInto::<String>::try_into(&sample.value) {
+                        info!("Message: {}", message);
+                        let lidar_command_on = message.to_lowercase().ends_with("on");
+                        if lidar_command_on {
+                            info!("Starting scan");
+                            activity.record();
+                            should_lidar_run.store(true, Ordering::Relaxed);
+                        } else {
+                            info!("Stopping scan");
+                            should_lidar_run.store(false, Ordering::Relaxed);
+                        }
+                        if let Some(state_file) = &state_file {
+                            if let Err(err) = store_motor_state(state_file, lidar_command_on).await
+                            {
+                                error!(?state_file, ?err, "Failed to persist motor state");
+                            }
+                        }
                     } else {
-                        info!("Stopping scan");
-                        should_lidar_run.store(false, Ordering::Relaxed);
+                        warn!("Failed to parse message: {:?}", sample.value);
+                        event_publisher.publish(
+                            rplidar::Event::new(
+                                "driver",
+                                events::Severity::Warn,
+                                events::COMMAND_REJECTED,
+                                "Failed to parse lidar state command",
+                            )
+                            .with_value("key_expr", &sample.key_expr),
+                        );
                     }
-                } else {
-                    warn!("Failed to parse message: {:?}", sample.value);
                 }
             }
         }
     });
 
-    let mut scan_counter = 0;
-    while let Some(mut scan) = scan_receiver.recv().await {
-        let capture_time = SystemTime::now();
-        scan_counter += 1;
-
-        if scan_counter % 80 == 0 {
-            info!("Scan counter: {}", scan_counter);
-        }
-
-        sort_scan(&mut scan)?;
-
-        let start_angle = scan.first().map(|point| point.angle()).unwrap_or_default();
-        let end_angle = scan
-            .iter()
-            .last()
-            .map(|point| point.angle())
-            .unwrap_or_default();
-
-        // laser scan
-        let laser_scan = foxglove::LaserScan {
-            timestamp: Some(system_time_to_proto_time(&capture_time)),
-            frame_id: args.frame_id.clone(),
-            pose: Some(pose),
-            start_angle: start_angle as f64,
-            end_angle: end_angle as f64,
-            ranges: scan.iter().map(|point| point.distance() as f64).collect(),
-            intensities: scan.iter().map(|point| point.quality as f64).collect(),
+    let driver_state = Arc::new(DriverState::default());
+
+    let latency_tracker = LatencyTracker::default();
+    let latency_topic = topics.topic(latency::LATENCY_TOPIC)?;
+    start_latency_publisher(
+        zenoh_session.clone(),
+        latency_topic,
+        "driver",
+        latency_tracker.clone(),
+    )
+    .await?;
+    if args.latency_probe {
+        let probe_topic = topics.topic(latency::PROBE_TOPIC)?;
+        let echo_topic = topics.topic(latency::ECHO_TOPIC)?;
+        start_probe_sender(
+            zenoh_session.clone(),
+            probe_topic,
+            echo_topic,
+            latency_tracker.clone(),
+        )
+        .await?;
+    }
+    let profile_topic = topics.topic(profiling::PROFILE_TOPIC)?;
+    start_profile_reporter(
+        zenoh_session.clone(),
+        profile_topic,
+        "driver",
+        profiler.clone(),
+    )
+    .await?;
+    let diagnostics_topic = topics.topic(diagnostics::DIAGNOSTICS_TOPIC)?;
+    if let Some(http_listen) = args.http_listen {
+        let health_check = driver_health_check(
+            driver_state.clone(),
+            should_lidar_run.clone(),
+            motor_idle.clone(),
+            device_info_receiver.clone(),
+            zenoh_session.clone(),
+            !args.connect.is_empty(),
+        );
+        monitoring::start_http_server(http_listen, Some(health_check)).await?;
+    }
+
+    let scan_rate_alert_publisher = zenoh_session
+        .declare_publisher(diagnostics_topic.clone())
+        .res()
+        .await
+        .unwrap();
+
+    start_diagnostics_publisher(zenoh_session.clone(), diagnostics_topic, {
+        let driver_state = driver_state.clone();
+        let should_lidar_run = should_lidar_run.clone();
+        let motor_idle = motor_idle.clone();
+        let connection_attempts = connection_attempts.clone();
+        let device_info_receiver = device_info_receiver.clone();
+        move || {
+            driver_diagnostics(
+                &driver_state,
+                &should_lidar_run,
+                &motor_idle,
+                &connection_attempts,
+                &device_info_receiver,
+            )
+        }
+    })
+    .await?;
+
+    let mut encode_pipeline = EncodePipeline::new(args.encode_pipeline_depth as usize);
+    let process_stats_topic = topics.topic(process_stats::PROCESS_STATS_TOPIC)?;
+    start_process_stats_publisher(zenoh_session.clone(), process_stats_topic, "driver", {
+        let queues = [
+            ("scans", scan_queue),
+            ("encode_pipeline", encode_pipeline.stats()),
+            ("events", event_publisher.stats()),
+            ("snapshot_requests", snapshot_counters.stats_handle()),
+        ];
+        move || {
+            queues
+                .iter()
+                .filter_map(|(name, queue)| queue.stats(name))
+                .collect()
+        }
+    })
+    .await?;
+
+    let mut systemd_notifier = SystemdNotifier::new(args.systemd_notify);
+    // while the lidar is stopped no scans arrive so the watchdog is pet from a timer
+    let mut idle_watchdog_interval = tokio::time::interval(
+        systemd_notifier
+            .watchdog_interval()
+            .unwrap_or(Duration::from_secs(1)),
+    );
+
+    let mut scan_rate_monitor = args
+        .min_scan_rate
+        .map(|min_scan_rate| ScanRateMonitor::new(min_scan_rate, scan_rate_grace_period));
+    let mut scan_rate_interval = tokio::time::interval(Duration::from_secs(1));
+
+    let rotation_rate_topic = topics.topic(scan_rate::ROTATION_RATE_TOPIC)?;
+    let rotation_rate_publisher = zenoh_session
+        .declare_publisher(rotation_rate_topic)
+        .res()
+        .await
+        .unwrap();
+    let mut rotation_rate_monitor = RotationRateMonitor::new(args.rotation_rate_tolerance);
+    let mut expected_rotation_rate = args.rotation_rate;
+    let mut rotation_rate_interval = tokio::time::interval(Duration::from_secs(1));
+
+    let mut subscriber_monitor = args.power_saving.subscriber_monitor();
+    let idle_timeout = args.power_saving.idle_timeout();
+    let mut idle_timeout_expired = false;
+    let mut power_saving_interval = tokio::time::interval(Duration::from_secs(1));
+
+    let mut scan_reporter = args.progress.reporter("scans");
+    let mut rosbridge_seq: u32 = 0;
+    let mut scan_encoders: Vec<ScanEncoder> = vec![];
+    let mut scan_publishers = ScanPublishers {
+        laser_scan: laser_scan_publisher.map(Arc::new),
+        point_cloud: point_cloud_publisher.map(Arc::new),
+        ros2_laser_scan: ros2_laser_scan_publisher.map(Arc::new),
+        ros2_point_cloud: ros2_point_cloud_publisher.map(Arc::new),
+        rosbridge: rosbridge_publisher.map(|(topic, publisher)| (topic, Arc::new(publisher))),
+        metadata: Arc::new(scan_metadata_publisher),
+        laser_scan_batch: (args.scan_batch_size > 1)
+            .then(|| LaserScanBatchEncoder::new(args.scan_batch_size as usize)),
+    };
+    // kept alive for the lifetime of the scan loop
+    let mut subscriber_listeners = vec![];
+    for publisher in scan_publishers.publishers() {
+        subscriber_listeners
+            .push(watch_subscribers(publisher, "driver", event_publisher.clone()).await?);
+    }
+    let deadline = args.capture_limits.deadline()?;
+    let mut error_throttle = RepeatThrottle::default();
+    let mut failure = None;
+    loop {
+        let mut frame = tokio::select! {
+            frame = scan_receiver.recv(), if !encode_pipeline.is_full() => match frame {
+                Some(frame) => frame,
+                None => break,
+            },
+            encoded = encode_pipeline.next() => {
+                let encoded = encoded
+                    .map_err(anyhow::Error::from)
+                    .and_then(|encoded| encoded);
+                let EncodedScan {
+                    encoder,
+                    payloads,
+                    capture_instant,
+                    span,
+                } = match encoded {
+                    Ok(encoded) => encoded,
+                    Err(err) => {
+                        // the encoder is lost with the job, the next scan gets a new one
+                        metrics::counter!(monitoring::ENCODE_ERRORS_TOTAL).increment(1);
+                        if let Some(repeated) = error_throttle.check(&err.to_string()) {
+                            error!(repeated, "Failed to encode scan: {:?}", err);
+                        }
+                        failure = args.failure_policy.handle(FailureClass::Encode, err);
+                        if failure.is_some() {
+                            break;
+                        }
+                        continue;
+                    }
+                };
+                scan_encoders.push(encoder);
+                let publish_start = Instant::now();
+                let published_bytes = match scan_publishers.publish(payloads, &span).await {
+                    Ok(published_bytes) => published_bytes,
+                    Err(err) => {
+                        metrics::counter!(monitoring::PUBLISH_ERRORS_TOTAL).increment(1);
+                        let err = anyhow::Error::from(ErrorWrapper::ZenohError(err));
+                        if let Some(repeated) = error_throttle.check(&err.to_string()) {
+                            error!(repeated, "Failed to publish scan: {:?}", err);
+                        }
+                        failure = args.failure_policy.handle(FailureClass::Zenoh, err);
+                        if failure.is_some() {
+                            break;
+                        }
+                        continue;
+                    }
+                };
+                error_throttle.flush();
+                profiler.record(profiling::PUBLISH, publish_start.elapsed());
+
+                let publish_latency = capture_instant.elapsed();
+                metrics::histogram!(monitoring::PUBLISH_LATENCY_SECONDS)
+                    .record(publish_latency.as_secs_f64());
+                latency_tracker.record(latency::CAPTURE_TO_PUBLISH, publish_latency);
+                metrics::counter!(monitoring::ZENOH_BYTES_OUT_TOTAL)
+                    .increment(published_bytes as u64);
+                scan_reporter.record(published_bytes);
+                systemd_notifier.notify_ready();
+                systemd_notifier.pet_watchdog();
+
+                if args
+                    .capture_limits
+                    .max_scans_reached(scan_reporter.total_messages())
+                {
+                    info!("Maximum scan count reached, exiting");
+                    break;
+                }
+                continue;
+            }
+            _ = idle_watchdog_interval.tick() => {
+                if !lidar_active(&should_lidar_run, &motor_idle) {
+                    systemd_notifier.notify_ready();
+                    systemd_notifier.pet_watchdog();
+                }
+                continue;
+            }
+            _ = scan_rate_interval.tick(), if scan_rate_monitor.is_some() => {
+                if let Some(monitor) = &mut scan_rate_monitor {
+                    let active = lidar_active(&should_lidar_run, &motor_idle);
+                    if let Some(alert) = monitor.check(Instant::now(), active) {
+                        if let ScanRateAlert::Degraded { rate } = alert {
+                            event_publisher.publish(
+                                rplidar::Event::new(
+                                    "driver",
+                                    events::Severity::Warn,
+                                    events::SCAN_RATE_DEGRADED,
+                                    "Scan rate below minimum",
+                                )
+                                .with_value("rate_hz", format!("{:.1}", rate))
+                                .with_value("min_rate_hz", monitor.min_rate()),
+                            );
+                        }
+                        let diagnostics = scan_rate_diagnostics(alert, monitor.min_rate());
+                        if let Err(err) = scan_rate_alert_publisher
+                            .put(diagnostics.encode_to_vec())
+                            .res()
+                            .await
+                        {
+                            error!(?err, "Failed to publish scan rate alert");
+                        }
+                    }
+                }
+                continue;
+            }
+            _ = rotation_rate_interval.tick() => {
+                let now = Instant::now();
+                if let Some(rate) = rotation_rate_monitor.rate(now) {
+                    metrics::gauge!(monitoring::ROTATION_RATE_HZ).set(rate);
+                    let message = rplidar::RotationRate::new(rate, expected_rotation_rate);
+                    if let Err(err) = rotation_rate_publisher
+                        .put(message.encode_to_vec())
+                        .res()
+                        .await
+                    {
+                        error!(?err, "Failed to publish rotation rate");
+                    }
+                }
+                if let Some(expected) = expected_rotation_rate {
+                    let active = lidar_active(&should_lidar_run, &motor_idle);
+                    if let Some(alert) = rotation_rate_monitor.check(now, active, expected) {
+                        if let RotationRateAlert::Deviating { rate, expected } = alert {
+                            event_publisher.publish(
+                                rplidar::Event::new(
+                                    "driver",
+                                    events::Severity::Warn,
+                                    events::ROTATION_RATE_DEVIATION,
+                                    "Rotation rate deviates from expected",
+                                )
+                                .with_value("rate_hz", format!("{:.2}", rate))
+                                .with_value("expected_hz", expected),
+                            );
+                        }
+                        let diagnostics =
+                            rotation_rate_diagnostics(alert, args.rotation_rate_tolerance);
+                        if let Err(err) = scan_rate_alert_publisher
+                            .put(diagnostics.encode_to_vec())
+                            .res()
+                            .await
+                        {
+                            error!(?err, "Failed to publish rotation rate alert");
+                        }
+                    }
+                }
+                continue;
+            }
+            _ = power_saving_interval.tick(), if args.power_saving.is_enabled() => {
+                let now = Instant::now();
+                let mut subscribers_idle = false;
+                if let Some(monitor) = &mut subscriber_monitor {
+                    let has_subscribers = match scan_publishers.has_subscribers().await {
+                        Ok(has_subscribers) => has_subscribers,
+                        Err(err) => {
+                            error!(?err, "Failed to query scan subscribers");
+                            // keep scanning when matching status is unknown
+                            true
+                        }
+                    };
+                    if let Some(idle) = monitor.update(now, has_subscribers) {
+                        if idle {
+                            info!("No scan subscribers, stopping motor to save power");
+                        } else {
+                            info!("Scan subscriber appeared, resuming");
+                        }
+                    }
+                    subscribers_idle = monitor.is_idle();
+                }
+                let activity_idle =
+                    idle_timeout.is_some_and(|timeout| activity.idle_for(now) >= timeout);
+                if activity_idle != idle_timeout_expired {
+                    idle_timeout_expired = activity_idle;
+                    if activity_idle {
+                        info!("Idle timeout reached, stopping motor");
+                    } else {
+                        info!("Activity after idle timeout, resuming");
+                    }
+                }
+                motor_idle.store(subscribers_idle || activity_idle, Ordering::Relaxed);
+                continue;
+            }
+            _ = wait_for_deadline(deadline) => {
+                info!("Capture duration reached, exiting");
+                break;
+            }
+            _ = signal::ctrl_c() => {
+                info!("ctrl-c received, exiting");
+                break;
+            }
         };
+        let capture_instant = frame.capture_instant;
+        let scan_span = info_span!(
+            "scan",
+            sequence = frame.sequence,
+            points = frame.points.len()
+        );
+        metrics::counter!(monitoring::SCANS_TOTAL).increment(1);
+        driver_state.record_scan(capture_instant);
+        if let Some(monitor) = &mut scan_rate_monitor {
+            monitor.record_scan(capture_instant);
+        }
+        rotation_rate_monitor.record_scan(capture_instant);
+        metrics::gauge!(monitoring::SCAN_POINTS).set(frame.points.len() as f64);
 
-        laser_scan_publisher
-            .put(laser_scan.encode_to_vec())
-            .res()
-            .await
-            .unwrap();
+        if device_info_receiver.has_changed().unwrap_or(false) {
+            if let Some(device_info) = device_info_receiver.borrow_and_update().as_ref() {
+                info!(?device_info, "Lidar connected");
+                let defaults = device_info.defaults();
+                if let Some(defaults) = &defaults {
+                    info!(?defaults, "Using model defaults");
+                }
+                if args.rotation_rate.is_none() {
+                    expected_rotation_rate = defaults.map(|defaults| defaults.rotation_rate);
+                }
+                let max_range = defaults.map(|defaults| defaults.max_range);
+                if max_range != model_max_range {
+                    model_max_range = max_range;
+                    config_receiver.mark_changed();
+                }
+                if args.frame_id_serial_suffix {
+                    frame_id = serial_frame_id(&args.frame_id, device_info);
+                    info!(frame_id, "Using frame_id with serial suffix");
+                }
+            }
+        }
 
-        // point cloud
-        let projected_scan = scan
-            .iter()
-            .filter(|scan| scan.is_valid())
-            .map(|scan_point| {
-                let x = scan_point.distance() * (-scan_point.angle()).cos();
-                let y = scan_point.distance() * (-scan_point.angle()).sin();
-                let quality = scan_point.quality;
+        if config_receiver.has_changed()? {
+            driver_config = config_receiver
+                .borrow_and_update()
+                .clone()
+                .with_default_max_range(model_max_range);
+            pose = driver_config.mounting_pose.to_foxglove_pose();
+        }
 
-                RpLidarProjectedPoint::new(x, y, scan_point.distance(), scan_point.angle(), quality)
+        info_span!(parent: &scan_span, "process_scan").in_scope(|| {
+            profiler.time(profiling::SORT, || {
+                sort_scan(&mut frame.points)?;
+                driver_config.apply_filters(&mut frame.points);
+                if let Some(temporal_filter) = &mut temporal_filter {
+                    let speed = velocity.as_ref().and_then(|velocity| velocity.speed());
+                    temporal_filter.apply(&mut frame.points, args.temporal_filter.smoothing(speed));
+                }
+                anyhow::Ok(())
             })
-            .collect::<Vec<_>>();
+        })?;
 
-        let point_cloud = rp_lidar_projected_points_to_foxglove_point_cloud(
-            &capture_time,
-            &args.frame_id,
-            &pose,
-            &projected_scan,
-        );
+        frame.frame_id.clone_from(&frame_id);
+        frame.pose = pose;
+        let frame = Arc::new(frame);
+        scan_broadcast.send(frame.clone());
 
-        point_cloud_publisher
-            .put(point_cloud.encode_to_vec())
-            .res()
-            .await
-            .unwrap();
+        // another driver publishes scans of this lidar, local consumers such as
+        // snapshots and zones still get every scan
+        if !*publisher_leader.borrow() {
+            systemd_notifier.notify_ready();
+            systemd_notifier.pet_watchdog();
+            continue;
+        }
+
+        let rosbridge = scan_publishers
+            .rosbridge
+            .as_ref()
+            .map(|(rosbridge_topic, _)| {
+                rosbridge_seq = rosbridge_seq.wrapping_add(1);
+                (rosbridge_topic.clone(), rosbridge_seq)
+            });
+        let job = EncodeJob {
+            encoder: scan_encoders.pop().unwrap_or_else(|| {
+                ScanEncoder::with_format(args.scan_format, !args.no_intensities)
+                    .with_range_resolution(args.range_resolution_mm)
+            }),
+            frame,
+            range_min: driver_config.filter.min_range,
+            range_max: driver_config
+                .filter
+                .max_range
+                .unwrap_or(ROS2_DEFAULT_RANGE_MAX),
+            laser_scan: scan_publishers.laser_scan.is_some(),
+            point_cloud: scan_publishers.point_cloud.is_some(),
+            ros2_laser_scan: scan_publishers.ros2_laser_scan.is_some(),
+            ros2_point_cloud: scan_publishers.ros2_point_cloud.is_some(),
+            compression: args.compression,
+            profiler: profiler.clone(),
+            rosbridge,
+            scan_mode: scan_mode_receiver.borrow().clone(),
+            span: scan_span,
+        };
+        encode_pipeline.submit(move || job.encode());
     }
 
+    // stop the motor before exiting so that the lidar doesn't keep spinning
+    lidar_shutdown.store(true, Ordering::Relaxed);
+    drop(scan_receiver);
+    match tokio::time::timeout(
+        LIDAR_SHUTDOWN_TIMEOUT,
+        tokio::task::spawn_blocking(move || lidar_thread.join()),
+    )
+    .await
+    {
+        Ok(_) => info!("Lidar stopped"),
+        Err(_) => warn!("Timed out waiting for lidar to stop"),
+    }
+
+    // the lidar thread stopping on a serial failure closes the scan queue
+    match failure.or_else(|| lidar_failure.take()) {
+        Some(failure) => Err(failure.into()),
+        None => Ok(()),
+    }
+}
+
+const LIDAR_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Scan activity shared with status reporting
+#[derive(Default)]
+struct DriverState {
+    scans: AtomicU64,
+    last_scan: Mutex<Option<Instant>>,
+}
+
+impl DriverState {
+    fn record_scan(&self, time: Instant) {
+        self.scans.fetch_add(1, Ordering::Relaxed);
+        *self.last_scan.lock().unwrap() = Some(time);
+    }
+
+    fn last_scan_age(&self) -> Option<Duration> {
+        self.last_scan.lock().unwrap().map(|time| time.elapsed())
+    }
+}
+
+const STALE_SCAN_AGE: Duration = Duration::from_secs(2);
+
+/// Lidar was commanded on and isn't stopped for power saving
+fn lidar_active(should_lidar_run: &AtomicBool, motor_idle: &AtomicBool) -> bool {
+    should_lidar_run.load(Ordering::Relaxed) && !motor_idle.load(Ordering::Relaxed)
+}
+
+fn driver_diagnostics(
+    driver_state: &DriverState,
+    should_lidar_run: &AtomicBool,
+    motor_idle: &AtomicBool,
+    connection_attempts: &AtomicU32,
+    device_info_receiver: &watch::Receiver<Option<LidarDeviceInfo>>,
+) -> rplidar::Diagnostics {
+    let lidar_on = should_lidar_run.load(Ordering::Relaxed);
+    let idle = motor_idle.load(Ordering::Relaxed);
+    let connection_attempts = connection_attempts.load(Ordering::Relaxed);
+    let last_scan_age = driver_state.last_scan_age();
+    let device_info = device_info_receiver.borrow().clone();
+
+    let (level, message) = match (lidar_on, &device_info, last_scan_age) {
+        (_, None, _) if connection_attempts > 0 => {
+            (diagnostics::Level::Warn, "Waiting for lidar device")
+        }
+        (_, None, _) => (diagnostics::Level::Error, "Lidar not connected"),
+        (false, Some(_), _) => (diagnostics::Level::Ok, "Lidar stopped"),
+        (true, Some(_), _) if idle => (diagnostics::Level::Ok, "Lidar idle without subscribers"),
+        (true, Some(_), Some(age)) if age < STALE_SCAN_AGE => (diagnostics::Level::Ok, "Scanning"),
+        (true, Some(_), _) => (diagnostics::Level::Warn, "No recent scans"),
+    };
+
+    let mut diagnostics = rplidar::Diagnostics::new("driver", level, message)
+        .with_value("lidar_on", lidar_on)
+        .with_value("motor_idle", idle)
+        .with_value("scans", driver_state.scans.load(Ordering::Relaxed));
+    if let Some(age) = last_scan_age {
+        diagnostics = diagnostics.with_value("last_scan_age", format!("{:.3}", age.as_secs_f64()));
+    }
+    if let Some(device_info) = device_info {
+        diagnostics = diagnostics
+            .with_value("serial_number", device_info.serial_number)
+            .with_value("firmware_version", device_info.firmware_version_string());
+    } else {
+        diagnostics = diagnostics.with_value("connection_attempts", connection_attempts);
+    }
+    diagnostics
+}
+
+/// Scanning stalled for this long is reported as not live
+const STALLED_SCAN_AGE: Duration = Duration::from_secs(10);
+
+fn driver_health_check(
+    driver_state: Arc<DriverState>,
+    should_lidar_run: Arc<AtomicBool>,
+    motor_idle: Arc<AtomicBool>,
+    device_info_receiver: watch::Receiver<Option<LidarDeviceInfo>>,
+    zenoh_session: Arc<Session>,
+    requires_zenoh_connection: bool,
+) -> HealthCheck {
+    Arc::new(move || {
+        let driver_state = driver_state.clone();
+        let should_lidar_run = should_lidar_run.clone();
+        let motor_idle = motor_idle.clone();
+        let device_info_receiver = device_info_receiver.clone();
+        let zenoh_session = zenoh_session.clone();
+        Box::pin(async move {
+            let lidar_on = lidar_active(&should_lidar_run, &motor_idle);
+            let lidar_connected = device_info_receiver.borrow().is_some();
+            let last_scan_age = driver_state.last_scan_age();
+            let stalled = lidar_on
+                && last_scan_age.map_or(true, |age| age > STALLED_SCAN_AGE)
+                && driver_state.scans.load(Ordering::Relaxed) > 0;
+
+            let info = zenoh_session.info();
+            let routers = info.routers_zid().res().await.count();
+            let peers = info.peers_zid().res().await.count();
+            let zenoh_connected = !requires_zenoh_connection || routers + peers > 0;
+
+            HealthReport {
+                live: !stalled,
+                ready: lidar_connected && zenoh_connected,
+                details: serde_json::json!({
+                    "lidar": {
+                        "connected": lidar_connected,
+                        "on": lidar_on,
+                        "last_scan_age": last_scan_age.map(|age| age.as_secs_f64()),
+                    },
+                    "zenoh": {
+                        "connected": zenoh_connected,
+                        "routers": routers,
+                        "peers": peers,
+                    },
+                }),
+            }
+        })
+    })
+}
+
+const MOTOR_STATE_ON: &str = "on";
+const MOTOR_STATE_OFF: &str = "off";
+
+async fn load_motor_state(path: &Path) -> Option<bool> {
+    match tokio::fs::read_to_string(path).await {
+        Ok(text) => match text.trim() {
+            MOTOR_STATE_ON => Some(true),
+            MOTOR_STATE_OFF => Some(false),
+            other => {
+                warn!("Ignoring unknown motor state {:?} in {:?}", other, path);
+                None
+            }
+        },
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => None,
+        Err(err) => {
+            warn!("Failed to read motor state file {:?}: {}", path, err);
+            None
+        }
+    }
+}
+
+async fn store_motor_state(path: &Path, lidar_on: bool) -> anyhow::Result<()> {
+    let state = if lidar_on {
+        MOTOR_STATE_ON
+    } else {
+        MOTOR_STATE_OFF
+    };
+    // write next to the target and rename so that a crash can't leave a half written file
+    let temp_path = path.with_extension("tmp");
+    tokio::fs::write(&temp_path, state).await?;
+    tokio::fs::rename(&temp_path, path).await?;
     Ok(())
 }
 
+struct LidarDriverHandle {
+    scan_receiver: QueueReceiver<ScanFrame>,
+    should_lidar_run: Arc<AtomicBool>,
+    /// motor stopped for power saving regardless of `should_lidar_run`
+    motor_idle: Arc<AtomicBool>,
+    /// failed attempts to reach the lidar since it was last connected
+    connection_attempts: Arc<AtomicU32>,
+    device_info_receiver: watch::Receiver<Option<LidarDeviceInfo>>,
+    scan_mode_receiver: watch::Receiver<Option<ScanModeInfo>>,
+    shutdown: Arc<AtomicBool>,
+    thread: thread::JoinHandle<()>,
+    scan_queue: QueueStatsHandle,
+    /// set when a serial failure stopped the lidar thread
+    failure: Arc<FailureSlot>,
+}
+
+#[allow(clippy::too_many_arguments)]
 fn start_lidar_driver(
     port: &str,
     start_with_lidar_running: bool,
-) -> anyhow::Result<(Receiver<Vec<ScanPoint>>, Arc<AtomicBool>)> {
-    let (scan_sender, scan_receiver) = channel(10);
+    baud_rate: Option<u32>,
+    scan_mode: Option<u16>,
+    motor_control: MotorControl,
+    allow_unsupported_firmware: bool,
+    event_publisher: EventPublisher,
+    queue_args: &ScanQueueArgs,
+    profiler: StageProfiler,
+    clock: CaptureClock,
+    failure_policy: FailurePolicyArgs,
+) -> anyhow::Result<LidarDriverHandle> {
+    let (scan_sender, scan_receiver) = scan_queue(
+        queue_args.scan_queue_capacity as usize,
+        queue_args.scan_queue_policy,
+    );
+    let scan_queue = scan_sender.stats();
     let should_lidar_run = Arc::new(AtomicBool::new(start_with_lidar_running));
+    let motor_idle = Arc::new(AtomicBool::new(false));
+    let connection_attempts = Arc::new(AtomicU32::new(0));
+    let shutdown = Arc::new(AtomicBool::new(false));
+    let (device_info_sender, device_info_receiver) = watch::channel(None);
+    let (scan_mode_sender, scan_mode_receiver) = watch::channel(None);
+    let failure = Arc::new(FailureSlot::default());
+    let stop_on_scan_error = failure_policy.action(FailureClass::Serial) != ErrorAction::Continue;
 
-    thread::spawn({
+    let thread = thread::spawn({
         let port = port.to_owned();
         let should_lidar_run = Arc::clone(&should_lidar_run);
-        move || loop {
-            if let Err(err) = lidar_loop(&port, scan_sender.clone(), should_lidar_run.clone()) {
-                error!("Lidar loop error: {}", err);
-                thread::sleep(Duration::from_secs(1));
+        let motor_idle = Arc::clone(&motor_idle);
+        let connection_attempts = Arc::clone(&connection_attempts);
+        let shutdown = Arc::clone(&shutdown);
+        let failure = Arc::clone(&failure);
+        move || {
+            let mut reconnecting = false;
+            let mut backoff = ReconnectBackoff::default();
+            // detected baud rate is kept for reconnects
+            let mut baud_rate = baud_rate;
+            let mut error_throttle = RepeatThrottle::default();
+            // continues across reconnects so consumers can spot dropped scans
+            let mut sequence = 0;
+            while !shutdown.load(Ordering::Relaxed) {
+                if let Err(err) = lidar_loop(
+                    &port,
+                    &mut baud_rate,
+                    scan_mode,
+                    motor_control,
+                    allow_unsupported_firmware,
+                    &scan_sender,
+                    &mut sequence,
+                    should_lidar_run.clone(),
+                    &motor_idle,
+                    &shutdown,
+                    &device_info_sender,
+                    &scan_mode_sender,
+                    &event_publisher,
+                    reconnecting,
+                    &mut error_throttle,
+                    &profiler,
+                    &clock,
+                    stop_on_scan_error,
+                ) {
+                    metrics::counter!(monitoring::SERIAL_ERRORS_TOTAL).increment(1);
+                    let was_connected = device_info_sender.send_replace(None).is_some();
+                    scan_mode_sender.send_replace(None);
+                    if was_connected {
+                        backoff.reset();
+                    }
+                    let message = err.to_string();
+                    if let Some(repeated) = error_throttle.check(&message) {
+                        error!(repeated, "Lidar loop error: {}", message);
+                        event_publisher.publish(
+                            rplidar::Event::new(
+                                "driver",
+                                events::Severity::Error,
+                                events::SERIAL_ERROR,
+                                &message,
+                            )
+                            .with_value("port", &port)
+                            .with_value("repeated", repeated),
+                        );
+                    }
+                    if let Some(serial_failure) = failure_policy.handle(FailureClass::Serial, err) {
+                        failure.set(serial_failure);
+                        break;
+                    }
+                    reconnecting = true;
+                    let delay = backoff.next_delay();
+                    connection_attempts.store(backoff.attempts(), Ordering::Relaxed);
+                    info!(
+                        attempts = backoff.attempts(),
+                        delay_s = delay.as_secs(),
+                        "Waiting for lidar device"
+                    );
+                    sleep_unless_shutdown(&shutdown, delay);
+                }
             }
         }
     });
 
-    Ok((scan_receiver, should_lidar_run))
+    Ok(LidarDriverHandle {
+        scan_receiver,
+        should_lidar_run,
+        motor_idle,
+        connection_attempts,
+        device_info_receiver,
+        scan_mode_receiver,
+        shutdown,
+        thread,
+        scan_queue,
+        failure,
+    })
+}
+
+/// Sleep for `duration` waking early when shutdown is requested
+fn sleep_unless_shutdown(shutdown: &AtomicBool, duration: Duration) {
+    let deadline = Instant::now() + duration;
+    while !shutdown.load(Ordering::Relaxed) {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            break;
+        }
+        thread::sleep(remaining.min(SHUTDOWN_POLL_INTERVAL));
+    }
 }
 
+/// Runs until shutdown is requested or an error occurs
+#[allow(clippy::too_many_arguments)]
 fn lidar_loop(
     port: &str,
-    scan_sender: Sender<Vec<ScanPoint>>,
+    baud_rate: &mut Option<u32>,
+    scan_mode: Option<u16>,
+    motor_control: MotorControl,
+    allow_unsupported_firmware: bool,
+    scan_sender: &QueueSender<ScanFrame>,
+    sequence: &mut u64,
     should_lidar_run: Arc<AtomicBool>,
+    motor_idle: &AtomicBool,
+    shutdown: &AtomicBool,
+    device_info_sender: &watch::Sender<Option<LidarDeviceInfo>>,
+    scan_mode_sender: &watch::Sender<Option<ScanModeInfo>>,
+    event_publisher: &EventPublisher,
+    reconnecting: bool,
+    connection_error_throttle: &mut RepeatThrottle,
+    profiler: &StageProfiler,
+    clock: &CaptureClock,
+    stop_on_scan_error: bool,
 ) -> anyhow::Result<()> {
-    let mut lidar = RplidarDevice::open_port(port)?;
+    let ConnectedLidar {
+        mut lidar,
+        mut motor,
+        device_info,
+        baud_rate: connected_baud_rate,
+    } = connect_lidar(port, *baud_rate, motor_control)?;
+    *baud_rate = Some(connected_baud_rate);
+    let device_info = LidarDeviceInfo::from(&device_info);
+    info!(
+        baud_rate = connected_baud_rate,
+        ?motor_control,
+        model = ?device_info.model_name,
+        "Connected to lidar"
+    );
+    match device_info.firmware_support() {
+        FirmwareSupport::Supported => (),
+        FirmwareSupport::Unsupported { minimum } if allow_unsupported_firmware => {
+            let message = format!(
+                "Firmware {} is older than known good {}, scans may be corrupted",
+                device_info.firmware_version_string(),
+                format_firmware_version(minimum)
+            );
+            warn!("{}", message);
+            event_publisher.publish(
+                rplidar::Event::new(
+                    "driver",
+                    events::Severity::Warn,
+                    events::UNSUPPORTED_FIRMWARE,
+                    &message,
+                )
+                .with_value("port", port),
+            );
+        }
+        FirmwareSupport::Unsupported { minimum } => anyhow::bail!(
+            "Firmware {} is older than known good {}, update the lidar or pass --allow-unsupported-firmware",
+            device_info.firmware_version_string(),
+            format_firmware_version(minimum)
+        ),
+        FirmwareSupport::Unknown => warn!(
+            "Firmware {} of unknown model {:#04x} can't be checked",
+            device_info.firmware_version_string(),
+            device_info.model
+        ),
+    }
+    let scan_options = device_info.scan_options(scan_mode);
+    let device_id = device_info.serial_number.clone();
+    device_info_sender.send_replace(Some(device_info));
+    connection_error_throttle.flush();
+    let mut scan_error_throttle = RepeatThrottle::default();
+    let mut stuck_scan_detector = StuckScanDetector::default();
+    let mut last_capture: Option<Instant> = None;
+    if reconnecting {
+        event_publisher.publish(
+            rplidar::Event::new(
+                "driver",
+                events::Severity::Info,
+                events::RECONNECTED,
+                "Lidar reconnected",
+            )
+            .with_value("port", port),
+        );
+    }
     // start with this flag opposite of desired so that we set the lidar to correct start
-    let mut lidar_running = !should_lidar_run.load(Ordering::Relaxed);
+    let mut lidar_running = !lidar_active(&should_lidar_run, motor_idle);
     loop {
-        match should_lidar_run.load(Ordering::Relaxed) {
+        if shutdown.load(Ordering::Relaxed) {
+            if lidar_running {
+                info!("Stopping lidar for shutdown");
+                motor.stop(&mut lidar)?;
+            }
+            return Ok(());
+        }
+        match lidar_active(&should_lidar_run, motor_idle) {
             true => {
                 if !lidar_running {
-                    lidar.start_motor()?;
-                    let scan_options = ScanOptions::with_mode(2);
-                    let _ = lidar.start_scan_with_options(&scan_options)?;
+                    motor.start(&mut lidar)?;
+                    let scan_mode =
+                        ScanModeInfo::from(&lidar.start_scan_with_options(&scan_options)?);
+                    if scan_mode.is_dense() {
+                        info!(?scan_mode, "Lidar running in dense scan mode");
+                    } else {
+                        info!(?scan_mode, "Lidar scan started");
+                    }
+                    scan_mode_sender.send_replace(Some(scan_mode));
                     lidar_running = true;
+                    last_capture = None;
                 }
-                match lidar.grab_scan() {
+                match profiler.time(profiling::GRAB, || lidar.grab_scan()) {
                     Ok(scan) => {
-                        scan_sender.blocking_send(scan)?;
+                        scan_error_throttle.flush();
+                        if let Some(stuck_scan) = stuck_scan_detector.check(&scan) {
+                            let message = stuck_scan.to_string();
+                            warn!("Lidar appears stuck, restarting scan: {}", message);
+                            event_publisher.publish(
+                                rplidar::Event::new(
+                                    "driver",
+                                    events::Severity::Error,
+                                    events::STUCK_SCAN,
+                                    &message,
+                                )
+                                .with_value("port", port),
+                            );
+                            lidar.stop()?;
+                            // scan is started again on the next iteration
+                            lidar_running = false;
+                            continue;
+                        }
+                        let mut frame =
+                            ScanFrame::captured(scan, *sequence, device_id.clone(), clock);
+                        *sequence += 1;
+                        if let Some(last) = last_capture.replace(frame.capture_instant) {
+                            frame.scan_duration = frame.capture_instant.duration_since(last);
+                        }
+                        match scan_sender.send_blocking(frame) {
+                            Ok(0) => (),
+                            Ok(dropped) => {
+                                metrics::counter!(monitoring::SCAN_CHANNEL_DROPS_TOTAL)
+                                    .increment(dropped as u64);
+                            }
+                            Err(err) => {
+                                if shutdown.load(Ordering::Relaxed) {
+                                    // receiver dropped for shutdown, motor stops on next iteration
+                                    continue;
+                                }
+                                metrics::counter!(monitoring::SCAN_CHANNEL_DROPS_TOTAL)
+                                    .increment(1);
+                                return Err(err.into());
+                            }
+                        }
                     }
                     Err(err) => match err {
                         RposError::OperationTimeout => continue,
-                        _ => info!("Error: {:?}", err),
+                        _ => {
+                            metrics::counter!(monitoring::SERIAL_ERRORS_TOTAL).increment(1);
+                            let message = format!("{:?}", err);
+                            if let Some(repeated) = scan_error_throttle.check(&message) {
+                                info!(repeated, "Error: {}", message);
+                                event_publisher.publish(
+                                    rplidar::Event::new(
+                                        "driver",
+                                        events::Severity::Warn,
+                                        events::SERIAL_ERROR,
+                                        &message,
+                                    )
+                                    .with_value("port", port)
+                                    .with_value("repeated", repeated),
+                                );
+                            }
+                            if stop_on_scan_error {
+                                anyhow::bail!("Failed to grab scan: {}", message);
+                            }
+                        }
                     },
                 }
             }
             false => match lidar_running {
                 true => {
                     info!("Stopping lidar");
-                    lidar.stop_motor()?;
+                    motor.stop(&mut lidar)?;
                     lidar_running = false;
                 }
                 false => thread::sleep(std::time::Duration::from_millis(500)),
@@ -269,3 +1803,396 @@ fn lidar_loop(
         }
     }
 }
+
+const CHECK_ZENOH_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Serialize, Debug)]
+struct CheckReport {
+    serial: SerialCheck,
+    zenoh: ZenohCheck,
+}
+
+impl CheckReport {
+    fn success(&self) -> bool {
+        self.serial.ok && self.zenoh.ok
+    }
+}
+
+#[derive(Serialize, Debug, Default)]
+struct SerialCheck {
+    ok: bool,
+    port: String,
+    baud_rate: Option<u32>,
+    model: Option<u8>,
+    model_name: Option<LidarModel>,
+    firmware_version: Option<String>,
+    hardware_version: Option<u8>,
+    serial_number: Option<String>,
+    firmware_support: Option<FirmwareSupport>,
+    health: Option<String>,
+    error: Option<String>,
+}
+
+#[derive(Serialize, Debug, Default)]
+struct ZenohCheck {
+    ok: bool,
+    zid: Option<String>,
+    connect_endpoints: usize,
+    routers: Vec<String>,
+    peers: Vec<String>,
+    error: Option<String>,
+}
+
+async fn run_connectivity_check(
+    port: &str,
+    baud_rate: Option<u32>,
+    motor_control: MotorControl,
+    zenoh_config: Config,
+) -> CheckReport {
+    let serial = tokio::task::spawn_blocking({
+        let port = port.to_owned();
+        move || check_serial(&port, baud_rate, motor_control)
+    })
+    .await
+    .unwrap_or_else(|err| SerialCheck {
+        port: port.to_owned(),
+        error: Some(format!("Serial check panicked: {}", err)),
+        ..Default::default()
+    });
+
+    let zenoh = check_zenoh(zenoh_config).await;
+
+    CheckReport { serial, zenoh }
+}
+
+fn check_serial(port: &str, baud_rate: Option<u32>, motor_control: MotorControl) -> SerialCheck {
+    let mut report = SerialCheck {
+        port: port.to_owned(),
+        ..Default::default()
+    };
+
+    let mut lidar = match connect_lidar(port, baud_rate, motor_control) {
+        Ok(ConnectedLidar {
+            lidar,
+            device_info,
+            baud_rate,
+            ..
+        }) => {
+            let device_info = LidarDeviceInfo::from(&device_info);
+            report.baud_rate = Some(baud_rate);
+            report.model = Some(device_info.model);
+            report.model_name = device_info.model_name;
+            report.firmware_version = Some(device_info.firmware_version_string());
+            report.hardware_version = Some(device_info.hardware_version);
+            report.firmware_support = Some(device_info.firmware_support());
+            report.serial_number = Some(device_info.serial_number);
+            lidar
+        }
+        Err(err) => {
+            report.error = Some(format!("{:?}", err));
+            return report;
+        }
+    };
+
+    match lidar.get_device_health() {
+        Ok(health) => report.health = Some(format!("{:?}", health)),
+        Err(err) => {
+            report.error = Some(format!(
+                "Lidar did not respond to health request: {:?}",
+                err
+            ));
+            return report;
+        }
+    }
+
+    report.ok = true;
+    report
+}
+
+async fn check_zenoh(zenoh_config: Config) -> ZenohCheck {
+    let mut report = ZenohCheck {
+        connect_endpoints: zenoh_config.connect.endpoints.len(),
+        ..Default::default()
+    };
+
+    let zenoh_session =
+        match tokio::time::timeout(CHECK_ZENOH_TIMEOUT, zenoh::open(zenoh_config).res()).await {
+            Ok(Ok(session)) => session,
+            Ok(Err(err)) => {
+                report.error = Some(format!("Failed to open zenoh session: {}", err));
+                return report;
+            }
+            Err(_) => {
+                report.error = Some("Timed out opening zenoh session".to_owned());
+                return report;
+            }
+        };
+
+    let info = zenoh_session.info();
+    report.zid = Some(info.zid().res().await.to_string());
+    report.routers = info
+        .routers_zid()
+        .res()
+        .await
+        .map(|zid| zid.to_string())
+        .collect();
+    report.peers = info
+        .peers_zid()
+        .res()
+        .await
+        .map(|zid| zid.to_string())
+        .collect();
+
+    // without explicit endpoints we rely on scouting so there is nothing we must reach
+    if report.connect_endpoints > 0 && report.routers.is_empty() && report.peers.is_empty() {
+        report.error = Some("None of the configured endpoints are reachable".to_owned());
+        return report;
+    }
+
+    report.ok = true;
+    report
+}
+
+/// Effective configuration is served as JSON on `<prefix>/config`
+const CONFIG_TOPIC: &str = "config";
+
+/// Options fixed at startup
+#[derive(Serialize, Debug, Clone)]
+struct StartupConfig {
+    serial_port: String,
+    baud_rate: Option<u32>,
+    scan_mode: Option<u16>,
+    motor_control: MotorControl,
+    scan_format: ScanFormat,
+    range_resolution_mm: u32,
+    intensities: bool,
+    monotonic_timestamps: bool,
+    laser_scan: bool,
+    point_cloud: bool,
+    ros2: bool,
+    scan_batch_size: u16,
+    compression: CompressionArgs,
+    min_scan_rate: Option<f64>,
+    rotation_rate_tolerance: f64,
+    power_saving: PowerSavingArgs,
+    priorities: PublisherPriorityArgs,
+    failure_policy: FailurePolicyArgs,
+}
+
+/// Complete configuration the driver is running with
+#[derive(Serialize, Debug)]
+struct EffectiveConfig {
+    #[serde(flatten)]
+    startup: StartupConfig,
+    frame_id: String,
+    lidar_on: bool,
+    motor_idle: bool,
+    device: Option<LidarDeviceInfo>,
+    active_scan_mode: Option<ScanModeInfo>,
+    expected_rotation_rate: Option<f64>,
+    /// filters, masks and mounting pose with model defaults applied
+    driver_config: DriverConfig,
+}
+
+/// Builds the effective configuration from startup options and current driver state
+struct ConfigReporter {
+    startup: StartupConfig,
+    frame_id: String,
+    frame_id_serial_suffix: bool,
+    rotation_rate: Option<f64>,
+    should_lidar_run: Arc<AtomicBool>,
+    motor_idle: Arc<AtomicBool>,
+    device_info_receiver: watch::Receiver<Option<LidarDeviceInfo>>,
+    scan_mode_receiver: watch::Receiver<Option<ScanModeInfo>>,
+    config_receiver: watch::Receiver<DriverConfig>,
+}
+
+impl ConfigReporter {
+    fn report(&self) -> EffectiveConfig {
+        let device_info = self.device_info_receiver.borrow().clone();
+        let defaults = device_info.as_ref().and_then(LidarDeviceInfo::defaults);
+        let frame_id = match &device_info {
+            Some(device_info) if self.frame_id_serial_suffix => {
+                serial_frame_id(&self.frame_id, device_info)
+            }
+            _ => self.frame_id.clone(),
+        };
+        EffectiveConfig {
+            startup: self.startup.clone(),
+            frame_id,
+            lidar_on: self.should_lidar_run.load(Ordering::Relaxed),
+            motor_idle: self.motor_idle.load(Ordering::Relaxed),
+            active_scan_mode: self.scan_mode_receiver.borrow().clone(),
+            expected_rotation_rate: self
+                .rotation_rate
+                .or(defaults.map(|defaults| defaults.rotation_rate)),
+            driver_config: self
+                .config_receiver
+                .borrow()
+                .clone()
+                .with_default_max_range(defaults.map(|defaults| defaults.max_range)),
+            device: device_info,
+        }
+    }
+}
+
+fn serial_frame_id(frame_id: &str, device_info: &LidarDeviceInfo) -> String {
+    format!("{}_{}", frame_id, device_info.serial_number)
+}
+
+/// Snapshot requests waiting for the scan loop
+const SNAPSHOT_REQUEST_QUEUE_SIZE: usize = 4;
+/// Snapshots collecting scans at the same time
+///
+/// Each one buffers up to [`snapshot::MAX_SNAPSHOT_SCANS`] projected scans
+const MAX_PENDING_SNAPSHOTS: usize = 4;
+
+struct SnapshotRequest {
+    scans: usize,
+    reply: oneshot::Sender<anyhow::Result<PathBuf>>,
+}
+
+struct PendingSnapshot {
+    remaining_scans: usize,
+    points: Vec<snapshot::PointXYZI>,
+    reply: oneshot::Sender<anyhow::Result<PathBuf>>,
+}
+
+/// Collect scans for snapshot requests and write them to `snapshot_dir`
+///
+/// Only subscribes to the scan broadcast while snapshots are pending
+async fn run_snapshots(
+    scan_broadcast: ScanBroadcast,
+    mut snapshot_receiver: Receiver<SnapshotRequest>,
+    snapshot_counters: Arc<QueueCounters>,
+    snapshot_dir: PathBuf,
+) {
+    let mut pending_snapshots: Vec<PendingSnapshot> = vec![];
+    let mut scan_subscriber: Option<ScanSubscriber> = None;
+    loop {
+        tokio::select! {
+            request = snapshot_receiver.recv(),
+                if pending_snapshots.len() < MAX_PENDING_SNAPSHOTS =>
+            {
+                let Some(request) = request else {
+                    break;
+                };
+                snapshot_counters.record_pop();
+                pending_snapshots.push(PendingSnapshot {
+                    remaining_scans: request.scans,
+                    points: vec![],
+                    reply: request.reply,
+                });
+                scan_subscriber.get_or_insert_with(|| scan_broadcast.subscribe("snapshot"));
+            }
+            frame = async { scan_subscriber.as_mut()?.recv().await }, if scan_subscriber.is_some() => {
+                let Some(frame) = frame else {
+                    break;
+                };
+                let points: Vec<_> = snapshot::project_scan(&frame.points).collect();
+                for pending in &mut pending_snapshots {
+                    pending.points.extend_from_slice(&points);
+                    pending.remaining_scans -= 1;
+                }
+                let (completed, waiting) = pending_snapshots
+                    .drain(..)
+                    .partition(|pending| pending.remaining_scans == 0);
+                pending_snapshots = waiting;
+                for pending in completed {
+                    let path = snapshot::snapshot_path(&snapshot_dir);
+                    tokio::task::spawn_blocking(move || {
+                        let result = snapshot::write_pcd(&path, &pending.points).map(|_| path);
+                        let _ = pending.reply.send(result);
+                    });
+                }
+                if pending_snapshots.is_empty() {
+                    scan_subscriber = None;
+                }
+            }
+        }
+    }
+}
+
+/// Ask the scan loop for a snapshot and wait for the written file
+///
+/// Fails right away instead of queueing when too many snapshots are requested
+async fn request_snapshot(
+    snapshot_sender: &Sender<SnapshotRequest>,
+    snapshot_counters: &QueueCounters,
+    parameters: &str,
+) -> anyhow::Result<PathBuf> {
+    let scans = snapshot::requested_scan_count(parameters)?;
+    let (reply, reply_receiver) = oneshot::channel();
+    snapshot_counters.record_push();
+    match snapshot_sender.try_send(SnapshotRequest { scans, reply }) {
+        Ok(()) => (),
+        Err(TrySendError::Full(_)) => {
+            snapshot_counters.record_pop();
+            snapshot_counters.record_drop();
+            anyhow::bail!("Too many pending snapshot requests");
+        }
+        Err(TrySendError::Closed(_)) => {
+            snapshot_counters.record_pop();
+            anyhow::bail!("Scan loop stopped");
+        }
+    }
+    reply_receiver
+        .await
+        .map_err(|_| anyhow::anyhow!("Scan loop stopped"))?
+}
+
+fn scan_rate_diagnostics(alert: ScanRateAlert, min_rate: f64) -> rplidar::Diagnostics {
+    match alert {
+        ScanRateAlert::Degraded { rate } => {
+            warn!(
+                "Scan rate {:.1} Hz stayed below minimum of {:.1} Hz",
+                rate, min_rate
+            );
+            rplidar::Diagnostics::new(
+                "scan_rate_monitor",
+                diagnostics::Level::Warn,
+                "Scan rate below minimum",
+            )
+        }
+        ScanRateAlert::Recovered { rate } => {
+            info!(rate, min_rate, "Scan rate recovered");
+            rplidar::Diagnostics::new(
+                "scan_rate_monitor",
+                diagnostics::Level::Ok,
+                "Scan rate recovered",
+            )
+        }
+    }
+    .with_value("rate_hz", format!("{:.1}", alert.rate()))
+    .with_value("min_rate_hz", min_rate)
+}
+
+fn rotation_rate_diagnostics(alert: RotationRateAlert, tolerance: f64) -> rplidar::Diagnostics {
+    match alert {
+        RotationRateAlert::Deviating { rate, expected } => {
+            warn!(
+                "Rotation rate {:.2} Hz deviates from expected {:.2} Hz by more than {:.0}%",
+                rate,
+                expected,
+                tolerance * 100.0
+            );
+            rplidar::Diagnostics::new(
+                "rotation_rate_monitor",
+                diagnostics::Level::Warn,
+                "Rotation rate deviates from expected",
+            )
+            .with_value("rate_hz", format!("{:.2}", rate))
+            .with_value("expected_hz", expected)
+        }
+        RotationRateAlert::Recovered { rate, expected } => {
+            info!(rate, expected, "Rotation rate recovered");
+            rplidar::Diagnostics::new(
+                "rotation_rate_monitor",
+                diagnostics::Level::Ok,
+                "Rotation rate recovered",
+            )
+            .with_value("rate_hz", format!("{:.2}", rate))
+            .with_value("expected_hz", expected)
+        }
+    }
+}
@@ -1,14 +1,44 @@
 use clap::Parser;
 use foxglove_ws::{Channel, FoxgloveWebSocket};
-use mcap::records::system_time_to_nanos;
+use mcap::{records::system_time_to_nanos, MessageStream};
+use memmap2::Mmap;
 use prost::Message;
 use prost_reflect::ReflectMessage;
-use std::{net::SocketAddr, sync::Arc, time::SystemTime};
-use tokio::signal;
-use tracing::{error, info};
-use zenoh::{config::Config, prelude::r#async::*, subscriber::FlumeSubscriber};
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, HashMap},
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::{self, RecvTimeoutError},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant, SystemTime},
+};
+use tokio::{runtime::Handle, signal};
+use tracing::{error, info, warn};
+use zenoh::{config::Config, prelude::r#async::*};
 
-use rplidar_zenoh_driver::{foxglove, setup_tracing, ErrorWrapper};
+use rplidar_zenoh_driver::{
+    assets::start_asset_server,
+    compression,
+    diagnostics::{self, start_diagnostics_publisher},
+    encoding::COMPACT_TOPIC_SUFFIX,
+    foxglove,
+    latency::{self, capture_latency, start_latency_publisher, start_probe_echo, LatencyTracker},
+    liveliness, monitoring,
+    peers::StaticPeersArgs,
+    progress::{ProgressArgs, ThroughputReporter},
+    rplidar,
+    runtime::RuntimeArgs,
+    scan_queue::{scan_queue, BackpressurePolicy, QueueClosed, QueueReceiver},
+    schema, setup_tracing_with_args, system_time_to_proto_time,
+    timestamping::sample_time,
+    topics::TopicBuilder,
+    zones, ErrorWrapper, TracingArgs,
+};
 
 #[derive(Parser, Debug)]
 #[command()]
@@ -31,6 +61,9 @@ struct Args {
     #[clap(short = 'e', long)]
     connect: Vec<zenoh_config::EndPoint>,
 
+    #[clap(flatten)]
+    static_peers: StaticPeersArgs,
+
     /// Endpoints to listen on.
     #[clap(long)]
     listen: Vec<zenoh_config::EndPoint>,
@@ -38,12 +71,95 @@ struct Args {
     /// foxglove bind address
     #[clap(long, default_value = "0.0.0.0:8765")]
     host: SocketAddr,
+
+    /// Serve prometheus metrics on /metrics at this address
+    #[clap(long)]
+    http_listen: Option<SocketAddr>,
+
+    /// Also offer point clouds converted to laser scans on <cloud_topic>/laser_scan
+    ///
+    /// Much smaller to view over low bandwidth links when the driver can't be reconfigured
+    #[clap(long)]
+    derived_laser_scan: bool,
+
+    /// Also offer compact scans decoded to foxglove.LaserScan on <scan_topic>/compact/laser_scan
+    ///
+    /// Studio can't draw ranges of --scan-format delta without decoding them
+    #[clap(long)]
+    decode_compact_scans: bool,
+
+    /// Maximum messages per second sent to Studio on each channel
+    ///
+    /// Unlimited when unset
+    #[clap(long)]
+    max_channel_rate: Option<f64>,
+
+    /// Messages queued per channel before the oldest are dropped
+    ///
+    /// Keeps a slow client from growing memory or stalling the zenoh subscribers.
+    /// Use 1 to always send the latest message
+    #[clap(long, default_value_t = 4, value_parser = clap::value_parser!(u32).range(1..))]
+    channel_queue_capacity: u32,
+
+    /// Serve this MCAP recording instead of live zenoh data
+    ///
+    /// Playback is controlled by publishing `play`, `pause` or `seek <seconds>`
+    /// on <prefix>/replay/control
+    #[clap(long)]
+    replay: Option<PathBuf>,
+
+    /// Start the recording over when replay reaches the end instead of pausing
+    #[clap(long, requires = "replay")]
+    replay_loop: bool,
+
+    /// How recorded timestamps are mapped when replaying
+    #[clap(long, value_enum, default_value_t = ReplayTimeMode::Preserve, requires = "replay")]
+    replay_time_mode: ReplayTimeMode,
+
+    /// Start replay this many seconds into the recording
+    #[clap(long, default_value_t = 0.0, requires = "replay")]
+    replay_start_offset: f64,
+
+    /// Playback speed, 2.0 replays twice as fast as recorded
+    #[clap(long, default_value_t = 1.0, requires = "replay")]
+    replay_speed: f64,
+
+    /// Directory with meshes and URDFs served on /assets/<path>
+    ///
+    /// Scene entities can reference files as http://<host>:<port>/assets/<path>
+    #[clap(long)]
+    assets_dir: Option<PathBuf>,
+
+    /// Bind address of the asset server
+    #[clap(long, default_value = "0.0.0.0:8766")]
+    assets_listen: SocketAddr,
+
+    #[clap(flatten)]
+    tracing: TracingArgs,
+
+    #[clap(flatten)]
+    runtime: RuntimeArgs,
+
+    #[clap(flatten)]
+    progress: ProgressArgs,
 }
 
-#[tokio::main]
-async fn main() -> anyhow::Result<()> {
+fn main() -> anyhow::Result<()> {
     let args: Args = Args::parse();
-    setup_tracing()?;
+    args.runtime.build()?.block_on(run(args))
+}
+
+async fn run(args: Args) -> anyhow::Result<()> {
+    setup_tracing_with_args("foxglove_server", &args.tracing)?;
+    let topics = TopicBuilder::new(&args.prefix)?;
+
+    if let Some(http_listen) = args.http_listen {
+        monitoring::start_http_server(http_listen, None).await?;
+    }
+
+    if let Some(assets_dir) = &args.assets_dir {
+        start_asset_server(args.assets_listen, assets_dir.clone()).await?;
+    }
 
     // start foxglove server
     let server = foxglove_ws::FoxgloveWebSocket::default();
@@ -62,6 +178,7 @@ async fn main() -> anyhow::Result<()> {
         zenoh_config.connect.endpoints.clone_from(&args.connect);
         info!(connect_endpoints= ?zenoh_config.connect.endpoints, "Configured connect endpoints");
     }
+    args.static_peers.apply(&mut zenoh_config)?;
 
     let zenoh_session = zenoh::open(zenoh_config)
         .res()
@@ -70,25 +187,158 @@ async fn main() -> anyhow::Result<()> {
     let zenoh_session = zenoh_session.into_arc();
     info!("Started zenoh session");
 
-    let scan_topic = format!("{}/{}", args.prefix, args.scan_topic)
-        .trim_matches('/')
-        .to_owned();
+    let bridge_stats = Arc::new(BridgeStats::default());
+    let diagnostics_topic = topics.topic(diagnostics::DIAGNOSTICS_TOPIC)?;
+    start_diagnostics_publisher(zenoh_session.clone(), diagnostics_topic, {
+        let bridge_stats = bridge_stats.clone();
+        move || bridge_stats.diagnostics()
+    })
+    .await?;
+
+    let latency_topic = topics.topic(latency::LATENCY_TOPIC)?;
+    start_latency_publisher(
+        zenoh_session.clone(),
+        latency_topic,
+        "foxglove_server",
+        bridge_stats.latency.clone(),
+    )
+    .await?;
+    start_bridge_stats_publisher(
+        zenoh_session.clone(),
+        topics.topic(monitoring::BRIDGE_STATS_TOPIC)?,
+        bridge_stats.clone(),
+    )
+    .await?;
+
+    start_probe_echo(
+        zenoh_session.clone(),
+        topics.topic(latency::PROBE_TOPIC)?,
+        topics.topic(latency::ECHO_TOPIC)?,
+        "foxglove_server",
+    )
+    .await?;
+
+    if let Some(replay) = &args.replay {
+        if args.replay_speed.is_nan() || args.replay_speed <= 0.0 {
+            anyhow::bail!("Replay speed must be positive");
+        }
+        let timing = ReplayTiming {
+            mode: args.replay_time_mode,
+            start_offset: Duration::try_from_secs_f64(args.replay_start_offset)?,
+            speed: args.replay_speed,
+        };
+        start_mcap_replay(
+            replay,
+            args.replay_loop,
+            timing,
+            &server,
+            &topics.topic(REPLAY_CONTROL_TOPIC)?,
+            zenoh_session.clone(),
+        )
+        .await?;
+        signal::ctrl_c().await?;
+        info!("ctrl-c received, exiting");
+        return Ok(());
+    }
+
+    start_status_channel(
+        &topics.topic(BRIDGE_STATUS_TOPIC)?,
+        &topics.topic(liveliness::DRIVER_ALIVE_TOPIC)?,
+        zenoh_session.clone(),
+        &server,
+        bridge_stats.clone(),
+    )
+    .await?;
+
+    let channel_limits = ChannelLimits::new(args.max_channel_rate, args.channel_queue_capacity)?;
+    let scan_topic = topics.topic(&args.scan_topic)?;
     start_proto_subscriber(
         &scan_topic,
+        None,
         zenoh_session.clone(),
         &server,
         &foxglove::LaserScan::default(),
+        &args.progress,
+        channel_limits,
+        bridge_stats.clone(),
+    )
+    .await?;
+
+    // polar scans from --scan-format f32, millimeter or delta, schema comes from the descriptor pool
+    let compact_scan_topic = topics.join(&[&args.scan_topic, COMPACT_TOPIC_SUFFIX])?;
+    start_proto_subscriber(
+        &compact_scan_topic,
+        None,
+        zenoh_session.clone(),
+        &server,
+        &rplidar::CompactLaserScan::default(),
+        &args.progress,
+        channel_limits,
+        bridge_stats.clone(),
     )
     .await?;
 
-    let cloud_topic = format!("{}/{}", args.prefix, args.cloud_topic)
-        .trim_matches('/')
-        .to_owned();
+    if args.decode_compact_scans {
+        start_proto_subscriber(
+            &compact_scan_topic,
+            Some(Conversion {
+                channel_topic: topics.join(&[
+                    &args.scan_topic,
+                    COMPACT_TOPIC_SUFFIX,
+                    DERIVED_LASER_SCAN_SUFFIX,
+                ])?,
+                convert: compact_laser_scan_to_laser_scan,
+            }),
+            zenoh_session.clone(),
+            &server,
+            &foxglove::LaserScan::default(),
+            &args.progress,
+            channel_limits,
+            bridge_stats.clone(),
+        )
+        .await?;
+    }
+
+    let cloud_topic = topics.topic(&args.cloud_topic)?;
     start_proto_subscriber(
         &cloud_topic,
+        None,
         zenoh_session.clone(),
         &server,
         &foxglove::PointCloud::default(),
+        &args.progress,
+        channel_limits,
+        bridge_stats.clone(),
+    )
+    .await?;
+
+    if args.derived_laser_scan {
+        start_proto_subscriber(
+            &cloud_topic,
+            Some(Conversion {
+                channel_topic: topics.join(&[&args.cloud_topic, DERIVED_LASER_SCAN_SUFFIX])?,
+                convert: point_cloud_to_laser_scan,
+            }),
+            zenoh_session.clone(),
+            &server,
+            &foxglove::LaserScan::default(),
+            &args.progress,
+            channel_limits,
+            bridge_stats.clone(),
+        )
+        .await?;
+    }
+
+    // zone states drawn by the driver's zone monitor
+    start_proto_subscriber(
+        &topics.topic(zones::ZONE_SCENE_TOPIC)?,
+        None,
+        zenoh_session.clone(),
+        &server,
+        &foxglove::SceneUpdate::default(),
+        &args.progress,
+        channel_limits,
+        bridge_stats.clone(),
     )
     .await?;
 
@@ -98,11 +348,37 @@ async fn main() -> anyhow::Result<()> {
     Ok(())
 }
 
+/// Re-encodes payloads for a channel derived from another topic
+struct Conversion {
+    channel_topic: String,
+    convert: ConvertFn,
+}
+
+type ConvertFn = fn(&[u8]) -> anyhow::Result<Vec<u8>>;
+
+const DERIVED_LASER_SCAN_SUFFIX: &str = "laser_scan";
+
+fn point_cloud_to_laser_scan(payload: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let point_cloud = foxglove::PointCloud::decode(payload)?;
+    Ok(point_cloud.to_laser_scan()?.encode_to_vec())
+}
+
+fn compact_laser_scan_to_laser_scan(payload: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let laser_scan = rplidar::CompactLaserScan::decode(payload)?;
+    Ok(laser_scan.to_laser_scan()?.encode_to_vec())
+}
+
+/// Bridge `topic` to Studio, converting payloads if `conversion` is set
+#[allow(clippy::too_many_arguments)]
 async fn start_proto_subscriber(
     topic: &str,
+    conversion: Option<Conversion>,
     zenoh_session: Arc<Session>,
     foxglove_server: &FoxgloveWebSocket,
     protobuf: &dyn ReflectMessage,
+    progress: &ProgressArgs,
+    limits: ChannelLimits,
+    bridge_stats: Arc<BridgeStats>,
 ) -> anyhow::Result<()> {
     info!(topic, "Starting proto subscriber");
     let zenoh_subscriber = zenoh_session
@@ -111,16 +387,58 @@ async fn start_proto_subscriber(
         .await
         .map_err(ErrorWrapper::ZenohError)?;
 
-    let foxglove_channel = create_publisher_for_protobuf(protobuf, foxglove_server, topic).await?;
+    let (channel_topic, convert) = match conversion {
+        Some(conversion) => (conversion.channel_topic, Some(conversion.convert)),
+        None => (topic.to_owned(), None),
+    };
+    let foxglove_channel =
+        create_publisher_for_protobuf(protobuf, foxglove_server, &channel_topic).await?;
+
+    // decouples zenoh from websocket sends so a slow client can't back up the subscriber
+    let (queue_sender, mut queue_receiver) =
+        scan_queue::<Sample>(limits.queue_capacity, BackpressurePolicy::DropOldest);
+    tokio::spawn({
+        let topic = channel_topic.clone();
+        let bridge_stats = bridge_stats.clone();
+        async move {
+            while let Ok(sample) = zenoh_subscriber.recv_async().await {
+                // never waits with drop-oldest
+                match queue_sender.send_blocking(sample) {
+                    Ok(0) => (),
+                    Ok(dropped) => {
+                        metrics::counter!(monitoring::BRIDGE_DROPPED_TOTAL, "topic" => topic.clone())
+                            .increment(dropped as u64);
+                        bridge_stats.record_dropped(&topic, dropped);
+                    }
+                    Err(QueueClosed) => break,
+                }
+            }
+        }
+    });
 
     tokio::spawn({
-        let topic = topic.to_owned();
+        let topic = channel_topic;
+        let mut reporter = progress.reporter(&topic);
         async move {
             loop {
-                if let Err(err) =
-                    zenoh_listener_loop(&topic, &zenoh_subscriber, &foxglove_channel).await
+                match zenoh_listener_loop(
+                    &topic,
+                    &mut queue_receiver,
+                    convert,
+                    &foxglove_channel,
+                    &mut reporter,
+                    limits,
+                    &bridge_stats,
+                )
+                .await
                 {
-                    error!(?topic, ?err, "Zenoh listener failed");
+                    Ok(()) => break,
+                    Err(err) => {
+                        metrics::counter!(monitoring::BRIDGE_SEND_ERRORS_TOTAL, "topic" => topic.clone())
+                            .increment(1);
+                        bridge_stats.send_errors.fetch_add(1, Ordering::Relaxed);
+                        error!(?topic, ?err, "Zenoh listener failed");
+                    }
                 }
             }
         }
@@ -128,33 +446,194 @@ async fn start_proto_subscriber(
     Ok(())
 }
 
+/// Send limits applied to every bridged channel
+#[derive(Debug, Clone, Copy)]
+struct ChannelLimits {
+    /// `None` sends as fast as messages arrive
+    min_interval: Option<Duration>,
+    queue_capacity: usize,
+}
+
+impl ChannelLimits {
+    fn new(max_rate: Option<f64>, queue_capacity: u32) -> anyhow::Result<Self> {
+        let min_interval = match max_rate {
+            Some(rate) if rate.is_nan() || rate <= 0.0 => {
+                anyhow::bail!("Maximum channel rate must be positive")
+            }
+            Some(rate) => Some(Duration::from_secs_f64(1.0 / rate)),
+            None => None,
+        };
+        Ok(Self {
+            min_interval,
+            queue_capacity: queue_capacity as usize,
+        })
+    }
+}
+
+/// Forward queued samples to Studio until the queue closes
 async fn zenoh_listener_loop(
     topic: &str,
-    zenoh_subscriber: &FlumeSubscriber<'_>,
+    queue: &mut QueueReceiver<Sample>,
+    convert: Option<ConvertFn>,
     foxglove_channel: &Channel,
+    reporter: &mut ThroughputReporter,
+    limits: ChannelLimits,
+    bridge_stats: &BridgeStats,
 ) -> anyhow::Result<()> {
-    let mut message_counter = 0;
+    let mut last_sent: Option<Instant> = None;
     loop {
-        let sample = zenoh_subscriber.recv_async().await?;
-        message_counter += 1;
-        let now = SystemTime::now();
-        let time_nanos = system_time_to_nanos(&now);
-        let payload = if let Ok(blob) = TryInto::<Vec<u8>>::try_into(&sample.value) {
+        if let (Some(min_interval), Some(last_sent)) = (limits.min_interval, last_sent) {
+            tokio::time::sleep_until((last_sent + min_interval).into()).await;
+        }
+        let Some(sample) = queue.recv().await else {
+            return Ok(());
+        };
+        last_sent = Some(Instant::now());
+        let capture_latency_hop = |hop: &str| format!("{}/{}", hop, topic);
+        // source time keeps Studio's clock on robot time when the viewer's clock is skewed
+        let time_nanos = system_time_to_nanos(&sample_time(&sample));
+        let payload = if let Some(payload) = compression::decompress(&sample.value)? {
+            payload
+        } else if let Ok(blob) = TryInto::<Vec<u8>>::try_into(&sample.value) {
             blob
         } else if let Ok(text) = TryInto::<String>::try_into(&sample.value) {
             text.encode_to_vec()
         } else {
             anyhow::bail!("Failed to convert message type");
         };
+        if let Some(latency) = capture_latency(&payload) {
+            bridge_stats
+                .latency
+                .record(&capture_latency_hop(latency::CAPTURE_TO_RECEIVE), latency);
+        }
+        let payload = match convert {
+            Some(convert) => convert(&payload)?,
+            None => payload,
+        };
         foxglove_channel.send(time_nanos, &payload).await?;
+        if let Some(latency) = capture_latency(&payload) {
+            bridge_stats
+                .latency
+                .record(&capture_latency_hop(latency::CAPTURE_TO_SEND), latency);
+        }
+        metrics::counter!(monitoring::BRIDGE_MESSAGES_TOTAL, "topic" => topic.to_owned())
+            .increment(1);
+        metrics::counter!(monitoring::BRIDGE_BYTES_OUT_TOTAL, "topic" => topic.to_owned())
+            .increment(payload.len() as u64);
+        bridge_stats.messages.fetch_add(1, Ordering::Relaxed);
+        bridge_stats.record_message(topic, payload.len());
+        reporter.record(payload.len());
+    }
+}
+
+#[derive(Default)]
+struct BridgeStats {
+    messages: AtomicU64,
+    send_errors: AtomicU64,
+    latency: LatencyTracker,
+    channels: Mutex<HashMap<String, ChannelCounters>>,
+}
+
+#[derive(Default)]
+struct ChannelCounters {
+    messages: u64,
+    bytes: u64,
+    dropped: u64,
+    last_message: Option<Instant>,
+}
+
+impl BridgeStats {
+    fn update_channel(&self, topic: &str, update: impl FnOnce(&mut ChannelCounters)) {
+        let mut channels = self.channels.lock().unwrap();
+        match channels.get_mut(topic) {
+            Some(counters) => update(counters),
+            None => update(channels.entry(topic.to_owned()).or_default()),
+        }
+    }
+
+    fn record_message(&self, topic: &str, bytes: usize) {
+        self.update_channel(topic, |counters| {
+            counters.messages += 1;
+            counters.bytes += bytes as u64;
+            counters.last_message = Some(Instant::now());
+        });
+    }
+
+    fn record_dropped(&self, topic: &str, dropped: usize) {
+        self.update_channel(topic, |counters| counters.dropped += dropped as u64);
+    }
+
+    /// Seconds since the last message per topic
+    fn last_message_age(&self) -> BTreeMap<String, f64> {
+        self.channels
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|(topic, counters)| {
+                let received = counters.last_message?;
+                Some((topic.clone(), received.elapsed().as_secs_f64()))
+            })
+            .collect()
+    }
 
-        if message_counter % 20 == 0 {
-            info!(
-                topic,
-                message_counter, "{} sent {} messages", topic, message_counter
-            );
+    fn to_proto(&self) -> rplidar::BridgeStats {
+        let mut channels: Vec<_> = self
+            .channels
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(topic, counters)| rplidar::ChannelStats {
+                topic: topic.clone(),
+                messages: counters.messages,
+                bytes: counters.bytes,
+                dropped: counters.dropped,
+                last_message_age: counters
+                    .last_message
+                    .map(|received| received.elapsed().as_secs_f64())
+                    .unwrap_or_default(),
+            })
+            .collect();
+        channels.sort_by(|a, b| a.topic.cmp(&b.topic));
+        rplidar::BridgeStats {
+            timestamp: Some(system_time_to_proto_time(&SystemTime::now())),
+            component: "foxglove_server".to_owned(),
+            send_errors: self.send_errors.load(Ordering::Relaxed),
+            channels,
         }
     }
+
+    fn diagnostics(&self) -> rplidar::Diagnostics {
+        rplidar::Diagnostics::new("foxglove_server", diagnostics::Level::Ok, "Bridging")
+            .with_value("messages", self.messages.load(Ordering::Relaxed))
+            .with_value("send_errors", self.send_errors.load(Ordering::Relaxed))
+    }
+}
+
+const BRIDGE_STATS_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Periodically publish bridge counters so they get recorded with the data
+async fn start_bridge_stats_publisher(
+    zenoh_session: Arc<Session>,
+    topic: String,
+    bridge_stats: Arc<BridgeStats>,
+) -> anyhow::Result<()> {
+    let publisher = zenoh_session
+        .declare_publisher(topic)
+        .res()
+        .await
+        .map_err(ErrorWrapper::ZenohError)?;
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(BRIDGE_STATS_INTERVAL);
+        loop {
+            interval.tick().await;
+            let stats = bridge_stats.to_proto();
+            if let Err(err) = publisher.put(stats.encode_to_vec()).res().await {
+                error!(?err, "Failed to publish bridge stats");
+            }
+        }
+    });
+    Ok(())
 }
 
 const PROTOBUF_ENCODING: &str = "protobuf";
@@ -164,7 +643,7 @@ async fn create_publisher_for_protobuf(
     foxglove_server: &FoxgloveWebSocket,
     topic: &str,
 ) -> anyhow::Result<Channel> {
-    let protobuf_schema_data = protobuf.descriptor().parent_pool().encode_to_vec();
+    let protobuf_schema_data = schema::encoded_file_descriptor_set(&protobuf.descriptor());
     foxglove_server
         .create_publisher(
             topic,
@@ -177,9 +656,344 @@ async fn create_publisher_for_protobuf(
         .await
 }
 
-#[allow(dead_code)]
+const REPLAY_CONTROL_TOPIC: &str = "replay/control";
+
+/// Playback command received on the replay control topic
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ReplayCommand {
+    Play,
+    Pause,
+    /// Offset from the start of the recording
+    Seek(Duration),
+}
+
+impl FromStr for ReplayCommand {
+    type Err = anyhow::Error;
+
+    fn from_str(command: &str) -> Result<Self, Self::Err> {
+        let mut parts = command.split_whitespace();
+        let replay_command = match (parts.next(), parts.next()) {
+            (Some("play"), None) => ReplayCommand::Play,
+            (Some("pause"), None) => ReplayCommand::Pause,
+            (Some("seek"), Some(seconds)) => {
+                let seconds: f64 = seconds.parse()?;
+                ReplayCommand::Seek(Duration::try_from_secs_f64(seconds)?)
+            }
+            _ => anyhow::bail!("Unknown replay command {:?}", command),
+        };
+        if parts.next().is_some() {
+            anyhow::bail!("Unexpected arguments in replay command {:?}", command);
+        }
+        Ok(replay_command)
+    }
+}
+
+/// How recorded log times map to the times sent to Studio
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+enum ReplayTimeMode {
+    /// Send the recorded timestamps, for offline analysis
+    Preserve,
+    /// Shift timestamps so playback starts at the current time, for feeding live systems
+    ShiftToNow,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ReplayTiming {
+    mode: ReplayTimeMode,
+    /// Initial position from the start of the recording
+    start_offset: Duration,
+    /// Time scale factor
+    speed: f64,
+}
+
+/// Advertise the channels of an MCAP file and play it back at recorded pace
+async fn start_mcap_replay(
+    path: &Path,
+    replay_loop: bool,
+    timing: ReplayTiming,
+    foxglove_server: &FoxgloveWebSocket,
+    control_topic: &str,
+    zenoh_session: Arc<Session>,
+) -> anyhow::Result<()> {
+    let file = std::fs::File::open(path)?;
+    // SAFETY: recordings aren't modified while they are replayed
+    let mapped = unsafe { Mmap::map(&file)? };
+
+    let (mcap_channels, start_time) = read_mcap_channels(&mapped)?;
+    let Some(start_time) = start_time else {
+        anyhow::bail!("Recording {:?} has no messages", path);
+    };
+    let mut channels = HashMap::new();
+    for mcap_channel in mcap_channels {
+        info!(topic = mcap_channel.topic, "Advertising recorded channel");
+        let channel = create_publisher_for_mcap_channel(&mcap_channel, foxglove_server).await?;
+        channels.insert(mcap_channel.id, channel);
+    }
+
+    let (command_sender, command_receiver) = mpsc::channel();
+    info!(control_topic, "Listening for replay commands");
+    let control_subscriber = zenoh_session
+        .declare_subscriber(control_topic)
+        .res()
+        .await
+        .map_err(ErrorWrapper::ZenohError)?;
+    tokio::spawn(async move {
+        while let Ok(sample) = control_subscriber.recv_async().await {
+            let command = TryInto::<String>::try_into(&sample.value)
+                .map_err(anyhow::Error::from)
+                .and_then(|command| command.parse::<ReplayCommand>());
+            match command {
+                Ok(command) => {
+                    info!(?command, "Replay command received");
+                    if command_sender.send(command).is_err() {
+                        break;
+                    }
+                }
+                Err(err) => warn!("Invalid replay command: {}", err),
+            }
+        }
+    });
+
+    let runtime = Handle::current();
+    let path = path.to_owned();
+    tokio::task::spawn_blocking(move || {
+        if let Err(err) = play_mcap(
+            &mapped,
+            start_time,
+            &channels,
+            &command_receiver,
+            replay_loop,
+            timing,
+            &runtime,
+        ) {
+            error!(?err, ?path, "Replay failed");
+        }
+    });
+    Ok(())
+}
+
+/// Channels in order of first use and the earliest log time
+fn read_mcap_channels(mapped: &[u8]) -> anyhow::Result<(Vec<Arc<mcap::Channel<'_>>>, Option<u64>)> {
+    let mut channels: Vec<Arc<mcap::Channel>> = vec![];
+    let mut start_time: Option<u64> = None;
+    for message in MessageStream::new(mapped)? {
+        let message = message?;
+        start_time = Some(start_time.map_or(message.log_time, |start| start.min(message.log_time)));
+        if !channels
+            .iter()
+            .any(|channel| channel.id == message.channel.id)
+        {
+            channels.push(message.channel);
+        }
+    }
+    Ok((channels, start_time))
+}
+
+async fn create_publisher_for_mcap_channel(
+    mcap_channel: &mcap::Channel<'_>,
+    foxglove_server: &FoxgloveWebSocket,
+) -> anyhow::Result<Channel> {
+    let (schema_name, schema_data, schema_encoding) = match &mcap_channel.schema {
+        Some(schema) => (
+            schema.name.as_str(),
+            schema.data.to_vec(),
+            Some(schema.encoding.as_str()),
+        ),
+        None => ("", vec![], None),
+    };
+    foxglove_server
+        .create_publisher(
+            &mcap_channel.topic,
+            &mcap_channel.message_encoding,
+            schema_name,
+            schema_data,
+            schema_encoding,
+            false,
+        )
+        .await
+}
+
+/// Send recorded messages to Studio with their original spacing scaled by the replay speed
+///
+/// Runs until the command sender is dropped. Seeking restarts reading the file and skips
+/// messages before the requested position
+fn play_mcap(
+    mapped: &[u8],
+    start_time: u64,
+    channels: &HashMap<u16, Channel>,
+    commands: &mpsc::Receiver<ReplayCommand>,
+    replay_loop: bool,
+    timing: ReplayTiming,
+    runtime: &Handle,
+) -> anyhow::Result<()> {
+    let mut position = start_time.saturating_add(timing.start_offset.as_nanos() as u64);
+    let mut paused = false;
+    'restart: loop {
+        while paused {
+            match commands.recv() {
+                Ok(ReplayCommand::Play) => paused = false,
+                Ok(ReplayCommand::Pause) => (),
+                Ok(ReplayCommand::Seek(offset)) => {
+                    position = start_time.saturating_add(offset.as_nanos() as u64)
+                }
+                Err(_) => return Ok(()),
+            }
+        }
+
+        let wall_start = Instant::now();
+        let wall_clock_start = system_time_to_nanos(&SystemTime::now());
+        let log_start = position;
+        for message in MessageStream::new(mapped)? {
+            let message = message?;
+            if message.log_time < position {
+                continue;
+            }
+            let elapsed = Duration::from_nanos(message.log_time - log_start).div_f64(timing.speed);
+            let due = wall_start + elapsed;
+            loop {
+                match commands.recv_timeout(due.saturating_duration_since(Instant::now())) {
+                    Ok(ReplayCommand::Play) => (),
+                    Ok(ReplayCommand::Pause) => {
+                        paused = true;
+                        position = message.log_time;
+                        continue 'restart;
+                    }
+                    Ok(ReplayCommand::Seek(offset)) => {
+                        position = start_time.saturating_add(offset.as_nanos() as u64);
+                        continue 'restart;
+                    }
+                    Err(RecvTimeoutError::Timeout) => break,
+                    Err(RecvTimeoutError::Disconnected) => return Ok(()),
+                }
+            }
+            if let Some(channel) = channels.get(&message.channel.id) {
+                let time_nanos = match timing.mode {
+                    ReplayTimeMode::Preserve => message.log_time,
+                    ReplayTimeMode::ShiftToNow => wall_clock_start + elapsed.as_nanos() as u64,
+                };
+                if let Err(err) = runtime.block_on(channel.send(time_nanos, &message.data)) {
+                    error!(
+                        ?err,
+                        topic = message.channel.topic,
+                        "Failed to send recorded message"
+                    );
+                }
+            }
+        }
+
+        info!("Replay reached the end of the recording");
+        position = start_time;
+        paused = !replay_loop;
+    }
+}
+
 const JSON_ENCODING: &str = "json";
 
+const BRIDGE_STATUS_TOPIC: &str = "bridge_status";
+const BRIDGE_STATUS_INTERVAL: Duration = Duration::from_secs(1);
+/// How long to wait for the driver's liveliness token
+const LIVELINESS_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Health of the robot side as seen by the bridge
+#[derive(Serialize, Debug)]
+struct BridgeStatus {
+    /// zenoh session is connected to at least one peer or router
+    zenoh_connected: bool,
+    /// driver's liveliness token is present
+    driver_alive: bool,
+    /// Seconds since the last message per topic
+    last_message_age: BTreeMap<String, f64>,
+}
+
+const BRIDGE_STATUS_SCHEMA: &str = r#"
+{
+"title": "BridgeStatus",
+"description": "Health of the robot side as seen by the foxglove bridge",
+"type": "object",
+"properties": {
+  "zenoh_connected": { "type": "boolean" },
+  "driver_alive": { "type": "boolean" },
+  "last_message_age": {
+    "type": "object",
+    "additionalProperties": { "type": "number" }
+  }
+}
+}
+"#;
+
+/// Publish a latched status channel so operators can tell why panels went blank
+async fn start_status_channel(
+    topic: &str,
+    alive_topic: &str,
+    zenoh_session: Arc<Session>,
+    foxglove_server: &FoxgloveWebSocket,
+    bridge_stats: Arc<BridgeStats>,
+) -> anyhow::Result<()> {
+    info!(topic, "Publishing bridge status");
+    let foxglove_channel = foxglove_server
+        .create_publisher(
+            topic,
+            JSON_ENCODING,
+            "BridgeStatus",
+            BRIDGE_STATUS_SCHEMA,
+            Some("jsonschema"),
+            true,
+        )
+        .await?;
+
+    let alive_topic = alive_topic.to_owned();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(BRIDGE_STATUS_INTERVAL);
+        loop {
+            interval.tick().await;
+            let status = BridgeStatus {
+                zenoh_connected: zenoh_connected(&zenoh_session).await,
+                driver_alive: driver_alive(&zenoh_session, &alive_topic).await,
+                last_message_age: bridge_stats.last_message_age(),
+            };
+            let payload = match serde_json::to_vec(&status) {
+                Ok(payload) => payload,
+                Err(err) => {
+                    error!(?err, "Failed to serialize bridge status");
+                    continue;
+                }
+            };
+            let time_nanos = system_time_to_nanos(&SystemTime::now());
+            if let Err(err) = foxglove_channel.send(time_nanos, &payload).await {
+                error!(?err, "Failed to send bridge status");
+            }
+        }
+    });
+    Ok(())
+}
+
+async fn zenoh_connected(zenoh_session: &Session) -> bool {
+    let info = zenoh_session.info();
+    info.routers_zid().res().await.next().is_some() || info.peers_zid().res().await.next().is_some()
+}
+
+async fn driver_alive(zenoh_session: &Session, alive_topic: &str) -> bool {
+    let replies = match zenoh_session
+        .liveliness()
+        .get(alive_topic)
+        .timeout(LIVELINESS_TIMEOUT)
+        .res()
+        .await
+    {
+        Ok(replies) => replies,
+        Err(err) => {
+            warn!(?err, "Failed to query driver liveliness");
+            return false;
+        }
+    };
+    while let Ok(reply) = replies.recv_async().await {
+        if reply.sample.is_ok() {
+            return true;
+        }
+    }
+    false
+}
+
 #[allow(dead_code)]
 async fn start_json_subscriber(
     topic: &str,
@@ -188,6 +1002,7 @@ async fn start_json_subscriber(
     type_name: &str,
     json_schema: &str,
     latched: bool,
+    progress: &ProgressArgs,
 ) -> anyhow::Result<()> {
     info!(topic, "Starting json subscriber");
     let zenoh_subscriber = zenoh_session
@@ -207,23 +1022,14 @@ async fn start_json_subscriber(
         .await?;
 
     tokio::spawn({
-        let topic = topic.to_owned();
+        let mut reporter = progress.reporter(topic);
         async move {
-            let mut message_counter = 0;
             loop {
                 let sample = zenoh_subscriber.recv_async().await.unwrap();
-                message_counter += 1;
-                let now = SystemTime::now();
-                let time_nanos = system_time_to_nanos(&now);
+                let time_nanos = system_time_to_nanos(&sample_time(&sample));
                 let payload: Vec<u8> = sample.value.try_into().unwrap();
                 foxglove_channel.send(time_nanos, &payload).await.unwrap();
-
-                if message_counter % 20 == 0 {
-                    info!(
-                        topic,
-                        message_counter, "{} sent {} messages", topic, message_counter
-                    );
-                }
+                reporter.record(payload.len());
             }
         }
     });
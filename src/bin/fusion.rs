@@ -0,0 +1,194 @@
+use clap::Parser;
+use futures::StreamExt;
+use prost::Message;
+use std::time::{Duration, Instant};
+use tokio::{signal, sync::mpsc};
+use tracing::{error, info, warn};
+use zenoh::{config::Config, prelude::r#async::*, publication::CongestionControl};
+
+use rplidar_zenoh_driver::{
+    compression::CompressionArgs,
+    foxglove,
+    fusion::{fuse_point_clouds, FusionSource, PlanarPose},
+    peers::StaticPeersArgs,
+    runtime::RuntimeArgs,
+    setup_tracing_with_args,
+    streams::subscribe_point_clouds,
+    topics::TopicBuilder,
+    ErrorWrapper, RpLidarProjectedPoint, TracingArgs,
+};
+
+#[derive(Parser, Debug)]
+#[command()]
+struct Args {
+    /// Lidar to fuse, repeat for each lidar
+    ///
+    /// Format is prefix or prefix:x_m:y_m:yaw_deg with the lidar pose in the fused frame
+    /// (e.g. front:0.3:0:0 rear:-0.3:0:180). Without a pose the mounting pose published
+    /// with the clouds is used. The first source drives the output rate
+    #[clap(long, required = true)]
+    source: Vec<FusionSource>,
+
+    /// point cloud topic of every source
+    #[clap(long, default_value = "point_cloud")]
+    cloud_topic: String,
+
+    /// Prefix of the fused cloud
+    #[clap(long, default_value = "fused")]
+    output_prefix: String,
+
+    /// Frame id of the fused cloud
+    #[clap(long, default_value = "base_link")]
+    frame_id: String,
+
+    /// Clouds older than this many seconds are left out of the fused cloud
+    #[clap(long, default_value_t = 0.3)]
+    max_age: f64,
+
+    /// Endpoints to connect to.
+    #[clap(short = 'e', long)]
+    connect: Vec<zenoh_config::EndPoint>,
+
+    #[clap(flatten)]
+    static_peers: StaticPeersArgs,
+
+    /// Endpoints to listen on.
+    #[clap(long)]
+    listen: Vec<zenoh_config::EndPoint>,
+
+    #[clap(flatten)]
+    compression: CompressionArgs,
+
+    #[clap(flatten)]
+    tracing: TracingArgs,
+
+    #[clap(flatten)]
+    runtime: RuntimeArgs,
+}
+
+const SOURCE_QUEUE_SIZE: usize = 10;
+
+/// Latest cloud of a source
+struct ReceivedCloud {
+    received: Instant,
+    point_cloud: foxglove::PointCloud,
+}
+
+fn main() -> anyhow::Result<()> {
+    let args: Args = Args::parse();
+    args.runtime.build()?.block_on(run(args))
+}
+
+async fn run(args: Args) -> anyhow::Result<()> {
+    setup_tracing_with_args("fusion", &args.tracing)?;
+    let max_age = Duration::try_from_secs_f64(args.max_age)?;
+    let output_topic = TopicBuilder::new(&args.output_prefix)?.topic(&args.cloud_topic)?;
+
+    // configure zenoh
+    let mut zenoh_config = Config::default();
+    if !args.listen.is_empty() {
+        zenoh_config.listen.endpoints.clone_from(&args.listen);
+        info!(listen_endpoints= ?zenoh_config.listen.endpoints, "Configured listening endpoints");
+    }
+    if !args.connect.is_empty() {
+        zenoh_config.connect.endpoints.clone_from(&args.connect);
+        info!(connect_endpoints= ?zenoh_config.connect.endpoints, "Configured connect endpoints");
+    }
+    args.static_peers.apply(&mut zenoh_config)?;
+
+    let zenoh_session = zenoh::open(zenoh_config)
+        .res()
+        .await
+        .map_err(ErrorWrapper::ZenohError)?
+        .into_arc();
+    info!("Started zenoh session");
+
+    let publisher = zenoh_session
+        .declare_publisher(output_topic.clone())
+        .congestion_control(CongestionControl::Drop)
+        .res()
+        .await
+        .map_err(ErrorWrapper::ZenohError)?;
+
+    let (cloud_sender, mut cloud_receiver) =
+        mpsc::channel::<(usize, foxglove::PointCloud)>(SOURCE_QUEUE_SIZE);
+    for (index, source) in args.source.iter().enumerate() {
+        let topic = TopicBuilder::new(&source.prefix)?.topic(&args.cloud_topic)?;
+        info!(topic, extrinsics = ?source.extrinsics, "Subscribing to source");
+        let point_clouds = subscribe_point_clouds(zenoh_session.clone(), &topic).await?;
+        let cloud_sender = cloud_sender.clone();
+        tokio::spawn(async move {
+            let mut point_clouds = std::pin::pin!(point_clouds);
+            while let Some(point_cloud) = point_clouds.next().await {
+                if let Err(err) = RpLidarProjectedPoint::check_point_cloud_layout(&point_cloud) {
+                    warn!(topic, "Unsupported point cloud layout: {}", err);
+                    continue;
+                }
+                if cloud_sender.send((index, point_cloud)).await.is_err() {
+                    break;
+                }
+            }
+        });
+    }
+    drop(cloud_sender);
+
+    info!(
+        output_topic,
+        sources = args.source.len(),
+        "Fusing point clouds"
+    );
+    let mut latest: Vec<Option<ReceivedCloud>> = args.source.iter().map(|_| None).collect();
+    loop {
+        tokio::select! {
+            received = cloud_receiver.recv() => {
+                let Some((index, point_cloud)) = received else {
+                    break;
+                };
+                latest[index] = Some(ReceivedCloud {
+                    received: Instant::now(),
+                    point_cloud,
+                });
+                if index != 0 {
+                    continue;
+                }
+                let clouds = args
+                    .source
+                    .iter()
+                    .zip(&latest)
+                    .filter_map(|(source, cloud)| {
+                        let cloud = cloud.as_ref()?;
+                        if cloud.received.elapsed() > max_age {
+                            return None;
+                        }
+                        let pose = source.extrinsics.unwrap_or_else(|| {
+                            PlanarPose::from_foxglove_pose(
+                                &cloud.point_cloud.pose.unwrap_or_default(),
+                            )
+                        });
+                        Some((pose, &cloud.point_cloud))
+                    })
+                    .collect::<Vec<_>>();
+                let timestamp = latest[0]
+                    .as_ref()
+                    .and_then(|cloud| cloud.point_cloud.timestamp.clone());
+                let fused = match fuse_point_clouds(timestamp, &args.frame_id, &clouds) {
+                    Ok(fused) => fused,
+                    Err(err) => {
+                        warn!(?err, "Failed to fuse point clouds");
+                        continue;
+                    }
+                };
+                let (payload, encoding) = args.compression.compress(fused.encode_to_vec())?;
+                if let Err(err) = publisher.put(Value::from(payload).encoding(encoding)).res().await {
+                    error!(?err, "Failed to publish fused point cloud");
+                }
+            }
+            _ = signal::ctrl_c() => {
+                info!("ctrl-c received, exiting");
+                break;
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -1,19 +1,59 @@
+use anyhow::Context;
 use clap::Parser;
 use mcap::{
     records::{system_time_to_nanos, MessageHeader},
     Channel, Schema, Writer,
 };
-use prost_reflect::ReflectMessage;
-use std::{borrow::Cow, collections::BTreeMap, fs, io::BufWriter, sync::Arc, time::SystemTime};
+use memmap2::Mmap;
+use prost::Message;
+use prost_reflect::{DynamicMessage, MessageDescriptor, ReflectMessage};
+use std::{
+    borrow::Cow,
+    collections::BTreeMap,
+    fs,
+    io::BufWriter,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime},
+};
 use tokio::{select, signal};
-use tracing::info;
-use zenoh::{config::Config, prelude::r#async::*};
+use tracing::{error, info, warn};
+use zenoh::{config::Config, prelude::r#async::*, subscriber::FlumeSubscriber, Session};
 
-use rplidar_zenoh_driver::{foxglove, setup_tracing};
+use rplidar_zenoh_driver::{
+    angle_stats, compression,
+    diagnostics::{self, start_diagnostics_publisher},
+    events, foxglove, inspect,
+    latency::{self, capture_latency, start_latency_publisher, start_probe_echo, LatencyTracker},
+    map_deviation,
+    mcap_options::McapWriteArgs,
+    monitoring,
+    peers::StaticPeersArgs,
+    people, process_stats,
+    progress::{wait_for_deadline, CaptureLimitArgs, ProgressArgs},
+    recovery::{recover_unfinished, set_aside},
+    reflectors,
+    ros::{self, Ros2Args, RosMessage},
+    rplidar,
+    runtime::RuntimeArgs,
+    scan_rate,
+    schedule::{RecordingSchedule, RecordingScheduleArgs},
+    schema, sector_quality, setup_tracing_with_args,
+    timestamping::sample_time,
+    topics::{TopicBuilder, TopicFilterArgs},
+    upload::UploadArgs,
+    TracingArgs,
+};
 
 #[derive(Parser, Debug)]
 #[command()]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// lidar prefix
     ///
     /// Prefix for all topics
@@ -32,112 +72,396 @@ struct Args {
     #[clap(long, default_value = "out.mcap")]
     output: String,
 
-    /// listen on
+    /// Endpoints to listen on.
     #[clap(long)]
-    listen: Vec<String>,
+    listen: Vec<zenoh_config::EndPoint>,
+
+    /// Endpoints to connect to.
+    #[clap(long)]
+    connect: Vec<zenoh_config::EndPoint>,
+
+    #[clap(flatten)]
+    static_peers: StaticPeersArgs,
+
+    #[clap(flatten)]
+    tracing: TracingArgs,
+
+    #[clap(flatten)]
+    runtime: RuntimeArgs,
+
+    #[clap(flatten)]
+    progress: ProgressArgs,
+
+    #[clap(flatten)]
+    capture_limits: CaptureLimitArgs,
+
+    #[clap(flatten)]
+    upload: UploadArgs,
 
-    /// connect to
+    #[clap(flatten)]
+    topic_filter: TopicFilterArgs,
+
+    #[clap(flatten)]
+    schedule: RecordingScheduleArgs,
+
+    /// Start a new numbered output file after this many seconds without messages
+    ///
+    /// Keeps each run of the lidar in its own recording
+    #[clap(long)]
+    split_after_idle: Option<f64>,
+
+    #[clap(flatten)]
+    mcap: McapWriteArgs,
+
+    /// Also record JSON copies of protobuf channels on `<topic>/json`
+    ///
+    /// Readable with plain MCAP and JSON tooling, ignored with --ros2
+    #[clap(long)]
+    json_side_channel: bool,
+
+    /// Shell command run whenever an output file is finished
+    ///
+    /// The path is passed as $1 and in RECORDING_PATH, with RECORDING_MESSAGES,
+    /// RECORDING_DURATION and RECORDING_BYTES describing the file.
+    /// Runs before the upload so it can e.g. compress or sync the file
     #[clap(long)]
-    connect: Vec<String>,
+    on_finish: Option<String>,
+
+    /// Don't record diagnostics, process stats, rotation rate, latency, scan metadata, people,
+    /// sector quality, reflectors, map deviation and angle stats
+    ///
+    /// By default they are recorded next to the scans for operational context
+    #[clap(long)]
+    no_auxiliary_topics: bool,
+
+    // --ros2 records CDR messages with the ros2 MCAP profile so recordings
+    // open directly in ros2 bag play. Events are not recorded in this mode
+    #[clap(flatten)]
+    ros2: Ros2Args,
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum Command {
+    /// Print channels, schemas, message counts, rates and gaps of a recording and exit
+    Inspect {
+        /// Recording to inspect
+        file: PathBuf,
+
+        /// Report pauses between messages of a channel longer than this many seconds
+        #[clap(long, default_value_t = 1.0)]
+        gap_threshold: f64,
+    },
 }
 
 const PROTOBUF_ENCODING: &str = "protobuf";
+const ROS2_PROFILE: &str = "ros2";
+const ROS2_SCHEMA_ENCODING: &str = "ros2msg";
+const CDR_ENCODING: &str = "cdr";
+const JSON_ENCODING: &str = "json";
+const JSON_TOPIC_SUFFIX: &str = "json";
 
-#[tokio::main]
-async fn main() -> anyhow::Result<()> {
+fn main() -> anyhow::Result<()> {
     let args: Args = Args::parse();
-    setup_tracing()?;
+    if let Some(Command::Inspect {
+        file,
+        gap_threshold,
+    }) = &args.command
+    {
+        return inspect_recording(file, *gap_threshold);
+    }
+    args.runtime.build()?.block_on(run(args))
+}
+
+fn inspect_recording(path: &Path, gap_threshold: f64) -> anyhow::Result<()> {
+    let file = fs::File::open(path)?;
+    // SAFETY: recordings aren't modified while they are inspected
+    let mapped = unsafe { Mmap::map(&file)? };
+    let summary = inspect::inspect(&mapped, Duration::try_from_secs_f64(gap_threshold)?)?;
+    println!("File: {}", path.display());
+    print!("{}", summary);
+    Ok(())
+}
 
-    info!(file = ?args.output, "Creating mcap output file");
-    let mut out = Writer::new(BufWriter::new(fs::File::create(&args.output)?))?;
+async fn run(args: Args) -> anyhow::Result<()> {
+    setup_tracing_with_args("mcap_logger", &args.tracing)?;
+    let topics = TopicBuilder::new(&args.prefix)?;
+    let schedule = args.schedule.schedule()?;
 
     let mut zenoh_config = Config::default();
     if !args.listen.is_empty() {
-        zenoh_config.listen.endpoints = args
-            .listen
-            .iter()
-            .map(|endpoint| endpoint.parse().unwrap())
-            .collect();
+        zenoh_config.listen.endpoints.clone_from(&args.listen);
         info!(listen_endpoints= ?zenoh_config.listen.endpoints, "Configured listening endpoints");
     }
 
     if !args.connect.is_empty() {
-        zenoh_config.connect.endpoints = args
-            .connect
-            .iter()
-            .map(|endpoint| endpoint.parse().unwrap())
-            .collect();
+        zenoh_config.connect.endpoints.clone_from(&args.connect);
         info!(connect_endpoints= ?zenoh_config.connect.endpoints, "Configured connect endpoints");
     }
+    args.static_peers.apply(&mut zenoh_config)?;
 
-    let zenoh_session = zenoh::open(zenoh_config).res().await.unwrap();
+    let zenoh_session = zenoh::open(zenoh_config).res().await.unwrap().into_arc();
     info!("Started zenoh session");
 
-    let scan_topic = format!("{}/{}", args.prefix, args.scan_topic);
-    let laser_scan_subscriber = zenoh_session
-        .declare_subscriber(&scan_topic)
-        .res()
-        .await
-        .unwrap();
+    let messages_written = Arc::new(AtomicU64::new(0));
+    // samples from misbehaving peers are skipped so the recording still gets finished
+    let samples_skipped = Arc::new(AtomicU64::new(0));
+    let diagnostics_topic = topics.topic(diagnostics::DIAGNOSTICS_TOPIC)?;
+    start_diagnostics_publisher(zenoh_session.clone(), diagnostics_topic, {
+        let messages_written = messages_written.clone();
+        let samples_skipped = samples_skipped.clone();
+        let output = args.output.clone();
+        move || {
+            rplidar::Diagnostics::new("mcap_logger", diagnostics::Level::Ok, "Recording")
+                .with_value("output", &output)
+                .with_value("messages", messages_written.load(Ordering::Relaxed))
+                .with_value("skipped", samples_skipped.load(Ordering::Relaxed))
+        }
+    })
+    .await?;
+
+    let latency_tracker = LatencyTracker::default();
+    let latency_topic = topics.topic(latency::LATENCY_TOPIC)?;
+    start_latency_publisher(
+        zenoh_session.clone(),
+        latency_topic,
+        "mcap_logger",
+        latency_tracker.clone(),
+    )
+    .await?;
+    start_probe_echo(
+        zenoh_session.clone(),
+        topics.topic(latency::PROBE_TOPIC)?,
+        topics.topic(latency::ECHO_TOPIC)?,
+        "mcap_logger",
+    )
+    .await?;
 
-    let point_cloud_topic = format!("{}/{}", args.prefix, args.cloud_topic);
-    let point_cloud_subscriber = zenoh_session
-        .declare_subscriber(&point_cloud_topic)
-        .res()
-        .await
-        .unwrap();
+    let topic_filter = args.topic_filter.filter()?;
+    let recorded = |topic: &str| {
+        let allowed = topic_filter.allows(topic);
+        if !allowed {
+            info!(topic, "Topic excluded from recording");
+        }
+        allowed
+    };
 
-    let laser_scan_message = foxglove::LaserScan::default();
-    let laser_scan_channel_id =
-        register_mcap_topic_for_protobuf(&laser_scan_message, &mut out, &scan_topic)?;
+    let scan_topic = if args.ros2.enabled {
+        args.ros2.scan_key_expr()?
+    } else {
+        topics.topic(&args.scan_topic)?
+    };
+    let laser_scan_subscriber = if recorded(&scan_topic) {
+        Some(
+            zenoh_session
+                .declare_subscriber(&scan_topic)
+                .res()
+                .await
+                .unwrap(),
+        )
+    } else {
+        None
+    };
 
-    let point_cloud_message = foxglove::PointCloud::default();
-    let point_cloud_channel_id =
-        register_mcap_topic_for_protobuf(&point_cloud_message, &mut out, &point_cloud_topic)?;
+    let point_cloud_topic = if args.ros2.enabled {
+        args.ros2.cloud_key_expr()?
+    } else {
+        topics.topic(&args.cloud_topic)?
+    };
+    let point_cloud_subscriber = if recorded(&point_cloud_topic) {
+        Some(
+            zenoh_session
+                .declare_subscriber(&point_cloud_topic)
+                .res()
+                .await
+                .unwrap(),
+        )
+    } else {
+        None
+    };
+
+    let events_topic = topics.topic(events::EVENTS_TOPIC)?;
+    let mut auxiliary_topics = vec![
+        (events_topic.clone(), rplidar::Event::default().descriptor()),
+        // bridge throughput recorded next to the data it describes
+        (
+            topics.topic(monitoring::BRIDGE_STATS_TOPIC)?,
+            rplidar::BridgeStats::default().descriptor(),
+        ),
+    ];
+    if !args.no_auxiliary_topics {
+        // operational context of the driver, topics nobody publishes stay empty
+        auxiliary_topics.extend([
+            (
+                topics.topic(diagnostics::DIAGNOSTICS_TOPIC)?,
+                rplidar::Diagnostics::default().descriptor(),
+            ),
+            (
+                topics.topic(process_stats::PROCESS_STATS_TOPIC)?,
+                rplidar::ProcessStats::default().descriptor(),
+            ),
+            (
+                topics.topic(scan_rate::ROTATION_RATE_TOPIC)?,
+                rplidar::RotationRate::default().descriptor(),
+            ),
+            (
+                topics.topic(latency::LATENCY_TOPIC)?,
+                rplidar::Latency::default().descriptor(),
+            ),
+            (
+                topics.join(&[&args.scan_topic, "metadata"])?,
+                rplidar::ScanMetadata::default().descriptor(),
+            ),
+            (
+                topics.topic(people::PEOPLE_TOPIC)?,
+                rplidar::PersonDetections::default().descriptor(),
+            ),
+            (
+                topics.topic(sector_quality::SECTOR_QUALITY_TOPIC)?,
+                rplidar::SectorQuality::default().descriptor(),
+            ),
+            (
+                topics.topic(reflectors::REFLECTORS_TOPIC)?,
+                rplidar::ReflectorMarkers::default().descriptor(),
+            ),
+            (
+                topics.topic(map_deviation::MAP_DEVIATION_TOPIC)?,
+                rplidar::MapDeviation::default().descriptor(),
+            ),
+            (
+                topics.topic(angle_stats::ANGLE_STATS_TOPIC)?,
+                rplidar::AngleStats::default().descriptor(),
+            ),
+        ]);
+    }
+    // protobuf channels would make ros2 recordings unreadable by ros2 bag
+    if args.ros2.enabled {
+        auxiliary_topics.clear();
+    }
+    auxiliary_topics.retain(|(topic, _)| recorded(topic));
+    let mut auxiliary_subscribers = vec![];
+    for (topic, _) in &auxiliary_topics {
+        auxiliary_subscribers.push(zenoh_session.declare_subscriber(topic).res().await.unwrap());
+    }
+    let mut auxiliary_counters = vec![0; auxiliary_topics.len()];
+
+    let layout = ChannelLayout {
+        ros2: args.ros2.enabled,
+        json: args.json_side_channel && !args.ros2.enabled,
+        laser_scan: laser_scan_subscriber.as_ref().map(|_| scan_topic.clone()),
+        point_cloud: point_cloud_subscriber
+            .as_ref()
+            .map(|_| point_cloud_topic.clone()),
+        auxiliary: auxiliary_topics,
+    };
+    let finished_handler = FinishedRecordingHandler {
+        upload: args.upload.clone(),
+        on_finish: args.on_finish.clone(),
+        zenoh_session: zenoh_session.clone(),
+        events_topic: events_topic.clone(),
+    };
+    let mut recorder = Recorder::new(layout, args.mcap, &args.output, args.split_after_idle)?;
 
     let mut laser_scan_counter = 0;
     let mut point_cloud_counter = 0;
+    let mut laser_scan_reporter = args.progress.reporter(&scan_topic);
+    let mut point_cloud_reporter = args.progress.reporter(&point_cloud_topic);
+    let deadline = args.capture_limits.deadline()?;
+    let mut window_open = schedule.map_or(true, |schedule| schedule.is_active(SystemTime::now()));
+    if let Some(schedule) = schedule {
+        info!(%schedule, window_open, "Recording on schedule");
+    }
     loop {
         select!(
-            sample = laser_scan_subscriber.recv_async() => {
+            sample = recv_optional(&laser_scan_subscriber) => {
                 let sample = sample.unwrap();
+                if !window_open {
+                    continue;
+                }
                 laser_scan_counter+= 1;
-                let now = SystemTime::now();
-                let time_nanos = system_time_to_nanos(&now);
-                let payload: Vec<u8> = sample.value.try_into()?;
-                out.write_to_known_channel(
-                    &MessageHeader {
-                        channel_id: laser_scan_channel_id,
-                        sequence: laser_scan_counter,
-                        log_time: time_nanos,
-                        publish_time: time_nanos,
-                    },
-                    &payload,
-                )?;
-                if laser_scan_counter % 20 == 0 {
-                    info!("laser_scan_counter: {}", laser_scan_counter);
+                let time_nanos = system_time_to_nanos(&sample_time(&sample));
+                let payload = match compression::decompress(&sample.value) {
+                    Ok(Some(payload)) => payload,
+                    Ok(None) => sample.value.try_into()?,
+                    Err(err) => {
+                        warn!(key_expr = %sample.key_expr, ?err, "Failed to decompress sample");
+                        samples_skipped.fetch_add(1, Ordering::Relaxed);
+                        continue;
+                    }
+                };
+                if let Some(latency) = capture_latency(&payload).filter(|_| !args.ros2.enabled) {
+                    latency_tracker.record(
+                        &format!("{}/{}", latency::CAPTURE_TO_RECEIVE, scan_topic),
+                        latency,
+                    );
+                }
+                recorder.write(|channels| channels.laser_scan.as_ref(), laser_scan_counter, time_nanos, &payload)?;
+                messages_written.fetch_add(1, Ordering::Relaxed);
+                laser_scan_reporter.record(payload.len());
+                if args.capture_limits.max_scans_reached(laser_scan_counter as u64) {
+                    info!("Maximum scan count reached, exiting");
+                    break;
                 }
             },
 
-            sample = point_cloud_subscriber.recv_async() => {
+            sample = recv_optional(&point_cloud_subscriber) => {
                 let sample = sample.unwrap();
+                if !window_open {
+                    continue;
+                }
                 point_cloud_counter+= 1;
-                let now = SystemTime::now();
-                let time_nanos = system_time_to_nanos(&now);
-                let payload: Vec<u8> = sample.value.try_into()?;
-                out.write_to_known_channel(
-                    &MessageHeader {
-                        channel_id: point_cloud_channel_id,
-                        sequence: point_cloud_counter,
-                        log_time: time_nanos,
-                        publish_time: time_nanos,
-                    },
-                    &payload,
-                )?;
-                if point_cloud_counter % 20 == 0 {
-                    info!("point_cloud_counter: {}", point_cloud_counter);
+                let time_nanos = system_time_to_nanos(&sample_time(&sample));
+                let payload = match compression::decompress(&sample.value) {
+                    Ok(Some(payload)) => payload,
+                    Ok(None) => sample.value.try_into()?,
+                    Err(err) => {
+                        warn!(key_expr = %sample.key_expr, ?err, "Failed to decompress sample");
+                        samples_skipped.fetch_add(1, Ordering::Relaxed);
+                        continue;
+                    }
+                };
+                if let Some(latency) = capture_latency(&payload).filter(|_| !args.ros2.enabled) {
+                    latency_tracker.record(
+                        &format!("{}/{}", latency::CAPTURE_TO_RECEIVE, point_cloud_topic),
+                        latency,
+                    );
                 }
+                recorder.write(|channels| channels.point_cloud.as_ref(), point_cloud_counter, time_nanos, &payload)?;
+                messages_written.fetch_add(1, Ordering::Relaxed);
+                point_cloud_reporter.record(payload.len());
+            },
+            sample = recv_auxiliary(&auxiliary_subscribers) => {
+                let (index, sample) = sample.unwrap();
+                if !window_open {
+                    continue;
+                }
+                auxiliary_counters[index] += 1;
+                let time_nanos = system_time_to_nanos(&sample_time(&sample));
+                let payload: Vec<u8> = sample.value.try_into()?;
+                recorder.write(|channels| channels.auxiliary.get(index), auxiliary_counters[index], time_nanos, &payload)?;
+                messages_written.fetch_add(1, Ordering::Relaxed);
             },
+            _ = wait_for_deadline(recorder.idle_deadline()) => {
+                info!("No data received, closing recording");
+                if let Some(finished) = recorder.finish()? {
+                    tokio::spawn(finished_handler.clone().handle(finished));
+                }
+            }
+            _ = wait_for_schedule_change(schedule) => {
+                // re-evaluated from the clock in case of wall clock jumps
+                let active = schedule.map_or(true, |schedule| schedule.is_active(SystemTime::now()));
+                if active != window_open {
+                    window_open = active;
+                    if window_open {
+                        info!("Recording window opened");
+                    } else {
+                        info!("Recording window closed");
+                    }
+                }
+            }
+            _ = wait_for_deadline(deadline) => {
+                info!("Capture duration reached, exiting");
+                break;
+            }
             _ = signal::ctrl_c() => {
                 info!("ctrl-c received, exiting");
                 break;
@@ -145,24 +469,510 @@ async fn main() -> anyhow::Result<()> {
         );
     }
 
-    out.finish()?;
-    info!("mcap file closed");
+    if let Some(finished) = recorder.finish()? {
+        finished_handler.handle(finished).await;
+    }
 
     Ok(())
 }
 
+/// Topics recorded into every output file, `None` for streams that aren't recorded
+struct ChannelLayout {
+    ros2: bool,
+    /// Also record JSON copies of protobuf channels
+    json: bool,
+    laser_scan: Option<String>,
+    point_cloud: Option<String>,
+    /// Protobuf topics recorded next to the scans, such as events and diagnostics
+    auxiliary: Vec<(String, MessageDescriptor)>,
+}
+
+/// Channel registered in one output file
+struct RecordedChannel {
+    id: u16,
+    json: Option<JsonChannel>,
+}
+
+/// JSON copy of a protobuf channel
+struct JsonChannel {
+    id: u16,
+    descriptor: MessageDescriptor,
+}
+
+/// Channels registered in one output file
+#[derive(Default)]
+struct ChannelIds {
+    laser_scan: Option<RecordedChannel>,
+    point_cloud: Option<RecordedChannel>,
+    /// Same order as `ChannelLayout::auxiliary`
+    auxiliary: Vec<RecordedChannel>,
+}
+
+impl ChannelLayout {
+    fn register(
+        &self,
+        mcap_writer: &mut Writer<BufWriter<fs::File>>,
+    ) -> anyhow::Result<ChannelIds> {
+        let mut channels = ChannelIds::default();
+        if let Some(topic) = &self.laser_scan {
+            channels.laser_scan = Some(if self.ros2 {
+                RecordedChannel {
+                    id: register_mcap_topic_for_ros2::<ros::LaserScan>(mcap_writer, topic)?,
+                    json: None,
+                }
+            } else {
+                self.register_protobuf(
+                    &foxglove::LaserScan::default().descriptor(),
+                    mcap_writer,
+                    topic,
+                )?
+            });
+        }
+        if let Some(topic) = &self.point_cloud {
+            channels.point_cloud = Some(if self.ros2 {
+                RecordedChannel {
+                    id: register_mcap_topic_for_ros2::<ros::PointCloud2>(mcap_writer, topic)?,
+                    json: None,
+                }
+            } else {
+                self.register_protobuf(
+                    &foxglove::PointCloud::default().descriptor(),
+                    mcap_writer,
+                    topic,
+                )?
+            });
+        }
+        for (topic, descriptor) in &self.auxiliary {
+            channels
+                .auxiliary
+                .push(self.register_protobuf(descriptor, mcap_writer, topic)?);
+        }
+        Ok(channels)
+    }
+
+    fn register_protobuf(
+        &self,
+        descriptor: &MessageDescriptor,
+        mcap_writer: &mut Writer<BufWriter<fs::File>>,
+        topic: &str,
+    ) -> anyhow::Result<RecordedChannel> {
+        let id = register_mcap_topic_for_protobuf(descriptor, mcap_writer, topic)?;
+        let json = if self.json {
+            Some(JsonChannel {
+                id: register_mcap_topic_for_json(mcap_writer, topic)?,
+                descriptor: descriptor.clone(),
+            })
+        } else {
+            None
+        };
+        Ok(RecordedChannel { id, json })
+    }
+}
+
+/// Open output file
+struct Recording {
+    path: PathBuf,
+    writer: Writer<BufWriter<fs::File>>,
+    channels: ChannelIds,
+    last_write: tokio::time::Instant,
+    messages: u64,
+    first_log_time: Option<u64>,
+    last_log_time: u64,
+}
+
+/// Closed output file
+struct FinishedRecording {
+    path: PathBuf,
+    messages: u64,
+    /// Between the first and last message
+    duration: Duration,
+}
+
+/// Writes messages to the output file
+///
+/// With an idle split the output is written as numbered segments, `out.mcap` becomes
+/// `out_000.mcap`, `out_001.mcap`... and the next segment is opened by the first
+/// message after a gap
+struct Recorder {
+    layout: ChannelLayout,
+    write_options: McapWriteArgs,
+    output: PathBuf,
+    split_after_idle: Option<Duration>,
+    next_segment: usize,
+    current: Option<Recording>,
+}
+
+impl Recorder {
+    fn new(
+        layout: ChannelLayout,
+        write_options: McapWriteArgs,
+        output: &str,
+        split_after_idle: Option<f64>,
+    ) -> anyhow::Result<Self> {
+        let split_after_idle = match split_after_idle {
+            Some(seconds) if seconds.is_nan() || seconds <= 0.0 => {
+                anyhow::bail!("Idle split time must be positive")
+            }
+            Some(seconds) => Some(Duration::from_secs_f64(seconds)),
+            None => None,
+        };
+        let output = PathBuf::from(output);
+        // segments of earlier runs are kept, numbering continues after the last one
+        let next_segment = if split_after_idle.is_some() {
+            next_free_segment(&output)?
+        } else {
+            0
+        };
+        let mut recorder = Self {
+            layout,
+            write_options,
+            output,
+            split_after_idle,
+            next_segment,
+            current: None,
+        };
+        // created up front so a bad output path fails at startup
+        recorder.open()?;
+        Ok(recorder)
+    }
+
+    fn open(&mut self) -> anyhow::Result<()> {
+        let path = if self.split_after_idle.is_some() {
+            segment_path(&self.output, self.next_segment)
+        } else {
+            self.output.clone()
+        };
+        self.next_segment += 1;
+
+        // a crashed run leaves the file without footer, keep a readable copy before overwriting
+        match recover_unfinished(&path) {
+            Ok(Some(report)) => info!(
+                recovered = ?report.recovered_path,
+                messages = report.messages,
+                truncated = report.truncated,
+                "Recovered unfinished recording"
+            ),
+            Ok(None) => (),
+            Err(err) => {
+                error!(?err, file = ?path, "Failed to recover unfinished recording");
+                let aside = set_aside(&path).with_context(|| {
+                    format!("Failed to move unrecovered recording {:?} aside", path)
+                })?;
+                warn!(file = ?aside, "Kept unrecovered recording");
+            }
+        }
+
+        info!(file = ?path, "Creating mcap output file");
+        let output_file = BufWriter::new(fs::File::create(&path)?);
+        let mut write_options = self.write_options.write_options();
+        if self.layout.ros2 {
+            write_options = write_options.profile(ROS2_PROFILE);
+        }
+        let mut writer = write_options.create(output_file)?;
+        let channels = self.layout.register(&mut writer)?;
+        self.current = Some(Recording {
+            path,
+            writer,
+            channels,
+            last_write: tokio::time::Instant::now(),
+            messages: 0,
+            first_log_time: None,
+            last_log_time: 0,
+        });
+        Ok(())
+    }
+
+    fn write(
+        &mut self,
+        channel: impl Fn(&ChannelIds) -> Option<&RecordedChannel>,
+        sequence: u32,
+        time_nanos: u64,
+        payload: &[u8],
+    ) -> anyhow::Result<()> {
+        if self.current.is_none() {
+            self.open()?;
+        }
+        let recording = self.current.as_mut().expect("recording opened above");
+        let Some(recorded_channel) = channel(&recording.channels) else {
+            return Ok(());
+        };
+        let header = MessageHeader {
+            channel_id: recorded_channel.id,
+            sequence,
+            log_time: time_nanos,
+            publish_time: time_nanos,
+        };
+        recording.writer.write_to_known_channel(&header, payload)?;
+        if let Some(json) = &recorded_channel.json {
+            match DynamicMessage::decode(json.descriptor.clone(), payload) {
+                Ok(message) => recording.writer.write_to_known_channel(
+                    &MessageHeader {
+                        channel_id: json.id,
+                        ..header
+                    },
+                    &serde_json::to_vec(&message)?,
+                )?,
+                Err(err) => warn!(?err, "Failed to convert message to JSON"),
+            }
+        }
+        recording.last_write = tokio::time::Instant::now();
+        recording.messages += 1;
+        recording.first_log_time.get_or_insert(time_nanos);
+        recording.last_log_time = recording.last_log_time.max(time_nanos);
+        Ok(())
+    }
+
+    /// When the current segment should be closed, `None` without idle splitting
+    fn idle_deadline(&self) -> Option<tokio::time::Instant> {
+        let split_after_idle = self.split_after_idle?;
+        self.current
+            .as_ref()
+            .filter(|recording| recording.messages > 0)
+            .map(|recording| recording.last_write + split_after_idle)
+    }
+
+    /// Close the current file
+    fn finish(&mut self) -> anyhow::Result<Option<FinishedRecording>> {
+        let Some(mut recording) = self.current.take() else {
+            return Ok(None);
+        };
+        recording.writer.finish()?;
+        info!(file = ?recording.path, messages = recording.messages, "mcap file closed");
+        let first_log_time = recording.first_log_time.unwrap_or(recording.last_log_time);
+        Ok(Some(FinishedRecording {
+            path: recording.path,
+            messages: recording.messages,
+            duration: Duration::from_nanos(recording.last_log_time - first_log_time),
+        }))
+    }
+}
+
+/// `out.mcap` segment 3 is `out_003.mcap`
+fn segment_path(output: &Path, segment: usize) -> PathBuf {
+    let (stem, extension) = segment_name_parts(output);
+    output.with_file_name(format!("{}_{:03}.{}", stem, segment, extension))
+}
+
+/// Segment after the highest numbered [`segment_path`] that already exists
+fn next_free_segment(output: &Path) -> anyhow::Result<usize> {
+    let (stem, extension) = segment_name_parts(output);
+    let prefix = format!("{}_", stem);
+    let suffix = format!(".{}", extension);
+    let directory = match output.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let entries = match fs::read_dir(directory) {
+        Ok(entries) => entries,
+        // reported when the first segment is created
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+        Err(err) => {
+            return Err(err).with_context(|| format!("Failed to list {:?}", directory));
+        }
+    };
+    let mut next_segment = 0;
+    for entry in entries {
+        let file_name = entry?.file_name();
+        let segment = file_name
+            .to_str()
+            .and_then(|name| name.strip_prefix(&prefix))
+            .and_then(|name| name.strip_suffix(&suffix))
+            .and_then(|segment| segment.parse::<usize>().ok());
+        if let Some(segment) = segment {
+            next_segment = next_segment.max(segment + 1);
+        }
+    }
+    Ok(next_segment)
+}
+
+fn segment_name_parts(output: &Path) -> (&str, &str) {
+    let stem = output
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("out");
+    let extension = output
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or("mcap");
+    (stem, extension)
+}
+
+/// Follow-up work once an output file is closed
+#[derive(Clone)]
+struct FinishedRecordingHandler {
+    upload: UploadArgs,
+    on_finish: Option<String>,
+    zenoh_session: Arc<Session>,
+    events_topic: String,
+}
+
+impl FinishedRecordingHandler {
+    /// Announce the recording, then run the hook and upload in that order
+    async fn handle(self, recording: FinishedRecording) {
+        let file = recording.path.display().to_string();
+        let bytes = tokio::fs::metadata(&recording.path)
+            .await
+            .map(|metadata| metadata.len())
+            .unwrap_or_default();
+        let duration = format!("{:.3}", recording.duration.as_secs_f64());
+        self.publish_event(
+            rplidar::Event::new(
+                "mcap_logger",
+                events::Severity::Info,
+                events::RECORDING_FINISHED,
+                "Recording finished",
+            )
+            .with_value("file", &file)
+            .with_value("messages", recording.messages)
+            .with_value("duration", &duration)
+            .with_value("bytes", bytes),
+        )
+        .await;
+
+        if let Some(command) = &self.on_finish {
+            // path is passed as $1 and the summary in the environment
+            let status = tokio::process::Command::new("sh")
+                .arg("-c")
+                .arg(command)
+                .arg("sh")
+                .arg(&recording.path)
+                .env("RECORDING_PATH", &recording.path)
+                .env("RECORDING_MESSAGES", recording.messages.to_string())
+                .env("RECORDING_DURATION", &duration)
+                .env("RECORDING_BYTES", bytes.to_string())
+                .status()
+                .await;
+            match status {
+                Ok(status) if status.success() => info!(command, file, "Recording hook finished"),
+                Ok(status) => warn!(command, file, %status, "Recording hook failed"),
+                Err(err) => error!(?err, command, file, "Failed to run recording hook"),
+            }
+        }
+
+        let upload_event = match self.upload.upload(&recording.path).await {
+            Ok(Some(url)) => {
+                info!(url, "Recording uploaded");
+                rplidar::Event::new(
+                    "mcap_logger",
+                    events::Severity::Info,
+                    events::RECORDING_UPLOADED,
+                    "Recording uploaded",
+                )
+                .with_value("file", &file)
+                .with_value("url", url)
+            }
+            Ok(None) => return,
+            Err(err) => {
+                error!(?err, "Failed to upload recording");
+                rplidar::Event::new(
+                    "mcap_logger",
+                    events::Severity::Error,
+                    events::RECORDING_UPLOAD_FAILED,
+                    &err.to_string(),
+                )
+                .with_value("file", &file)
+            }
+        };
+        self.publish_event(upload_event).await;
+    }
+
+    /// Published directly since the process may exit right after
+    async fn publish_event(&self, event: rplidar::Event) {
+        if let Err(err) = self
+            .zenoh_session
+            .put(&self.events_topic, event.encode_to_vec())
+            .res()
+            .await
+        {
+            error!(?err, kind = event.kind, "Failed to publish recording event");
+        }
+    }
+}
+
+/// Resolves when the recording window opens or closes, never without a schedule
+async fn wait_for_schedule_change(schedule: Option<RecordingSchedule>) {
+    match schedule {
+        Some(schedule) => {
+            tokio::time::sleep(schedule.until_next_change(SystemTime::now())).await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+/// Receive from whichever subscriber has a sample first, wait forever without subscribers
+///
+/// Returns the index of the subscriber with the sample
+async fn recv_auxiliary(subscribers: &[FlumeSubscriber<'_>]) -> anyhow::Result<(usize, Sample)> {
+    if subscribers.is_empty() {
+        return std::future::pending().await;
+    }
+    let (sample, index, _) = futures::future::select_all(
+        subscribers
+            .iter()
+            .map(|subscriber| Box::pin(subscriber.recv_async())),
+    )
+    .await;
+    Ok((index, sample?))
+}
+
+/// Receive from subscriber if present, otherwise wait forever
+async fn recv_optional(subscriber: &Option<FlumeSubscriber<'_>>) -> anyhow::Result<Sample> {
+    match subscriber {
+        Some(subscriber) => Ok(subscriber.recv_async().await?),
+        None => std::future::pending().await,
+    }
+}
+
+/// Register `<topic>/json` channel for JSON copies of protobuf messages
+///
+/// Schemaless so plain MCAP tooling can read it without protobuf descriptors
+fn register_mcap_topic_for_json(
+    mcap_writer: &mut Writer<BufWriter<fs::File>>,
+    topic: &str,
+) -> anyhow::Result<u16> {
+    let my_channel = Channel {
+        topic: format!("{}/{}", topic, JSON_TOPIC_SUFFIX),
+        schema: None,
+        message_encoding: JSON_ENCODING.to_owned(),
+        metadata: BTreeMap::default(),
+    };
+
+    Ok(mcap_writer.add_channel(&my_channel)?)
+}
+
+/// Register channel using rosbag2 conventions
+///
+/// zenoh key expression ns/scan is recorded as ROS topic /ns/scan
+fn register_mcap_topic_for_ros2<M: RosMessage>(
+    mcap_writer: &mut Writer<BufWriter<fs::File>>,
+    key_expr: &str,
+) -> anyhow::Result<u16> {
+    let schema = Some(Arc::new(Schema {
+        name: M::TYPE_NAME.to_owned(),
+        encoding: ROS2_SCHEMA_ENCODING.to_owned(),
+        data: Cow::from(M::DEFINITION.as_bytes()),
+    }));
+
+    let my_channel = Channel {
+        topic: format!("/{}", key_expr),
+        schema,
+        message_encoding: CDR_ENCODING.to_owned(),
+        metadata: BTreeMap::default(),
+    };
+
+    Ok(mcap_writer.add_channel(&my_channel)?)
+}
+
 fn register_mcap_topic_for_protobuf(
-    protobuf: &dyn ReflectMessage,
+    descriptor: &MessageDescriptor,
     mcap_writer: &mut Writer<BufWriter<fs::File>>,
     topic: &str,
 ) -> anyhow::Result<u16> {
     let schema = Some(Arc::new(Schema {
-        name: protobuf.descriptor().full_name().to_owned(),
+        name: descriptor.full_name().to_owned(),
         encoding: PROTOBUF_ENCODING.to_owned(),
-        // this includes all files
-        // filter to only include relevant
         // https://mcap.dev/guides/cpp/protobuf#register-schema
-        data: Cow::from(protobuf.descriptor().parent_pool().encode_to_vec()),
+        data: Cow::from(schema::encoded_file_descriptor_set(descriptor)),
     }));
 
     let my_channel = Channel {
@@ -0,0 +1,505 @@
+use anyhow::Context;
+use clap::Parser;
+use prost::Message;
+use prost_reflect::{DynamicMessage, MessageDescriptor, ReflectMessage};
+use rumqttc::{AsyncClient, MqttOptions, QoS};
+use serde::Serialize;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use tokio::signal;
+use tracing::{error, info, warn};
+use zenoh::{config::Config, prelude::r#async::*};
+
+use rplidar_zenoh_driver::{
+    diagnostics, events, foxglove, latency,
+    peers::StaticPeersArgs,
+    process_stats, rplidar,
+    runtime::RuntimeArgs,
+    setup_tracing_with_args,
+    timestamping::sample_time,
+    topics::TopicBuilder,
+    zones::{Zone, ZoneArgs},
+    ErrorWrapper, TracingArgs,
+};
+
+#[derive(Parser, Debug)]
+#[command()]
+struct Args {
+    /// lidar prefix
+    ///
+    /// Prefix for all topics
+    #[clap(long, default_value = "rplidar")]
+    prefix: String,
+
+    /// laser scan topic
+    #[clap(long, default_value = "laser_scan")]
+    scan_topic: String,
+
+    /// Endpoints to connect to.
+    #[clap(short = 'e', long)]
+    connect: Vec<zenoh_config::EndPoint>,
+
+    #[clap(flatten)]
+    static_peers: StaticPeersArgs,
+
+    /// Endpoints to listen on.
+    #[clap(long)]
+    listen: Vec<zenoh_config::EndPoint>,
+
+    /// MQTT broker host
+    #[clap(long, default_value = "localhost")]
+    mqtt_host: String,
+
+    /// MQTT broker port
+    #[clap(long, default_value_t = 1883)]
+    mqtt_port: u16,
+
+    /// MQTT client id
+    #[clap(long, default_value = "rplidar-zenoh-mqtt-bridge")]
+    mqtt_client_id: String,
+
+    /// MQTT username
+    #[clap(long, requires = "mqtt_password")]
+    mqtt_username: Option<String>,
+
+    /// MQTT password
+    #[clap(long, requires = "mqtt_username")]
+    mqtt_password: Option<String>,
+
+    /// Prefix for all MQTT topics
+    #[clap(long, default_value = "rplidar")]
+    mqtt_prefix: String,
+
+    /// Topics republished as JSON
+    #[clap(long, value_enum)]
+    forward: Vec<ForwardTopic>,
+
+    /// Publish distance and angle of the nearest obstacle in each scan
+    #[clap(long)]
+    nearest_obstacle: bool,
+
+    /// Minimum seconds between nearest obstacle messages
+    #[clap(long, default_value_t = 0.5)]
+    nearest_obstacle_interval: f64,
+
+    // zone state is published as ON/OFF
+    #[clap(flatten)]
+    zones: ZoneArgs,
+
+    /// Announce zones as binary sensors via Home Assistant MQTT discovery
+    #[clap(long)]
+    homeassistant_discovery: bool,
+
+    /// Home Assistant discovery prefix
+    #[clap(long, default_value = "homeassistant")]
+    homeassistant_prefix: String,
+
+    #[clap(flatten)]
+    tracing: TracingArgs,
+
+    #[clap(flatten)]
+    runtime: RuntimeArgs,
+}
+
+/// Driver topics that can be republished to MQTT
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+enum ForwardTopic {
+    Diagnostics,
+    Events,
+    Latency,
+    ProcessStats,
+}
+
+impl ForwardTopic {
+    fn topic(&self) -> &'static str {
+        match self {
+            ForwardTopic::Diagnostics => diagnostics::DIAGNOSTICS_TOPIC,
+            ForwardTopic::Events => events::EVENTS_TOPIC,
+            ForwardTopic::Latency => latency::LATENCY_TOPIC,
+            ForwardTopic::ProcessStats => process_stats::PROCESS_STATS_TOPIC,
+        }
+    }
+
+    fn descriptor(&self) -> MessageDescriptor {
+        match self {
+            ForwardTopic::Diagnostics => rplidar::Diagnostics::default().descriptor(),
+            ForwardTopic::Events => rplidar::Event::default().descriptor(),
+            ForwardTopic::Latency => rplidar::Latency::default().descriptor(),
+            ForwardTopic::ProcessStats => rplidar::ProcessStats::default().descriptor(),
+        }
+    }
+}
+
+const MQTT_KEEP_ALIVE: Duration = Duration::from_secs(5);
+const MQTT_QUEUE_SIZE: usize = 100;
+const MQTT_RECONNECT_DELAY: Duration = Duration::from_secs(1);
+const NEAREST_OBSTACLE_TOPIC: &str = "nearest_obstacle";
+const ZONES_TOPIC: &str = "zones";
+const ZONE_OCCUPIED: &str = "ON";
+const ZONE_CLEAR: &str = "OFF";
+
+fn main() -> anyhow::Result<()> {
+    let args: Args = Args::parse();
+    args.runtime.build()?.block_on(run(args))
+}
+
+async fn run(args: Args) -> anyhow::Result<()> {
+    setup_tracing_with_args("mqtt_bridge", &args.tracing)?;
+    let topics = TopicBuilder::new(&args.prefix)?;
+
+    if args.forward.is_empty() && !args.nearest_obstacle && args.zones.zone.is_empty() {
+        anyhow::bail!("Nothing to bridge, use --forward, --nearest-obstacle or --zone");
+    }
+
+    let mut mqtt_options = MqttOptions::new(&args.mqtt_client_id, &args.mqtt_host, args.mqtt_port);
+    mqtt_options.set_keep_alive(MQTT_KEEP_ALIVE);
+    if let (Some(username), Some(password)) = (&args.mqtt_username, &args.mqtt_password) {
+        mqtt_options.set_credentials(username, password);
+    }
+    let (mqtt_client, mut mqtt_event_loop) = AsyncClient::new(mqtt_options, MQTT_QUEUE_SIZE);
+    info!(host = %args.mqtt_host, port = args.mqtt_port, "Connecting to MQTT broker");
+
+    // the event loop drives the connection and reconnects on the next poll after an error
+    tokio::spawn(async move {
+        loop {
+            if let Err(err) = mqtt_event_loop.poll().await {
+                error!(?err, "MQTT connection error");
+                tokio::time::sleep(MQTT_RECONNECT_DELAY).await;
+            }
+        }
+    });
+
+    // configure zenoh
+    let mut zenoh_config = Config::default();
+    if !args.listen.is_empty() {
+        zenoh_config.listen.endpoints.clone_from(&args.listen);
+        info!(listen_endpoints= ?zenoh_config.listen.endpoints, "Configured listening endpoints");
+    }
+    if !args.connect.is_empty() {
+        zenoh_config.connect.endpoints.clone_from(&args.connect);
+        info!(connect_endpoints= ?zenoh_config.connect.endpoints, "Configured connect endpoints");
+    }
+    args.static_peers.apply(&mut zenoh_config)?;
+
+    let zenoh_session = zenoh::open(zenoh_config)
+        .res()
+        .await
+        .map_err(ErrorWrapper::ZenohError)?
+        .into_arc();
+    info!("Started zenoh session");
+
+    for forward in &args.forward {
+        let topic = topics.topic(forward.topic())?;
+        let mqtt_topic = format!("{}/{}", args.mqtt_prefix, forward.topic());
+        start_json_forwarder(
+            &topic,
+            mqtt_topic,
+            forward.descriptor(),
+            zenoh_session.clone(),
+            mqtt_client.clone(),
+        )
+        .await?;
+    }
+
+    if args.nearest_obstacle {
+        let scan_topic = topics.topic(&args.scan_topic)?;
+        let mqtt_topic = format!("{}/{}", args.mqtt_prefix, NEAREST_OBSTACLE_TOPIC);
+        start_nearest_obstacle_publisher(
+            &scan_topic,
+            mqtt_topic,
+            Duration::try_from_secs_f64(args.nearest_obstacle_interval)
+                .context("Invalid nearest obstacle interval")?,
+            zenoh_session.clone(),
+            mqtt_client.clone(),
+        )
+        .await?;
+    }
+
+    if !args.zones.zone.is_empty() {
+        let scan_topic = topics.topic(&args.scan_topic)?;
+        let zone_topic_prefix = format!("{}/{}", args.mqtt_prefix, ZONES_TOPIC);
+        if args.homeassistant_discovery {
+            announce_zones_to_homeassistant(
+                &args.zones.zone,
+                &args.homeassistant_prefix,
+                &args.mqtt_client_id,
+                &zone_topic_prefix,
+                &mqtt_client,
+            )
+            .await?;
+        }
+        start_zone_monitor(
+            &scan_topic,
+            zone_topic_prefix,
+            &args.zones,
+            zenoh_session.clone(),
+            mqtt_client.clone(),
+        )
+        .await?;
+    }
+
+    signal::ctrl_c().await?;
+    info!("ctrl-c received, exiting");
+    mqtt_client.disconnect().await?;
+
+    Ok(())
+}
+
+/// Decode protobuf messages from zenoh and publish them to MQTT as JSON
+async fn start_json_forwarder(
+    topic: &str,
+    mqtt_topic: String,
+    descriptor: MessageDescriptor,
+    zenoh_session: Arc<Session>,
+    mqtt_client: AsyncClient,
+) -> anyhow::Result<()> {
+    info!(topic, %mqtt_topic, "Forwarding topic to MQTT");
+    let subscriber = zenoh_session
+        .declare_subscriber(topic)
+        .res()
+        .await
+        .map_err(ErrorWrapper::ZenohError)?;
+
+    tokio::spawn(async move {
+        while let Ok(sample) = subscriber.recv_async().await {
+            let Ok(payload) = TryInto::<Vec<u8>>::try_into(&sample.value) else {
+                warn!("Failed to convert message type on {}", sample.key_expr);
+                continue;
+            };
+            let json = match DynamicMessage::decode(descriptor.clone(), payload.as_slice())
+                .map_err(anyhow::Error::from)
+                .and_then(|message| Ok(serde_json::to_vec(&message)?))
+            {
+                Ok(json) => json,
+                Err(err) => {
+                    warn!(
+                        "Failed to convert message on {} to JSON: {}",
+                        sample.key_expr, err
+                    );
+                    continue;
+                }
+            };
+            if let Err(err) = mqtt_client
+                .publish(&mqtt_topic, QoS::AtMostOnce, false, json)
+                .await
+            {
+                error!(?err, %mqtt_topic, "Failed to publish to MQTT");
+            }
+        }
+    });
+    Ok(())
+}
+
+#[derive(Serialize, Debug)]
+struct NearestObstacle {
+    /// Capture time in seconds since unix epoch
+    ///
+    /// Source time of the sample for scans without a capture time
+    timestamp: f64,
+    frame_id: String,
+    /// Distance in meters
+    distance: f64,
+    /// Angle in radians
+    angle: f64,
+}
+
+impl NearestObstacle {
+    fn from_laser_scan(laser_scan: &foxglove::LaserScan, sample_time: SystemTime) -> Option<Self> {
+        let (angle, distance) = laser_scan
+            .valid_points()
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))?;
+        let timestamp = laser_scan
+            .timestamp
+            .as_ref()
+            .map(|timestamp| timestamp.seconds as f64 + timestamp.nanos as f64 * 1e-9)
+            .unwrap_or_else(|| {
+                sample_time
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs_f64()
+            });
+
+        Some(Self {
+            timestamp,
+            frame_id: laser_scan.frame_id.clone(),
+            distance,
+            angle,
+        })
+    }
+}
+
+/// Publish nearest obstacle from laser scans at most once per `interval`
+async fn start_nearest_obstacle_publisher(
+    scan_topic: &str,
+    mqtt_topic: String,
+    interval: Duration,
+    zenoh_session: Arc<Session>,
+    mqtt_client: AsyncClient,
+) -> anyhow::Result<()> {
+    info!(scan_topic, %mqtt_topic, "Publishing nearest obstacle to MQTT");
+    let subscriber = zenoh_session
+        .declare_subscriber(scan_topic)
+        .res()
+        .await
+        .map_err(ErrorWrapper::ZenohError)?;
+
+    tokio::spawn(async move {
+        let mut last_published: Option<Instant> = None;
+        while let Ok(sample) = subscriber.recv_async().await {
+            if last_published.is_some_and(|last| last.elapsed() < interval) {
+                continue;
+            }
+            let Ok(payload) = TryInto::<Vec<u8>>::try_into(&sample.value) else {
+                continue;
+            };
+            let laser_scan = match foxglove::LaserScan::decode(payload.as_slice()) {
+                Ok(laser_scan) => laser_scan,
+                Err(err) => {
+                    warn!("Failed to decode laser scan: {}", err);
+                    continue;
+                }
+            };
+            let Some(nearest_obstacle) =
+                NearestObstacle::from_laser_scan(&laser_scan, sample_time(&sample))
+            else {
+                continue;
+            };
+            let json = match serde_json::to_vec(&nearest_obstacle) {
+                Ok(json) => json,
+                Err(err) => {
+                    error!(?err, "Failed to serialize nearest obstacle");
+                    continue;
+                }
+            };
+            last_published = Some(Instant::now());
+            if let Err(err) = mqtt_client
+                .publish(&mqtt_topic, QoS::AtMostOnce, false, json)
+                .await
+            {
+                error!(?err, %mqtt_topic, "Failed to publish to MQTT");
+            }
+        }
+    });
+    Ok(())
+}
+
+#[derive(Serialize, Debug)]
+struct HomeAssistantDevice {
+    identifiers: Vec<String>,
+    name: String,
+    manufacturer: String,
+}
+
+/// Home Assistant MQTT discovery config for a binary sensor
+#[derive(Serialize, Debug)]
+struct HomeAssistantBinarySensor {
+    name: String,
+    unique_id: String,
+    state_topic: String,
+    device_class: String,
+    payload_on: String,
+    payload_off: String,
+    device: HomeAssistantDevice,
+}
+
+/// Publish retained discovery configs so zones show up as occupancy sensors
+async fn announce_zones_to_homeassistant(
+    zones: &[Zone],
+    discovery_prefix: &str,
+    node_id: &str,
+    zone_topic_prefix: &str,
+    mqtt_client: &AsyncClient,
+) -> anyhow::Result<()> {
+    // home assistant only allows alphanumerics, underscore and dash in node ids
+    let node_id: String = node_id
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    for zone in zones {
+        let config = HomeAssistantBinarySensor {
+            name: zone.name.clone(),
+            unique_id: format!("{}_{}", node_id, zone.name),
+            state_topic: format!("{}/{}", zone_topic_prefix, zone.name),
+            device_class: "occupancy".to_owned(),
+            payload_on: ZONE_OCCUPIED.to_owned(),
+            payload_off: ZONE_CLEAR.to_owned(),
+            device: HomeAssistantDevice {
+                identifiers: vec![node_id.clone()],
+                name: node_id.clone(),
+                manufacturer: "Slamtec".to_owned(),
+            },
+        };
+        let topic = format!(
+            "{}/binary_sensor/{}/{}/config",
+            discovery_prefix, node_id, zone.name
+        );
+        info!(zone = %zone.name, %topic, "Announcing zone to Home Assistant");
+        mqtt_client
+            .publish(topic, QoS::AtLeastOnce, true, serde_json::to_vec(&config)?)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Publish zone state when it changes
+///
+/// States are retained so new subscribers see the current state
+async fn start_zone_monitor(
+    scan_topic: &str,
+    zone_topic_prefix: String,
+    args: &ZoneArgs,
+    zenoh_session: Arc<Session>,
+    mqtt_client: AsyncClient,
+) -> anyhow::Result<()> {
+    info!(scan_topic, zones = args.zone.len(), "Monitoring zones");
+    let zones = args.zone.clone();
+    let min_points = args.zone_min_points;
+    let mut debouncers = args.debouncers()?;
+    let subscriber = zenoh_session
+        .declare_subscriber(scan_topic)
+        .res()
+        .await
+        .map_err(ErrorWrapper::ZenohError)?;
+
+    tokio::spawn(async move {
+        while let Ok(sample) = subscriber.recv_async().await {
+            let Ok(payload) = TryInto::<Vec<u8>>::try_into(&sample.value) else {
+                continue;
+            };
+            let laser_scan = match foxglove::LaserScan::decode(payload.as_slice()) {
+                Ok(laser_scan) => laser_scan,
+                Err(err) => {
+                    warn!("Failed to decode laser scan: {}", err);
+                    continue;
+                }
+            };
+            for (zone, debouncer) in zones.iter().zip(debouncers.iter_mut()) {
+                let points = laser_scan
+                    .valid_points()
+                    .filter(|(angle, range)| zone.contains(*angle, *range))
+                    .count();
+                let Some(occupied) = debouncer.update(points >= min_points, Instant::now()) else {
+                    continue;
+                };
+                info!(zone = %zone.name, occupied, "Zone state changed");
+                let topic = format!("{}/{}", zone_topic_prefix, zone.name);
+                let payload = if occupied { ZONE_OCCUPIED } else { ZONE_CLEAR };
+                if let Err(err) = mqtt_client
+                    .publish(&topic, QoS::AtLeastOnce, true, payload)
+                    .await
+                {
+                    error!(?err, %topic, "Failed to publish to MQTT");
+                }
+            }
+        }
+    });
+    Ok(())
+}
@@ -0,0 +1,185 @@
+use clap::Parser;
+use prost::Message;
+use std::net::SocketAddr;
+use tokio::signal;
+use tracing::{info, warn};
+use zenoh::{config::Config, prelude::r#async::*};
+
+use rplidar_zenoh_driver::{
+    compression, foxglove, peers::StaticPeersArgs, runtime::RuntimeArgs, setup_tracing_with_args,
+    topics::TopicBuilder, ErrorWrapper, RpLidarProjectedPoint, TracingArgs,
+};
+
+#[derive(Parser, Debug)]
+#[command()]
+struct Args {
+    /// lidar prefix
+    ///
+    /// Prefix for all topics
+    #[clap(long, default_value = "rplidar")]
+    prefix: String,
+
+    /// point cloud topic
+    #[clap(long, default_value = "point_cloud")]
+    cloud_topic: String,
+
+    /// Endpoints to connect to.
+    #[clap(short = 'e', long)]
+    connect: Vec<zenoh_config::EndPoint>,
+
+    #[clap(flatten)]
+    static_peers: StaticPeersArgs,
+
+    /// Endpoints to listen on.
+    #[clap(long)]
+    listen: Vec<zenoh_config::EndPoint>,
+
+    /// Address of a running rerun viewer
+    ///
+    /// A new viewer is spawned when not set
+    #[clap(long)]
+    viewer: Option<SocketAddr>,
+
+    #[clap(flatten)]
+    tracing: TracingArgs,
+
+    #[clap(flatten)]
+    runtime: RuntimeArgs,
+}
+
+const RERUN_APPLICATION_ID: &str = "rplidar";
+const CAPTURE_TIMELINE: &str = "capture_time";
+const POINT_RADIUS: f32 = 0.01;
+
+fn main() -> anyhow::Result<()> {
+    let args: Args = Args::parse();
+    args.runtime.build()?.block_on(run(args))
+}
+
+async fn run(args: Args) -> anyhow::Result<()> {
+    setup_tracing_with_args("rerun_viewer", &args.tracing)?;
+    let topics = TopicBuilder::new(&args.prefix)?;
+
+    let recording_builder = rerun::RecordingStreamBuilder::new(RERUN_APPLICATION_ID);
+    let recording = match args.viewer {
+        Some(viewer) => {
+            info!(?viewer, "Connecting to rerun viewer");
+            recording_builder.connect_opts(viewer, None)?
+        }
+        None => {
+            info!("Spawning rerun viewer");
+            recording_builder.spawn()?
+        }
+    };
+
+    // configure zenoh
+    let mut zenoh_config = Config::default();
+    if !args.listen.is_empty() {
+        zenoh_config.listen.endpoints.clone_from(&args.listen);
+        info!(listen_endpoints= ?zenoh_config.listen.endpoints, "Configured listening endpoints");
+    }
+    if !args.connect.is_empty() {
+        zenoh_config.connect.endpoints.clone_from(&args.connect);
+        info!(connect_endpoints= ?zenoh_config.connect.endpoints, "Configured connect endpoints");
+    }
+    args.static_peers.apply(&mut zenoh_config)?;
+
+    let zenoh_session = zenoh::open(zenoh_config)
+        .res()
+        .await
+        .map_err(ErrorWrapper::ZenohError)?;
+    info!("Started zenoh session");
+
+    let cloud_topic = topics.topic(&args.cloud_topic)?;
+    let subscriber = zenoh_session
+        .declare_subscriber(&cloud_topic)
+        .res()
+        .await
+        .map_err(ErrorWrapper::ZenohError)?;
+
+    loop {
+        tokio::select! {
+            sample = subscriber.recv_async() => {
+                let sample = sample?;
+                let payload = match compression::decompress(&sample.value) {
+                    Ok(Some(payload)) => payload,
+                    Ok(None) => sample.value.try_into()?,
+                    Err(err) => {
+                        warn!("Failed to decompress point cloud: {}", err);
+                        continue;
+                    }
+                };
+                let point_cloud = match foxglove::PointCloud::decode(payload.as_slice()) {
+                    Ok(point_cloud) => point_cloud,
+                    Err(err) => {
+                        warn!("Failed to decode point cloud: {}", err);
+                        continue;
+                    }
+                };
+                let points = match RpLidarProjectedPoint::from_foxglove_point_cloud(&point_cloud) {
+                    Ok(points) => points,
+                    Err(err) => {
+                        warn!("Unsupported point cloud layout: {}", err);
+                        continue;
+                    }
+                };
+                log_point_cloud(&recording, &cloud_topic, &point_cloud, &points)?;
+            }
+            _ = signal::ctrl_c() => {
+                info!("ctrl-c received, exiting");
+                break;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn log_point_cloud(
+    recording: &rerun::RecordingStream,
+    entity_path: &str,
+    point_cloud: &foxglove::PointCloud,
+    points: &[RpLidarProjectedPoint],
+) -> anyhow::Result<()> {
+    if let Some(timestamp) = &point_cloud.timestamp {
+        recording.set_time_nanos(
+            CAPTURE_TIMELINE,
+            timestamp.seconds * 1_000_000_000 + timestamp.nanos as i64,
+        );
+    }
+
+    if let Some(pose) = &point_cloud.pose {
+        let translation = pose
+            .position
+            .map(|position| [position.x as f32, position.y as f32, position.z as f32])
+            .unwrap_or_default();
+        let rotation = pose
+            .orientation
+            .map(|orientation| {
+                rerun::Quaternion::from_xyzw([
+                    orientation.x as f32,
+                    orientation.y as f32,
+                    orientation.z as f32,
+                    orientation.w as f32,
+                ])
+            })
+            .unwrap_or(rerun::Quaternion::IDENTITY);
+        recording.log(
+            entity_path,
+            &rerun::Transform3D::from_translation_rotation(translation, rotation),
+        )?;
+    }
+
+    recording.log(
+        entity_path,
+        &rerun::Points3D::new(points.iter().map(|point| [point.x, point.y, 0.0]))
+            .with_colors(points.iter().map(|point| quality_color(point.quality)))
+            .with_radii([POINT_RADIUS]),
+    )?;
+    Ok(())
+}
+
+/// Blend from red for low quality to green for high quality returns
+fn quality_color(quality: u8) -> rerun::Color {
+    rerun::Color::from_rgb(255 - quality, quality, 0)
+}
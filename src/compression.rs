@@ -0,0 +1,161 @@
+use std::io::Read;
+
+use anyhow::Context;
+use serde::Serialize;
+use zenoh::prelude::r#async::*;
+
+/// Encoding of lz4 compressed payloads, the block is prefixed with the uncompressed size
+pub const LZ4_ENCODING: &str = "application/octet-stream+lz4";
+/// Encoding of zstd compressed payloads
+pub const ZSTD_ENCODING: &str = "application/octet-stream+zstd";
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[cfg_attr(feature = "serde-types", derive(serde::Deserialize))]
+#[serde(rename_all = "kebab-case")]
+pub enum Compression {
+    #[default]
+    None,
+    /// Fast with moderate ratio
+    Lz4,
+    /// Slower with better ratio
+    Zstd,
+}
+
+/// Payload compression options for the publisher
+#[derive(clap::Args, Debug, Clone, Copy, Serialize)]
+#[cfg_attr(feature = "serde-types", derive(serde::Deserialize))]
+pub struct CompressionArgs {
+    /// Compress point cloud payloads
+    ///
+    /// The zenoh encoding is set so subscribers in this crate decompress transparently
+    #[clap(long, value_enum, default_value_t = Compression::None)]
+    pub compression: Compression,
+
+    /// Payloads smaller than this many bytes are sent uncompressed
+    #[clap(long, default_value_t = 1024)]
+    pub compression_min_size: usize,
+
+    /// zstd compression level
+    #[clap(long, default_value_t = 3)]
+    pub zstd_level: i32,
+}
+
+impl CompressionArgs {
+    /// Compress `payload` if enabled and large enough
+    ///
+    /// Returns the payload with the encoding it should be published with
+    pub fn compress(&self, payload: Vec<u8>) -> anyhow::Result<(Vec<u8>, Encoding)> {
+        if payload.len() < self.compression_min_size {
+            return Ok((payload, Encoding::APP_OCTET_STREAM));
+        }
+        match self.compression {
+            Compression::None => Ok((payload, Encoding::APP_OCTET_STREAM)),
+            Compression::Lz4 => Ok((
+                lz4_flex::compress_prepend_size(&payload),
+                Encoding::from(LZ4_ENCODING),
+            )),
+            Compression::Zstd => Ok((
+                zstd::bulk::compress(&payload, self.zstd_level)?,
+                Encoding::from(ZSTD_ENCODING),
+            )),
+        }
+    }
+}
+
+/// Largest accepted decompressed payload
+///
+/// A dense scan from the fastest models is well below 1 MiB as a point cloud, the limit
+/// leaves room for batches and fused clouds while keeping a peer from claiming gigabytes
+pub const MAX_DECOMPRESSED_SIZE: usize = 16 * 1024 * 1024;
+
+/// Decompress a received value
+///
+/// Returns `None` when the value isn't tagged with a compressed encoding. Payloads that
+/// would decompress to more than [`MAX_DECOMPRESSED_SIZE`] are rejected
+pub fn decompress(value: &Value) -> anyhow::Result<Option<Vec<u8>>> {
+    let encoding = value.encoding.to_string();
+    if encoding == LZ4_ENCODING {
+        Ok(Some(decompress_lz4(&value.payload.contiguous())?))
+    } else if encoding == ZSTD_ENCODING {
+        Ok(Some(decompress_zstd(&value.payload.contiguous())?))
+    } else {
+        Ok(None)
+    }
+}
+
+/// The size prefix is checked before anything is allocated
+fn decompress_lz4(payload: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let (prefix, block) = payload
+        .split_first_chunk::<4>()
+        .context("lz4 payload is missing its size prefix")?;
+    let size = u32::from_le_bytes(*prefix) as usize;
+    if size > MAX_DECOMPRESSED_SIZE {
+        anyhow::bail!(
+            "lz4 payload claims {} bytes, more than the {} byte limit",
+            size,
+            MAX_DECOMPRESSED_SIZE
+        );
+    }
+    let mut decompressed = vec![0; size];
+    let written = lz4_flex::decompress_into(block, &mut decompressed)?;
+    decompressed.truncate(written);
+    Ok(decompressed)
+}
+
+/// Streams through the decoder so memory grows with the actual output only
+fn decompress_zstd(payload: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let mut decompressed = vec![];
+    zstd::stream::Decoder::new(payload)?
+        .take(MAX_DECOMPRESSED_SIZE as u64 + 1)
+        .read_to_end(&mut decompressed)?;
+    if decompressed.len() > MAX_DECOMPRESSED_SIZE {
+        anyhow::bail!(
+            "zstd payload decompresses to more than the {} byte limit",
+            MAX_DECOMPRESSED_SIZE
+        );
+    }
+    Ok(decompressed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(compression: Compression) -> CompressionArgs {
+        CompressionArgs {
+            compression,
+            compression_min_size: 0,
+            zstd_level: 3,
+        }
+    }
+
+    #[test]
+    fn round_trips_payloads() {
+        let payload = (0..10_000u32)
+            .flat_map(|i| (i % 251).to_le_bytes())
+            .collect::<Vec<_>>();
+        for compression in [Compression::Lz4, Compression::Zstd] {
+            let (compressed, encoding) = args(compression).compress(payload.clone()).unwrap();
+            let value = Value::from(compressed).encoding(encoding);
+            assert_eq!(decompress(&value).unwrap(), Some(payload.clone()));
+        }
+        assert_eq!(decompress(&Value::from(payload)).unwrap(), None);
+    }
+
+    #[test]
+    fn rejects_oversized_lz4_prefix() {
+        let mut payload = u32::MAX.to_le_bytes().to_vec();
+        payload.extend_from_slice(&[0; 16]);
+        assert!(decompress_lz4(&payload).is_err());
+        assert!(decompress_lz4(&[0, 1]).is_err());
+    }
+
+    #[test]
+    fn rejects_oversized_zstd_output() {
+        let payload = vec![0; MAX_DECOMPRESSED_SIZE + 1];
+        let compressed = zstd::bulk::compress(&payload, 3).unwrap();
+        assert!(decompress_zstd(&compressed).is_err());
+        let fits = zstd::bulk::compress(&payload[1..], 3).unwrap();
+        assert_eq!(decompress_zstd(&fits).unwrap().len(), MAX_DECOMPRESSED_SIZE);
+    }
+}
@@ -0,0 +1,365 @@
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{Context, Result};
+use rplidar_driver::ScanPoint;
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+use tracing::{error, info, warn};
+use zenoh::{prelude::r#async::*, Session};
+
+use crate::{foxglove, ErrorWrapper};
+
+const CONFIG_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// JSON merge patches of [`DriverConfig`] received on `<prefix>/config/set`
+pub const CONFIG_SET_TOPIC: &str = "config/set";
+/// [`ConfigUpdateAck`] for every update is published on `<prefix>/config/ack`
+pub const CONFIG_ACK_TOPIC: &str = "config/ack";
+
+/// Driver configuration that can be changed while the lidar is scanning
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DriverConfig {
+    pub filter: FilterConfig,
+    /// Angular sectors removed from every scan
+    ///
+    /// Useful for hiding parts of the robot that are in view of the lidar
+    pub masks: Vec<AngleMask>,
+    pub mounting_pose: MountingPose,
+}
+
+impl DriverConfig {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file {:?}", path))?;
+        let config: DriverConfig = serde_yaml::from_str(&text)
+            .with_context(|| format!("Failed to parse config file {:?}", path))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Write the config as YAML, comments and formatting of an existing file are lost
+    ///
+    /// Written next to the target and renamed so a crash can't leave a half written file
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let text = serde_yaml::to_string(self)?;
+        let temp_path = path.with_extension("tmp");
+        // synced before the rename so a power loss can't leave an empty config behind
+        let mut file = std::fs::File::create(&temp_path)
+            .with_context(|| format!("Failed to create config file {:?}", temp_path))?;
+        file.write_all(text.as_bytes())
+            .and_then(|()| file.sync_all())
+            .with_context(|| format!("Failed to write config file {:?}", temp_path))?;
+        std::fs::rename(&temp_path, path)
+            .with_context(|| format!("Failed to replace config file {:?}", path))
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        self.filter.validate()?;
+        for mask in &self.masks {
+            mask.validate()?;
+        }
+        Ok(())
+    }
+
+    /// Apply a JSON merge patch (RFC 7396) to this config
+    ///
+    /// The patched config is parsed and validated as a whole so a bad field rejects
+    /// the entire update
+    pub fn merge_patch(&self, patch: &serde_json::Value) -> Result<Self> {
+        let mut value = serde_json::to_value(self)?;
+        merge_json(&mut value, patch);
+        let config: DriverConfig =
+            serde_json::from_value(value).context("Config update doesn't match schema")?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Use `max_range` when the config doesn't limit range itself
+    pub fn with_default_max_range(mut self, max_range: Option<f32>) -> Self {
+        if self.filter.max_range.is_none() {
+            self.filter.max_range =
+                max_range.filter(|max_range| *max_range > self.filter.min_range);
+        }
+        self
+    }
+
+    /// Invalidate points rejected by the filter or covered by a mask
+    ///
+    /// Points are kept in the scan with zero distance so that angles stay aligned
+    pub fn apply_filters(&self, scan: &mut [ScanPoint]) {
+        for point in scan.iter_mut().filter(|point| point.is_valid()) {
+            let masked = self.masks.iter().any(|mask| mask.contains(point.angle()));
+            if masked || !self.filter.accepts(point) {
+                point.dist_mm_q2 = 0;
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FilterConfig {
+    /// Minimum range in meters
+    pub min_range: f32,
+    /// Maximum range in meters
+    pub max_range: Option<f32>,
+    /// Minimum quality reported by the lidar
+    pub min_quality: u8,
+}
+
+impl Default for FilterConfig {
+    fn default() -> Self {
+        Self {
+            min_range: 0.0,
+            max_range: None,
+            min_quality: 0,
+        }
+    }
+}
+
+impl FilterConfig {
+    fn validate(&self) -> Result<()> {
+        if !self.min_range.is_finite() || self.min_range < 0.0 {
+            anyhow::bail!("filter.min_range must be a non-negative finite number");
+        }
+        if let Some(max_range) = self.max_range {
+            if !max_range.is_finite() || max_range <= self.min_range {
+                anyhow::bail!("filter.max_range must be larger than filter.min_range");
+            }
+        }
+        Ok(())
+    }
+
+    pub fn accepts(&self, point: &ScanPoint) -> bool {
+        let distance = point.distance();
+        distance >= self.min_range
+            && self
+                .max_range
+                .map_or(true, |max_range| distance <= max_range)
+            && point.quality >= self.min_quality
+    }
+}
+
+/// Sector between two angles in degrees going clockwise from `start` to `end`
+///
+/// Sectors can wrap around zero (e.g. start 350 end 10)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AngleMask {
+    pub start: f32,
+    pub end: f32,
+}
+
+impl AngleMask {
+    fn validate(&self) -> Result<()> {
+        if !(0.0..=360.0).contains(&self.start) || !(0.0..=360.0).contains(&self.end) {
+            anyhow::bail!("mask angles must be between 0 and 360 degrees");
+        }
+        Ok(())
+    }
+
+    /// Check if angle in radians falls within the mask
+    pub fn contains(&self, angle: f32) -> bool {
+        let angle = angle.to_degrees().rem_euclid(360.0);
+        if self.start <= self.end {
+            angle >= self.start && angle <= self.end
+        } else {
+            angle >= self.start || angle <= self.end
+        }
+    }
+}
+
+/// Pose of the lidar on the robot
+///
+/// Position in meters, orientation in degrees
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MountingPose {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+    pub roll: f64,
+    pub pitch: f64,
+    pub yaw: f64,
+}
+
+impl MountingPose {
+    pub fn to_foxglove_pose(&self) -> foxglove::Pose {
+        let (sr, cr) = (self.roll.to_radians() / 2.0).sin_cos();
+        let (sp, cp) = (self.pitch.to_radians() / 2.0).sin_cos();
+        let (sy, cy) = (self.yaw.to_radians() / 2.0).sin_cos();
+
+        foxglove::Pose {
+            position: Some(foxglove::Vector3 {
+                x: self.x,
+                y: self.y,
+                z: self.z,
+            }),
+            orientation: Some(foxglove::Quaternion {
+                x: sr * cp * cy - cr * sp * sy,
+                y: cr * sp * cy + sr * cp * sy,
+                z: cr * cp * sy - sr * sp * cy,
+                w: cr * cp * cy + sr * sp * sy,
+            }),
+        }
+    }
+}
+
+/// Poll config file for changes and publish new versions on the watch channel
+///
+/// Invalid configs are logged and ignored so that a typo doesn't stop the driver
+pub fn start_config_file_watcher(path: PathBuf, config_sender: Arc<watch::Sender<DriverConfig>>) {
+    tokio::spawn(async move {
+        let mut last_modified = file_modified_time(&path);
+        let mut interval = tokio::time::interval(CONFIG_POLL_INTERVAL);
+        loop {
+            interval.tick().await;
+            let modified = file_modified_time(&path);
+            if modified == last_modified {
+                continue;
+            }
+            last_modified = modified;
+
+            match DriverConfig::load(&path) {
+                Ok(config) => {
+                    if *config_sender.borrow() != config {
+                        info!(?path, ?config, "Reloaded driver config");
+                        config_sender.send_replace(config);
+                    }
+                }
+                Err(err) => error!(
+                    ?path,
+                    ?err,
+                    "Failed to reload driver config, keeping previous"
+                ),
+            }
+        }
+    });
+}
+
+fn file_modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+fn merge_json(target: &mut serde_json::Value, patch: &serde_json::Value) {
+    let serde_json::Value::Object(patch) = patch else {
+        *target = patch.clone();
+        return;
+    };
+    if !target.is_object() {
+        *target = serde_json::Value::Object(Default::default());
+    }
+    if let serde_json::Value::Object(target) = target {
+        for (key, value) in patch {
+            if value.is_null() {
+                target.remove(key);
+            } else {
+                merge_json(
+                    target.entry(key.clone()).or_insert(serde_json::Value::Null),
+                    value,
+                );
+            }
+        }
+    }
+}
+
+/// Result of a config update
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "serde-types", derive(serde::Deserialize))]
+pub struct ConfigUpdateAck {
+    pub accepted: bool,
+    pub error: Option<String>,
+    /// Whether the update was written back to the config file
+    #[serde(default)]
+    pub persisted: bool,
+    /// Config in effect after the update
+    pub config: DriverConfig,
+}
+
+/// Apply config updates received on `set_topic` and acknowledge each on `ack_topic`
+///
+/// Updates last until the next update or config file change. With `persist_path`
+/// accepted updates are also written to that file so they survive restarts
+pub async fn start_config_update_subscriber(
+    zenoh_session: Arc<Session>,
+    set_topic: String,
+    ack_topic: String,
+    config_sender: Arc<watch::Sender<DriverConfig>>,
+    persist_path: Option<PathBuf>,
+) -> Result<()> {
+    let subscriber = zenoh_session
+        .declare_subscriber(set_topic)
+        .res()
+        .await
+        .map_err(ErrorWrapper::ZenohError)?;
+    let ack_publisher = zenoh_session
+        .declare_publisher(ack_topic)
+        .res()
+        .await
+        .map_err(ErrorWrapper::ZenohError)?;
+
+    tokio::spawn(async move {
+        while let Ok(sample) = subscriber.recv_async().await {
+            let payload = sample.value.payload.contiguous();
+            let update = serde_json::from_slice::<serde_json::Value>(&payload)
+                .context("Config update isn't valid JSON")
+                .and_then(|patch| config_sender.borrow().merge_patch(&patch));
+            let ack = match update {
+                Ok(config) => {
+                    info!(?config, "Applied config update");
+                    config_sender.send_replace(config.clone());
+                    // the file watcher sees an unchanged config when it reloads the file
+                    let persisted = match &persist_path {
+                        Some(path) => match config.save(path) {
+                            Ok(()) => {
+                                info!(?path, "Persisted config update");
+                                true
+                            }
+                            Err(err) => {
+                                error!(?path, ?err, "Failed to persist config update");
+                                false
+                            }
+                        },
+                        None => false,
+                    };
+                    ConfigUpdateAck {
+                        accepted: true,
+                        error: None,
+                        persisted,
+                        config,
+                    }
+                }
+                Err(err) => {
+                    warn!("Rejected config update: {:?}", err);
+                    ConfigUpdateAck {
+                        accepted: false,
+                        error: Some(format!("{:#}", err)),
+                        persisted: false,
+                        config: config_sender.borrow().clone(),
+                    }
+                }
+            };
+            match serde_json::to_vec(&ack) {
+                Ok(ack) => {
+                    if let Err(err) = ack_publisher
+                        .put(Value::from(ack).encoding(Encoding::APP_JSON))
+                        .res()
+                        .await
+                    {
+                        error!(?err, "Failed to publish config update ack");
+                    }
+                }
+                Err(err) => error!(?err, "Failed to serialize config update ack"),
+            }
+        }
+    });
+    Ok(())
+}
@@ -0,0 +1,156 @@
+use std::{
+    fs,
+    io::{self, ErrorKind},
+    os::fd::AsRawFd,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+
+/// Running in the background without a service manager
+///
+/// Logs are lost once detached unless --log-file is set. The working directory is kept
+/// so relative paths in other options still work
+#[derive(clap::Args, Debug, Clone)]
+pub struct DaemonArgs {
+    /// Detach from the terminal and run in the background
+    #[clap(long)]
+    pub daemon: bool,
+
+    /// Write the process id to this file, refusing to start while that process runs
+    #[clap(long)]
+    pub pid_file: Option<PathBuf>,
+}
+
+impl DaemonArgs {
+    /// Detach when requested and write the pid file
+    ///
+    /// Must be called before any threads, including the async runtime, are started.
+    /// Keep the returned pid file until exit, it's removed when dropped
+    pub fn start(&self) -> anyhow::Result<Option<PidFile>> {
+        // checked before detaching so the error still reaches the terminal
+        if let Some(path) = &self.pid_file {
+            check_not_running(path)?;
+        }
+        if self.daemon {
+            detach().context("Failed to detach")?;
+        }
+        self.pid_file.as_deref().map(PidFile::create).transpose()
+    }
+}
+
+/// Pid file of this process, removed on drop
+#[derive(Debug)]
+pub struct PidFile {
+    path: PathBuf,
+}
+
+impl PidFile {
+    pub fn create(path: &Path) -> anyhow::Result<Self> {
+        check_not_running(path)?;
+        fs::write(path, format!("{}\n", std::process::id()))
+            .with_context(|| format!("Failed to write pid file {:?}", path))?;
+        Ok(Self {
+            path: path.to_owned(),
+        })
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Fails when the pid file names another running process, stale files are ignored
+fn check_not_running(path: &Path) -> anyhow::Result<()> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err).with_context(|| format!("Failed to read pid file {:?}", path)),
+    };
+    let Ok(pid) = contents.trim().parse::<libc::pid_t>() else {
+        return Ok(());
+    };
+    if pid as u32 != std::process::id() && process_exists(pid) {
+        anyhow::bail!("Already running with pid {} according to {:?}", pid, path);
+    }
+    Ok(())
+}
+
+fn process_exists(pid: libc::pid_t) -> bool {
+    // SAFETY: signal 0 only checks whether the process exists
+    if unsafe { libc::kill(pid, 0) } == 0 {
+        return true;
+    }
+    // exists but belongs to another user
+    io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+/// Double fork into a new session with stdio on /dev/null
+fn detach() -> io::Result<()> {
+    fork_and_exit_parent()?;
+    // SAFETY: the child isn't a process group leader so setsid can't fail on that
+    if unsafe { libc::setsid() } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    // the session leader exits so the daemon can never acquire a terminal
+    fork_and_exit_parent()?;
+
+    let dev_null = fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/null")?;
+    for fd in [libc::STDIN_FILENO, libc::STDOUT_FILENO, libc::STDERR_FILENO] {
+        // SAFETY: both descriptors are open for the duration of the call
+        if unsafe { libc::dup2(dev_null.as_raw_fd(), fd) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+fn fork_and_exit_parent() -> io::Result<()> {
+    // SAFETY: only called before other threads exist, the child continues single threaded
+    match unsafe { libc::fork() } {
+        -1 => Err(io::Error::last_os_error()),
+        0 => Ok(()),
+        _ => std::process::exit(0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pid_file_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "rplidar-zenoh-driver-{}-{}.pid",
+            name,
+            std::process::id()
+        ))
+    }
+
+    #[test]
+    fn replaces_stale_pid_file() {
+        let path = pid_file_path("stale");
+        // above the kernel's pid limit
+        fs::write(&path, format!("{}\n", libc::pid_t::MAX)).unwrap();
+        let pid_file = PidFile::create(&path).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap().trim(),
+            std::process::id().to_string()
+        );
+        drop(pid_file);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn refuses_running_pid() {
+        let path = pid_file_path("running");
+        // pid 1 always exists
+        fs::write(&path, "1\n").unwrap();
+        assert!(PidFile::create(&path).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+}
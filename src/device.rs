@@ -0,0 +1,365 @@
+use std::time::Duration;
+
+use rplidar_driver::{RplidarDevice, RplidarDeviceInfo, ScanMode, ScanOptions};
+use serde::Serialize;
+use serialport::SerialPort;
+use tracing::debug;
+
+/// Serial timeout used by the rplidar driver
+const SERIAL_TIMEOUT: Duration = Duration::from_millis(1);
+
+/// Baud rates used by supported models, most common first
+const MODEL_BAUD_RATES: &[u32] = &[115_200, 256_000, 460_800, 1_000_000];
+
+/// Scan mode used when the model is unknown
+const FALLBACK_SCAN_MODE: u16 = 2;
+
+/// Answer type of measurements sent as dense capsules
+const ANS_TYPE_MEASUREMENT_DENSE_CAPSULED: u8 = 0x85;
+
+/// rplidar product line
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "serde-types", derive(serde::Deserialize))]
+pub enum LidarModel {
+    A1,
+    A2,
+    A3,
+    S1,
+    S2,
+    S3,
+    C1,
+}
+
+/// Settings that differ between models
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ModelDefaults {
+    pub baud_rate: u32,
+    /// `None` uses the typical scan mode reported by the lidar
+    pub scan_mode: Option<u16>,
+    /// Longest range the model reports reliably in meters
+    pub max_range: f32,
+    /// Nominal rotations per second at the default motor speed
+    pub rotation_rate: f64,
+}
+
+/// Firmware support of the connected lidar
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "serde-types", derive(serde::Deserialize))]
+pub enum FirmwareSupport {
+    /// At or above the minimum known good version
+    Supported,
+    /// Older than the minimum known good version for the model
+    Unsupported { minimum: u16 },
+    /// Model isn't in the compatibility matrix
+    Unknown,
+}
+
+impl LidarModel {
+    /// Oldest firmware known to scan without corrupted measurements, major in the upper byte
+    pub fn min_firmware_version(&self) -> u16 {
+        match self {
+            // earlier A series firmware mangles express scan capsules
+            LidarModel::A1 => 0x0118,
+            LidarModel::A2 => 0x0118,
+            LidarModel::A3 => 0x011A,
+            LidarModel::S1 => 0x0102,
+            LidarModel::S2 => 0x0101,
+            LidarModel::S3 => 0x0100,
+            LidarModel::C1 => 0x0100,
+        }
+    }
+
+    /// Major model is the upper nibble of the model id from device info
+    pub fn from_model_id(model: u8) -> Option<Self> {
+        match model >> 4 {
+            1 => Some(LidarModel::A1),
+            2 => Some(LidarModel::A2),
+            3 => Some(LidarModel::A3),
+            4 => Some(LidarModel::C1),
+            6 => Some(LidarModel::S1),
+            7 => Some(LidarModel::S2),
+            8 => Some(LidarModel::S3),
+            _ => None,
+        }
+    }
+
+    pub fn defaults(&self) -> ModelDefaults {
+        // scan mode 2 is boost on A series, newer models report their best mode as typical
+        let (baud_rate, scan_mode, max_range, rotation_rate) = match self {
+            LidarModel::A1 => (115_200, Some(2), 12.0, 5.5),
+            LidarModel::A2 => (115_200, Some(2), 12.0, 10.0),
+            LidarModel::A3 => (256_000, None, 25.0, 10.0),
+            LidarModel::S1 => (256_000, None, 40.0, 10.0),
+            LidarModel::S2 => (1_000_000, None, 30.0, 10.0),
+            LidarModel::S3 => (1_000_000, None, 40.0, 10.0),
+            LidarModel::C1 => (460_800, None, 12.0, 10.0),
+        };
+        ModelDefaults {
+            baud_rate,
+            scan_mode,
+            max_range,
+            rotation_rate,
+        }
+    }
+}
+
+/// Identity of the connected lidar
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "serde-types", derive(serde::Deserialize))]
+pub struct LidarDeviceInfo {
+    pub model: u8,
+    pub firmware_version: u16,
+    pub hardware_version: u8,
+    /// Serial number as uppercase hex
+    pub serial_number: String,
+    /// `None` for models without known defaults
+    pub model_name: Option<LidarModel>,
+}
+
+impl LidarDeviceInfo {
+    /// Firmware version formatted as major.minor
+    pub fn firmware_version_string(&self) -> String {
+        format_firmware_version(self.firmware_version)
+    }
+
+    pub fn defaults(&self) -> Option<ModelDefaults> {
+        self.model_name.map(|model| model.defaults())
+    }
+
+    /// Check the firmware against the known good matrix
+    pub fn firmware_support(&self) -> FirmwareSupport {
+        match self.model_name {
+            Some(model) if self.firmware_version >= model.min_firmware_version() => {
+                FirmwareSupport::Supported
+            }
+            Some(model) => FirmwareSupport::Unsupported {
+                minimum: model.min_firmware_version(),
+            },
+            None => FirmwareSupport::Unknown,
+        }
+    }
+
+    /// Scan options for this model unless overridden by `scan_mode`
+    pub fn scan_options(&self, scan_mode: Option<u16>) -> ScanOptions {
+        let scan_mode = match (scan_mode, self.defaults()) {
+            (Some(scan_mode), _) => Some(scan_mode),
+            (None, Some(defaults)) => defaults.scan_mode,
+            (None, None) => Some(FALLBACK_SCAN_MODE),
+        };
+        scan_mode.map_or_else(ScanOptions::default, ScanOptions::with_mode)
+    }
+}
+
+impl From<&RplidarDeviceInfo> for LidarDeviceInfo {
+    fn from(device_info: &RplidarDeviceInfo) -> Self {
+        Self {
+            model: device_info.model,
+            model_name: LidarModel::from_model_id(device_info.model),
+            firmware_version: device_info.firmware_version,
+            hardware_version: device_info.hardware_version,
+            serial_number: device_info
+                .serialnumber
+                .iter()
+                .map(|byte| format!("{:02X}", byte))
+                .collect(),
+        }
+    }
+}
+
+/// Scan mode the lidar is running in
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature = "serde-types", derive(serde::Deserialize))]
+pub struct ScanModeInfo {
+    pub id: u16,
+    pub name: String,
+    pub us_per_sample: f32,
+    /// Meters
+    pub max_distance: f32,
+    pub answer_type: u8,
+}
+
+impl ScanModeInfo {
+    pub fn is_dense(&self) -> bool {
+        self.answer_type == ANS_TYPE_MEASUREMENT_DENSE_CAPSULED
+    }
+}
+
+impl From<&ScanMode> for ScanModeInfo {
+    fn from(scan_mode: &ScanMode) -> Self {
+        Self {
+            id: scan_mode.id,
+            name: scan_mode.name.clone(),
+            us_per_sample: scan_mode.us_per_sample,
+            max_distance: scan_mode.max_distance,
+            answer_type: scan_mode.ans_type,
+        }
+    }
+}
+
+pub type SerialLidar = RplidarDevice<dyn SerialPort>;
+
+/// How the motor is switched on and off
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[cfg_attr(feature = "serde-types", derive(serde::Deserialize))]
+#[serde(rename_all = "kebab-case")]
+pub enum MotorControl {
+    /// Motor speed commands, works on A2 and newer
+    #[default]
+    Command,
+    /// Serial DTR line, motor runs while DTR is low as on the A1 adapter board
+    Dtr,
+    /// Serial DTR line, motor runs while DTR is high
+    DtrInverted,
+}
+
+impl MotorControl {
+    /// DTR level that runs the motor, `None` when the motor isn't controlled by DTR
+    fn dtr_running_level(&self) -> Option<bool> {
+        match self {
+            MotorControl::Command => None,
+            MotorControl::Dtr => Some(false),
+            MotorControl::DtrInverted => Some(true),
+        }
+    }
+}
+
+/// Switches the lidar motor using the configured [`MotorControl`]
+pub struct LidarMotor {
+    control: MotorControl,
+    /// handle to the same serial port as the lidar, used to drive DTR
+    dtr: Option<Box<dyn SerialPort>>,
+}
+
+impl LidarMotor {
+    pub fn start(&mut self, lidar: &mut SerialLidar) -> anyhow::Result<()> {
+        self.set_running(lidar, true)
+    }
+
+    pub fn stop(&mut self, lidar: &mut SerialLidar) -> anyhow::Result<()> {
+        self.set_running(lidar, false)
+    }
+
+    fn set_running(&mut self, lidar: &mut SerialLidar, running: bool) -> anyhow::Result<()> {
+        match (self.control.dtr_running_level(), &mut self.dtr) {
+            (Some(running_level), Some(dtr)) => {
+                dtr.write_data_terminal_ready(running == running_level)?;
+            }
+            _ if running => lidar.start_motor()?,
+            _ => lidar.stop_motor()?,
+        }
+        Ok(())
+    }
+}
+
+/// Lidar that responded to a device info request
+pub struct ConnectedLidar {
+    pub lidar: SerialLidar,
+    pub motor: LidarMotor,
+    pub device_info: RplidarDeviceInfo,
+    /// baud rate that worked so reconnects can skip detection
+    pub baud_rate: u32,
+}
+
+/// Open the lidar at `baud_rate`
+///
+/// With DTR motor control the motor is left stopped
+pub fn open_lidar(
+    port: &str,
+    baud_rate: u32,
+    motor_control: MotorControl,
+) -> anyhow::Result<(SerialLidar, LidarMotor)> {
+    let mut serial_port = serialport::new(port, baud_rate)
+        .timeout(SERIAL_TIMEOUT)
+        .open()?;
+    let dtr = match motor_control.dtr_running_level() {
+        Some(running_level) => {
+            serial_port.write_data_terminal_ready(!running_level)?;
+            Some(serial_port.try_clone()?)
+        }
+        None => {
+            // DTR controls the motor on A series lidars
+            serial_port.write_data_terminal_ready(false)?;
+            None
+        }
+    };
+    let motor = LidarMotor {
+        control: motor_control,
+        dtr,
+    };
+    Ok((RplidarDevice::with_stream(serial_port), motor))
+}
+
+/// Connect to the lidar and read its device info
+///
+/// Without a `baud_rate` the baud rates of all supported models are tried
+pub fn connect_lidar(
+    port: &str,
+    baud_rate: Option<u32>,
+    motor_control: MotorControl,
+) -> anyhow::Result<ConnectedLidar> {
+    let candidates = match baud_rate {
+        Some(baud_rate) => vec![baud_rate],
+        None => MODEL_BAUD_RATES.to_vec(),
+    };
+    let mut last_error = None;
+    for baud_rate in candidates {
+        let (mut lidar, motor) = open_lidar(port, baud_rate, motor_control)?;
+        match lidar.get_device_info() {
+            Ok(device_info) => {
+                return Ok(ConnectedLidar {
+                    lidar,
+                    motor,
+                    device_info,
+                    baud_rate,
+                })
+            }
+            Err(err) => {
+                debug!(baud_rate, ?err, "Lidar did not respond");
+                last_error = Some(err);
+            }
+        }
+    }
+    match last_error {
+        Some(err) => Err(anyhow::anyhow!(
+            "Lidar did not respond to device info request: {:?}",
+            err
+        )),
+        None => anyhow::bail!("No baud rates to try"),
+    }
+}
+
+/// Delay before the first reconnect attempt
+const RECONNECT_INITIAL_DELAY: Duration = Duration::from_secs(1);
+/// Longest delay between reconnect attempts
+const RECONNECT_MAX_DELAY: Duration = Duration::from_secs(30);
+
+/// Exponential backoff between attempts to reach the lidar
+#[derive(Debug, Default)]
+pub struct ReconnectBackoff {
+    attempts: u32,
+}
+
+impl ReconnectBackoff {
+    /// Failed attempts since the lidar was last connected
+    pub fn attempts(&self) -> u32 {
+        self.attempts
+    }
+
+    /// Record a failed attempt and return how long to wait before the next one
+    pub fn next_delay(&mut self) -> Duration {
+        let delay = RECONNECT_INITIAL_DELAY
+            .saturating_mul(2_u32.saturating_pow(self.attempts))
+            .min(RECONNECT_MAX_DELAY);
+        self.attempts = self.attempts.saturating_add(1);
+        delay
+    }
+
+    pub fn reset(&mut self) {
+        self.attempts = 0;
+    }
+}
+
+/// Firmware version formatted as major.minor
+pub fn format_firmware_version(firmware_version: u16) -> String {
+    format!("{}.{:02}", firmware_version >> 8, firmware_version & 0xff)
+}
@@ -0,0 +1,60 @@
+use std::{sync::Arc, time::Duration, time::SystemTime};
+
+use prost::Message;
+use tracing::error;
+use zenoh::{prelude::r#async::*, Session};
+
+use crate::{foxglove, rplidar, system_time_to_proto_time, ErrorWrapper};
+
+pub use rplidar::diagnostics::Level;
+
+pub const DIAGNOSTICS_TOPIC: &str = "diagnostics";
+const DIAGNOSTICS_INTERVAL: Duration = Duration::from_secs(5);
+
+impl rplidar::Diagnostics {
+    pub fn new(component: &str, level: Level, message: &str) -> Self {
+        Self {
+            timestamp: Some(system_time_to_proto_time(&SystemTime::now())),
+            component: component.to_owned(),
+            level: level as i32,
+            message: message.to_owned(),
+            values: vec![],
+        }
+    }
+
+    pub fn with_value(mut self, key: &str, value: impl ToString) -> Self {
+        self.values.push(foxglove::KeyValuePair {
+            key: key.to_owned(),
+            value: value.to_string(),
+        });
+        self
+    }
+}
+
+/// Periodically publish diagnostics produced by `report`
+pub async fn start_diagnostics_publisher<F>(
+    zenoh_session: Arc<Session>,
+    topic: String,
+    report: F,
+) -> anyhow::Result<()>
+where
+    F: Fn() -> rplidar::Diagnostics + Send + 'static,
+{
+    let publisher = zenoh_session
+        .declare_publisher(topic)
+        .res()
+        .await
+        .map_err(ErrorWrapper::ZenohError)?;
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(DIAGNOSTICS_INTERVAL);
+        loop {
+            interval.tick().await;
+            let diagnostics = report();
+            if let Err(err) = publisher.put(diagnostics.encode_to_vec()).res().await {
+                error!(?err, "Failed to publish diagnostics");
+            }
+        }
+    });
+    Ok(())
+}
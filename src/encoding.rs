@@ -0,0 +1,483 @@
+use std::{sync::Arc, time::SystemTime};
+
+use futures::stream::{FuturesOrdered, StreamExt};
+use prost::{
+    encoding::{decode_varint, encode_key, encode_varint, WireType},
+    Message,
+};
+use rplidar_driver::ScanPoint;
+use serde::Serialize;
+use tokio::task::{JoinError, JoinHandle};
+
+use crate::{
+    foxglove,
+    projection::project,
+    rp_lidar_projected_point_descriptor, rplidar,
+    scan_queue::{QueueCounters, QueueStatsHandle},
+    system_time_to_proto_time, RpLidarProjectedPoint,
+};
+
+/// Compact scans use a different schema so they are published on `<scan_topic>/compact`
+pub const COMPACT_TOPIC_SUFFIX: &str = "compact";
+/// Batched scans are published on `<scan_topic>/batch`
+pub const BATCH_TOPIC_SUFFIX: &str = "batch";
+
+/// Laser scan payload format
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[cfg_attr(feature = "serde-types", derive(serde::Deserialize))]
+#[serde(rename_all = "kebab-case")]
+pub enum ScanFormat {
+    /// foxglove.LaserScan with f64 ranges
+    #[default]
+    Foxglove,
+    /// rplidar.CompactLaserScan with f32 ranges in meters
+    F32,
+    /// rplidar.CompactLaserScan with u16 ranges in millimeters
+    Millimeter,
+    /// rplidar.CompactLaserScan with delta and varint encoded ranges
+    ///
+    /// Neighboring ranges are similar so most points take one or two bytes
+    Delta,
+}
+
+impl ScanFormat {
+    /// Suffix appended to the scan topic for this format
+    pub fn topic_suffix(&self) -> Option<&'static str> {
+        match self {
+            ScanFormat::Foxglove => None,
+            ScanFormat::F32 | ScanFormat::Millimeter | ScanFormat::Delta => {
+                Some(COMPACT_TOPIC_SUFFIX)
+            }
+        }
+    }
+}
+
+/// Encodes scans into foxglove messages reusing buffers between scans
+///
+/// Ranges, intensities, point data and field descriptors keep their allocations
+/// so the only per scan allocation is the encoded payload handed to zenoh
+pub struct ScanEncoder {
+    laser_scan: foxglove::LaserScan,
+    compact_laser_scan: rplidar::CompactLaserScan,
+    point_cloud: foxglove::PointCloud,
+    format: ScanFormat,
+    intensities: bool,
+    range_resolution_mm: u32,
+}
+
+impl Default for ScanEncoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ScanEncoder {
+    pub fn new() -> Self {
+        Self::with_format(ScanFormat::Foxglove, true)
+    }
+
+    /// Encoder producing laser scans in `format`, optionally leaving out intensities
+    pub fn with_format(format: ScanFormat, intensities: bool) -> Self {
+        let (point_stride, fields) = rp_lidar_projected_point_descriptor();
+        Self {
+            laser_scan: foxglove::LaserScan::default(),
+            compact_laser_scan: rplidar::CompactLaserScan::default(),
+            point_cloud: foxglove::PointCloud {
+                point_stride,
+                fields,
+                ..Default::default()
+            },
+            format,
+            intensities,
+            range_resolution_mm: 1,
+        }
+    }
+
+    /// Quantization step of ranges in the delta format
+    pub fn with_range_resolution(mut self, range_resolution_mm: u32) -> Self {
+        self.range_resolution_mm = range_resolution_mm.max(1);
+        self
+    }
+
+    /// Encode a laser scan in the configured format
+    pub fn encode_laser_scan(
+        &mut self,
+        timestamp: &SystemTime,
+        frame_id: &str,
+        pose: &foxglove::Pose,
+        scan: &[ScanPoint],
+    ) -> Vec<u8> {
+        // zenoh takes ownership of the payload so it can't come from a reused buffer
+        match self.format {
+            ScanFormat::Foxglove => self
+                .laser_scan(timestamp, frame_id, pose, scan)
+                .encode_to_vec(),
+            ScanFormat::F32 | ScanFormat::Millimeter | ScanFormat::Delta => self
+                .compact_laser_scan(timestamp, frame_id, pose, scan)
+                .encode_to_vec(),
+        }
+    }
+
+    /// Fill the reused laser scan message
+    pub fn laser_scan(
+        &mut self,
+        timestamp: &SystemTime,
+        frame_id: &str,
+        pose: &foxglove::Pose,
+        scan: &[ScanPoint],
+    ) -> &foxglove::LaserScan {
+        let laser_scan = &mut self.laser_scan;
+        laser_scan.timestamp = Some(system_time_to_proto_time(timestamp));
+        laser_scan.frame_id.clear();
+        laser_scan.frame_id.push_str(frame_id);
+        laser_scan.pose = Some(*pose);
+        laser_scan.start_angle = scan.first().map(|point| point.angle()).unwrap_or_default() as f64;
+        laser_scan.end_angle = scan.last().map(|point| point.angle()).unwrap_or_default() as f64;
+        laser_scan.ranges.clear();
+        laser_scan
+            .ranges
+            .extend(scan.iter().map(|point| point.distance() as f64));
+        laser_scan.intensities.clear();
+        if self.intensities {
+            laser_scan
+                .intensities
+                .extend(scan.iter().map(|point| point.quality as f64));
+        }
+        laser_scan
+    }
+
+    /// Fill the reused compact laser scan message
+    pub fn compact_laser_scan(
+        &mut self,
+        timestamp: &SystemTime,
+        frame_id: &str,
+        pose: &foxglove::Pose,
+        scan: &[ScanPoint],
+    ) -> &rplidar::CompactLaserScan {
+        let laser_scan = &mut self.compact_laser_scan;
+        laser_scan.timestamp = Some(system_time_to_proto_time(timestamp));
+        laser_scan.frame_id.clear();
+        laser_scan.frame_id.push_str(frame_id);
+        laser_scan.pose = Some(*pose);
+        laser_scan.start_angle = scan.first().map(|point| point.angle()).unwrap_or_default() as f64;
+        laser_scan.end_angle = scan.last().map(|point| point.angle()).unwrap_or_default() as f64;
+        laser_scan.ranges.clear();
+        laser_scan.ranges_mm.clear();
+        laser_scan.ranges_delta.clear();
+        laser_scan.range_resolution_mm = 0;
+        match self.format {
+            ScanFormat::Millimeter => {
+                for point in scan {
+                    let millimeters = (point.distance() * 1000.0)
+                        .round()
+                        .clamp(0.0, u16::MAX as f32) as u16;
+                    laser_scan
+                        .ranges_mm
+                        .extend_from_slice(&millimeters.to_le_bytes());
+                }
+            }
+            ScanFormat::Delta => {
+                laser_scan.range_resolution_mm = self.range_resolution_mm;
+                encode_delta_ranges(
+                    scan.iter().map(|point| point.distance()),
+                    self.range_resolution_mm,
+                    &mut laser_scan.ranges_delta,
+                );
+            }
+            ScanFormat::Foxglove | ScanFormat::F32 => laser_scan
+                .ranges
+                .extend(scan.iter().map(|point| point.distance())),
+        }
+        laser_scan.intensities.clear();
+        if self.intensities {
+            laser_scan
+                .intensities
+                .extend(scan.iter().map(|point| point.quality));
+        }
+        laser_scan
+    }
+
+    pub fn encode_point_cloud(
+        &mut self,
+        timestamp: &SystemTime,
+        frame_id: &str,
+        pose: &foxglove::Pose,
+        scan: &[ScanPoint],
+    ) -> Vec<u8> {
+        self.point_cloud(timestamp, frame_id, pose, scan)
+            .encode_to_vec()
+    }
+
+    /// Project the scan into the reused point cloud message
+    pub fn point_cloud(
+        &mut self,
+        timestamp: &SystemTime,
+        frame_id: &str,
+        pose: &foxglove::Pose,
+        scan: &[ScanPoint],
+    ) -> &foxglove::PointCloud {
+        let point_cloud = &mut self.point_cloud;
+        point_cloud.timestamp = Some(system_time_to_proto_time(timestamp));
+        point_cloud.frame_id.clear();
+        point_cloud.frame_id.push_str(frame_id);
+        point_cloud.pose = Some(*pose);
+        point_cloud.data.clear();
+        for scan_point in scan.iter().filter(|point| point.is_valid()) {
+            let (x, y) = project(scan_point.angle(), scan_point.distance());
+            let point = RpLidarProjectedPoint::new(
+                x,
+                y,
+                scan_point.distance(),
+                scan_point.angle(),
+                scan_point.quality,
+            );
+            point_cloud
+                .data
+                .extend_from_slice(&point.to_foxglove_blob());
+        }
+        point_cloud
+    }
+}
+
+/// Append ranges in meters as zigzag varint deltas of `resolution_mm` steps
+pub fn encode_delta_ranges(
+    ranges: impl Iterator<Item = f32>,
+    resolution_mm: u32,
+    buffer: &mut Vec<u8>,
+) {
+    let mut previous = 0i64;
+    for range in ranges {
+        let quantized = (range * 1000.0 / resolution_mm as f32).round() as i64;
+        let delta = quantized - previous;
+        encode_varint(((delta << 1) ^ (delta >> 63)) as u64, buffer);
+        previous = quantized;
+    }
+}
+
+/// Ranges in meters from [`encode_delta_ranges`] output
+pub fn decode_delta_ranges(mut data: &[u8], resolution_mm: u32) -> anyhow::Result<Vec<f64>> {
+    let mut ranges = vec![];
+    let mut previous = 0i64;
+    while !data.is_empty() {
+        let zigzag = decode_varint(&mut data)?;
+        previous += (zigzag >> 1) as i64 ^ -((zigzag & 1) as i64);
+        ranges.push((previous * resolution_mm as i64) as f64 / 1000.0);
+    }
+    Ok(ranges)
+}
+
+impl rplidar::CompactLaserScan {
+    /// Expand into a foxglove.LaserScan for consumers that only understand foxglove schemas
+    pub fn to_laser_scan(&self) -> anyhow::Result<foxglove::LaserScan> {
+        let ranges = if !self.ranges_delta.is_empty() {
+            decode_delta_ranges(&self.ranges_delta, self.range_resolution_mm.max(1))?
+        } else if self.ranges_mm.is_empty() {
+            self.ranges.iter().map(|range| *range as f64).collect()
+        } else {
+            self.ranges_mm
+                .chunks_exact(2)
+                .map(|millimeters| {
+                    u16::from_le_bytes([millimeters[0], millimeters[1]]) as f64 / 1000.0
+                })
+                .collect()
+        };
+        Ok(foxglove::LaserScan {
+            timestamp: self.timestamp.clone(),
+            frame_id: self.frame_id.clone(),
+            pose: self.pose,
+            start_angle: self.start_angle,
+            end_angle: self.end_angle,
+            ranges,
+            intensities: self
+                .intensities
+                .iter()
+                .map(|quality| *quality as f64)
+                .collect(),
+        })
+    }
+}
+
+/// Field number of `scans` in rplidar.LaserScanBatch
+const LASER_SCAN_BATCH_SCANS_FIELD: u32 = 1;
+
+/// Builds rplidar.LaserScanBatch payloads from already encoded laser scans
+///
+/// An embedded message is encoded the same way as a bytes field holding the encoded message
+/// so scans are appended without decoding them again
+pub struct LaserScanBatchEncoder {
+    buffer: Vec<u8>,
+    count: usize,
+    batch_size: usize,
+}
+
+impl LaserScanBatchEncoder {
+    pub fn new(batch_size: usize) -> Self {
+        Self {
+            buffer: vec![],
+            count: 0,
+            batch_size,
+        }
+    }
+
+    /// Append an encoded foxglove.LaserScan
+    ///
+    /// Returns the batch payload once it holds `batch_size` scans
+    pub fn push(&mut self, laser_scan: &[u8]) -> Option<Vec<u8>> {
+        encode_key(
+            LASER_SCAN_BATCH_SCANS_FIELD,
+            WireType::LengthDelimited,
+            &mut self.buffer,
+        );
+        encode_varint(laser_scan.len() as u64, &mut self.buffer);
+        self.buffer.extend_from_slice(laser_scan);
+        self.count += 1;
+        if self.count >= self.batch_size {
+            self.count = 0;
+            Some(std::mem::take(&mut self.buffer))
+        } else {
+            None
+        }
+    }
+}
+
+/// Runs encoding jobs on the blocking thread pool
+///
+/// Up to `depth` jobs run concurrently and results are handed back in submission order
+pub struct EncodePipeline<T> {
+    in_flight: FuturesOrdered<JoinHandle<T>>,
+    depth: usize,
+    counters: Arc<QueueCounters>,
+}
+
+impl<T: Send + 'static> EncodePipeline<T> {
+    pub fn new(depth: usize) -> Self {
+        Self {
+            in_flight: FuturesOrdered::new(),
+            depth,
+            counters: QueueCounters::new(depth),
+        }
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.in_flight.len() >= self.depth
+    }
+
+    pub fn submit(&mut self, job: impl FnOnce() -> T + Send + 'static) {
+        self.counters.record_push();
+        self.in_flight.push_back(tokio::task::spawn_blocking(job));
+    }
+
+    pub fn stats(&self) -> QueueStatsHandle {
+        self.counters.stats_handle()
+    }
+
+    /// Result of the oldest job
+    ///
+    /// Pending while no jobs are in flight. Cancel safe so it can be used in `select!`
+    pub async fn next(&mut self) -> Result<T, JoinError> {
+        match self.in_flight.next().await {
+            Some(result) => {
+                self.counters.record_pop();
+                result
+            }
+            None => std::future::pending().await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scan() -> Vec<ScanPoint> {
+        (0..360u32)
+            .map(|degree| ScanPoint {
+                angle_z_q14: (degree * 16384 / 90) as u16,
+                dist_mm_q2: (1000 + degree * 7) * 4,
+                quality: (degree % 64) as u8,
+                flag: 0,
+            })
+            .collect()
+    }
+
+    fn encode(
+        format: ScanFormat,
+        scan: &[ScanPoint],
+    ) -> (foxglove::LaserScan, foxglove::LaserScan) {
+        let timestamp =
+            SystemTime::UNIX_EPOCH + std::time::Duration::from_millis(1_700_000_000_123);
+        let pose = foxglove::Pose::default();
+        let expected = ScanEncoder::new()
+            .laser_scan(&timestamp, "laser", &pose, scan)
+            .clone();
+        let payload = ScanEncoder::with_format(format, true)
+            .encode_laser_scan(&timestamp, "laser", &pose, scan);
+        let compact = rplidar::CompactLaserScan::decode(payload.as_slice()).unwrap();
+        (compact.to_laser_scan().unwrap(), expected)
+    }
+
+    #[test]
+    fn f32_scans_round_trip() {
+        let (decoded, expected) = encode(ScanFormat::F32, &scan());
+        assert_eq!(decoded, expected);
+    }
+
+    #[test]
+    fn millimeter_scans_round_trip() {
+        let (mut decoded, expected) = encode(ScanFormat::Millimeter, &scan());
+        assert_eq!(decoded.ranges.len(), expected.ranges.len());
+        for (decoded, expected) in decoded.ranges.iter().zip(&expected.ranges) {
+            assert!((decoded - expected).abs() < 0.0006);
+        }
+        // everything else is carried over exactly
+        decoded.ranges.clone_from(&expected.ranges);
+        assert_eq!(decoded, expected);
+    }
+
+    #[test]
+    fn batches_decode_as_laser_scan_batch() {
+        let timestamp = SystemTime::UNIX_EPOCH;
+        let pose = foxglove::Pose::default();
+        let scan = scan();
+        let mut encoder = ScanEncoder::new();
+        let mut batch = LaserScanBatchEncoder::new(3);
+        let mut expected = vec![];
+        let mut payload = None;
+        for count in 1..=3 {
+            let scan = &scan[..count * 100];
+            expected.push(encoder.laser_scan(&timestamp, "laser", &pose, scan).clone());
+            let laser_scan = encoder.encode_laser_scan(&timestamp, "laser", &pose, scan);
+            payload = batch.push(&laser_scan);
+            assert_eq!(payload.is_some(), count == 3);
+        }
+        let decoded = rplidar::LaserScanBatch::decode(payload.unwrap().as_slice()).unwrap();
+        assert_eq!(decoded.scans, expected);
+
+        // the encoder starts over after handing out a batch
+        let laser_scan = encoder.encode_laser_scan(&timestamp, "laser", &pose, &scan);
+        assert!(batch.push(&laser_scan).is_none());
+    }
+
+    #[test]
+    fn delta_ranges_round_trip() {
+        let ranges = [0.0, 1.234, 1.236, 1.2, 0.0, 12.5, 12.49];
+        let mut buffer = vec![];
+        encode_delta_ranges(ranges.iter().copied(), 1, &mut buffer);
+        // small steps fit a single byte
+        assert!(buffer.len() < ranges.len() * 3);
+        let decoded = decode_delta_ranges(&buffer, 1).unwrap();
+        assert_eq!(decoded.len(), ranges.len());
+        for (decoded, range) in decoded.iter().zip(ranges) {
+            assert!((decoded - range as f64).abs() < 0.0006);
+        }
+    }
+
+    #[test]
+    fn quantizes_delta_ranges() {
+        let mut buffer = vec![];
+        encode_delta_ranges([1.234f32, 1.236].into_iter(), 10, &mut buffer);
+        assert_eq!(buffer.len(), 3);
+        assert_eq!(decode_delta_ranges(&buffer, 10).unwrap(), vec![1.23, 1.24]);
+        assert!(decode_delta_ranges(&[0x80], 1).is_err());
+    }
+}
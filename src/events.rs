@@ -0,0 +1,109 @@
+use std::{sync::Arc, time::SystemTime};
+
+use prost::Message;
+use tokio::sync::mpsc::{channel, error::TrySendError, Sender};
+use tracing::{error, warn};
+use zenoh::{prelude::r#async::*, Session};
+
+use crate::{
+    foxglove, rplidar,
+    scan_queue::{QueueCounters, QueueStatsHandle},
+    system_time_to_proto_time, ErrorWrapper,
+};
+
+pub use rplidar::event::Severity;
+
+pub const EVENTS_TOPIC: &str = "events";
+const EVENT_QUEUE_SIZE: usize = 100;
+
+pub const SERIAL_ERROR: &str = "serial_error";
+pub const RECONNECTED: &str = "reconnected";
+pub const COMMAND_REJECTED: &str = "command_rejected";
+pub const SCAN_RATE_DEGRADED: &str = "scan_rate_degraded";
+pub const ROTATION_RATE_DEVIATION: &str = "rotation_rate_deviation";
+pub const STUCK_SCAN: &str = "stuck_scan";
+pub const UNSUPPORTED_FIRMWARE: &str = "unsupported_firmware";
+pub const SUBSCRIBER_JOINED: &str = "subscriber_joined";
+pub const SUBSCRIBERS_LEFT: &str = "subscribers_left";
+pub const RECORDING_FINISHED: &str = "recording_finished";
+pub const RECORDING_UPLOADED: &str = "recording_uploaded";
+pub const RECORDING_UPLOAD_FAILED: &str = "recording_upload_failed";
+pub const ZONE_ENTERED: &str = "zone_entered";
+pub const ZONE_EXITED: &str = "zone_exited";
+
+impl rplidar::Event {
+    pub fn new(component: &str, severity: Severity, kind: &str, message: &str) -> Self {
+        Self {
+            timestamp: Some(system_time_to_proto_time(&SystemTime::now())),
+            component: component.to_owned(),
+            severity: severity as i32,
+            kind: kind.to_owned(),
+            message: message.to_owned(),
+            values: vec![],
+        }
+    }
+
+    pub fn with_value(mut self, key: &str, value: impl ToString) -> Self {
+        self.values.push(foxglove::KeyValuePair {
+            key: key.to_owned(),
+            value: value.to_string(),
+        });
+        self
+    }
+}
+
+/// Handle for publishing events
+///
+/// Cheap to clone and never blocks so it can be used from the lidar thread
+#[derive(Clone)]
+pub struct EventPublisher {
+    sender: Sender<rplidar::Event>,
+    counters: Arc<QueueCounters>,
+}
+
+impl EventPublisher {
+    pub fn publish(&self, event: rplidar::Event) {
+        // counted before sending so the publisher task can't pop it first
+        self.counters.record_push();
+        match self.sender.try_send(event) {
+            Ok(()) => (),
+            Err(TrySendError::Full(event)) => {
+                self.counters.record_pop();
+                self.counters.record_drop();
+                warn!(kind = event.kind, "Event queue full, dropping event")
+            }
+            Err(TrySendError::Closed(_)) => self.counters.record_pop(),
+        }
+    }
+
+    pub fn stats(&self) -> QueueStatsHandle {
+        self.counters.stats_handle()
+    }
+}
+
+/// Publish events on `topic` until all [`EventPublisher`] handles are dropped
+pub async fn start_event_publisher(
+    zenoh_session: Arc<Session>,
+    topic: String,
+) -> anyhow::Result<EventPublisher> {
+    let publisher = zenoh_session
+        .declare_publisher(topic)
+        .res()
+        .await
+        .map_err(ErrorWrapper::ZenohError)?;
+
+    let (sender, mut receiver) = channel::<rplidar::Event>(EVENT_QUEUE_SIZE);
+    let counters = QueueCounters::new(EVENT_QUEUE_SIZE);
+    tokio::spawn({
+        let counters = counters.clone();
+        async move {
+            while let Some(event) = receiver.recv().await {
+                counters.record_pop();
+                if let Err(err) = publisher.put(event.encode_to_vec()).res().await {
+                    error!(?err, "Failed to publish event");
+                }
+            }
+        }
+    });
+    Ok(EventPublisher { sender, counters })
+}
@@ -0,0 +1,201 @@
+use std::{fmt, sync::Mutex};
+
+use serde::Serialize;
+use tracing::error;
+
+/// What the driver does when an operation fails
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "serde-types", derive(serde::Deserialize))]
+#[serde(rename_all = "kebab-case")]
+pub enum ErrorAction {
+    /// Log the error, drop what failed and keep going
+    Continue,
+    /// Stop and exit with the restart code of the failure class
+    Restart,
+    /// Stop and exit with the fatal code of the failure class
+    Exit,
+}
+
+/// Kinds of failures with their own policy and exit codes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailureClass {
+    /// Connecting to or reading from the lidar
+    Serial,
+    /// Publishing scans
+    Zenoh,
+    /// Encoding scans
+    Encode,
+}
+
+impl FailureClass {
+    pub fn name(&self) -> &'static str {
+        match self {
+            FailureClass::Serial => "serial",
+            FailureClass::Zenoh => "zenoh",
+            FailureClass::Encode => "encode",
+        }
+    }
+
+    /// Process exit code for stopping with `action`
+    ///
+    /// Restart codes are 10 and up, fatal codes 20 and up, so a supervisor can be told
+    /// not to restart on fatal codes, e.g. with systemd's RestartPreventExitStatus
+    pub fn exit_code(&self, action: ErrorAction) -> i32 {
+        let class = match self {
+            FailureClass::Serial => 0,
+            FailureClass::Zenoh => 1,
+            FailureClass::Encode => 2,
+        };
+        match action {
+            ErrorAction::Continue => 0,
+            ErrorAction::Restart => 10 + class,
+            ErrorAction::Exit => 20 + class,
+        }
+    }
+}
+
+/// Failure handling per failure class
+///
+/// Serial errors with continue reconnect with backoff, zenoh and encode errors with
+/// continue drop the scan
+#[derive(clap::Args, Debug, Clone, Copy, Serialize)]
+#[cfg_attr(feature = "serde-types", derive(serde::Deserialize))]
+pub struct FailurePolicyArgs {
+    /// Action for failures of every class without its own action
+    #[clap(long, value_enum, default_value_t = ErrorAction::Continue)]
+    pub on_error: ErrorAction,
+
+    /// Action when the lidar can't be connected or read
+    #[clap(long, value_enum)]
+    pub on_serial_error: Option<ErrorAction>,
+
+    /// Action when scans can't be published
+    #[clap(long, value_enum)]
+    pub on_zenoh_error: Option<ErrorAction>,
+
+    /// Action when scans can't be encoded
+    #[clap(long, value_enum)]
+    pub on_encode_error: Option<ErrorAction>,
+}
+
+impl FailurePolicyArgs {
+    pub fn action(&self, class: FailureClass) -> ErrorAction {
+        match class {
+            FailureClass::Serial => self.on_serial_error,
+            FailureClass::Zenoh => self.on_zenoh_error,
+            FailureClass::Encode => self.on_encode_error,
+        }
+        .unwrap_or(self.on_error)
+    }
+
+    /// Failure when `err` should stop the driver
+    ///
+    /// Callers log errors themselves, often throttled, so continuing is silent
+    pub fn handle(&self, class: FailureClass, err: anyhow::Error) -> Option<Failure> {
+        let action = self.action(class);
+        if action == ErrorAction::Continue {
+            return None;
+        }
+        let failure = Failure {
+            class,
+            action,
+            error: err,
+        };
+        error!(
+            class = class.name(),
+            ?action,
+            exit_code = failure.exit_code(),
+            "Stopping after error"
+        );
+        Some(failure)
+    }
+}
+
+/// Failure that stops the driver
+#[derive(Debug)]
+pub struct Failure {
+    pub class: FailureClass,
+    pub action: ErrorAction,
+    pub error: anyhow::Error,
+}
+
+impl Failure {
+    pub fn exit_code(&self) -> i32 {
+        self.class.exit_code(self.action)
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} failure: {}", self.class.name(), self.error)
+    }
+}
+
+impl std::error::Error for Failure {}
+
+/// First failure reported from a thread that can't return it
+#[derive(Default)]
+pub struct FailureSlot {
+    failure: Mutex<Option<Failure>>,
+}
+
+impl FailureSlot {
+    /// Keeps the first failure, later ones are only logged by the policy
+    pub fn set(&self, failure: Failure) {
+        let mut slot = self.failure.lock().unwrap();
+        if slot.is_none() {
+            *slot = Some(failure);
+        }
+    }
+
+    pub fn take(&self) -> Option<Failure> {
+        self.failure.lock().unwrap().take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn class_actions_override_default() {
+        let args = FailurePolicyArgs {
+            on_error: ErrorAction::Restart,
+            on_serial_error: Some(ErrorAction::Continue),
+            on_zenoh_error: None,
+            on_encode_error: Some(ErrorAction::Exit),
+        };
+        assert_eq!(args.action(FailureClass::Serial), ErrorAction::Continue);
+        assert_eq!(args.action(FailureClass::Zenoh), ErrorAction::Restart);
+        assert_eq!(args.action(FailureClass::Encode), ErrorAction::Exit);
+
+        assert!(args
+            .handle(FailureClass::Serial, anyhow::anyhow!("unplugged"))
+            .is_none());
+        let failure = args
+            .handle(FailureClass::Encode, anyhow::anyhow!("bad scan"))
+            .unwrap();
+        assert_eq!(failure.exit_code(), 22);
+        assert_eq!(failure.to_string(), "encode failure: bad scan");
+    }
+
+    #[test]
+    fn exit_codes_are_distinct() {
+        let classes = [
+            FailureClass::Serial,
+            FailureClass::Zenoh,
+            FailureClass::Encode,
+        ];
+        let mut codes = classes
+            .iter()
+            .flat_map(|class| {
+                [ErrorAction::Restart, ErrorAction::Exit].map(|action| class.exit_code(action))
+            })
+            .collect::<Vec<_>>();
+        codes.sort();
+        codes.dedup();
+        assert_eq!(codes, vec![10, 11, 12, 20, 21, 22]);
+        // the generic error exit code of main and clap's usage error code are avoided
+        assert!(!codes.contains(&1) && !codes.contains(&2));
+    }
+}
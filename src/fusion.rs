@@ -0,0 +1,191 @@
+use std::str::FromStr;
+
+use prost_types::Timestamp;
+
+use crate::{
+    config::MountingPose, foxglove, projection::unproject, rp_lidar_projected_point_descriptor,
+    RpLidarProjectedPoint,
+};
+
+/// Pose of a planar lidar in the fused frame
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct PlanarPose {
+    pub x: f64,
+    pub y: f64,
+    /// Radians, counter clockwise
+    pub yaw: f64,
+}
+
+impl PlanarPose {
+    /// Drops height, roll and pitch, the lidars are assumed to scan the same plane
+    pub fn from_foxglove_pose(pose: &foxglove::Pose) -> Self {
+        let position = pose.position.unwrap_or_default();
+        let yaw = pose
+            .orientation
+            .map(|q| (2.0 * (q.w * q.z + q.x * q.y)).atan2(1.0 - 2.0 * (q.y * q.y + q.z * q.z)))
+            .unwrap_or_default();
+        Self {
+            x: position.x,
+            y: position.y,
+            yaw,
+        }
+    }
+
+    pub fn transform(&self, x: f64, y: f64) -> (f64, f64) {
+        let (sin, cos) = self.yaw.sin_cos();
+        (self.x + x * cos - y * sin, self.y + x * sin + y * cos)
+    }
+}
+
+/// Point cloud source of the fusion node
+///
+/// Parsed from `prefix` or `prefix:x:y:yaw_deg`. Without extrinsics the mounting
+/// pose published with each cloud is used
+#[derive(Debug, Clone)]
+pub struct FusionSource {
+    pub prefix: String,
+    pub extrinsics: Option<PlanarPose>,
+}
+
+impl FromStr for FusionSource {
+    type Err = anyhow::Error;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<_> = text.split(':').collect();
+        let (prefix, extrinsics) = match parts.as_slice() {
+            [prefix] => (prefix, None),
+            [prefix, x, y, yaw] => (
+                prefix,
+                Some(PlanarPose {
+                    x: x.parse()?,
+                    y: y.parse()?,
+                    yaw: yaw.parse::<f64>()?.to_radians(),
+                }),
+            ),
+            _ => anyhow::bail!("source must be in format prefix or prefix:x_m:y_m:yaw_deg"),
+        };
+        if prefix.is_empty() {
+            anyhow::bail!("source prefix can't be empty");
+        }
+        Ok(Self {
+            prefix: prefix.to_string(),
+            extrinsics,
+        })
+    }
+}
+
+/// Merge clouds into one cloud in a common frame
+///
+/// Each cloud is moved by its pose. Distance and angle of the merged points are
+/// relative to the origin of the common frame so the cloud keeps the driver layout
+pub fn fuse_point_clouds(
+    timestamp: Option<Timestamp>,
+    frame_id: &str,
+    clouds: &[(PlanarPose, &foxglove::PointCloud)],
+) -> anyhow::Result<foxglove::PointCloud> {
+    let mut data = vec![];
+    for (pose, point_cloud) in clouds {
+        for point in RpLidarProjectedPoint::from_foxglove_point_cloud(point_cloud)? {
+            let (x, y) = pose.transform(point.x as f64, point.y as f64);
+            let (angle, distance) = unproject(x as f32, y as f32);
+            let fused =
+                RpLidarProjectedPoint::new(x as f32, y as f32, distance, angle, point.quality);
+            data.extend_from_slice(&fused.to_foxglove_blob());
+        }
+    }
+    let (point_stride, fields) = rp_lidar_projected_point_descriptor();
+    Ok(foxglove::PointCloud {
+        timestamp,
+        frame_id: frame_id.to_owned(),
+        // points are already in the common frame
+        pose: Some(MountingPose::default().to_foxglove_pose()),
+        point_stride,
+        fields,
+        data,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cloud(points: &[(f32, f32)]) -> foxglove::PointCloud {
+        let (point_stride, fields) = rp_lidar_projected_point_descriptor();
+        foxglove::PointCloud {
+            point_stride,
+            fields,
+            data: points
+                .iter()
+                .flat_map(|(x, y)| {
+                    RpLidarProjectedPoint::new(*x, *y, x.hypot(*y), 0.0, 100).to_foxglove_blob()
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn parses_sources() {
+        let source: FusionSource = "front".parse().unwrap();
+        assert_eq!(source.prefix, "front");
+        assert!(source.extrinsics.is_none());
+
+        let source: FusionSource = "rear:-0.5:0:180".parse().unwrap();
+        assert_eq!(source.prefix, "rear");
+        let extrinsics = source.extrinsics.unwrap();
+        assert_eq!(extrinsics.x, -0.5);
+        assert!((extrinsics.yaw - std::f64::consts::PI).abs() < 1e-9);
+
+        assert!("rear:1:2".parse::<FusionSource>().is_err());
+        assert!(":1:2:3".parse::<FusionSource>().is_err());
+    }
+
+    #[test]
+    fn yaw_from_mounting_pose() {
+        let pose = MountingPose {
+            x: 0.2,
+            yaw: 90.0,
+            ..Default::default()
+        };
+        let planar = PlanarPose::from_foxglove_pose(&pose.to_foxglove_pose());
+        assert!((planar.x - 0.2).abs() < 1e-9);
+        assert!((planar.yaw - std::f64::consts::FRAC_PI_2).abs() < 1e-9);
+    }
+
+    #[test]
+    fn merges_front_and_rear() {
+        let front = cloud(&[(1.0, 0.0)]);
+        let rear = cloud(&[(1.0, 0.0)]);
+        let fused = fuse_point_clouds(
+            None,
+            "base_link",
+            &[
+                (
+                    PlanarPose {
+                        x: 0.5,
+                        y: 0.0,
+                        yaw: 0.0,
+                    },
+                    &front,
+                ),
+                (
+                    PlanarPose {
+                        x: -0.5,
+                        y: 0.0,
+                        yaw: std::f64::consts::PI,
+                    },
+                    &rear,
+                ),
+            ],
+        )
+        .unwrap();
+        assert_eq!(fused.frame_id, "base_link");
+        let points = RpLidarProjectedPoint::from_foxglove_point_cloud(&fused).unwrap();
+        assert_eq!(points.len(), 2);
+        assert!((points[0].x - 1.5).abs() < 1e-6);
+        assert!((points[1].x + 1.5).abs() < 1e-6);
+        assert!(points[1].y.abs() < 1e-6);
+        assert!((points[1].distance - 1.5).abs() < 1e-6);
+        assert!((points[1].angle - std::f32::consts::PI).abs() < 1e-5);
+    }
+}
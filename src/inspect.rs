@@ -0,0 +1,209 @@
+use std::{collections::BTreeMap, fmt, time::Duration};
+
+use mcap::{read::Options, MessageStream};
+
+/// Number of largest gaps printed per channel
+const GAPS_SHOWN: usize = 5;
+
+/// Contents of a recording as reported by `mcap_logger inspect`
+#[derive(Debug, Default)]
+pub struct RecordingSummary {
+    /// By channel id
+    pub channels: BTreeMap<u16, ChannelSummary>,
+    /// Reading stopped at a damaged record, e.g. the file was never finished
+    pub truncated: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct ChannelSummary {
+    pub topic: String,
+    pub message_encoding: String,
+    /// Schema name and encoding
+    pub schema: Option<(String, String)>,
+    pub messages: u64,
+    pub bytes: u64,
+    pub first_log_time: u64,
+    pub last_log_time: u64,
+    /// Intervals between consecutive messages longer than the gap threshold
+    pub gaps: Vec<Gap>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Gap {
+    /// Log time of the message before the gap
+    pub start: u64,
+    pub duration: Duration,
+}
+
+impl ChannelSummary {
+    fn record(&mut self, log_time: u64, bytes: usize, gap_threshold: Duration) {
+        if self.messages == 0 {
+            self.first_log_time = log_time;
+        } else if log_time > self.last_log_time {
+            let duration = Duration::from_nanos(log_time - self.last_log_time);
+            if duration > gap_threshold {
+                self.gaps.push(Gap {
+                    start: self.last_log_time,
+                    duration,
+                });
+            }
+        }
+        self.last_log_time = self.last_log_time.max(log_time);
+        self.messages += 1;
+        self.bytes += bytes as u64;
+    }
+
+    pub fn duration(&self) -> Duration {
+        Duration::from_nanos(self.last_log_time - self.first_log_time)
+    }
+
+    /// Average message rate in Hz, `None` with fewer than two messages
+    pub fn rate(&self) -> Option<f64> {
+        let seconds = self.duration().as_secs_f64();
+        (self.messages > 1 && seconds > 0.0).then(|| (self.messages - 1) as f64 / seconds)
+    }
+}
+
+impl RecordingSummary {
+    pub fn start(&self) -> Option<u64> {
+        self.channels
+            .values()
+            .map(|channel| channel.first_log_time)
+            .min()
+    }
+
+    pub fn end(&self) -> Option<u64> {
+        self.channels
+            .values()
+            .map(|channel| channel.last_log_time)
+            .max()
+    }
+
+    pub fn messages(&self) -> u64 {
+        self.channels.values().map(|channel| channel.messages).sum()
+    }
+}
+
+/// Read every message of a recording
+///
+/// Unfinished recordings are read up to the first damaged record
+pub fn inspect(mapped: &[u8], gap_threshold: Duration) -> anyhow::Result<RecordingSummary> {
+    let mut summary = RecordingSummary::default();
+    for message in MessageStream::new_with_options(mapped, Options::IgnoreEndMagic.into())? {
+        let message = match message {
+            Ok(message) => message,
+            Err(_) => {
+                summary.truncated = true;
+                break;
+            }
+        };
+        let channel = summary
+            .channels
+            .entry(message.channel.id)
+            .or_insert_with(|| ChannelSummary {
+                topic: message.channel.topic.clone(),
+                message_encoding: message.channel.message_encoding.clone(),
+                schema: message
+                    .channel
+                    .schema
+                    .as_ref()
+                    .map(|schema| (schema.name.clone(), schema.encoding.clone())),
+                ..Default::default()
+            });
+        channel.record(message.log_time, message.data.len(), gap_threshold);
+    }
+    Ok(summary)
+}
+
+impl fmt::Display for RecordingSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (Some(start), Some(end)) = (self.start(), self.end()) else {
+            return writeln!(f, "No messages");
+        };
+        writeln!(
+            f,
+            "Duration: {:.3}s",
+            Duration::from_nanos(end - start).as_secs_f64()
+        )?;
+        writeln!(f, "Messages: {}", self.messages())?;
+        writeln!(f, "Channels: {}", self.channels.len())?;
+        if self.truncated {
+            writeln!(f, "Warning: recording is truncated or damaged")?;
+        }
+        for (id, channel) in &self.channels {
+            writeln!(f)?;
+            writeln!(f, "[{}] {}", id, channel.topic)?;
+            writeln!(f, "  encoding: {}", channel.message_encoding)?;
+            match &channel.schema {
+                Some((name, encoding)) => writeln!(f, "  schema: {} ({})", name, encoding)?,
+                None => writeln!(f, "  schema: none")?,
+            }
+            writeln!(f, "  messages: {}", channel.messages)?;
+            writeln!(f, "  bytes: {}", channel.bytes)?;
+            match channel.rate() {
+                Some(rate) => writeln!(f, "  rate: {:.2} Hz", rate)?,
+                None => writeln!(f, "  rate: -")?,
+            }
+            writeln!(
+                f,
+                "  start offset: {:.3}s",
+                Duration::from_nanos(channel.first_log_time - start).as_secs_f64()
+            )?;
+            writeln!(f, "  gaps: {}", channel.gaps.len())?;
+            let mut gaps = channel.gaps.clone();
+            gaps.sort_by(|a, b| b.duration.cmp(&a.duration));
+            for gap in gaps.iter().take(GAPS_SHOWN) {
+                writeln!(
+                    f,
+                    "    {:.3}s at {:.3}s",
+                    gap.duration.as_secs_f64(),
+                    Duration::from_nanos(gap.start - start).as_secs_f64()
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECOND: u64 = 1_000_000_000;
+
+    #[test]
+    fn records_rate_and_gaps() {
+        let mut channel = ChannelSummary::default();
+        for log_time in [
+            0,
+            SECOND / 10,
+            2 * SECOND / 10,
+            3 * SECOND,
+            3 * SECOND + SECOND / 10,
+        ] {
+            channel.record(log_time, 10, Duration::from_secs(1));
+        }
+        assert_eq!(channel.messages, 5);
+        assert_eq!(channel.bytes, 50);
+        assert_eq!(
+            channel.duration(),
+            Duration::from_nanos(3 * SECOND + SECOND / 10)
+        );
+        assert_eq!(
+            channel.gaps,
+            vec![Gap {
+                start: 2 * SECOND / 10,
+                duration: Duration::from_nanos(3 * SECOND - 2 * SECOND / 10),
+            }]
+        );
+        assert!((channel.rate().unwrap() - 4.0 / 3.1).abs() < 1e-9);
+    }
+
+    #[test]
+    fn single_message_has_no_rate() {
+        let mut channel = ChannelSummary::default();
+        channel.record(SECOND, 10, Duration::from_secs(1));
+        assert_eq!(channel.rate(), None);
+        assert!(channel.gaps.is_empty());
+    }
+}
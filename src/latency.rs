@@ -0,0 +1,233 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use prost::Message;
+use prost_types::Timestamp;
+use tracing::{error, warn};
+use zenoh::{prelude::r#async::*, Session};
+
+use crate::{rplidar, system_time_to_proto_time, ErrorWrapper};
+
+pub const LATENCY_TOPIC: &str = "latency";
+pub const PROBE_TOPIC: &str = "latency/probe";
+pub const ECHO_TOPIC: &str = "latency/echo";
+const LATENCY_INTERVAL: Duration = Duration::from_secs(5);
+const PROBE_INTERVAL: Duration = Duration::from_secs(1);
+/// Probes without an echo after this many newer probes are forgotten
+const MAX_PENDING_PROBES: usize = 32;
+
+pub const CAPTURE_TO_PUBLISH: &str = "capture_to_publish";
+pub const CAPTURE_TO_RECEIVE: &str = "capture_to_receive";
+pub const CAPTURE_TO_SEND: &str = "capture_to_send";
+
+/// Prefix of any foxglove message that starts with a timestamp
+///
+/// LaserScan and PointCloud both carry capture time in field 1,
+/// the remaining fields are skipped while decoding
+#[derive(Clone, PartialEq, prost::Message)]
+struct TimestampedMessage {
+    #[prost(message, optional, tag = "1")]
+    timestamp: Option<Timestamp>,
+}
+
+/// Time elapsed since the capture timestamp embedded in an encoded message
+///
+/// Relies on clocks being synchronized between hosts. Returns `None` when the
+/// message has no timestamp or the capture time is in the future
+pub fn capture_latency(payload: &[u8]) -> Option<Duration> {
+    let timestamp = TimestampedMessage::decode(payload).ok()?.timestamp?;
+    let capture_time = UNIX_EPOCH
+        + Duration::new(
+            u64::try_from(timestamp.seconds).ok()?,
+            u32::try_from(timestamp.nanos).ok()?,
+        );
+    SystemTime::now().duration_since(capture_time).ok()
+}
+
+#[derive(Default)]
+struct LatencyWindow {
+    count: u64,
+    total: Duration,
+    min: Duration,
+    max: Duration,
+}
+
+impl LatencyWindow {
+    fn record(&mut self, latency: Duration) {
+        if self.count == 0 || latency < self.min {
+            self.min = latency;
+        }
+        self.max = self.max.max(latency);
+        self.total += latency;
+        self.count += 1;
+    }
+}
+
+/// Collects latency samples per hop
+#[derive(Clone, Default)]
+pub struct LatencyTracker {
+    windows: Arc<Mutex<BTreeMap<String, LatencyWindow>>>,
+}
+
+impl LatencyTracker {
+    pub fn record(&self, hop: &str, latency: Duration) {
+        self.windows
+            .lock()
+            .unwrap()
+            .entry(hop.to_owned())
+            .or_default()
+            .record(latency);
+    }
+
+    /// Summaries of all hops since the previous call
+    pub(crate) fn take_summaries(&self, component: &str) -> Vec<rplidar::Latency> {
+        let windows = std::mem::take(&mut *self.windows.lock().unwrap());
+        let now = SystemTime::now();
+        windows
+            .into_iter()
+            .filter(|(_, window)| window.count > 0)
+            .map(|(hop, window)| rplidar::Latency {
+                timestamp: Some(system_time_to_proto_time(&now)),
+                component: component.to_owned(),
+                hop,
+                count: window.count,
+                min_ms: window.min.as_secs_f64() * 1000.0,
+                mean_ms: window.total.as_secs_f64() * 1000.0 / window.count as f64,
+                max_ms: window.max.as_secs_f64() * 1000.0,
+            })
+            .collect()
+    }
+}
+
+/// Periodically publish latency summaries collected by `tracker`
+pub async fn start_latency_publisher(
+    zenoh_session: Arc<Session>,
+    topic: String,
+    component: &str,
+    tracker: LatencyTracker,
+) -> anyhow::Result<()> {
+    let publisher = zenoh_session
+        .declare_publisher(topic)
+        .res()
+        .await
+        .map_err(ErrorWrapper::ZenohError)?;
+
+    let component = component.to_owned();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(LATENCY_INTERVAL);
+        loop {
+            interval.tick().await;
+            for summary in tracker.take_summaries(&component) {
+                if let Err(err) = publisher.put(summary.encode_to_vec()).res().await {
+                    error!(?err, "Failed to publish latency");
+                }
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Echo latency probes back to the prober on `<echo_topic>/<component>`
+pub async fn start_probe_echo(
+    zenoh_session: Arc<Session>,
+    probe_topic: String,
+    echo_topic: String,
+    component: &str,
+) -> anyhow::Result<()> {
+    let subscriber = zenoh_session
+        .declare_subscriber(probe_topic)
+        .res()
+        .await
+        .map_err(ErrorWrapper::ZenohError)?;
+    let publisher = zenoh_session
+        .declare_publisher(format!("{}/{}", echo_topic, component))
+        .res()
+        .await
+        .map_err(ErrorWrapper::ZenohError)?;
+
+    tokio::spawn(async move {
+        while let Ok(sample) = subscriber.recv_async().await {
+            if let Err(err) = publisher.put(sample.value).res().await {
+                error!(?err, "Failed to echo latency probe");
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Send numbered probes and record round trip time of echoes per responder
+///
+/// Round trip time doesn't depend on clock synchronization between hosts
+pub async fn start_probe_sender(
+    zenoh_session: Arc<Session>,
+    probe_topic: String,
+    echo_topic: String,
+    tracker: LatencyTracker,
+) -> anyhow::Result<()> {
+    let publisher = zenoh_session
+        .declare_publisher(probe_topic)
+        .res()
+        .await
+        .map_err(ErrorWrapper::ZenohError)?;
+    let echo_prefix = format!("{}/", echo_topic);
+    let subscriber = zenoh_session
+        .declare_subscriber(format!("{}/*", echo_topic))
+        .res()
+        .await
+        .map_err(ErrorWrapper::ZenohError)?;
+
+    let pending = Arc::new(Mutex::new(VecDeque::<(u64, Instant)>::new()));
+
+    tokio::spawn({
+        let pending = pending.clone();
+        async move {
+            let mut interval = tokio::time::interval(PROBE_INTERVAL);
+            let mut sequence: u64 = 0;
+            loop {
+                interval.tick().await;
+                sequence += 1;
+                {
+                    let mut pending = pending.lock().unwrap();
+                    pending.push_back((sequence, Instant::now()));
+                    while pending.len() > MAX_PENDING_PROBES {
+                        pending.pop_front();
+                    }
+                }
+                if let Err(err) = publisher.put(sequence.to_le_bytes().to_vec()).res().await {
+                    error!(?err, "Failed to publish latency probe");
+                }
+            }
+        }
+    });
+
+    tokio::spawn(async move {
+        while let Ok(sample) = subscriber.recv_async().await {
+            let Ok(payload) = TryInto::<Vec<u8>>::try_into(&sample.value) else {
+                continue;
+            };
+            let Ok(sequence) = <[u8; 8]>::try_from(payload.as_slice()) else {
+                warn!(key_expr = %sample.key_expr, "Malformed latency probe echo");
+                continue;
+            };
+            let sequence = u64::from_le_bytes(sequence);
+            let sent = pending
+                .lock()
+                .unwrap()
+                .iter()
+                .find(|(pending_sequence, _)| *pending_sequence == sequence)
+                .map(|(_, sent)| *sent);
+            if let Some(sent) = sent {
+                let responder = sample
+                    .key_expr
+                    .as_str()
+                    .strip_prefix(&echo_prefix)
+                    .unwrap_or("unknown");
+                tracker.record(&format!("round_trip/{}", responder), sent.elapsed());
+            }
+        }
+    });
+    Ok(())
+}
@@ -1,24 +1,11 @@
 use std::time::{SystemTime, UNIX_EPOCH};
 
-use anyhow::{Context, Result};
+use anyhow::Result;
 use once_cell::sync::Lazy;
 use prost_reflect::DescriptorPool;
 use prost_types::Timestamp;
-use tracing::{dispatcher, Dispatch};
-use tracing_subscriber::{prelude::__tracing_subscriber_SubscriberExt, EnvFilter, Registry};
-
-pub fn setup_tracing() -> anyhow::Result<()> {
-    let filter = EnvFilter::builder()
-        .with_default_directive(tracing_subscriber::filter::LevelFilter::INFO.into())
-        .parse("")?;
-
-    let subscriber = Registry::default()
-        .with(filter)
-        .with(tracing_logfmt::layer());
-    dispatcher::set_global_default(Dispatch::new(subscriber))
-        .context("Global logger has already been set!")?;
-    Ok(())
-}
+
+pub use logging::{setup_tracing, setup_tracing_with_args, TracingArgs};
 
 static FILE_DESCRIPTOR_SET: &[u8] =
     include_bytes!(concat!(env!("OUT_DIR"), "/file_descriptor_set.bin"));
@@ -27,18 +14,79 @@ static DESCRIPTOR_POOL: Lazy<DescriptorPool> = Lazy::new(|| {
     DescriptorPool::decode(FILE_DESCRIPTOR_SET).expect("Failed to load file descriptor set")
 });
 
+pub mod access_control;
+pub mod angle_stats;
+pub mod arbitration;
+pub mod assets;
+pub mod compression;
+pub mod config;
+pub mod daemon;
+pub mod device;
+pub mod diagnostics;
+pub mod encoding;
+pub mod events;
+pub mod failure;
+pub mod fusion;
+pub mod inspect;
+pub mod latency;
+pub mod liveliness;
+pub mod log_level;
+pub mod logging;
+pub mod map_deviation;
+pub mod mcap_options;
+pub mod monitoring;
+pub mod peers;
+pub mod people;
+#[cfg(feature = "nalgebra")]
+pub mod pose;
+pub mod power_saving;
+pub mod process_stats;
+pub mod profiling;
+pub mod progress;
+pub mod projection;
+pub mod qos;
+pub mod recovery;
+pub mod reflectors;
+pub mod remote;
+pub mod ros;
+pub mod rosbridge;
+pub mod runtime;
+pub mod scan_broadcast;
+pub mod scan_publisher;
+pub mod scan_queue;
+pub mod scan_rate;
+pub mod schedule;
+pub mod schema;
+pub mod sector_quality;
+pub mod snapshot;
+pub mod streams;
+pub mod stuck_scan;
+pub mod subscribers;
+pub mod systemd;
+pub mod temporal_filter;
+pub mod timestamping;
+pub mod topics;
+pub mod upload;
+pub mod zones;
+
 /// protobuf
 pub mod foxglove {
     #![allow(non_snake_case)]
     include!(concat!(env!("OUT_DIR"), "/foxglove.rs"));
 }
 
+/// protobuf messages specific to this driver
+pub mod rplidar {
+    include!(concat!(env!("OUT_DIR"), "/rplidar.rs"));
+}
+
 #[derive(thiserror::Error, Debug)]
 pub enum ErrorWrapper {
     #[error("Zenoh error {0:?}")]
     ZenohError(#[from] zenoh::Error),
 }
 
+#[cfg_attr(feature = "serde-types", derive(serde::Serialize, serde::Deserialize))]
 pub struct RpLidarProjectedPoint {
     pub x: f32,
     pub y: f32,
@@ -70,10 +118,24 @@ impl RpLidarProjectedPoint {
         result
     }
 
-    pub fn from_foxglove_point_cloud(point_cloud: &foxglove::PointCloud) -> Result<Vec<Self>> {
-        let (point_stride, point_cloud_fields) = rp_lidar_projected_point_descriptor();
+    /// Check that a point cloud uses the packed layout of this type
+    pub fn check_point_cloud_layout(point_cloud: &foxglove::PointCloud) -> Result<()> {
+        if point_cloud.point_stride != RP_LIDAR_PROJECTED_POINT_STRIDE {
+            anyhow::bail!(
+                "point_stride {} doesn't match expected {}",
+                point_cloud.point_stride,
+                RP_LIDAR_PROJECTED_POINT_STRIDE
+            );
+        }
+        if point_cloud.fields != rp_lidar_projected_point_fields() {
+            anyhow::bail!("point cloud fields don't match rplidar projected point layout");
+        }
+        Ok(())
+    }
 
-        assert_eq!(point_cloud.fields, point_cloud_fields);
+    pub fn from_foxglove_point_cloud(point_cloud: &foxglove::PointCloud) -> Result<Vec<Self>> {
+        Self::check_point_cloud_layout(point_cloud)?;
+        let point_stride = RP_LIDAR_PROJECTED_POINT_STRIDE;
 
         let data = &point_cloud.data;
 
@@ -82,9 +144,6 @@ impl RpLidarProjectedPoint {
         let mut parsed_point_cloud = Vec::with_capacity(expected_len as usize);
 
         for chunk in data.chunks_exact(point_stride as usize) {
-            if chunk.len() != point_stride as usize {
-                anyhow::bail!("chunk size doesn't equal point_stride");
-            }
             let x = f32::from_le_bytes(chunk[0..4].try_into()?);
             let y = f32::from_le_bytes(chunk[4..8].try_into()?);
             let distance = f32::from_le_bytes(chunk[8..12].try_into()?);
@@ -99,10 +158,12 @@ impl RpLidarProjectedPoint {
     }
 }
 
-pub fn rp_lidar_projected_point_descriptor() -> (u32, Vec<foxglove::PackedElementField>) {
-    //                      x   y   dis ang quality
-    let point_stride = 4 + 4 + 4 + 4 + 1;
-    let point_cloud_fields = vec![
+/// Size in bytes of a packed [`RpLidarProjectedPoint`]
+//                                               x   y   dis ang quality
+pub const RP_LIDAR_PROJECTED_POINT_STRIDE: u32 = 4 + 4 + 4 + 4 + 1;
+
+static RP_LIDAR_PROJECTED_POINT_FIELDS: Lazy<Vec<foxglove::PackedElementField>> = Lazy::new(|| {
+    vec![
         foxglove::PackedElementField {
             name: "x".to_string(),
             offset: 0,
@@ -128,36 +189,69 @@ pub fn rp_lidar_projected_point_descriptor() -> (u32, Vec<foxglove::PackedElemen
             offset: 16,
             r#type: foxglove::packed_element_field::NumericType::Uint8 as i32,
         },
-    ];
+    ]
+});
+
+/// Packed fields of [`RpLidarProjectedPoint`], built once
+pub fn rp_lidar_projected_point_fields() -> &'static [foxglove::PackedElementField] {
+    &RP_LIDAR_PROJECTED_POINT_FIELDS
+}
 
-    (point_stride, point_cloud_fields)
+/// Point stride and an owned copy of the packed fields
+///
+/// Prefer [`rp_lidar_projected_point_fields`] when a reference is enough
+pub fn rp_lidar_projected_point_descriptor() -> (u32, Vec<foxglove::PackedElementField>) {
+    (
+        RP_LIDAR_PROJECTED_POINT_STRIDE,
+        rp_lidar_projected_point_fields().to_vec(),
+    )
 }
 
+/// New point cloud message holding `points`
+///
+/// Copies the field descriptors into the message, use
+/// [`fill_rp_lidar_projected_point_cloud`] with a reused message when publishing every scan
 pub fn rp_lidar_projected_points_to_foxglove_point_cloud(
     timestamp: &SystemTime,
     frame_id: &str,
     pose: &foxglove::Pose,
     points: &[RpLidarProjectedPoint],
 ) -> foxglove::PointCloud {
-    let (point_stride, point_cloud_fields) = rp_lidar_projected_point_descriptor();
-
-    foxglove::PointCloud {
-        timestamp: Some(system_time_to_proto_time(timestamp)),
-        frame_id: frame_id.to_owned(),
-        pose: Some(*pose),
-        point_stride,
-        fields: point_cloud_fields.clone(),
-        data: points
-            .iter()
-            .flat_map(|point| point.to_foxglove_blob())
-            .collect(),
+    let mut point_cloud = foxglove::PointCloud::default();
+    fill_rp_lidar_projected_point_cloud(&mut point_cloud, timestamp, frame_id, pose, points);
+    point_cloud
+}
+
+/// Replace the contents of a reused point cloud message with `points`
+///
+/// Field descriptors are only copied in when the message doesn't have them yet and the
+/// data buffer keeps its allocation
+pub fn fill_rp_lidar_projected_point_cloud(
+    point_cloud: &mut foxglove::PointCloud,
+    timestamp: &SystemTime,
+    frame_id: &str,
+    pose: &foxglove::Pose,
+    points: &[RpLidarProjectedPoint],
+) {
+    if point_cloud.fields != rp_lidar_projected_point_fields() {
+        point_cloud.fields = rp_lidar_projected_point_fields().to_vec();
+    }
+    point_cloud.point_stride = RP_LIDAR_PROJECTED_POINT_STRIDE;
+    point_cloud.timestamp = Some(system_time_to_proto_time(timestamp));
+    point_cloud.frame_id.clear();
+    point_cloud.frame_id.push_str(frame_id);
+    point_cloud.pose = Some(*pose);
+    point_cloud.data.clear();
+    for point in points {
+        point_cloud
+            .data
+            .extend_from_slice(&point.to_foxglove_blob());
     }
 }
 
+/// Times before the unix epoch, e.g. from a clock that isn't set yet, are clamped to the epoch
 pub fn system_time_to_proto_time(time: &SystemTime) -> Timestamp {
-    let duration = time
-        .duration_since(UNIX_EPOCH)
-        .expect("Time went backwards");
+    let duration = time.duration_since(UNIX_EPOCH).unwrap_or_default();
     Timestamp {
         seconds: duration.as_secs() as i64,
         nanos: duration.subsec_nanos() as i32,
@@ -0,0 +1,25 @@
+use std::sync::Arc;
+
+use zenoh::{liveliness::LivelinessToken, prelude::r#async::*, Session};
+
+use crate::ErrorWrapper;
+
+/// Liveliness token held by the driver while it runs
+pub const DRIVER_ALIVE_TOPIC: &str = "alive";
+
+/// Declare a liveliness token on `topic`
+///
+/// zenoh drops the token when the session closes or the process dies so other
+/// processes can tell whether the driver is still running
+pub async fn declare_alive_token(
+    zenoh_session: &Arc<Session>,
+    topic: String,
+) -> anyhow::Result<LivelinessToken<'static>> {
+    let token = zenoh_session
+        .liveliness()
+        .declare_token(topic)
+        .res()
+        .await
+        .map_err(ErrorWrapper::ZenohError)?;
+    Ok(token)
+}
@@ -0,0 +1,112 @@
+use std::{sync::Arc, time::Duration};
+
+use anyhow::Context;
+use serde::Deserialize;
+use tracing::{error, info, warn};
+use zenoh::{prelude::r#async::*, Session};
+
+use crate::{logging::set_log_filter, ErrorWrapper};
+
+/// [`LogLevelRequest`]s in JSON received on `<prefix>/log_level`
+pub const LOG_LEVEL_TOPIC: &str = "log_level";
+
+/// Change of the log filter
+///
+/// `{"filter": "debug", "duration_s": 300}` logs at debug for five minutes,
+/// `{}` restores the default right away
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LogLevelRequest {
+    /// Directives in `RUST_LOG` syntax, the default filter when missing
+    #[serde(default)]
+    pub filter: Option<String>,
+    /// Seconds after which the default filter is restored, kept until the next request
+    /// when missing
+    #[serde(default)]
+    pub duration_s: Option<f64>,
+}
+
+impl LogLevelRequest {
+    pub fn parse(payload: &[u8]) -> anyhow::Result<Self> {
+        let request: LogLevelRequest =
+            serde_json::from_slice(payload).context("Log level request isn't valid JSON")?;
+        if let Some(duration_s) = request.duration_s {
+            Duration::try_from_secs_f64(duration_s)
+                .with_context(|| format!("Invalid duration {}", duration_s))?;
+        }
+        Ok(request)
+    }
+
+    pub fn duration(&self) -> Option<Duration> {
+        self.duration_s
+            .and_then(|s| Duration::try_from_secs_f64(s).ok())
+    }
+}
+
+/// Apply log filter changes received on `topic`
+///
+/// A new request replaces the previous one including its pending restore
+pub async fn start_log_level_subscriber(
+    zenoh_session: Arc<Session>,
+    topic: String,
+) -> anyhow::Result<()> {
+    let subscriber = zenoh_session
+        .declare_subscriber(&topic)
+        .res()
+        .await
+        .map_err(ErrorWrapper::ZenohError)?;
+    info!(topic, "Listening for log level changes");
+
+    tokio::spawn(async move {
+        let mut restore: Option<tokio::task::JoinHandle<()>> = None;
+        while let Ok(sample) = subscriber.recv_async().await {
+            let payload = sample.value.payload.contiguous();
+            let request = match LogLevelRequest::parse(&payload) {
+                Ok(request) => request,
+                Err(err) => {
+                    warn!("Rejected log level request: {:?}", err);
+                    continue;
+                }
+            };
+            if let Err(err) = set_log_filter(request.filter.as_deref()) {
+                warn!("Rejected log level request: {:?}", err);
+                continue;
+            }
+            if let Some(restore) = restore.take() {
+                restore.abort();
+            }
+            let duration = request.duration();
+            info!(filter = ?request.filter, ?duration, "Changed log filter");
+            restore = duration.map(|duration| {
+                tokio::spawn(async move {
+                    tokio::time::sleep(duration).await;
+                    match set_log_filter(None) {
+                        Ok(()) => info!("Restored default log filter"),
+                        Err(err) => error!(?err, "Failed to restore default log filter"),
+                    }
+                })
+            });
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_requests() {
+        let request = LogLevelRequest::parse(br#"{"filter": "debug", "duration_s": 300}"#).unwrap();
+        assert_eq!(request.filter.as_deref(), Some("debug"));
+        assert_eq!(request.duration(), Some(Duration::from_secs(300)));
+
+        let reset = LogLevelRequest::parse(b"{}").unwrap();
+        assert_eq!(reset.filter, None);
+        assert_eq!(reset.duration(), None);
+
+        assert!(LogLevelRequest::parse(br#"{"duration_s": -1}"#).is_err());
+        assert!(LogLevelRequest::parse(br#"{"level": "debug"}"#).is_err());
+        assert!(LogLevelRequest::parse(b"debug").is_err());
+    }
+}
@@ -0,0 +1,190 @@
+use std::{
+    fs::OpenOptions,
+    path::PathBuf,
+    sync::{Mutex, OnceLock},
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result};
+use tracing::{dispatcher, info, warn, Dispatch};
+use tracing_subscriber::{
+    prelude::__tracing_subscriber_SubscriberExt, reload, EnvFilter, Registry,
+};
+
+/// Handle of the filter installed by [`setup_tracing_with_args`]
+static FILTER_HANDLE: OnceLock<reload::Handle<EnvFilter, Registry>> = OnceLock::new();
+
+/// Tracing export options shared by all binaries
+#[derive(clap::Args, Debug, Clone, Default)]
+pub struct TracingArgs {
+    /// Export spans to this OTLP gRPC endpoint (e.g. http://localhost:4317)
+    ///
+    /// Requires the otel feature
+    #[clap(long)]
+    pub otlp_endpoint: Option<String>,
+
+    /// Append logs to this file instead of writing them to stdout
+    #[clap(long)]
+    pub log_file: Option<PathBuf>,
+}
+
+pub fn setup_tracing() -> Result<()> {
+    setup_tracing_with_args(env!("CARGO_PKG_NAME"), &TracingArgs::default())
+}
+
+fn default_filter() -> Result<EnvFilter> {
+    Ok(EnvFilter::builder()
+        .with_default_directive(tracing_subscriber::filter::LevelFilter::INFO.into())
+        .parse("")?)
+}
+
+/// Replace the log filter at runtime, `None` restores the default
+///
+/// Directives use the `RUST_LOG` syntax, e.g. `debug` or `info,rplidar_zenoh_driver=trace`
+pub fn set_log_filter(directives: Option<&str>) -> Result<()> {
+    let filter = match directives {
+        Some(directives) => EnvFilter::builder()
+            .parse(directives)
+            .with_context(|| format!("Invalid log filter {:?}", directives))?,
+        None => default_filter()?,
+    };
+    FILTER_HANDLE
+        .get()
+        .context("Tracing isn't set up")?
+        .reload(filter)
+        .context("Failed to replace log filter")
+}
+
+pub fn setup_tracing_with_args(service_name: &str, args: &TracingArgs) -> Result<()> {
+    let (filter, filter_handle) = reload::Layer::new(default_filter()?);
+
+    let (stdout_layer, file_layer) = match &args.log_file {
+        Some(path) => {
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .with_context(|| format!("Failed to open log file {:?}", path))?;
+            (
+                None,
+                Some(tracing_logfmt::builder().layer_with_writer(Mutex::new(file))),
+            )
+        }
+        None => (Some(tracing_logfmt::layer()), None),
+    };
+    let subscriber = Registry::default()
+        .with(filter)
+        .with(stdout_layer)
+        .with(file_layer);
+
+    #[cfg(feature = "otel")]
+    let subscriber = subscriber.with(otlp_layer(service_name, args)?);
+
+    dispatcher::set_global_default(Dispatch::new(subscriber))
+        .context("Global logger has already been set!")?;
+    let _ = FILTER_HANDLE.set(filter_handle);
+
+    if cfg!(not(feature = "otel")) && args.otlp_endpoint.is_some() {
+        warn!(
+            service_name,
+            "Built without otel feature, OTLP export is disabled"
+        );
+    }
+    Ok(())
+}
+
+#[cfg(feature = "otel")]
+fn otlp_layer<S>(
+    service_name: &str,
+    args: &TracingArgs,
+) -> Result<Option<tracing_opentelemetry::OpenTelemetryLayer<S, opentelemetry_sdk::trace::Tracer>>>
+where
+    S: tracing::Subscriber + for<'span> tracing_subscriber::registry::LookupSpan<'span>,
+{
+    use opentelemetry_otlp::WithExportConfig;
+
+    let Some(endpoint) = &args.otlp_endpoint else {
+        return Ok(None);
+    };
+
+    let tracer = opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(
+            opentelemetry_otlp::new_exporter()
+                .tonic()
+                .with_endpoint(endpoint),
+        )
+        .with_trace_config(opentelemetry_sdk::trace::config().with_resource(
+            opentelemetry_sdk::Resource::new(vec![opentelemetry::KeyValue::new(
+                "service.name",
+                service_name.to_owned(),
+            )]),
+        ))
+        .install_batch(opentelemetry_sdk::runtime::Tokio)
+        .context("Failed to install OTLP exporter")?;
+
+    Ok(Some(tracing_opentelemetry::layer().with_tracer(tracer)))
+}
+
+const REPEAT_SUMMARY_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Rolls identical repeated messages into periodic "repeated N times" summaries
+///
+/// Meant for error paths in tight loops, such as a disconnected serial port
+pub struct RepeatThrottle {
+    last_message: Option<String>,
+    suppressed: u64,
+    last_logged: Instant,
+    interval: Duration,
+}
+
+impl Default for RepeatThrottle {
+    fn default() -> Self {
+        Self::new(REPEAT_SUMMARY_INTERVAL)
+    }
+}
+
+impl RepeatThrottle {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            last_message: None,
+            suppressed: 0,
+            last_logged: Instant::now(),
+            interval,
+        }
+    }
+
+    /// Returns number of suppressed repeats if `message` should be logged now
+    ///
+    /// New messages are always logged, repeats at most once per interval
+    pub fn check(&mut self, message: &str) -> Option<u64> {
+        if self.last_message.as_deref() != Some(message) {
+            self.flush();
+            self.last_message = Some(message.to_owned());
+            self.last_logged = Instant::now();
+            return Some(0);
+        }
+        if self.last_logged.elapsed() >= self.interval {
+            let suppressed = std::mem::take(&mut self.suppressed);
+            self.last_logged = Instant::now();
+            return Some(suppressed);
+        }
+        self.suppressed += 1;
+        None
+    }
+
+    /// Log summary of pending repeats and forget the last message
+    ///
+    /// Call once the condition causing the repeats has cleared
+    pub fn flush(&mut self) {
+        if let Some(message) = self.last_message.take() {
+            if self.suppressed > 0 {
+                info!(
+                    repeated = self.suppressed,
+                    "Message repeated {} times: {}", self.suppressed, message
+                );
+            }
+        }
+        self.suppressed = 0;
+    }
+}
@@ -0,0 +1,294 @@
+use std::{
+    f32::consts::TAU,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::Context;
+use prost::Message;
+use serde::{Deserialize, Serialize};
+use tracing::{error, info};
+use zenoh::{prelude::r#async::*, publication::CongestionControl, Session};
+
+use crate::{
+    fill_rp_lidar_projected_point_cloud, foxglove, rplidar, scan_broadcast::ScanBroadcast,
+    system_time_to_proto_time, ErrorWrapper, RpLidarProjectedPoint,
+};
+
+pub const MAP_DEVIATION_TOPIC: &str = "map_deviation";
+pub const MAP_DEVIATION_POINTS_TOPIC: &str = "map_deviation/points";
+
+/// Monitoring of a static space against a reference map
+///
+/// The map is the expected range in every angular bin. When the file doesn't exist it's
+/// learned from the first scans and saved, delete it to capture a new reference
+#[derive(clap::Args, Debug, Clone)]
+pub struct MapDeviationArgs {
+    /// Reference map file, enables publishing on <prefix>/map_deviation
+    #[clap(long)]
+    pub reference_map: Option<PathBuf>,
+
+    /// Angular bins of a newly learned reference map
+    #[clap(long, default_value_t = 720, value_parser = clap::value_parser!(u32).range(1..=36000))]
+    pub reference_map_bins: u32,
+
+    /// Scans used to learn a new reference map
+    #[clap(long, default_value_t = 50)]
+    pub reference_map_scans: usize,
+
+    /// Points further than this many meters from the reference range deviate
+    #[clap(long, default_value_t = 0.15)]
+    pub map_deviation_tolerance: f32,
+}
+
+/// Expected range per angular bin, 0 where the reference saw nothing
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReferenceMap {
+    ranges: Vec<f32>,
+}
+
+impl ReferenceMap {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read reference map {:?}", path))?;
+        let map: ReferenceMap = serde_json::from_str(&text)
+            .with_context(|| format!("Failed to parse reference map {:?}", path))?;
+        if map.ranges.is_empty() {
+            anyhow::bail!("Reference map {:?} has no bins", path);
+        }
+        Ok(map)
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        std::fs::write(path, serde_json::to_vec(self)?)
+            .with_context(|| format!("Failed to write reference map {:?}", path))
+    }
+
+    fn bin(&self, angle: f32) -> usize {
+        let bins = self.ranges.len();
+        ((angle.rem_euclid(TAU) / TAU * bins as f32) as usize).min(bins - 1)
+    }
+
+    /// Points of a scan further than `tolerance` from the reference
+    ///
+    /// Points are `(angle, distance)` in radians and meters, invalid points are skipped
+    pub fn deviations(
+        &self,
+        points: impl Iterator<Item = (f32, f32)>,
+        tolerance: f32,
+    ) -> (u32, Vec<(f32, f32)>) {
+        let mut compared = 0;
+        let mut deviating = vec![];
+        for (angle, distance) in points.filter(|(_, distance)| *distance > 0.0) {
+            compared += 1;
+            let reference = self.ranges[self.bin(angle)];
+            if reference <= 0.0 || (distance - reference).abs() > tolerance {
+                deviating.push((angle, distance));
+            }
+        }
+        (compared, deviating)
+    }
+}
+
+/// Collects ranges per bin and takes the median of each
+pub struct ReferenceMapBuilder {
+    samples: Vec<Vec<f32>>,
+    scans: usize,
+}
+
+impl ReferenceMapBuilder {
+    pub fn new(bins: usize) -> Self {
+        Self {
+            samples: vec![vec![]; bins],
+            scans: 0,
+        }
+    }
+
+    pub fn scans(&self) -> usize {
+        self.scans
+    }
+
+    pub fn add(&mut self, points: impl Iterator<Item = (f32, f32)>) {
+        let bins = self.samples.len();
+        for (angle, distance) in points.filter(|(_, distance)| *distance > 0.0) {
+            let bin = ((angle.rem_euclid(TAU) / TAU * bins as f32) as usize).min(bins - 1);
+            self.samples[bin].push(distance);
+        }
+        self.scans += 1;
+    }
+
+    pub fn build(self) -> ReferenceMap {
+        let ranges = self
+            .samples
+            .into_iter()
+            .map(|mut samples| {
+                if samples.is_empty() {
+                    return 0.0;
+                }
+                samples.sort_by(|a, b| a.total_cmp(b));
+                samples[samples.len() / 2]
+            })
+            .collect();
+        ReferenceMap { ranges }
+    }
+}
+
+/// Compare every scan from `scan_broadcast` with the reference map
+///
+/// Publishes the score on `topic` and the deviating points as a point cloud on `points_topic`
+pub async fn start_map_deviation_monitor(
+    zenoh_session: Arc<Session>,
+    topic: String,
+    points_topic: String,
+    args: MapDeviationArgs,
+    scan_broadcast: ScanBroadcast,
+) -> anyhow::Result<()> {
+    let Some(path) = args.reference_map.clone() else {
+        return Ok(());
+    };
+    let mut reference = if path.exists() {
+        let reference = ReferenceMap::load(&path)?;
+        info!(?path, bins = reference.ranges.len(), "Loaded reference map");
+        Some(reference)
+    } else {
+        info!(
+            ?path,
+            scans = args.reference_map_scans,
+            "Reference map not found, learning it from the next scans"
+        );
+        None
+    };
+    let publisher = zenoh_session
+        .declare_publisher(topic.clone())
+        .congestion_control(CongestionControl::Drop)
+        .res()
+        .await
+        .map_err(ErrorWrapper::ZenohError)?;
+    let points_publisher = zenoh_session
+        .declare_publisher(points_topic.clone())
+        .congestion_control(CongestionControl::Drop)
+        .res()
+        .await
+        .map_err(ErrorWrapper::ZenohError)?;
+    info!(topic, points_topic, "Publishing map deviation");
+
+    let mut builder = ReferenceMapBuilder::new(args.reference_map_bins as usize);
+    let mut scan_subscriber = scan_broadcast.subscribe("map_deviation");
+    tokio::spawn(async move {
+        let mut point_cloud = foxglove::PointCloud::default();
+        while let Some(frame) = scan_subscriber.recv().await {
+            let points = frame
+                .points
+                .iter()
+                .filter(|point| point.is_valid())
+                .map(|point| (point.angle(), point.distance()));
+            let Some(reference) = &reference else {
+                builder.add(points);
+                if builder.scans() >= args.reference_map_scans {
+                    let learned = std::mem::replace(
+                        &mut builder,
+                        ReferenceMapBuilder::new(args.reference_map_bins as usize),
+                    )
+                    .build();
+                    match learned.save(&path) {
+                        Ok(()) => info!(?path, "Saved reference map"),
+                        Err(err) => error!(?err, "Failed to save reference map"),
+                    }
+                    reference = Some(learned);
+                }
+                continue;
+            };
+
+            let (compared, deviating) = reference.deviations(points, args.map_deviation_tolerance);
+            let deviation = rplidar::MapDeviation {
+                timestamp: Some(system_time_to_proto_time(&frame.capture_time)),
+                frame_id: frame.frame_id.clone(),
+                score: if compared == 0 {
+                    0.0
+                } else {
+                    deviating.len() as f64 / compared as f64
+                },
+                compared_points: compared,
+                deviating_points: deviating.len() as u32,
+            };
+            if let Err(err) = publisher.put(deviation.encode_to_vec()).res().await {
+                error!(?err, "Failed to publish map deviation");
+            }
+
+            // same projection as the published point clouds
+            let deviating = deviating
+                .into_iter()
+                .map(|(angle, distance)| {
+                    RpLidarProjectedPoint::new(
+                        distance * (-angle).cos(),
+                        distance * (-angle).sin(),
+                        distance,
+                        angle,
+                        0,
+                    )
+                })
+                .collect::<Vec<_>>();
+            fill_rp_lidar_projected_point_cloud(
+                &mut point_cloud,
+                &frame.capture_time,
+                &frame.frame_id,
+                &frame.pose,
+                &deviating,
+            );
+            if let Err(err) = points_publisher
+                .put(point_cloud.encode_to_vec())
+                .res()
+                .await
+            {
+                error!(?err, "Failed to publish map deviation points");
+            }
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn room(distance: f32) -> impl Iterator<Item = (f32, f32)> {
+        (0..360).map(move |step| ((step as f32).to_radians(), distance))
+    }
+
+    #[test]
+    fn learns_median_ranges() {
+        let mut builder = ReferenceMapBuilder::new(4);
+        builder.add(room(2.0));
+        builder.add(room(2.1));
+        // a person walking through doesn't end up in the map
+        builder.add(
+            room(2.0).map(|(angle, distance)| (angle, if angle < 1.0 { 0.5 } else { distance })),
+        );
+        assert_eq!(builder.scans(), 3);
+        let map = builder.build();
+        assert_eq!(map.ranges.len(), 4);
+        assert!(map.ranges.iter().all(|range| (*range - 2.0).abs() < 0.11));
+    }
+
+    #[test]
+    fn finds_moved_objects() {
+        let map = ReferenceMap {
+            ranges: vec![2.0, 2.0, 0.0, 2.0],
+        };
+        let (compared, deviating) = map.deviations(
+            [
+                (0.1, 2.05),
+                // pallet placed in front of the wall
+                (1.7, 1.0),
+                // something appeared where the reference saw nothing
+                (3.5, 3.0),
+                (5.0, 2.0),
+                (5.5, 0.0),
+            ]
+            .into_iter(),
+            0.15,
+        );
+        assert_eq!(compared, 4);
+        assert_eq!(deviating, vec![(1.7, 1.0), (3.5, 3.0)]);
+    }
+}
@@ -0,0 +1,45 @@
+use mcap::WriteOptions;
+
+use crate::compression::Compression;
+
+/// Default chunk size of the mcap writer
+const DEFAULT_CHUNK_SIZE: u64 = 768 * 1024;
+
+/// Layout of written mcap files
+///
+/// Smaller uncompressed files without indexes are cheaper to write on weak hardware
+/// but slower to seek in and some tools need the summary to open them
+#[derive(clap::Args, Debug, Clone, Copy)]
+pub struct McapWriteArgs {
+    /// Compression of mcap chunks
+    #[clap(long, value_enum, default_value_t = Compression::Zstd)]
+    pub mcap_compression: Compression,
+
+    /// Target size of mcap chunks in bytes, 0 writes messages without chunks
+    #[clap(long, default_value_t = DEFAULT_CHUNK_SIZE)]
+    pub mcap_chunk_size: u64,
+
+    /// Don't write message indexes, readers have to scan chunks to find messages
+    #[clap(long)]
+    pub mcap_no_message_indexes: bool,
+
+    /// Don't write statistics to the summary
+    #[clap(long)]
+    pub mcap_no_statistics: bool,
+}
+
+impl McapWriteArgs {
+    pub fn write_options(&self) -> WriteOptions {
+        let compression = match self.mcap_compression {
+            Compression::None => None,
+            Compression::Lz4 => Some(mcap::Compression::Lz4),
+            Compression::Zstd => Some(mcap::Compression::Zstd),
+        };
+        WriteOptions::new()
+            .compression(compression)
+            .use_chunks(self.mcap_chunk_size > 0)
+            .chunk_size(Some(self.mcap_chunk_size))
+            .emit_message_indexes(!self.mcap_no_message_indexes)
+            .emit_statistics(!self.mcap_no_statistics)
+    }
+}
@@ -0,0 +1,91 @@
+use std::{future::Future, net::SocketAddr, pin::Pin, sync::Arc};
+
+use anyhow::Result;
+use axum::{http::StatusCode, routing::get, Json, Router};
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder};
+use serde::Serialize;
+use tracing::{error, info};
+
+pub const SCANS_TOTAL: &str = "rplidar_scans_total";
+pub const SCAN_POINTS: &str = "rplidar_scan_points";
+pub const ROTATION_RATE_HZ: &str = "rplidar_rotation_rate_hz";
+pub const SERIAL_ERRORS_TOTAL: &str = "rplidar_serial_errors_total";
+pub const ENCODE_ERRORS_TOTAL: &str = "rplidar_encode_errors_total";
+pub const PUBLISH_ERRORS_TOTAL: &str = "rplidar_publish_errors_total";
+pub const SCAN_CHANNEL_DROPS_TOTAL: &str = "rplidar_scan_channel_drops_total";
+pub const SCAN_BROADCAST_SKIPPED_TOTAL: &str = "rplidar_scan_broadcast_skipped_total";
+pub const PUBLISH_LATENCY_SECONDS: &str = "rplidar_publish_latency_seconds";
+pub const ZENOH_BYTES_OUT_TOTAL: &str = "rplidar_zenoh_bytes_out_total";
+pub const PIPELINE_STAGE_SECONDS: &str = "rplidar_pipeline_stage_seconds";
+
+pub const BRIDGE_MESSAGES_TOTAL: &str = "rplidar_bridge_messages_total";
+pub const BRIDGE_BYTES_OUT_TOTAL: &str = "rplidar_bridge_bytes_out_total";
+pub const BRIDGE_SEND_ERRORS_TOTAL: &str = "rplidar_bridge_send_errors_total";
+pub const BRIDGE_DROPPED_TOTAL: &str = "rplidar_bridge_dropped_total";
+
+/// Bridge counters published as rplidar.BridgeStats
+pub const BRIDGE_STATS_TOPIC: &str = "bridge_stats";
+
+const LATENCY_BUCKETS: &[f64] = &[
+    0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0,
+];
+
+/// Result of a health check served on `/healthz` and `/readyz`
+#[derive(Debug, Serialize)]
+pub struct HealthReport {
+    /// Process is working, failing this should lead to a restart
+    pub live: bool,
+    /// Process is connected and producing data
+    pub ready: bool,
+    /// Component specific details
+    pub details: serde_json::Value,
+}
+
+pub type HealthCheck =
+    Arc<dyn Fn() -> Pin<Box<dyn Future<Output = HealthReport> + Send>> + Send + Sync>;
+
+/// Serve prometheus metrics on `/metrics` and optionally health on `/healthz` and `/readyz`
+pub async fn start_http_server(bind: SocketAddr, health_check: Option<HealthCheck>) -> Result<()> {
+    let handle = PrometheusBuilder::new()
+        .set_buckets_for_metric(Matcher::Suffix("_seconds".to_owned()), LATENCY_BUCKETS)?
+        .install_recorder()?;
+
+    let mut app = Router::new().route("/metrics", get(move || std::future::ready(handle.render())));
+
+    if let Some(health_check) = health_check {
+        app = app
+            .route(
+                "/healthz",
+                get({
+                    let health_check = health_check.clone();
+                    move || health_response(health_check.clone(), |report| report.live)
+                }),
+            )
+            .route(
+                "/readyz",
+                get(move || health_response(health_check.clone(), |report| report.ready)),
+            );
+    }
+
+    let listener = tokio::net::TcpListener::bind(bind).await?;
+    info!(%bind, "Serving http monitoring endpoints");
+    tokio::spawn(async move {
+        if let Err(err) = axum::serve(listener, app).await {
+            error!(?err, "Monitoring http server failed");
+        }
+    });
+    Ok(())
+}
+
+async fn health_response(
+    health_check: HealthCheck,
+    passing: fn(&HealthReport) -> bool,
+) -> (StatusCode, Json<HealthReport>) {
+    let report = health_check().await;
+    let status = if passing(&report) {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(report))
+}
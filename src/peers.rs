@@ -0,0 +1,33 @@
+use tracing::info;
+use zenoh::config::Config;
+use zenoh_config::EndPoint;
+
+/// Fixed peer list for networks without multicast or internet access
+#[derive(clap::Args, Debug, Clone, Default)]
+pub struct StaticPeersArgs {
+    /// Connect only to these peers and disable scouting
+    ///
+    /// Multicast and gossip discovery are turned off so offline networks come up
+    /// the same way every time
+    #[clap(long, conflicts_with = "connect")]
+    pub static_peers: Vec<EndPoint>,
+}
+
+impl StaticPeersArgs {
+    pub fn apply(&self, zenoh_config: &mut Config) -> anyhow::Result<()> {
+        if self.static_peers.is_empty() {
+            return Ok(());
+        }
+        for key in ["scouting/multicast/enabled", "scouting/gossip/enabled"] {
+            zenoh_config
+                .insert_json5(key, "false")
+                .map_err(|err| anyhow::anyhow!("Failed to disable {}: {:?}", key, err))?;
+        }
+        zenoh_config
+            .connect
+            .endpoints
+            .clone_from(&self.static_peers);
+        info!(static_peers = ?self.static_peers, "Scouting disabled, connecting to static peers");
+        Ok(())
+    }
+}
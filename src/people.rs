@@ -0,0 +1,270 @@
+use std::sync::Arc;
+
+use prost::Message;
+use rplidar_driver::ScanPoint;
+use tracing::{error, info};
+use zenoh::{prelude::r#async::*, publication::CongestionControl, Session};
+
+use crate::{rplidar, scan_broadcast::ScanBroadcast, system_time_to_proto_time, ErrorWrapper};
+
+pub const PEOPLE_TOPIC: &str = "people";
+
+/// Typical leg width the confidence is centered on
+const NOMINAL_LEG_WIDTH: f64 = 0.12;
+/// Confidence of a person seen from a single leg relative to a pair
+const SINGLE_LEG_FACTOR: f64 = 0.5;
+/// Segments need this many points to be considered a leg
+const MIN_LEG_POINTS: usize = 3;
+/// Depth of the arc relative to its half width below which a segment is flat
+const MIN_LEG_BULGE: f64 = 0.1;
+
+/// Leg based person detection on 2D scans
+///
+/// Scans are split into segments at range jumps. Small arcs bulging toward the sensor
+/// are legs and legs closer than person width are paired into people
+#[derive(clap::Args, Debug, Clone, Copy)]
+pub struct PersonDetectorArgs {
+    /// Publish people detected from leg patterns on <prefix>/people
+    #[clap(long)]
+    pub detect_people: bool,
+
+    /// Narrowest segment in meters considered a leg
+    #[clap(long, default_value_t = 0.05)]
+    pub leg_min_width: f64,
+
+    /// Widest segment in meters considered a leg
+    #[clap(long, default_value_t = 0.25)]
+    pub leg_max_width: f64,
+
+    /// Maximum distance in meters between the legs of one person
+    #[clap(long, default_value_t = 0.5)]
+    pub leg_pair_distance: f64,
+
+    /// Jump between neighboring points in meters that starts a new segment
+    #[clap(long, default_value_t = 0.1)]
+    pub leg_segment_gap: f64,
+
+    /// Ignore legs further than this many meters
+    #[clap(long, default_value_t = 6.0)]
+    pub people_max_range: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Point {
+    x: f64,
+    y: f64,
+}
+
+impl Point {
+    fn distance(&self, other: &Point) -> f64 {
+        (self.x - other.x).hypot(self.y - other.y)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Leg {
+    x: f64,
+    y: f64,
+    confidence: f64,
+}
+
+impl PersonDetectorArgs {
+    /// People in a scan ordered by confidence
+    pub fn detect(&self, scan: &[ScanPoint]) -> Vec<rplidar::PersonDetection> {
+        // same projection as the published point clouds
+        let points = scan
+            .iter()
+            .filter(|point| point.is_valid())
+            .map(|point| Point {
+                x: point.distance() as f64 * (-point.angle() as f64).cos(),
+                y: point.distance() as f64 * (-point.angle() as f64).sin(),
+            })
+            .collect::<Vec<_>>();
+        let legs = self.find_legs(&points);
+        let mut people = pair_legs(&legs, self.leg_pair_distance);
+        people.sort_by(|a, b| b.confidence.total_cmp(&a.confidence));
+        people
+    }
+
+    fn find_legs(&self, points: &[Point]) -> Vec<Leg> {
+        let mut legs = vec![];
+        let mut start = 0;
+        for end in 1..=points.len() {
+            let split = end == points.len()
+                || points[end - 1].distance(&points[end]) > self.leg_segment_gap;
+            if split {
+                if let Some(leg) = self.leg(&points[start..end]) {
+                    legs.push(leg);
+                }
+                start = end;
+            }
+        }
+        legs
+    }
+
+    fn leg(&self, segment: &[Point]) -> Option<Leg> {
+        if segment.len() < MIN_LEG_POINTS {
+            return None;
+        }
+        let first = segment.first()?;
+        let last = segment.last()?;
+        let width = first.distance(last);
+        if width < self.leg_min_width || width > self.leg_max_width {
+            return None;
+        }
+        let count = segment.len() as f64;
+        let x = segment.iter().map(|point| point.x).sum::<f64>() / count;
+        let y = segment.iter().map(|point| point.y).sum::<f64>() / count;
+        if x.hypot(y) > self.people_max_range {
+            return None;
+        }
+
+        // a leg is convex toward the sensor so its middle sticks out of the chord
+        // between its edges, a flat wall stays on it
+        let side = |point: &Point| {
+            ((last.x - first.x) * (point.y - first.y) - (last.y - first.y) * (point.x - first.x))
+                / width
+        };
+        let sensor_side = side(&Point { x: 0.0, y: 0.0 }).signum();
+        let depth = segment[1..segment.len() - 1]
+            .iter()
+            .map(|point| side(point) * sensor_side)
+            .fold(f64::NEG_INFINITY, f64::max);
+        let bulge = (depth / (width / 2.0)).clamp(0.0, 1.0);
+        if bulge < MIN_LEG_BULGE {
+            return None;
+        }
+        let width_score = 1.0
+            - ((width - NOMINAL_LEG_WIDTH).abs() / (self.leg_max_width - self.leg_min_width))
+                .min(1.0);
+        Some(Leg {
+            x,
+            y,
+            confidence: (bulge + width_score) / 2.0,
+        })
+    }
+}
+
+/// Greedily pair the closest legs, unpaired legs are people with one leg occluded
+fn pair_legs(legs: &[Leg], max_distance: f64) -> Vec<rplidar::PersonDetection> {
+    let mut candidates = vec![];
+    for (i, a) in legs.iter().enumerate() {
+        for (j, b) in legs.iter().enumerate().skip(i + 1) {
+            let distance = (a.x - b.x).hypot(a.y - b.y);
+            if distance <= max_distance {
+                candidates.push((distance, i, j));
+            }
+        }
+    }
+    candidates.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let mut paired = vec![false; legs.len()];
+    let mut people = vec![];
+    for (_, i, j) in candidates {
+        if paired[i] || paired[j] {
+            continue;
+        }
+        paired[i] = true;
+        paired[j] = true;
+        people.push(rplidar::PersonDetection {
+            x: (legs[i].x + legs[j].x) / 2.0,
+            y: (legs[i].y + legs[j].y) / 2.0,
+            confidence: (legs[i].confidence + legs[j].confidence) / 2.0,
+            legs: 2,
+        });
+    }
+    for (leg, _) in legs.iter().zip(paired).filter(|(_, paired)| !paired) {
+        people.push(rplidar::PersonDetection {
+            x: leg.x,
+            y: leg.y,
+            confidence: leg.confidence * SINGLE_LEG_FACTOR,
+            legs: 1,
+        });
+    }
+    people
+}
+
+/// Detect people in every scan from `scan_broadcast` and publish them on `topic`
+pub async fn start_person_detector(
+    zenoh_session: Arc<Session>,
+    topic: String,
+    args: PersonDetectorArgs,
+    scan_broadcast: ScanBroadcast,
+) -> anyhow::Result<()> {
+    let publisher = zenoh_session
+        .declare_publisher(topic.clone())
+        .congestion_control(CongestionControl::Drop)
+        .res()
+        .await
+        .map_err(ErrorWrapper::ZenohError)?;
+    info!(topic, "Publishing person detections");
+
+    let mut scan_subscriber = scan_broadcast.subscribe("people");
+    tokio::spawn(async move {
+        while let Some(frame) = scan_subscriber.recv().await {
+            let detections = rplidar::PersonDetections {
+                timestamp: Some(system_time_to_proto_time(&frame.capture_time)),
+                frame_id: frame.frame_id.clone(),
+                people: args.detect(&frame.points),
+            };
+            if let Err(err) = publisher.put(detections.encode_to_vec()).res().await {
+                error!(?err, "Failed to publish person detections");
+            }
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args() -> PersonDetectorArgs {
+        PersonDetectorArgs {
+            detect_people: true,
+            leg_min_width: 0.05,
+            leg_max_width: 0.25,
+            leg_pair_distance: 0.5,
+            leg_segment_gap: 0.1,
+            people_max_range: 6.0,
+        }
+    }
+
+    /// Points on the side of a circle centered at `(x, y)` facing a sensor at the origin
+    fn leg_arc(x: f64, y: f64, radius: f64) -> Vec<Point> {
+        (-3..=3)
+            .map(|step| {
+                let angle = std::f64::consts::PI + step as f64 * 0.35;
+                Point {
+                    x: x + radius * angle.cos(),
+                    y: y + radius * angle.sin(),
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn pairs_two_legs_into_person() {
+        let mut points = leg_arc(2.0, -0.15, 0.06);
+        points.extend(leg_arc(2.0, 0.15, 0.06));
+        let legs = args().find_legs(&points);
+        assert_eq!(legs.len(), 2);
+
+        let people = pair_legs(&legs, 0.5);
+        assert_eq!(people.len(), 1);
+        assert_eq!(people[0].legs, 2);
+        assert!((people[0].y).abs() < 0.01);
+        assert!(people[0].confidence > 0.5);
+    }
+
+    #[test]
+    fn ignores_flat_walls() {
+        let points = (0..20)
+            .map(|step| Point {
+                x: 2.0,
+                y: step as f64 * 0.01,
+            })
+            .collect::<Vec<_>>();
+        assert!(args().find_legs(&points).is_empty());
+    }
+}
@@ -0,0 +1,171 @@
+//! nalgebra helpers for mounting poses
+//!
+//! Poses follow the foxglove convention, orientation is a quaternion and euler angles
+//! are roll, pitch and yaw applied as yaw * pitch * roll
+
+use nalgebra::{Isometry3, Point3, Quaternion, Translation3, Unit, UnitQuaternion, Vector3};
+use rplidar_driver::ScanPoint;
+
+use crate::{config::MountingPose, foxglove, projection::project};
+
+/// Rigid transform of a pose, a missing orientation is the identity
+pub fn pose_to_isometry(pose: &foxglove::Pose) -> Isometry3<f64> {
+    let position = pose.position.unwrap_or_default();
+    let rotation = pose
+        .orientation
+        .map(|q| UnitQuaternion::from_quaternion(Quaternion::new(q.w, q.x, q.y, q.z)))
+        .unwrap_or_else(UnitQuaternion::identity);
+    Isometry3::from_parts(
+        Translation3::new(position.x, position.y, position.z),
+        rotation,
+    )
+}
+
+pub fn isometry_to_pose(isometry: &Isometry3<f64>) -> foxglove::Pose {
+    let translation = isometry.translation.vector;
+    let rotation = isometry.rotation;
+    foxglove::Pose {
+        position: Some(foxglove::Vector3 {
+            x: translation.x,
+            y: translation.y,
+            z: translation.z,
+        }),
+        orientation: Some(foxglove::Quaternion {
+            x: rotation.i,
+            y: rotation.j,
+            z: rotation.k,
+            w: rotation.w,
+        }),
+    }
+}
+
+/// Pose from a position in meters and roll, pitch and yaw in radians
+pub fn pose_from_euler(position: Vector3<f64>, roll: f64, pitch: f64, yaw: f64) -> foxglove::Pose {
+    isometry_to_pose(&Isometry3::from_parts(
+        position.into(),
+        UnitQuaternion::from_euler_angles(roll, pitch, yaw),
+    ))
+}
+
+/// Pose from a position in meters and a rotation of `angle` radians around `axis`
+pub fn pose_from_axis_angle(
+    position: Vector3<f64>,
+    axis: Vector3<f64>,
+    angle: f64,
+) -> anyhow::Result<foxglove::Pose> {
+    let Some(axis) = Unit::try_new(axis, f64::EPSILON) else {
+        anyhow::bail!("rotation axis can't be zero");
+    };
+    Ok(isometry_to_pose(&Isometry3::from_parts(
+        position.into(),
+        UnitQuaternion::from_axis_angle(&axis, angle),
+    )))
+}
+
+impl MountingPose {
+    pub fn to_isometry(&self) -> Isometry3<f64> {
+        Isometry3::from_parts(
+            Translation3::new(self.x, self.y, self.z),
+            UnitQuaternion::from_euler_angles(
+                self.roll.to_radians(),
+                self.pitch.to_radians(),
+                self.yaw.to_radians(),
+            ),
+        )
+    }
+}
+
+/// Moves points from the lidar frame into the frame the lidar is mounted in
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MountingTransform {
+    isometry: Isometry3<f64>,
+}
+
+impl MountingTransform {
+    pub fn new(pose: &foxglove::Pose) -> Self {
+        Self {
+            isometry: pose_to_isometry(pose),
+        }
+    }
+
+    /// Transform a point of the lidar plane
+    pub fn apply(&self, x: f32, y: f32) -> Point3<f64> {
+        self.isometry * Point3::new(x as f64, y as f64, 0.0)
+    }
+
+    /// Project valid points of a scan and transform them
+    pub fn apply_scan<'a>(
+        &'a self,
+        scan: &'a [ScanPoint],
+    ) -> impl Iterator<Item = Point3<f64>> + 'a {
+        scan.iter().filter(|point| point.is_valid()).map(|point| {
+            let (x, y) = project(point.angle(), point.distance());
+            self.apply(x, y)
+        })
+    }
+}
+
+impl From<&MountingPose> for MountingTransform {
+    fn from(pose: &MountingPose) -> Self {
+        Self {
+            isometry: pose.to_isometry(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_poses_equal(a: &foxglove::Pose, b: &foxglove::Pose) {
+        let (a, b) = (pose_to_isometry(a), pose_to_isometry(b));
+        assert!((a.translation.vector - b.translation.vector).norm() < 1e-9);
+        assert!(a.rotation.angle_to(&b.rotation) < 1e-9);
+    }
+
+    #[test]
+    fn matches_mounting_pose_conversion() {
+        let mounting = MountingPose {
+            x: 0.1,
+            y: -0.2,
+            z: 0.3,
+            roll: 180.0,
+            pitch: 10.0,
+            yaw: 45.0,
+        };
+        assert_poses_equal(
+            &isometry_to_pose(&mounting.to_isometry()),
+            &mounting.to_foxglove_pose(),
+        );
+        assert_poses_equal(
+            &pose_from_euler(
+                Vector3::new(0.1, -0.2, 0.3),
+                180f64.to_radians(),
+                10f64.to_radians(),
+                45f64.to_radians(),
+            ),
+            &mounting.to_foxglove_pose(),
+        );
+    }
+
+    #[test]
+    fn axis_angle_yaw() {
+        let pose =
+            pose_from_axis_angle(Vector3::zeros(), Vector3::z(), 90f64.to_radians()).unwrap();
+        let transform = MountingTransform::new(&pose);
+        let point = transform.apply(1.0, 0.0);
+        assert!((point - Point3::new(0.0, 1.0, 0.0)).norm() < 1e-6);
+        assert!(pose_from_axis_angle(Vector3::zeros(), Vector3::zeros(), 1.0).is_err());
+    }
+
+    #[test]
+    fn upside_down_mounting_mirrors_y() {
+        let transform = MountingTransform::from(&MountingPose {
+            z: 0.5,
+            roll: 180.0,
+            ..Default::default()
+        });
+        let point = transform.apply(1.0, 2.0);
+        assert!((point - Point3::new(1.0, -2.0, 0.5)).norm() < 1e-6);
+    }
+}
@@ -0,0 +1,105 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+
+/// Motor power saving options
+#[derive(clap::Args, Debug, Clone, Default, Serialize)]
+pub struct PowerSavingArgs {
+    /// Stop the motor after this many seconds without subscribers on any scan topic
+    ///
+    /// The motor is restarted as soon as a subscriber appears. Explicit off commands still win
+    #[clap(long)]
+    pub subscriber_idle_timeout: Option<f64>,
+
+    /// Stop the motor after this many minutes without an on command or snapshot query
+    ///
+    /// Any such activity restarts the motor
+    #[clap(long)]
+    pub idle_timeout: Option<f64>,
+}
+
+impl PowerSavingArgs {
+    pub fn subscriber_monitor(&self) -> Option<SubscriberIdleMonitor> {
+        self.subscriber_idle_timeout
+            .map(|timeout| SubscriberIdleMonitor::new(Duration::from_secs_f64(timeout)))
+    }
+
+    pub fn idle_timeout(&self) -> Option<Duration> {
+        self.idle_timeout
+            .map(|minutes| Duration::from_secs_f64(minutes * 60.0))
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.subscriber_idle_timeout.is_some() || self.idle_timeout.is_some()
+    }
+}
+
+/// Decides when the motor can be stopped because nobody consumes scans
+pub struct SubscriberIdleMonitor {
+    timeout: Duration,
+    last_subscribed: Instant,
+    idle: bool,
+}
+
+impl SubscriberIdleMonitor {
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            last_subscribed: Instant::now(),
+            idle: false,
+        }
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.idle
+    }
+
+    /// Update with the current subscriber state
+    ///
+    /// Returns the new idle state when it changes
+    pub fn update(&mut self, now: Instant, has_subscribers: bool) -> Option<bool> {
+        if has_subscribers {
+            self.last_subscribed = now;
+        }
+        let idle = !has_subscribers && now.duration_since(self.last_subscribed) >= self.timeout;
+        if idle != self.idle {
+            self.idle = idle;
+            Some(idle)
+        } else {
+            None
+        }
+    }
+}
+
+/// Time of the last explicit user activity, shared between command handlers
+pub struct ActivityTracker {
+    started: Instant,
+    /// milliseconds since `started`
+    last_activity: AtomicU64,
+}
+
+impl ActivityTracker {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            started: Instant::now(),
+            last_activity: AtomicU64::new(0),
+        })
+    }
+
+    pub fn record(&self) {
+        let elapsed = self.started.elapsed().as_millis() as u64;
+        self.last_activity.fetch_max(elapsed, Ordering::Relaxed);
+    }
+
+    pub fn idle_for(&self, now: Instant) -> Duration {
+        let last_activity =
+            self.started + Duration::from_millis(self.last_activity.load(Ordering::Relaxed));
+        now.saturating_duration_since(last_activity)
+    }
+}
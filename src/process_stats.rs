@@ -0,0 +1,143 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
+
+use prost::Message;
+use tracing::error;
+use zenoh::{prelude::r#async::*, Session};
+
+use crate::{rplidar, system_time_to_proto_time, ErrorWrapper};
+
+pub const PROCESS_STATS_TOPIC: &str = "process_stats";
+const PROCESS_STATS_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Samples CPU and memory usage of the current process from procfs
+pub struct ProcessSampler {
+    last_cpu_time: Option<Duration>,
+    last_sample: Instant,
+}
+
+struct ProcfsSample {
+    cpu_time: Duration,
+    rss_bytes: u64,
+    threads: u32,
+}
+
+impl Default for ProcessSampler {
+    fn default() -> Self {
+        Self {
+            last_cpu_time: None,
+            last_sample: Instant::now(),
+        }
+    }
+}
+
+impl ProcessSampler {
+    pub fn sample(&mut self, component: &str) -> rplidar::ProcessStats {
+        let now = Instant::now();
+        let metrics = tokio::runtime::Handle::current().metrics();
+        let mut stats = rplidar::ProcessStats {
+            timestamp: Some(system_time_to_proto_time(&SystemTime::now())),
+            component: component.to_owned(),
+            tokio_workers: metrics.num_workers() as u32,
+            tokio_alive_tasks: metrics.num_alive_tasks() as u64,
+            ..Default::default()
+        };
+
+        match read_procfs() {
+            Ok(sample) => {
+                if let Some(last_cpu_time) = self.last_cpu_time {
+                    let wall_time = now.duration_since(self.last_sample).as_secs_f64();
+                    let cpu_time = sample.cpu_time.saturating_sub(last_cpu_time).as_secs_f64();
+                    if wall_time > 0.0 {
+                        stats.cpu_percent = cpu_time / wall_time * 100.0;
+                    }
+                }
+                self.last_cpu_time = Some(sample.cpu_time);
+                stats.rss_bytes = sample.rss_bytes;
+                stats.threads = sample.threads;
+            }
+            Err(err) => error!(?err, "Failed to read process stats"),
+        }
+        self.last_sample = now;
+        stats
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn read_procfs() -> anyhow::Result<ProcfsSample> {
+    // SAFETY: sysconf only reads system configuration
+    let (ticks_per_second, page_size) = unsafe {
+        (
+            libc::sysconf(libc::_SC_CLK_TCK),
+            libc::sysconf(libc::_SC_PAGESIZE),
+        )
+    };
+
+    let stat = std::fs::read_to_string("/proc/self/stat")?;
+    // process name can contain spaces so skip past it
+    let fields: Vec<&str> = stat
+        .rsplit_once(')')
+        .map(|(_, rest)| rest.split_whitespace().collect())
+        .unwrap_or_default();
+    // fields start at the 3rd stat field
+    let field = |index: usize| -> anyhow::Result<u64> {
+        Ok(fields
+            .get(index - 3)
+            .ok_or_else(|| anyhow::anyhow!("Missing stat field {}", index))?
+            .parse()?)
+    };
+    let cpu_ticks = field(14)? + field(15)?;
+    let threads = field(20)? as u32;
+
+    let statm = std::fs::read_to_string("/proc/self/statm")?;
+    let rss_pages: u64 = statm
+        .split_whitespace()
+        .nth(1)
+        .ok_or_else(|| anyhow::anyhow!("Missing statm resident field"))?
+        .parse()?;
+
+    Ok(ProcfsSample {
+        cpu_time: Duration::from_secs_f64(cpu_ticks as f64 / ticks_per_second as f64),
+        rss_bytes: rss_pages * page_size as u64,
+        threads,
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn read_procfs() -> anyhow::Result<ProcfsSample> {
+    anyhow::bail!("Process stats are only supported on linux")
+}
+
+/// Periodically publish process stats with queue stats produced by `queues`
+pub async fn start_process_stats_publisher<F>(
+    zenoh_session: Arc<Session>,
+    topic: String,
+    component: &str,
+    queues: F,
+) -> anyhow::Result<()>
+where
+    F: Fn() -> Vec<rplidar::QueueStats> + Send + 'static,
+{
+    let publisher = zenoh_session
+        .declare_publisher(topic)
+        .res()
+        .await
+        .map_err(ErrorWrapper::ZenohError)?;
+
+    let component = component.to_owned();
+    tokio::spawn(async move {
+        let mut sampler = ProcessSampler::default();
+        let mut interval = tokio::time::interval(PROCESS_STATS_INTERVAL);
+        loop {
+            interval.tick().await;
+            let mut stats = sampler.sample(&component);
+            stats.queues = queues();
+            if let Err(err) = publisher.put(stats.encode_to_vec()).res().await {
+                error!(?err, "Failed to publish process stats");
+            }
+        }
+    });
+    Ok(())
+}
@@ -0,0 +1,104 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use prost::Message;
+use tracing::{error, info};
+use zenoh::{prelude::r#async::*, Session};
+
+use crate::{latency::LatencyTracker, monitoring, ErrorWrapper};
+
+pub const PROFILE_TOPIC: &str = "profile";
+const PROFILE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Waiting for and reading a full rotation from the serial port
+pub const GRAB: &str = "grab";
+/// Sorting and filtering the scan
+pub const SORT: &str = "sort";
+/// Projecting points into point clouds
+pub const PROJECT: &str = "project";
+/// Serializing payloads
+pub const ENCODE: &str = "encode";
+/// Handing payloads to zenoh
+pub const PUBLISH: &str = "publish";
+
+/// Records per stage timings of the scan pipeline when profiling is enabled
+///
+/// Cheap to clone and a no-op when disabled
+#[derive(Clone, Default)]
+pub struct StageProfiler {
+    tracker: Option<LatencyTracker>,
+}
+
+impl StageProfiler {
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            tracker: enabled.then(LatencyTracker::default),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.tracker.is_some()
+    }
+
+    pub fn record(&self, stage: &'static str, duration: Duration) {
+        if let Some(tracker) = &self.tracker {
+            metrics::histogram!(monitoring::PIPELINE_STAGE_SECONDS, "stage" => stage)
+                .record(duration.as_secs_f64());
+            tracker.record(stage, duration);
+        }
+    }
+
+    /// Run `f` and record its duration under `stage`
+    pub fn time<T>(&self, stage: &'static str, f: impl FnOnce() -> T) -> T {
+        if !self.is_enabled() {
+            return f();
+        }
+        let start = Instant::now();
+        let result = f();
+        self.record(stage, start.elapsed());
+        result
+    }
+}
+
+/// Periodically log stage summaries and publish them as rplidar.Latency on `topic`
+///
+/// Does nothing when `profiler` is disabled
+pub async fn start_profile_reporter(
+    zenoh_session: Arc<Session>,
+    topic: String,
+    component: &str,
+    profiler: StageProfiler,
+) -> anyhow::Result<()> {
+    let Some(tracker) = profiler.tracker else {
+        return Ok(());
+    };
+    let publisher = zenoh_session
+        .declare_publisher(topic)
+        .res()
+        .await
+        .map_err(ErrorWrapper::ZenohError)?;
+
+    let component = component.to_owned();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(PROFILE_INTERVAL);
+        loop {
+            interval.tick().await;
+            for summary in tracker.take_summaries(&component) {
+                info!(
+                    stage = %summary.hop,
+                    count = summary.count,
+                    min_ms = %format!("{:.2}", summary.min_ms),
+                    mean_ms = %format!("{:.2}", summary.mean_ms),
+                    max_ms = %format!("{:.2}", summary.max_ms),
+                    "Pipeline stage timing"
+                );
+                if let Err(err) = publisher.put(summary.encode_to_vec()).res().await {
+                    error!(?err, "Failed to publish stage timing");
+                }
+            }
+        }
+    });
+    Ok(())
+}
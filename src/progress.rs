@@ -0,0 +1,123 @@
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+use tracing::info;
+
+/// Controls for periodic progress summaries
+#[derive(clap::Args, Debug, Clone)]
+pub struct ProgressArgs {
+    /// Seconds between throughput summaries
+    #[clap(long, default_value_t = 10)]
+    pub summary_interval: u64,
+
+    /// Don't log periodic throughput summaries
+    #[clap(long)]
+    pub quiet: bool,
+}
+
+impl ProgressArgs {
+    pub fn reporter(&self, name: &str) -> ThroughputReporter {
+        ThroughputReporter::new(name, Duration::from_secs(self.summary_interval), self.quiet)
+    }
+}
+
+/// Counts messages and bytes and periodically logs a structured summary
+pub struct ThroughputReporter {
+    name: String,
+    interval: Duration,
+    quiet: bool,
+    window_start: Instant,
+    window_messages: u64,
+    window_bytes: u64,
+    total_messages: u64,
+    total_bytes: u64,
+}
+
+impl ThroughputReporter {
+    pub fn new(name: &str, interval: Duration, quiet: bool) -> Self {
+        Self {
+            name: name.to_owned(),
+            interval,
+            quiet,
+            window_start: Instant::now(),
+            window_messages: 0,
+            window_bytes: 0,
+            total_messages: 0,
+            total_bytes: 0,
+        }
+    }
+
+    pub fn record(&mut self, bytes: usize) {
+        self.window_messages += 1;
+        self.window_bytes += bytes as u64;
+        self.total_messages += 1;
+        self.total_bytes += bytes as u64;
+
+        let elapsed = self.window_start.elapsed();
+        if elapsed < self.interval {
+            return;
+        }
+
+        if !self.quiet {
+            let seconds = elapsed.as_secs_f64();
+            info!(
+                name = self.name,
+                rate_hz = format!("{:.2}", self.window_messages as f64 / seconds),
+                bytes_per_second = (self.window_bytes as f64 / seconds) as u64,
+                messages = self.window_messages,
+                bytes = self.window_bytes,
+                total_messages = self.total_messages,
+                total_bytes = self.total_bytes,
+                "Throughput summary"
+            );
+        }
+
+        self.window_start = Instant::now();
+        self.window_messages = 0;
+        self.window_bytes = 0;
+    }
+
+    pub fn total_messages(&self) -> u64 {
+        self.total_messages
+    }
+}
+
+/// Limits for bounded captures such as calibration runs or smoke tests
+#[derive(clap::Args, Debug, Clone)]
+pub struct CaptureLimitArgs {
+    /// Exit cleanly after this many seconds
+    #[clap(long)]
+    pub duration: Option<f64>,
+
+    /// Exit cleanly after this many scans
+    #[clap(long)]
+    pub max_scans: Option<u64>,
+}
+
+impl CaptureLimitArgs {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        self.deadline().map(|_| ())
+    }
+
+    pub fn deadline(&self) -> anyhow::Result<Option<tokio::time::Instant>> {
+        self.duration
+            .map(|seconds| {
+                let duration = Duration::try_from_secs_f64(seconds)
+                    .with_context(|| format!("Invalid capture duration {}", seconds))?;
+                Ok(tokio::time::Instant::now() + duration)
+            })
+            .transpose()
+    }
+
+    pub fn max_scans_reached(&self, scans: u64) -> bool {
+        self.max_scans.map_or(false, |max_scans| scans >= max_scans)
+    }
+}
+
+/// Resolves once the deadline passes or never if there is no deadline
+pub async fn wait_for_deadline(deadline: Option<tokio::time::Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
@@ -0,0 +1,246 @@
+//! Conversions between laser scans and point clouds
+//!
+//! Angles are rplidar angles in radians going clockwise, the same as the driver's laser
+//! scans and the `angle` of [`RpLidarProjectedPoint`]. Points are in the lidar frame with
+//! x forward and y to the left
+
+use std::f32::consts::TAU;
+
+use crate::{foxglove, rp_lidar_projected_point_descriptor, RpLidarProjectedPoint};
+
+/// Position of a return at `angle` and `distance`
+pub fn project(angle: f32, distance: f32) -> (f32, f32) {
+    (distance * (-angle).cos(), distance * (-angle).sin())
+}
+
+/// Angle in `[0, TAU)` and distance of a point, inverse of [`project`]
+pub fn unproject(x: f32, y: f32) -> (f32, f32) {
+    ((-y.atan2(x)).rem_euclid(TAU), x.hypot(y))
+}
+
+/// Ranges that are NaN, infinite or not positive are no returns
+pub fn is_valid_range(range: f64) -> bool {
+    range.is_finite() && range > 0.0
+}
+
+impl foxglove::LaserScan {
+    /// Angle of the range at `index`, interpolated between start and end angle
+    pub fn range_angle(&self, index: usize) -> f64 {
+        if self.ranges.len() < 2 {
+            return self.start_angle;
+        }
+        self.start_angle
+            + (self.end_angle - self.start_angle) * index as f64 / (self.ranges.len() - 1) as f64
+    }
+
+    /// Angle and range of every valid return
+    pub fn valid_points(&self) -> impl Iterator<Item = (f64, f64)> + '_ {
+        self.ranges
+            .iter()
+            .enumerate()
+            .filter(|(_, range)| is_valid_range(**range))
+            .map(|(index, range)| (self.range_angle(index), *range))
+    }
+
+    /// Project valid returns into a point cloud of [`RpLidarProjectedPoint`]s
+    ///
+    /// Intensities are clamped into the quality byte, scans without intensities get 0
+    pub fn to_point_cloud(&self) -> foxglove::PointCloud {
+        let (point_stride, fields) = rp_lidar_projected_point_descriptor();
+        let mut data = vec![];
+        for (index, range) in self.ranges.iter().enumerate() {
+            if !is_valid_range(*range) {
+                continue;
+            }
+            let angle = self.range_angle(index) as f32;
+            let distance = *range as f32;
+            let (x, y) = project(angle, distance);
+            let quality = self
+                .intensities
+                .get(index)
+                .map(|intensity| intensity.clamp(0.0, u8::MAX as f64) as u8)
+                .unwrap_or_default();
+            let point = RpLidarProjectedPoint::new(x, y, distance, angle, quality);
+            data.extend_from_slice(&point.to_foxglove_blob());
+        }
+        foxglove::PointCloud {
+            timestamp: self.timestamp.clone(),
+            frame_id: self.frame_id.clone(),
+            pose: self.pose,
+            point_stride,
+            fields,
+            data,
+        }
+    }
+}
+
+impl foxglove::PointCloud {
+    /// Convert a point cloud of [`RpLidarProjectedPoint`]s back into a laser scan
+    ///
+    /// Keeps one range per point in cloud order. Invalid points aren't part of the cloud
+    /// so angles are only approximately even, good enough for viewing over slow links.
+    /// See [`Self::to_laser_scan_with_bins`] for evenly spaced ranges
+    pub fn to_laser_scan(&self) -> anyhow::Result<foxglove::LaserScan> {
+        let points = RpLidarProjectedPoint::from_foxglove_point_cloud(self)?;
+        Ok(foxglove::LaserScan {
+            timestamp: self.timestamp.clone(),
+            frame_id: self.frame_id.clone(),
+            pose: self.pose,
+            start_angle: points.first().map(|point| point.angle).unwrap_or_default() as f64,
+            end_angle: points.last().map(|point| point.angle).unwrap_or_default() as f64,
+            ranges: points.iter().map(|point| point.distance as f64).collect(),
+            intensities: points.iter().map(|point| point.quality as f64).collect(),
+        })
+    }
+
+    /// Back-project into `bins` evenly spaced ranges over a full turn starting at 0
+    ///
+    /// Angles come from x and y so clouds moved into another frame work too. Each bin
+    /// keeps its closest point, bins without points are NaN
+    pub fn to_laser_scan_with_bins(&self, bins: usize) -> anyhow::Result<foxglove::LaserScan> {
+        if bins == 0 {
+            anyhow::bail!("laser scan needs at least one bin");
+        }
+        let points = RpLidarProjectedPoint::from_foxglove_point_cloud(self)?;
+        Ok(back_project(self, &points, bins))
+    }
+}
+
+fn back_project(
+    point_cloud: &foxglove::PointCloud,
+    points: &[RpLidarProjectedPoint],
+    bins: usize,
+) -> foxglove::LaserScan {
+    let step = TAU / bins as f32;
+    let mut ranges = vec![f64::NAN; bins];
+    let mut intensities = vec![0.0; bins];
+    for point in points {
+        let (angle, distance) = unproject(point.x, point.y);
+        if !is_valid_range(distance as f64) {
+            continue;
+        }
+        let bin = (angle / step).round() as usize % bins;
+        if ranges[bin].is_nan() || (distance as f64) < ranges[bin] {
+            ranges[bin] = distance as f64;
+            intensities[bin] = point.quality as f64;
+        }
+    }
+    foxglove::LaserScan {
+        timestamp: point_cloud.timestamp.clone(),
+        frame_id: point_cloud.frame_id.clone(),
+        pose: point_cloud.pose,
+        start_angle: 0.0,
+        end_angle: (step * (bins - 1) as f32) as f64,
+        ranges,
+        intensities,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn laser_scan(ranges: Vec<f64>) -> foxglove::LaserScan {
+        let count = ranges.len();
+        foxglove::LaserScan {
+            start_angle: 0.0,
+            end_angle: (TAU * (count - 1) as f32 / count as f32) as f64,
+            intensities: vec![47.0; count],
+            ranges,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn unprojects_projected_points() {
+        for angle in [0.0f32, 0.5, 1.6, 3.0, 4.7, 6.2] {
+            let (x, y) = project(angle, 2.0);
+            let (unprojected, distance) = unproject(x, y);
+            assert!((unprojected - angle).abs() < 1e-5);
+            assert!((distance - 2.0).abs() < 1e-5);
+        }
+        // rplidar angles go clockwise so 90 degrees is to the right
+        let (x, y) = project(TAU / 4.0, 1.0);
+        assert!(x.abs() < 1e-6);
+        assert!((y + 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn skips_invalid_ranges() {
+        let scan = laser_scan(vec![1.0, f64::NAN, 0.0, f64::INFINITY, -1.0, 2.0, 3.0, 4.0]);
+        assert_eq!(
+            scan.valid_points()
+                .map(|(_, range)| range)
+                .collect::<Vec<_>>(),
+            vec![1.0, 2.0, 3.0, 4.0]
+        );
+        let points =
+            RpLidarProjectedPoint::from_foxglove_point_cloud(&scan.to_point_cloud()).unwrap();
+        assert_eq!(points.len(), 4);
+        assert_eq!(points[0].quality, 47);
+        assert!((points[1].angle - scan.range_angle(5) as f32).abs() < 1e-6);
+    }
+
+    #[test]
+    fn round_trips_laser_scan() {
+        let scan = laser_scan(vec![1.0, 1.5, f64::NAN, 2.5, 3.0, 0.0, 1.2, 0.8]);
+        let back = scan.to_point_cloud().to_laser_scan_with_bins(8).unwrap();
+        assert_eq!(back.start_angle, 0.0);
+        assert!((back.end_angle - scan.end_angle).abs() < 1e-6);
+        for (range, expected) in back.ranges.iter().zip(&scan.ranges) {
+            if is_valid_range(*expected) {
+                assert!((range - expected).abs() < 1e-5);
+            } else {
+                assert!(range.is_nan());
+            }
+        }
+        assert_eq!(back.intensities[0], 47.0);
+        assert_eq!(back.intensities[2], 0.0);
+    }
+
+    fn point_cloud(points: &[(f32, f32)]) -> foxglove::PointCloud {
+        let (point_stride, fields) = rp_lidar_projected_point_descriptor();
+        let data = points
+            .iter()
+            .flat_map(|(x, y)| {
+                let (angle, distance) = unproject(*x, *y);
+                RpLidarProjectedPoint::new(*x, *y, distance, angle, 10).to_foxglove_blob()
+            })
+            .collect();
+        foxglove::PointCloud {
+            point_stride,
+            fields,
+            data,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn keeps_every_point_without_bins() {
+        // close together and far apart, binning would merge the first two
+        let angles = [0.1f32, 0.105, 3.0, 3.01, 5.5];
+        let points = angles
+            .iter()
+            .enumerate()
+            .map(|(index, angle)| project(*angle, 1.0 + index as f32))
+            .collect::<Vec<_>>();
+        let scan = point_cloud(&points).to_laser_scan().unwrap();
+        assert_eq!(scan.ranges.len(), angles.len());
+        for (index, range) in scan.ranges.iter().enumerate() {
+            assert!((range - (1.0 + index as f64)).abs() < 1e-5);
+        }
+        assert!((scan.start_angle - 0.1).abs() < 1e-5);
+        assert!((scan.end_angle - 5.5).abs() < 1e-5);
+        assert_eq!(scan.intensities, vec![10.0; angles.len()]);
+    }
+
+    #[test]
+    fn keeps_closest_point_per_bin() {
+        let point_cloud = point_cloud(&[(1.0, 0.0), (0.5, 0.01), (0.0, -2.0)]);
+        let scan = point_cloud.to_laser_scan_with_bins(4).unwrap();
+        assert!((scan.ranges[0] - 0.5).abs() < 1e-3);
+        assert!((scan.ranges[1] - 2.0).abs() < 1e-6);
+        assert!(scan.ranges[2].is_nan());
+        assert!(point_cloud.to_laser_scan_with_bins(0).is_err());
+    }
+}
@@ -0,0 +1,58 @@
+use serde::Serialize;
+use zenoh::publication::Priority;
+
+/// zenoh publisher priority, highest first
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "serde-types", derive(serde::Deserialize))]
+#[serde(rename_all = "kebab-case")]
+pub enum PublisherPriority {
+    RealTime,
+    InteractiveHigh,
+    InteractiveLow,
+    DataHigh,
+    Data,
+    DataLow,
+    Background,
+}
+
+impl From<PublisherPriority> for Priority {
+    fn from(priority: PublisherPriority) -> Self {
+        match priority {
+            PublisherPriority::RealTime => Priority::RealTime,
+            PublisherPriority::InteractiveHigh => Priority::InteractiveHigh,
+            PublisherPriority::InteractiveLow => Priority::InteractiveLow,
+            PublisherPriority::DataHigh => Priority::DataHigh,
+            PublisherPriority::Data => Priority::Data,
+            PublisherPriority::DataLow => Priority::DataLow,
+            PublisherPriority::Background => Priority::Background,
+        }
+    }
+}
+
+/// Priorities of the scan publishers
+///
+/// When the link saturates zenoh sends higher priorities first so the lightweight
+/// laser scans keep flowing while point clouds are dropped
+#[derive(clap::Args, Debug, Clone, Copy, Serialize)]
+#[cfg_attr(feature = "serde-types", derive(serde::Deserialize))]
+pub struct PublisherPriorityArgs {
+    /// Priority of laser scan publishers
+    #[clap(long, value_enum, default_value_t = PublisherPriority::DataHigh)]
+    pub laser_scan_priority: PublisherPriority,
+
+    /// Priority of point cloud publishers
+    ///
+    /// Point clouds are always dropped instead of blocking when the link is congested
+    #[clap(long, value_enum, default_value_t = PublisherPriority::DataLow)]
+    pub point_cloud_priority: PublisherPriority,
+}
+
+impl PublisherPriorityArgs {
+    pub fn laser_scan(&self) -> Priority {
+        self.laser_scan_priority.into()
+    }
+
+    pub fn point_cloud(&self) -> Priority {
+        self.point_cloud_priority.into()
+    }
+}
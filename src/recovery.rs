@@ -0,0 +1,264 @@
+use std::{
+    fs,
+    io::{self, BufWriter, ErrorKind},
+    path::{Path, PathBuf},
+};
+
+use mcap::{
+    read::{LinearReader, Options},
+    records::Record,
+    MessageStream, WriteOptions, MAGIC,
+};
+use memmap2::Mmap;
+use tracing::{info, warn};
+
+/// Outcome of recovering an unfinished recording
+#[derive(Debug)]
+pub struct RecoveryReport {
+    pub recovered_path: PathBuf,
+    pub messages: u64,
+    /// Whether reading stopped at a truncated or corrupt record
+    pub truncated: bool,
+}
+
+/// Rewrite a recording that was never finished, e.g. after a crash or power loss
+///
+/// Unfinished files have no footer or index and some tools refuse to open them.
+/// Messages are read in order up to the first damaged record and written to
+/// `<stem>.recovered.<ext>` next to the original, which gets new indexes and a footer.
+/// Copies from earlier recoveries are kept by numbering later ones.
+/// Returns `None` when `path` doesn't exist or was finished cleanly
+pub fn recover_unfinished(path: &Path) -> anyhow::Result<Option<RecoveryReport>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let file = fs::File::open(path)?;
+    // SAFETY: the previous writer is gone and nothing else writes recordings
+    let mapped = unsafe { Mmap::map(&file)? };
+    if !mapped.starts_with(MAGIC) {
+        warn!(?path, "Not an mcap file, skipping recovery");
+        return Ok(None);
+    }
+    if mapped.ends_with(MAGIC) {
+        return Ok(None);
+    }
+    info!(?path, "Found unfinished recording, recovering");
+
+    let profile = LinearReader::new_with_options(&mapped, Options::IgnoreEndMagic.into())?
+        .next()
+        .and_then(|record| match record {
+            Ok(Record::Header(header)) => Some(header.profile),
+            _ => None,
+        })
+        .unwrap_or_default();
+
+    let (output_file, recovered_path) = create_unused(path, "recovered")?;
+    let output_file = BufWriter::new(output_file);
+    let mut writer = WriteOptions::new().profile(profile).create(output_file)?;
+
+    let mut messages = 0;
+    let mut truncated = false;
+    for message in MessageStream::new_with_options(&mapped, Options::IgnoreEndMagic.into())? {
+        match message {
+            Ok(message) => {
+                writer.write(&message)?;
+                messages += 1;
+            }
+            Err(err) => {
+                warn!(
+                    ?err,
+                    messages, "Recording is damaged, stopping recovery here"
+                );
+                truncated = true;
+                break;
+            }
+        }
+    }
+    writer.finish()?;
+
+    Ok(Some(RecoveryReport {
+        recovered_path,
+        messages,
+        truncated,
+    }))
+}
+
+/// Move a recording that couldn't be recovered out of the way so it isn't overwritten
+///
+/// `out.mcap` is renamed to `out.unrecovered.mcap`, returns the new path
+pub fn set_aside(path: &Path) -> anyhow::Result<PathBuf> {
+    let aside_path = (0..)
+        .map(|index| tagged_path(path, "unrecovered", index))
+        .find(|candidate| !candidate.exists())
+        .expect("unbounded range");
+    fs::rename(path, &aside_path)?;
+    Ok(aside_path)
+}
+
+/// Create the first `tagged_path` that doesn't exist yet so earlier copies are kept
+fn create_unused(path: &Path, tag: &str) -> io::Result<(fs::File, PathBuf)> {
+    let mut index = 0;
+    loop {
+        let candidate = tagged_path(path, tag, index);
+        match fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&candidate)
+        {
+            Ok(file) => return Ok((file, candidate)),
+            Err(err) if err.kind() == ErrorKind::AlreadyExists => index += 1,
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// `out.mcap` with tag `recovered` is `out.recovered.mcap`, then `out.recovered.1.mcap` and up
+fn tagged_path(path: &Path, tag: &str, index: usize) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("out");
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or("mcap");
+    if index == 0 {
+        path.with_file_name(format!("{}.{}.{}", stem, tag, extension))
+    } else {
+        path.with_file_name(format!("{}.{}.{}.{}", stem, tag, index, extension))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use mcap::{records::MessageHeader, Channel};
+
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "rplidar-zenoh-driver-recovery-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn write_recording(path: &Path, messages: u32, use_chunks: bool, finish: bool) {
+        let file = BufWriter::new(fs::File::create(path).unwrap());
+        let mut writer = WriteOptions::new()
+            .use_chunks(use_chunks)
+            .create(file)
+            .unwrap();
+        let channel_id = writer
+            .add_channel(&Channel {
+                topic: "scan".to_owned(),
+                schema: None,
+                message_encoding: "application/octet-stream".to_owned(),
+                metadata: BTreeMap::default(),
+            })
+            .unwrap();
+        for sequence in 0..messages {
+            let header = MessageHeader {
+                channel_id,
+                sequence,
+                log_time: sequence as u64,
+                publish_time: sequence as u64,
+            };
+            writer
+                .write_to_known_channel(&header, &[sequence as u8; 16])
+                .unwrap();
+        }
+        if finish {
+            writer.finish().unwrap();
+        } else {
+            writer.flush().unwrap();
+            // dropping the writer finishes the file, forgetting it leaves what a crash would
+            std::mem::forget(writer);
+        }
+    }
+
+    fn count_messages(path: &Path) -> usize {
+        let data = fs::read(path).unwrap();
+        MessageStream::new(&data)
+            .unwrap()
+            .map(|message| message.unwrap())
+            .count()
+    }
+
+    #[test]
+    fn recovers_unfinished_recording() {
+        let dir = temp_dir("unfinished");
+        let path = dir.join("out.mcap");
+        write_recording(&path, 10, true, false);
+
+        let report = recover_unfinished(&path).unwrap().unwrap();
+        assert_eq!(report.recovered_path, dir.join("out.recovered.mcap"));
+        assert_eq!(report.messages, 10);
+        assert!(!report.truncated);
+        assert_eq!(count_messages(&report.recovered_path), 10);
+
+        // a second crash keeps the first copy
+        let report = recover_unfinished(&path).unwrap().unwrap();
+        assert_eq!(report.recovered_path, dir.join("out.recovered.1.mcap"));
+        assert!(dir.join("out.recovered.mcap").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn skips_finished_and_foreign_files() {
+        let dir = temp_dir("skipped");
+        let finished = dir.join("finished.mcap");
+        write_recording(&finished, 3, true, true);
+        assert!(recover_unfinished(&finished).unwrap().is_none());
+
+        let foreign = dir.join("foreign.mcap");
+        fs::write(&foreign, "not a recording").unwrap();
+        assert!(recover_unfinished(&foreign).unwrap().is_none());
+
+        assert!(recover_unfinished(&dir.join("missing.mcap"))
+            .unwrap()
+            .is_none());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn stops_at_truncated_record() {
+        let dir = temp_dir("truncated");
+        let path = dir.join("out.mcap");
+        // without chunks every message is its own record
+        write_recording(&path, 5, false, false);
+        let file = fs::OpenOptions::new().write(true).open(&path).unwrap();
+        file.set_len(file.metadata().unwrap().len() - 4).unwrap();
+
+        let report = recover_unfinished(&path).unwrap().unwrap();
+        assert!(report.truncated);
+        assert_eq!(report.messages, 4);
+        assert_eq!(count_messages(&report.recovered_path), 4);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn sets_aside_unique_names() {
+        let dir = temp_dir("aside");
+        let path = dir.join("out.mcap");
+        fs::write(&path, "first").unwrap();
+        assert_eq!(set_aside(&path).unwrap(), dir.join("out.unrecovered.mcap"));
+        fs::write(&path, "second").unwrap();
+        assert_eq!(
+            set_aside(&path).unwrap(),
+            dir.join("out.unrecovered.1.mcap")
+        );
+        assert_eq!(
+            fs::read_to_string(dir.join("out.unrecovered.mcap")).unwrap(),
+            "first"
+        );
+        assert!(!path.exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
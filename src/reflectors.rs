@@ -0,0 +1,314 @@
+use std::{f64::consts::TAU, sync::Arc};
+
+use prost::Message;
+use rplidar_driver::ScanPoint;
+use tracing::{error, info};
+use zenoh::{prelude::r#async::*, publication::CongestionControl, Session};
+
+use crate::{rplidar, scan_broadcast::ScanBroadcast, system_time_to_proto_time, ErrorWrapper};
+
+pub const REFLECTORS_TOPIC: &str = "reflectors";
+
+/// Retro-reflective marker detection
+///
+/// Reflective tape returns far more light than the surfaces around it, so markers are
+/// runs of neighboring points above an intensity threshold. Markers are tracked between
+/// scans by position to keep their ids stable
+#[derive(clap::Args, Debug, Clone, Copy)]
+pub struct ReflectorArgs {
+    /// Publish reflector markers on <prefix>/reflectors
+    #[clap(long)]
+    pub detect_reflectors: bool,
+
+    /// Points with at least this intensity are on a reflector
+    #[clap(long, default_value_t = 200)]
+    pub reflector_min_intensity: u8,
+
+    /// Reflectors need at least this many bright points
+    #[clap(long, default_value_t = 2)]
+    pub reflector_min_points: usize,
+
+    /// Widest segment in meters considered a reflector
+    #[clap(long, default_value_t = 0.2)]
+    pub reflector_max_width: f64,
+
+    /// Jump between neighboring points in meters that splits a reflector
+    #[clap(long, default_value_t = 0.05)]
+    pub reflector_segment_gap: f64,
+
+    /// Reflectors closer than this many meters to a tracked one keep its id
+    #[clap(long, default_value_t = 0.3)]
+    pub reflector_match_distance: f64,
+
+    /// Scans a reflector may be missing before its id is dropped
+    #[clap(long, default_value_t = 5)]
+    pub reflector_max_missed: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Return {
+    x: f64,
+    y: f64,
+    intensity: u8,
+}
+
+impl Return {
+    fn distance(&self, other: &Return) -> f64 {
+        (self.x - other.x).hypot(self.y - other.y)
+    }
+}
+
+impl ReflectorArgs {
+    /// Reflectors in a scan without ids
+    pub fn detect(&self, scan: &[ScanPoint]) -> Vec<rplidar::ReflectorMarker> {
+        // same projection as the published point clouds
+        let returns = scan
+            .iter()
+            .filter(|point| point.is_valid())
+            .map(|point| Return {
+                x: point.distance() as f64 * (-point.angle() as f64).cos(),
+                y: point.distance() as f64 * (-point.angle() as f64).sin(),
+                intensity: point.quality,
+            })
+            .collect::<Vec<_>>();
+        self.find_markers(&returns)
+    }
+
+    fn find_markers(&self, returns: &[Return]) -> Vec<rplidar::ReflectorMarker> {
+        let bright = |point: &Return| point.intensity >= self.reflector_min_intensity;
+        let mut segments = vec![];
+        let mut current: Vec<Return> = vec![];
+        for point in returns {
+            let joins = current
+                .last()
+                .is_some_and(|last| last.distance(point) <= self.reflector_segment_gap);
+            if (!bright(point) || !joins) && !current.is_empty() {
+                segments.push(std::mem::take(&mut current));
+            }
+            if bright(point) {
+                current.push(*point);
+            }
+        }
+        if !current.is_empty() {
+            segments.push(current);
+        }
+
+        // a reflector straddling the start of the scan ends up split in two
+        if let (Some(first), Some(last)) = (returns.first(), returns.last()) {
+            if segments.len() > 1
+                && bright(first)
+                && bright(last)
+                && last.distance(first) <= self.reflector_segment_gap
+            {
+                let tail = segments.pop().unwrap_or_default();
+                segments[0].splice(0..0, tail);
+            }
+        }
+
+        segments
+            .iter()
+            .filter_map(|segment| self.marker(segment))
+            .collect()
+    }
+
+    fn marker(&self, segment: &[Return]) -> Option<rplidar::ReflectorMarker> {
+        if segment.len() < self.reflector_min_points {
+            return None;
+        }
+        let width = segment.first()?.distance(segment.last()?);
+        if width > self.reflector_max_width {
+            return None;
+        }
+        let count = segment.len() as f64;
+        let x = segment.iter().map(|point| point.x).sum::<f64>() / count;
+        let y = segment.iter().map(|point| point.y).sum::<f64>() / count;
+        Some(rplidar::ReflectorMarker {
+            id: 0,
+            range: x.hypot(y),
+            // inverse of the projection above
+            bearing: (-y.atan2(x)).rem_euclid(TAU),
+            x,
+            y,
+            width,
+            intensity: segment.iter().map(|point| point.intensity).max()? as u32,
+            points: segment.len() as u32,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Track {
+    id: u32,
+    x: f64,
+    y: f64,
+    missed: u32,
+}
+
+/// Assigns stable ids to reflectors between scans
+#[derive(Debug)]
+pub struct ReflectorTracker {
+    match_distance: f64,
+    max_missed: u32,
+    next_id: u32,
+    tracks: Vec<Track>,
+}
+
+impl ReflectorTracker {
+    pub fn new(match_distance: f64, max_missed: u32) -> Self {
+        Self {
+            match_distance,
+            max_missed,
+            next_id: 1,
+            tracks: vec![],
+        }
+    }
+
+    /// Greedily match the closest marker and track pairs, new markers get a new id
+    pub fn update(&mut self, markers: &mut [rplidar::ReflectorMarker]) {
+        let mut candidates = vec![];
+        for (i, marker) in markers.iter().enumerate() {
+            for (j, track) in self.tracks.iter().enumerate() {
+                let distance = (marker.x - track.x).hypot(marker.y - track.y);
+                if distance <= self.match_distance {
+                    candidates.push((distance, i, j));
+                }
+            }
+        }
+        candidates.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let mut marker_matched = vec![false; markers.len()];
+        let mut track_matched = vec![false; self.tracks.len()];
+        for (_, i, j) in candidates {
+            if marker_matched[i] || track_matched[j] {
+                continue;
+            }
+            marker_matched[i] = true;
+            track_matched[j] = true;
+            let track = &mut self.tracks[j];
+            track.x = markers[i].x;
+            track.y = markers[i].y;
+            track.missed = 0;
+            markers[i].id = track.id;
+        }
+
+        for (track, matched) in self.tracks.iter_mut().zip(&track_matched) {
+            if !matched {
+                track.missed += 1;
+            }
+        }
+        self.tracks.retain(|track| track.missed <= self.max_missed);
+
+        for (marker, _) in markers
+            .iter_mut()
+            .zip(marker_matched)
+            .filter(|(_, matched)| !matched)
+        {
+            marker.id = self.next_id;
+            self.next_id = self.next_id.wrapping_add(1).max(1);
+            self.tracks.push(Track {
+                id: marker.id,
+                x: marker.x,
+                y: marker.y,
+                missed: 0,
+            });
+        }
+    }
+}
+
+/// Detect reflectors in every scan from `scan_broadcast` and publish them on `topic`
+pub async fn start_reflector_detector(
+    zenoh_session: Arc<Session>,
+    topic: String,
+    args: ReflectorArgs,
+    scan_broadcast: ScanBroadcast,
+) -> anyhow::Result<()> {
+    let publisher = zenoh_session
+        .declare_publisher(topic.clone())
+        .congestion_control(CongestionControl::Drop)
+        .res()
+        .await
+        .map_err(ErrorWrapper::ZenohError)?;
+    info!(topic, "Publishing reflector markers");
+
+    let mut tracker =
+        ReflectorTracker::new(args.reflector_match_distance, args.reflector_max_missed);
+    let mut scan_subscriber = scan_broadcast.subscribe("reflectors");
+    tokio::spawn(async move {
+        while let Some(frame) = scan_subscriber.recv().await {
+            let mut markers = args.detect(&frame.points);
+            tracker.update(&mut markers);
+            let message = rplidar::ReflectorMarkers {
+                timestamp: Some(system_time_to_proto_time(&frame.capture_time)),
+                frame_id: frame.frame_id.clone(),
+                markers,
+            };
+            if let Err(err) = publisher.put(message.encode_to_vec()).res().await {
+                error!(?err, "Failed to publish reflector markers");
+            }
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args() -> ReflectorArgs {
+        ReflectorArgs {
+            detect_reflectors: true,
+            reflector_min_intensity: 200,
+            reflector_min_points: 2,
+            reflector_max_width: 0.2,
+            reflector_segment_gap: 0.05,
+            reflector_match_distance: 0.3,
+            reflector_max_missed: 1,
+        }
+    }
+
+    /// Wall at x = 2 with bright points at the given indices
+    fn wall(bright: &[usize]) -> Vec<Return> {
+        (0..40)
+            .map(|step| Return {
+                x: 2.0,
+                y: -0.4 + step as f64 * 0.02,
+                intensity: if bright.contains(&step) { 250 } else { 40 },
+            })
+            .collect()
+    }
+
+    #[test]
+    fn finds_bright_segments() {
+        let markers = args().find_markers(&wall(&[10, 11, 12, 30, 31]));
+        assert_eq!(markers.len(), 2);
+        assert_eq!(markers[0].points, 3);
+        assert!((markers[0].y - -0.18).abs() < 1e-9);
+        assert!((markers[0].range - 2.0f64.hypot(0.18)).abs() < 1e-9);
+        assert_eq!(markers[0].intensity, 250);
+
+        // single spikes and wide bright surfaces aren't markers
+        assert!(args().find_markers(&wall(&[5])).is_empty());
+        assert!(args()
+            .find_markers(&wall(&(0..20).collect::<Vec<_>>()))
+            .is_empty());
+    }
+
+    #[test]
+    fn keeps_ids_between_scans() {
+        let mut tracker = ReflectorTracker::new(0.3, 1);
+        let mut first = args().find_markers(&wall(&[10, 11, 30, 31]));
+        tracker.update(&mut first);
+        assert_eq!(first.iter().map(|m| m.id).collect::<Vec<_>>(), vec![1, 2]);
+
+        // first marker moved slightly, second one disappeared
+        let mut second = args().find_markers(&wall(&[11, 12]));
+        tracker.update(&mut second);
+        assert_eq!(second[0].id, 1);
+
+        let mut third = args().find_markers(&wall(&[11, 12]));
+        tracker.update(&mut third);
+        let mut fourth = args().find_markers(&wall(&[11, 12, 30, 31]));
+        tracker.update(&mut fourth);
+        assert_eq!(fourth.iter().map(|m| m.id).collect::<Vec<_>>(), vec![1, 3]);
+    }
+}
@@ -0,0 +1,159 @@
+use std::time::{Duration, Instant};
+
+use tracing::{error, info, warn};
+use zenoh::{config::Config, prelude::r#async::*, publication::CongestionControl, Session};
+use zenoh_config::EndPoint;
+
+use crate::{
+    device::ReconnectBackoff, encoding::ScanEncoder, qos::PublisherPriority,
+    scan_broadcast::ScanBroadcast, ErrorWrapper,
+};
+
+/// Downsampled copy of the scans for a remote router
+///
+/// Published from a separate client mode session so on-robot peers keep receiving
+/// full rate scans while a cloud router only gets a trickle
+#[derive(clap::Args, Debug, Clone)]
+pub struct RemoteArgs {
+    /// Remote router to also publish scans to, e.g. a cloud zenoh router
+    #[clap(long)]
+    pub remote_connect: Vec<EndPoint>,
+
+    /// Maximum scans per second published to the remote router
+    #[clap(long, default_value_t = 1.0)]
+    pub remote_scan_rate: f64,
+
+    /// Also publish point clouds to the remote router
+    #[clap(long)]
+    pub remote_point_cloud: bool,
+
+    /// Priority of publishers on the remote session
+    #[clap(long, value_enum, default_value_t = PublisherPriority::DataLow)]
+    pub remote_priority: PublisherPriority,
+}
+
+impl RemoteArgs {
+    pub fn is_enabled(&self) -> bool {
+        !self.remote_connect.is_empty()
+    }
+
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.remote_scan_rate.is_nan() || self.remote_scan_rate <= 0.0 {
+            anyhow::bail!("Remote scan rate must be positive");
+        }
+        Ok(())
+    }
+
+    fn min_scan_interval(&self) -> Duration {
+        Duration::from_secs_f64(1.0 / self.remote_scan_rate)
+    }
+}
+
+/// Topics published on the remote session
+pub struct RemoteTopics {
+    pub laser_scan: String,
+    /// `None` when point clouds aren't published
+    pub point_cloud: Option<String>,
+}
+
+/// Publish scans from `scan_broadcast` to the remote router
+///
+/// The session is opened in the background and retried until the router is reachable
+/// so an offline uplink doesn't hold up the driver
+pub fn start_remote_publisher(
+    args: &RemoteArgs,
+    topics: RemoteTopics,
+    encoder: ScanEncoder,
+    scan_broadcast: ScanBroadcast,
+) -> anyhow::Result<()> {
+    args.validate()?;
+    let mut zenoh_config = Config::default();
+    zenoh_config
+        .insert_json5("mode", "\"client\"")
+        .map_err(|err| anyhow::anyhow!("Failed to configure remote session: {:?}", err))?;
+    zenoh_config
+        .connect
+        .endpoints
+        .clone_from(&args.remote_connect);
+    info!(remote_connect = ?args.remote_connect, rate = args.remote_scan_rate, "Publishing scans to remote router");
+
+    let args = args.clone();
+    tokio::spawn(async move {
+        let mut backoff = ReconnectBackoff::default();
+        let session = loop {
+            match zenoh::open(zenoh_config.clone()).res().await {
+                Ok(session) => break session,
+                Err(err) => {
+                    let delay = backoff.next_delay();
+                    warn!(?err, ?delay, "Failed to open remote session, retrying");
+                    tokio::time::sleep(delay).await;
+                }
+            }
+        };
+        info!("Remote session opened");
+        if let Err(err) = publish_remote(session, &args, topics, encoder, scan_broadcast).await {
+            error!(?err, "Remote publisher stopped");
+        }
+    });
+    Ok(())
+}
+
+async fn publish_remote(
+    session: Session,
+    args: &RemoteArgs,
+    topics: RemoteTopics,
+    mut encoder: ScanEncoder,
+    scan_broadcast: ScanBroadcast,
+) -> anyhow::Result<()> {
+    let laser_scan_publisher = session
+        .declare_publisher(topics.laser_scan)
+        .priority(args.remote_priority.into())
+        .congestion_control(CongestionControl::Drop)
+        .res()
+        .await
+        .map_err(ErrorWrapper::ZenohError)?;
+    let point_cloud_publisher = match topics.point_cloud {
+        Some(topic) => Some(
+            session
+                .declare_publisher(topic)
+                .priority(args.remote_priority.into())
+                .congestion_control(CongestionControl::Drop)
+                .res()
+                .await
+                .map_err(ErrorWrapper::ZenohError)?,
+        ),
+        None => None,
+    };
+
+    let min_scan_interval = args.min_scan_interval();
+    let mut last_published: Option<Instant> = None;
+    let mut scan_subscriber = scan_broadcast.subscribe("remote");
+    while let Some(frame) = scan_subscriber.recv().await {
+        if last_published.is_some_and(|last| frame.capture_instant - last < min_scan_interval) {
+            continue;
+        }
+        last_published = Some(frame.capture_instant);
+
+        let laser_scan = encoder.encode_laser_scan(
+            &frame.capture_time,
+            &frame.frame_id,
+            &frame.pose,
+            &frame.points,
+        );
+        if let Err(err) = laser_scan_publisher.put(laser_scan).res().await {
+            warn!(?err, "Failed to publish laser scan to remote router");
+        }
+        if let Some(point_cloud_publisher) = &point_cloud_publisher {
+            let point_cloud = encoder.encode_point_cloud(
+                &frame.capture_time,
+                &frame.frame_id,
+                &frame.pose,
+                &frame.points,
+            );
+            if let Err(err) = point_cloud_publisher.put(point_cloud).res().await {
+                warn!(?err, "Failed to publish point cloud to remote router");
+            }
+        }
+    }
+    Ok(())
+}
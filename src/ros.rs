@@ -0,0 +1,594 @@
+//! Minimal ROS 2 message types encoded as CDR
+//!
+//! Only the messages published by this driver and the velocity it consumes are implemented.
+//! Payloads can be consumed by zenoh-bridge-ros2dds without any glue code
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rplidar_driver::ScanPoint;
+
+use crate::topics::{TopicBuilder, TopicError};
+
+/// ROS 2 output options shared by the driver and logger
+#[derive(clap::Args, Debug, Clone, Default)]
+pub struct Ros2Args {
+    /// Use ROS 2 sensor_msgs encoded in CDR
+    ///
+    /// Key expressions follow zenoh-bridge-ros2dds so ROS 2 nodes can subscribe directly
+    #[clap(long = "ros2")]
+    pub enabled: bool,
+
+    /// ROS 2 namespace for the ROS 2 topics
+    #[clap(long, default_value = "")]
+    pub ros2_namespace: String,
+
+    /// ROS 2 laser scan topic
+    #[clap(long, default_value = "scan")]
+    pub ros2_scan_topic: String,
+
+    /// ROS 2 point cloud topic
+    #[clap(long, default_value = "points")]
+    pub ros2_cloud_topic: String,
+}
+
+impl Ros2Args {
+    /// zenoh-bridge-ros2dds maps ROS topic /ns/scan to key expression ns/scan
+    fn key_expr(&self, topic: &str) -> Result<String, TopicError> {
+        TopicBuilder::new(&self.ros2_namespace)?.topic(topic)
+    }
+
+    pub fn scan_key_expr(&self) -> Result<String, TopicError> {
+        self.key_expr(&self.ros2_scan_topic)
+    }
+
+    pub fn cloud_key_expr(&self) -> Result<String, TopicError> {
+        self.key_expr(&self.ros2_cloud_topic)
+    }
+}
+
+/// Name and ros2msg definition used for MCAP schemas
+pub trait RosMessage {
+    const TYPE_NAME: &'static str;
+    /// Definition including dependencies in the ros2msg MCAP schema format
+    const DEFINITION: &'static str;
+}
+
+/// std_msgs/Header dependency appended to definitions of stamped messages
+macro_rules! header_definition {
+    () => {
+        "\
+================================================================================
+MSG: std_msgs/Header
+builtin_interfaces/Time stamp
+string frame_id
+================================================================================
+MSG: builtin_interfaces/Time
+int32 sec
+uint32 nanosec
+"
+    };
+}
+
+/// CDR little endian encapsulation header
+const CDR_LE_HEADER: [u8; 4] = [0x00, 0x01, 0x00, 0x00];
+
+/// Range ROS consumers use for "no return" (REP 117)
+const NO_RETURN_RANGE: f32 = f32::INFINITY;
+
+pub struct CdrWriter {
+    buffer: Vec<u8>,
+}
+
+impl Default for CdrWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CdrWriter {
+    pub fn new() -> Self {
+        Self {
+            buffer: CDR_LE_HEADER.to_vec(),
+        }
+    }
+
+    /// Alignment is relative to the end of the encapsulation header
+    fn align(&mut self, alignment: usize) {
+        let offset = self.buffer.len() - CDR_LE_HEADER.len();
+        let padding = (alignment - offset % alignment) % alignment;
+        self.buffer.extend(std::iter::repeat(0).take(padding));
+    }
+
+    pub fn write_u8(&mut self, value: u8) {
+        self.buffer.push(value);
+    }
+
+    pub fn write_bool(&mut self, value: bool) {
+        self.buffer.push(value as u8);
+    }
+
+    pub fn write_i32(&mut self, value: i32) {
+        self.align(4);
+        self.buffer.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u32(&mut self, value: u32) {
+        self.align(4);
+        self.buffer.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_f32(&mut self, value: f32) {
+        self.align(4);
+        self.buffer.extend_from_slice(&value.to_le_bytes());
+    }
+
+    /// Strings are length prefixed and null terminated
+    pub fn write_string(&mut self, value: &str) {
+        self.write_u32(value.len() as u32 + 1);
+        self.buffer.extend_from_slice(value.as_bytes());
+        self.buffer.push(0);
+    }
+
+    pub fn write_f32_sequence(&mut self, values: &[f32]) {
+        self.write_u32(values.len() as u32);
+        for value in values {
+            self.buffer.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+
+    pub fn write_u8_sequence(&mut self, values: &[u8]) {
+        self.write_u32(values.len() as u32);
+        self.buffer.extend_from_slice(values);
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.buffer
+    }
+}
+
+pub trait CdrEncode {
+    fn encode(&self, writer: &mut CdrWriter);
+
+    fn to_cdr(&self) -> Vec<u8> {
+        let mut writer = CdrWriter::new();
+        self.encode(&mut writer);
+        writer.finish()
+    }
+}
+
+/// builtin_interfaces/msg/Time
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Time {
+    pub sec: i32,
+    pub nanosec: u32,
+}
+
+/// Times before the unix epoch are clamped to the epoch
+impl From<&SystemTime> for Time {
+    fn from(time: &SystemTime) -> Self {
+        let duration = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+        Self {
+            sec: duration.as_secs() as i32,
+            nanosec: duration.subsec_nanos(),
+        }
+    }
+}
+
+impl CdrEncode for Time {
+    fn encode(&self, writer: &mut CdrWriter) {
+        writer.write_i32(self.sec);
+        writer.write_u32(self.nanosec);
+    }
+}
+
+/// std_msgs/msg/Header
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Header {
+    pub stamp: Time,
+    pub frame_id: String,
+}
+
+impl CdrEncode for Header {
+    fn encode(&self, writer: &mut CdrWriter) {
+        self.stamp.encode(writer);
+        writer.write_string(&self.frame_id);
+    }
+}
+
+/// sensor_msgs/msg/LaserScan
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct LaserScan {
+    pub header: Header,
+    pub angle_min: f32,
+    pub angle_max: f32,
+    pub angle_increment: f32,
+    pub time_increment: f32,
+    pub scan_time: f32,
+    pub range_min: f32,
+    pub range_max: f32,
+    pub ranges: Vec<f32>,
+    pub intensities: Vec<f32>,
+}
+
+impl LaserScan {
+    /// Build from a sorted scan
+    ///
+    /// rplidar angles go clockwise while ROS angles go counterclockwise
+    /// so the scan is reversed and angles negated.
+    /// Invalid points are reported as no return
+    pub fn from_scan(
+        stamp: &SystemTime,
+        frame_id: &str,
+        scan: &[ScanPoint],
+        range_min: f32,
+        range_max: f32,
+        scan_time: f32,
+    ) -> Self {
+        let angle_min = scan.last().map(|point| -point.angle()).unwrap_or_default();
+        let angle_max = scan.first().map(|point| -point.angle()).unwrap_or_default();
+        let angle_increment = if scan.len() > 1 {
+            (angle_max - angle_min) / (scan.len() - 1) as f32
+        } else {
+            0.0
+        };
+        let time_increment = if scan.is_empty() {
+            0.0
+        } else {
+            scan_time / scan.len() as f32
+        };
+
+        Self {
+            header: Header {
+                stamp: Time::from(stamp),
+                frame_id: frame_id.to_owned(),
+            },
+            angle_min,
+            angle_max,
+            angle_increment,
+            time_increment,
+            scan_time,
+            range_min,
+            range_max,
+            ranges: scan
+                .iter()
+                .rev()
+                .map(|point| {
+                    if point.is_valid() {
+                        point.distance()
+                    } else {
+                        NO_RETURN_RANGE
+                    }
+                })
+                .collect(),
+            intensities: scan
+                .iter()
+                .rev()
+                .map(|point| point.quality as f32)
+                .collect(),
+        }
+    }
+}
+
+impl RosMessage for LaserScan {
+    const TYPE_NAME: &'static str = "sensor_msgs/msg/LaserScan";
+    const DEFINITION: &'static str = concat!(
+        "\
+std_msgs/Header header
+float32 angle_min
+float32 angle_max
+float32 angle_increment
+float32 time_increment
+float32 scan_time
+float32 range_min
+float32 range_max
+float32[] ranges
+float32[] intensities
+",
+        header_definition!()
+    );
+}
+
+impl CdrEncode for LaserScan {
+    fn encode(&self, writer: &mut CdrWriter) {
+        self.header.encode(writer);
+        writer.write_f32(self.angle_min);
+        writer.write_f32(self.angle_max);
+        writer.write_f32(self.angle_increment);
+        writer.write_f32(self.time_increment);
+        writer.write_f32(self.scan_time);
+        writer.write_f32(self.range_min);
+        writer.write_f32(self.range_max);
+        writer.write_f32_sequence(&self.ranges);
+        writer.write_f32_sequence(&self.intensities);
+    }
+}
+
+/// sensor_msgs/msg/PointField
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct PointField {
+    pub name: String,
+    pub offset: u32,
+    pub datatype: u8,
+    pub count: u32,
+}
+
+impl PointField {
+    pub const FLOAT32: u8 = 7;
+
+    fn float32(name: &str, offset: u32) -> Self {
+        Self {
+            name: name.to_owned(),
+            offset,
+            datatype: Self::FLOAT32,
+            count: 1,
+        }
+    }
+}
+
+impl CdrEncode for PointField {
+    fn encode(&self, writer: &mut CdrWriter) {
+        writer.write_string(&self.name);
+        writer.write_u32(self.offset);
+        writer.write_u8(self.datatype);
+        writer.write_u32(self.count);
+    }
+}
+
+/// sensor_msgs/msg/PointCloud2
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct PointCloud2 {
+    pub header: Header,
+    pub height: u32,
+    pub width: u32,
+    pub fields: Vec<PointField>,
+    pub is_bigendian: bool,
+    pub point_step: u32,
+    pub row_step: u32,
+    pub data: Vec<u8>,
+    pub is_dense: bool,
+}
+
+impl PointCloud2 {
+    /// Unordered cloud of valid points with x, y, z and intensity fields
+    pub fn from_scan(stamp: &SystemTime, frame_id: &str, scan: &[ScanPoint]) -> Self {
+        //               x   y   z   intensity
+        let point_step = 4 + 4 + 4 + 4;
+        let mut data = Vec::with_capacity(scan.len() * point_step as usize);
+        let mut width = 0;
+        for point in scan.iter().filter(|point| point.is_valid()) {
+            let x = point.distance() * (-point.angle()).cos();
+            let y = point.distance() * (-point.angle()).sin();
+            data.extend_from_slice(&x.to_le_bytes());
+            data.extend_from_slice(&y.to_le_bytes());
+            data.extend_from_slice(&0f32.to_le_bytes());
+            data.extend_from_slice(&(point.quality as f32).to_le_bytes());
+            width += 1;
+        }
+
+        Self {
+            header: Header {
+                stamp: Time::from(stamp),
+                frame_id: frame_id.to_owned(),
+            },
+            height: 1,
+            width,
+            fields: vec![
+                PointField::float32("x", 0),
+                PointField::float32("y", 4),
+                PointField::float32("z", 8),
+                PointField::float32("intensity", 12),
+            ],
+            is_bigendian: false,
+            point_step,
+            row_step: point_step * width,
+            data,
+            is_dense: true,
+        }
+    }
+}
+
+impl RosMessage for PointCloud2 {
+    const TYPE_NAME: &'static str = "sensor_msgs/msg/PointCloud2";
+    const DEFINITION: &'static str = concat!(
+        "\
+std_msgs/Header header
+uint32 height
+uint32 width
+sensor_msgs/PointField[] fields
+bool is_bigendian
+uint32 point_step
+uint32 row_step
+uint8[] data
+bool is_dense
+",
+        header_definition!(),
+        "\
+================================================================================
+MSG: sensor_msgs/PointField
+uint8 INT8=1
+uint8 UINT8=2
+uint8 INT16=3
+uint8 UINT16=4
+uint8 INT32=5
+uint8 UINT32=6
+uint8 FLOAT32=7
+uint8 FLOAT64=8
+string name
+uint32 offset
+uint8 datatype
+uint32 count
+"
+    );
+}
+
+impl CdrEncode for PointCloud2 {
+    fn encode(&self, writer: &mut CdrWriter) {
+        self.header.encode(writer);
+        writer.write_u32(self.height);
+        writer.write_u32(self.width);
+        writer.write_u32(self.fields.len() as u32);
+        for field in &self.fields {
+            field.encode(writer);
+        }
+        writer.write_bool(self.is_bigendian);
+        writer.write_u32(self.point_step);
+        writer.write_u32(self.row_step);
+        writer.write_u8_sequence(&self.data);
+        writer.write_bool(self.is_dense);
+    }
+}
+
+/// geometry_msgs/msg/Twist
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Twist {
+    /// linear x, y, z in m/s
+    pub linear: [f64; 3],
+    /// angular x, y, z in rad/s
+    pub angular: [f64; 3],
+}
+
+impl Twist {
+    /// Decode a CDR payload in either byte order
+    pub fn from_cdr(payload: &[u8]) -> anyhow::Result<Self> {
+        // encapsulation header followed by six doubles, already 8 byte aligned
+        if payload.len() < 4 + 6 * 8 {
+            anyhow::bail!("Twist payload too short ({} bytes)", payload.len());
+        }
+        let little_endian = match payload[1] {
+            0x00 => false,
+            0x01 => true,
+            kind => anyhow::bail!("Unsupported CDR encapsulation {:#04x}", kind),
+        };
+        let mut values = [0.0; 6];
+        for (index, value) in values.iter_mut().enumerate() {
+            let start = 4 + index * 8;
+            let bytes: [u8; 8] = payload[start..start + 8].try_into()?;
+            *value = if little_endian {
+                f64::from_le_bytes(bytes)
+            } else {
+                f64::from_be_bytes(bytes)
+            };
+        }
+        Ok(Self {
+            linear: [values[0], values[1], values[2]],
+            angular: [values[3], values[4], values[5]],
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header() -> Header {
+        Header {
+            stamp: Time { sec: 1, nanosec: 2 },
+            frame_id: "ab".to_owned(),
+        }
+    }
+
+    #[rustfmt::skip]
+    const HEADER_BYTES: [u8; 20] = [
+        0x00, 0x01, 0x00, 0x00, // little endian encapsulation
+        0x01, 0x00, 0x00, 0x00, // sec
+        0x02, 0x00, 0x00, 0x00, // nanosec
+        0x03, 0x00, 0x00, 0x00, b'a', b'b', 0x00, // frame_id with terminator
+        0x00, // padding to 4
+    ];
+
+    #[test]
+    fn encodes_laser_scan() {
+        let laser_scan = LaserScan {
+            header: header(),
+            angle_min: -1.0,
+            angle_max: 1.0,
+            angle_increment: 0.5,
+            time_increment: 0.25,
+            scan_time: 2.0,
+            range_min: 0.25,
+            range_max: 4.0,
+            ranges: vec![1.0, NO_RETURN_RANGE],
+            intensities: vec![8.0],
+        };
+        #[rustfmt::skip]
+        let body = [
+            0x00, 0x00, 0x80, 0xbf, // angle_min
+            0x00, 0x00, 0x80, 0x3f, // angle_max
+            0x00, 0x00, 0x00, 0x3f, // angle_increment
+            0x00, 0x00, 0x80, 0x3e, // time_increment
+            0x00, 0x00, 0x00, 0x40, // scan_time
+            0x00, 0x00, 0x80, 0x3e, // range_min
+            0x00, 0x00, 0x80, 0x40, // range_max
+            0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x80, 0x3f, 0x00, 0x00, 0x80, 0x7f, // ranges
+            0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x41, // intensities
+        ];
+        assert_eq!(laser_scan.to_cdr(), [&HEADER_BYTES[..], &body].concat());
+    }
+
+    #[test]
+    fn encodes_point_cloud() {
+        let point_cloud = PointCloud2 {
+            header: header(),
+            height: 1,
+            width: 1,
+            fields: vec![PointField::float32("x", 0)],
+            is_bigendian: false,
+            point_step: 4,
+            row_step: 4,
+            data: 1f32.to_le_bytes().to_vec(),
+            is_dense: true,
+        };
+        #[rustfmt::skip]
+        let body = [
+            0x01, 0x00, 0x00, 0x00, // height
+            0x01, 0x00, 0x00, 0x00, // width
+            0x01, 0x00, 0x00, 0x00, // fields length
+            0x02, 0x00, 0x00, 0x00, b'x', 0x00, // name
+            0x00, 0x00, // padding to 4
+            0x00, 0x00, 0x00, 0x00, // offset
+            0x07, // datatype
+            0x00, 0x00, 0x00, // padding to 4
+            0x01, 0x00, 0x00, 0x00, // count
+            0x00, // is_bigendian
+            0x00, 0x00, 0x00, // padding to 4
+            0x04, 0x00, 0x00, 0x00, // point_step
+            0x04, 0x00, 0x00, 0x00, // row_step
+            0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0x80, 0x3f, // data
+            0x01, // is_dense
+        ];
+        assert_eq!(point_cloud.to_cdr(), [&HEADER_BYTES[..], &body].concat());
+    }
+
+    #[test]
+    fn decodes_twist() {
+        // doubles are 8 byte aligned relative to the end of the encapsulation header so they
+        // follow it without padding
+        let values = [0.5f64, 0.0, 0.0, 0.0, 0.0, -1.25];
+        let mut little_endian = vec![0x00, 0x01, 0x00, 0x00];
+        let mut big_endian = vec![0x00, 0x00, 0x00, 0x00];
+        for value in values {
+            little_endian.extend_from_slice(&value.to_le_bytes());
+            big_endian.extend_from_slice(&value.to_be_bytes());
+        }
+        assert_eq!(&little_endian[4..12], &[0, 0, 0, 0, 0, 0, 0xe0, 0x3f]);
+        let expected = Twist {
+            linear: [0.5, 0.0, 0.0],
+            angular: [0.0, 0.0, -1.25],
+        };
+        assert_eq!(Twist::from_cdr(&little_endian).unwrap(), expected);
+        assert_eq!(Twist::from_cdr(&big_endian).unwrap(), expected);
+
+        little_endian[1] = 0x02;
+        assert!(Twist::from_cdr(&little_endian).is_err());
+    }
+
+    #[test]
+    fn rejects_truncated_twist() {
+        let mut payload = vec![0x00, 0x01, 0x00, 0x00];
+        payload.extend(std::iter::repeat(0).take(6 * 8));
+        assert!(Twist::from_cdr(&payload).is_ok());
+        assert!(Twist::from_cdr(&payload[..payload.len() - 1]).is_err());
+        assert!(Twist::from_cdr(&payload[..4]).is_err());
+        assert!(Twist::from_cdr(&[]).is_err());
+    }
+}
@@ -0,0 +1,85 @@
+//! rosbridge v2 protocol JSON messages for ROS 1 web tooling
+
+use serde::Serialize;
+
+use crate::ros;
+
+/// rosbridge publish operation
+#[derive(Serialize, Debug)]
+pub struct Publish<'a, M: Serialize> {
+    op: &'static str,
+    topic: &'a str,
+    msg: M,
+}
+
+impl<'a, M: Serialize> Publish<'a, M> {
+    pub fn new(topic: &'a str, msg: M) -> Self {
+        Self {
+            op: "publish",
+            topic,
+            msg,
+        }
+    }
+}
+
+/// ROS 1 time
+#[derive(Serialize, Debug, Clone, Copy, Default)]
+pub struct Time {
+    pub secs: u32,
+    pub nsecs: u32,
+}
+
+/// ROS 1 std_msgs/Header
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct Header {
+    pub seq: u32,
+    pub stamp: Time,
+    pub frame_id: String,
+}
+
+/// ROS 1 sensor_msgs/LaserScan
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct LaserScan {
+    pub header: Header,
+    pub angle_min: f32,
+    pub angle_max: f32,
+    pub angle_increment: f32,
+    pub time_increment: f32,
+    pub scan_time: f32,
+    pub range_min: f32,
+    pub range_max: f32,
+    pub ranges: Vec<f32>,
+    pub intensities: Vec<f32>,
+}
+
+impl LaserScan {
+    /// Convert from the ROS 2 message
+    ///
+    /// JSON can't represent infinity so no return ranges are reported as 0,
+    /// which is below range_min and treated as invalid by consumers
+    pub fn from_ros2(laser_scan: ros::LaserScan, seq: u32) -> Self {
+        Self {
+            header: Header {
+                seq,
+                stamp: Time {
+                    secs: laser_scan.header.stamp.sec as u32,
+                    nsecs: laser_scan.header.stamp.nanosec,
+                },
+                frame_id: laser_scan.header.frame_id,
+            },
+            angle_min: laser_scan.angle_min,
+            angle_max: laser_scan.angle_max,
+            angle_increment: laser_scan.angle_increment,
+            time_increment: laser_scan.time_increment,
+            scan_time: laser_scan.scan_time,
+            range_min: laser_scan.range_min,
+            range_max: laser_scan.range_max,
+            ranges: laser_scan
+                .ranges
+                .into_iter()
+                .map(|range| if range.is_finite() { range } else { 0.0 })
+                .collect(),
+            intensities: laser_scan.intensities,
+        }
+    }
+}
@@ -0,0 +1,34 @@
+use tokio::runtime::Runtime;
+
+/// Shape of the tokio runtime
+///
+/// zenoh doesn't support tokio's current thread scheduler so a multi threaded runtime
+/// is always used. On single core boards use `--worker-threads 1`
+#[derive(clap::Args, Debug, Clone, Default)]
+pub struct RuntimeArgs {
+    /// Number of tokio worker threads
+    ///
+    /// Defaults to the number of CPU cores
+    #[clap(long, value_parser = clap::value_parser!(u16).range(1..))]
+    pub worker_threads: Option<u16>,
+
+    /// Maximum number of threads for blocking work such as scan encoding
+    ///
+    /// Defaults to tokio's limit of 512. Threads are only started when needed
+    #[clap(long, value_parser = clap::value_parser!(u16).range(1..))]
+    pub max_blocking_threads: Option<u16>,
+}
+
+impl RuntimeArgs {
+    pub fn build(&self) -> std::io::Result<Runtime> {
+        let mut builder = tokio::runtime::Builder::new_multi_thread();
+        builder.enable_all();
+        if let Some(worker_threads) = self.worker_threads {
+            builder.worker_threads(worker_threads as usize);
+        }
+        if let Some(max_blocking_threads) = self.max_blocking_threads {
+            builder.max_blocking_threads(max_blocking_threads as usize);
+        }
+        builder.build()
+    }
+}
@@ -0,0 +1,262 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
+
+use rplidar_driver::ScanPoint;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::warn;
+
+use crate::{foxglove, monitoring, timestamping::CaptureClock};
+
+/// Scans buffered for each in-process consumer before it starts skipping
+pub const SCAN_BROADCAST_CAPACITY: usize = 16;
+
+/// Scan and its capture metadata as it moves from the lidar thread to the publishers
+///
+/// The lidar thread captures it, processing sorts and filters the points and sets the
+/// frame and pose before it's shared with in-process consumers
+#[derive(Debug)]
+pub struct ScanFrame {
+    pub points: Vec<ScanPoint>,
+    pub capture_time: SystemTime,
+    pub capture_instant: Instant,
+    /// Counts scans grabbed since the driver started, gaps mean scans were dropped
+    pub sequence: u64,
+    /// Serial number of the lidar
+    pub device_id: String,
+    /// Time since the previous scan, zero for the first scan after the lidar started
+    pub scan_duration: Duration,
+    pub frame_id: String,
+    pub pose: foxglove::Pose,
+}
+
+impl ScanFrame {
+    /// Scan grabbed just now, stamped by `clock`
+    pub fn captured(
+        points: Vec<ScanPoint>,
+        sequence: u64,
+        device_id: String,
+        clock: &CaptureClock,
+    ) -> Self {
+        let (capture_time, capture_instant) = clock.now();
+        Self {
+            points,
+            capture_time,
+            capture_instant,
+            sequence,
+            device_id,
+            scan_duration: Duration::ZERO,
+            frame_id: String::new(),
+            pose: foxglove::Pose::default(),
+        }
+    }
+}
+
+/// Fans processed scans out to in-process consumers
+///
+/// Scans are shared behind an `Arc` so consumers don't copy them. A consumer that falls
+/// more than the capacity behind skips the oldest scans instead of slowing down the others
+#[derive(Clone)]
+pub struct ScanBroadcast {
+    sender: broadcast::Sender<Arc<ScanFrame>>,
+}
+
+impl ScanBroadcast {
+    pub fn new(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity);
+        Self { sender }
+    }
+
+    /// Subscribe to scans sent from now on
+    ///
+    /// `name` identifies the consumer in logs and metrics
+    pub fn subscribe(&self, name: &'static str) -> ScanSubscriber {
+        ScanSubscriber {
+            receiver: self.sender.subscribe(),
+            name,
+        }
+    }
+
+    /// Hand a scan to all current subscribers
+    pub fn send(&self, frame: Arc<ScanFrame>) {
+        // no subscribers isn't an error, consumers come and go
+        let _ = self.sender.send(frame);
+    }
+
+    pub fn subscriber_count(&self) -> usize {
+        self.sender.receiver_count()
+    }
+}
+
+pub struct ScanSubscriber {
+    receiver: broadcast::Receiver<Arc<ScanFrame>>,
+    name: &'static str,
+}
+
+impl ScanSubscriber {
+    /// Next scan, `None` once the broadcast is dropped
+    ///
+    /// Skipped scans are logged and counted. Cancel safe so it can be used in `select!`
+    pub async fn recv(&mut self) -> Option<Arc<ScanFrame>> {
+        loop {
+            match self.receiver.recv().await {
+                Ok(frame) => return Some(frame),
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!(
+                        "Scan consumer {} fell behind, skipped {} scans",
+                        self.name, skipped
+                    );
+                    metrics::counter!(monitoring::SCAN_BROADCAST_SKIPPED_TOTAL, "consumer" => self.name)
+                        .increment(skipped);
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    }
+}
+
+#[cfg(feature = "serde-types")]
+mod serde_support {
+    use std::time::{Duration, Instant, SystemTime};
+
+    use rplidar_driver::ScanPoint;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::ScanFrame;
+    use crate::foxglove;
+
+    /// Raw fields of a [`ScanPoint`] so nothing is lost to unit conversions
+    #[derive(Serialize, Deserialize)]
+    struct PointRecord {
+        angle_z_q14: u16,
+        dist_mm_q2: u32,
+        quality: u8,
+        flag: u8,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct PoseRecord {
+        position: [f64; 3],
+        /// x, y, z, w
+        orientation: [f64; 4],
+    }
+
+    /// Serialized [`ScanFrame`], the capture instant is process local so it's left out
+    #[derive(Serialize, Deserialize)]
+    struct FrameRecord {
+        points: Vec<PointRecord>,
+        capture_time: SystemTime,
+        sequence: u64,
+        device_id: String,
+        scan_duration: Duration,
+        frame_id: String,
+        pose: PoseRecord,
+    }
+
+    impl Serialize for ScanFrame {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let position = self.pose.position.unwrap_or_default();
+            let orientation = self.pose.orientation.unwrap_or(foxglove::Quaternion {
+                x: 0.0,
+                y: 0.0,
+                z: 0.0,
+                w: 1.0,
+            });
+            FrameRecord {
+                points: self
+                    .points
+                    .iter()
+                    .map(|point| PointRecord {
+                        angle_z_q14: point.angle_z_q14,
+                        dist_mm_q2: point.dist_mm_q2,
+                        quality: point.quality,
+                        flag: point.flag,
+                    })
+                    .collect(),
+                capture_time: self.capture_time,
+                sequence: self.sequence,
+                device_id: self.device_id.clone(),
+                scan_duration: self.scan_duration,
+                frame_id: self.frame_id.clone(),
+                pose: PoseRecord {
+                    position: [position.x, position.y, position.z],
+                    orientation: [orientation.x, orientation.y, orientation.z, orientation.w],
+                },
+            }
+            .serialize(serializer)
+        }
+    }
+
+    impl<'de> Deserialize<'de> for ScanFrame {
+        /// The capture instant is estimated from the age of the capture time
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            let record = FrameRecord::deserialize(deserializer)?;
+            let age = SystemTime::now()
+                .duration_since(record.capture_time)
+                .unwrap_or_default();
+            let now = Instant::now();
+            let [x, y, z] = record.pose.position;
+            let [qx, qy, qz, qw] = record.pose.orientation;
+            Ok(ScanFrame {
+                points: record
+                    .points
+                    .into_iter()
+                    .map(|point| ScanPoint {
+                        angle_z_q14: point.angle_z_q14,
+                        dist_mm_q2: point.dist_mm_q2,
+                        quality: point.quality,
+                        flag: point.flag,
+                    })
+                    .collect(),
+                capture_time: record.capture_time,
+                capture_instant: now.checked_sub(age).unwrap_or(now),
+                sequence: record.sequence,
+                device_id: record.device_id,
+                scan_duration: record.scan_duration,
+                frame_id: record.frame_id,
+                pose: foxglove::Pose {
+                    position: Some(foxglove::Vector3 { x, y, z }),
+                    orientation: Some(foxglove::Quaternion {
+                        x: qx,
+                        y: qy,
+                        z: qz,
+                        w: qw,
+                    }),
+                },
+            })
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::timestamping::CaptureClock;
+
+        #[test]
+        fn round_trips_json() {
+            let mut frame = ScanFrame::captured(
+                vec![ScanPoint {
+                    angle_z_q14: 4096,
+                    dist_mm_q2: 4000,
+                    quality: 47,
+                    flag: 1,
+                }],
+                7,
+                "ABCD".to_owned(),
+                &CaptureClock::System,
+            );
+            frame.scan_duration = Duration::from_millis(100);
+            frame.frame_id = "laser".to_owned();
+            let json = serde_json::to_string(&frame).unwrap();
+            let decoded: ScanFrame = serde_json::from_str(&json).unwrap();
+            assert_eq!(decoded.points[0].dist_mm_q2, 4000);
+            assert_eq!(decoded.points[0].flag, 1);
+            assert_eq!(decoded.capture_time, frame.capture_time);
+            assert_eq!(decoded.sequence, 7);
+            assert_eq!(decoded.device_id, "ABCD");
+            assert_eq!(decoded.scan_duration, frame.scan_duration);
+            assert_eq!(decoded.pose.orientation.unwrap().w, 1.0);
+        }
+    }
+}
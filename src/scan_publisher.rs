@@ -0,0 +1,282 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use prost::Message;
+use tracing::{info_span, Instrument};
+use zenoh::{prelude::r#async::*, publication::Publisher};
+
+use crate::{
+    compression::CompressionArgs,
+    device::ScanModeInfo,
+    encoding::{LaserScanBatchEncoder, ScanEncoder},
+    profiling::{self, StageProfiler},
+    ros::{self, CdrEncode},
+    rosbridge, rplidar,
+    scan_broadcast::ScanFrame,
+    system_time_to_proto_time,
+};
+
+/// Per scan inputs moved out of the scan loop so encoding can run on the blocking pool
+pub struct EncodeJob {
+    pub encoder: ScanEncoder,
+    /// shared with the other in-process consumers
+    pub frame: Arc<ScanFrame>,
+    pub range_min: f32,
+    pub range_max: f32,
+    pub laser_scan: bool,
+    pub point_cloud: bool,
+    pub ros2_laser_scan: bool,
+    pub ros2_point_cloud: bool,
+    pub compression: CompressionArgs,
+    pub profiler: StageProfiler,
+    /// rosbridge topic and sequence number
+    pub rosbridge: Option<(String, u32)>,
+    pub scan_mode: Option<ScanModeInfo>,
+    pub span: tracing::Span,
+}
+
+impl EncodeJob {
+    pub fn encode(mut self) -> anyhow::Result<EncodedScan> {
+        let _entered = info_span!(parent: &self.span, "encode_scan").entered();
+        let encode_start = Instant::now();
+        let mut project_time = Duration::ZERO;
+        let encoder = &mut self.encoder;
+        let mut payloads = ScanPayloads::default();
+        if self.laser_scan {
+            payloads.laser_scan = Some(encoder.encode_laser_scan(
+                &self.frame.capture_time,
+                &self.frame.frame_id,
+                &self.frame.pose,
+                &self.frame.points,
+            ));
+        }
+        if self.point_cloud {
+            let project_start = Instant::now();
+            let point_cloud = encoder.point_cloud(
+                &self.frame.capture_time,
+                &self.frame.frame_id,
+                &self.frame.pose,
+                &self.frame.points,
+            );
+            project_time += project_start.elapsed();
+            let payload = point_cloud.encode_to_vec();
+            payloads.point_cloud = Some(self.compression.compress(payload)?);
+        }
+
+        let ros_laser_scan = (self.ros2_laser_scan || self.rosbridge.is_some()).then(|| {
+            ros::LaserScan::from_scan(
+                &self.frame.capture_time,
+                &self.frame.frame_id,
+                &self.frame.points,
+                self.range_min,
+                self.range_max,
+                self.frame.scan_duration.as_secs_f32(),
+            )
+        });
+        if self.ros2_laser_scan {
+            payloads.ros2_laser_scan = ros_laser_scan.as_ref().map(CdrEncode::to_cdr);
+        }
+        if let (Some((rosbridge_topic, seq)), Some(laser_scan)) = (&self.rosbridge, ros_laser_scan)
+        {
+            let message = rosbridge::Publish::new(
+                rosbridge_topic,
+                rosbridge::LaserScan::from_ros2(laser_scan, *seq),
+            );
+            payloads.rosbridge_laser_scan = Some(serde_json::to_vec(&message)?);
+        }
+        if self.ros2_point_cloud {
+            let project_start = Instant::now();
+            let point_cloud = ros::PointCloud2::from_scan(
+                &self.frame.capture_time,
+                &self.frame.frame_id,
+                &self.frame.points,
+            );
+            project_time += project_start.elapsed();
+            payloads.ros2_point_cloud = Some(point_cloud.to_cdr());
+        }
+
+        payloads.metadata = Some(self.scan_metadata().encode_to_vec());
+
+        if self.point_cloud || self.ros2_point_cloud {
+            self.profiler.record(profiling::PROJECT, project_time);
+        }
+        self.profiler
+            .record(profiling::ENCODE, encode_start.elapsed() - project_time);
+
+        Ok(EncodedScan {
+            encoder: self.encoder,
+            payloads,
+            capture_instant: self.frame.capture_instant,
+            span: self.span,
+        })
+    }
+
+    fn scan_metadata(&self) -> rplidar::ScanMetadata {
+        let point_count = self.frame.points.len();
+        let mut metadata = rplidar::ScanMetadata {
+            timestamp: Some(system_time_to_proto_time(&self.frame.capture_time)),
+            frame_id: self.frame.frame_id.clone(),
+            point_count: point_count as u32,
+            sequence: self.frame.sequence,
+            device_id: self.frame.device_id.clone(),
+            ..Default::default()
+        };
+        if point_count > 0 {
+            metadata.angular_resolution = std::f64::consts::TAU / point_count as f64;
+        }
+        if !self.frame.scan_duration.is_zero() {
+            metadata.scan_frequency = 1.0 / self.frame.scan_duration.as_secs_f64();
+        }
+        if let Some(scan_mode) = &self.scan_mode {
+            metadata.scan_mode_id = scan_mode.id as u32;
+            metadata.scan_mode_name.clone_from(&scan_mode.name);
+            metadata.dense = scan_mode.is_dense();
+            metadata.sample_duration_us = scan_mode.us_per_sample as f64;
+            metadata.max_distance = scan_mode.max_distance as f64;
+        }
+        metadata
+    }
+}
+
+pub struct EncodedScan {
+    /// handed back so its buffers are reused for the next scan
+    pub encoder: ScanEncoder,
+    pub payloads: ScanPayloads,
+    pub capture_instant: Instant,
+    pub span: tracing::Span,
+}
+
+#[derive(Default)]
+pub struct ScanPayloads {
+    pub laser_scan: Option<Vec<u8>>,
+    /// payload and encoding which marks compressed payloads
+    pub point_cloud: Option<(Vec<u8>, Encoding)>,
+    pub ros2_laser_scan: Option<Vec<u8>>,
+    pub rosbridge_laser_scan: Option<Vec<u8>>,
+    pub ros2_point_cloud: Option<Vec<u8>>,
+    pub metadata: Option<Vec<u8>>,
+}
+
+/// Publishers are shared so matching listeners can outlive a borrow of them
+pub struct ScanPublishers {
+    pub laser_scan: Option<Arc<Publisher<'static>>>,
+    pub point_cloud: Option<Arc<Publisher<'static>>>,
+    pub ros2_laser_scan: Option<Arc<Publisher<'static>>>,
+    pub ros2_point_cloud: Option<Arc<Publisher<'static>>>,
+    /// rosbridge topic name and publisher
+    pub rosbridge: Option<(String, Arc<Publisher<'static>>)>,
+    pub metadata: Arc<Publisher<'static>>,
+    pub laser_scan_batch: Option<LaserScanBatchEncoder>,
+}
+
+impl ScanPublishers {
+    pub fn publishers(&self) -> impl Iterator<Item = &Arc<Publisher<'static>>> {
+        [
+            self.laser_scan.as_ref(),
+            self.point_cloud.as_ref(),
+            self.ros2_laser_scan.as_ref(),
+            self.ros2_point_cloud.as_ref(),
+            self.rosbridge.as_ref().map(|(_, publisher)| publisher),
+            Some(&self.metadata),
+        ]
+        .into_iter()
+        .flatten()
+    }
+
+    /// Whether any publisher has matching subscribers
+    pub async fn has_subscribers(&self) -> zenoh::Result<bool> {
+        for publisher in self.publishers() {
+            if publisher
+                .matching_status()
+                .res()
+                .await?
+                .matching_subscribers()
+            {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Publish encoded payloads returning the number of bytes sent
+    ///
+    /// Stops at the first failed put, the remaining payloads of the scan are dropped
+    pub async fn publish(
+        &mut self,
+        payloads: ScanPayloads,
+        scan_span: &tracing::Span,
+    ) -> zenoh::Result<usize> {
+        let mut published_bytes = 0;
+
+        if let (Some(publisher), Some(payload)) = (&self.laser_scan, payloads.laser_scan) {
+            let payload = match &mut self.laser_scan_batch {
+                Some(laser_scan_batch) => laser_scan_batch.push(&payload),
+                None => Some(payload),
+            };
+            if let Some(payload) = payload {
+                published_bytes += payload.len();
+                publisher
+                    .put(payload)
+                    .res()
+                    .instrument(info_span!(parent: scan_span, "publish_laser_scan"))
+                    .await?;
+            }
+        }
+
+        if let (Some(publisher), Some((payload, encoding))) =
+            (&self.point_cloud, payloads.point_cloud)
+        {
+            published_bytes += payload.len();
+            publisher
+                .put(Value::from(payload).encoding(encoding))
+                .res()
+                .instrument(info_span!(parent: scan_span, "publish_point_cloud"))
+                .await?;
+        }
+
+        if let (Some(publisher), Some(payload)) = (&self.ros2_laser_scan, payloads.ros2_laser_scan)
+        {
+            published_bytes += payload.len();
+            publisher
+                .put(payload)
+                .res()
+                .instrument(info_span!(parent: scan_span, "publish_ros2_laser_scan"))
+                .await?;
+        }
+
+        if let (Some((_, publisher)), Some(payload)) =
+            (&self.rosbridge, payloads.rosbridge_laser_scan)
+        {
+            published_bytes += payload.len();
+            publisher
+                .put(payload)
+                .res()
+                .instrument(info_span!(parent: scan_span, "publish_rosbridge_laser_scan"))
+                .await?;
+        }
+
+        if let (Some(publisher), Some(payload)) =
+            (&self.ros2_point_cloud, payloads.ros2_point_cloud)
+        {
+            published_bytes += payload.len();
+            publisher
+                .put(payload)
+                .res()
+                .instrument(info_span!(parent: scan_span, "publish_ros2_point_cloud"))
+                .await?;
+        }
+
+        if let Some(payload) = payloads.metadata {
+            published_bytes += payload.len();
+            self.metadata
+                .put(payload)
+                .res()
+                .instrument(info_span!(parent: scan_span, "publish_scan_metadata"))
+                .await?;
+        }
+
+        Ok(published_bytes)
+    }
+}
@@ -0,0 +1,317 @@
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Condvar, Mutex,
+    },
+};
+
+use tokio::sync::Notify;
+
+/// What the producer does when the queue is full
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BackpressurePolicy {
+    /// Wait for the consumer to catch up
+    #[default]
+    Block,
+    /// Discard the oldest queued item so the newest always gets in
+    DropOldest,
+}
+
+/// Queue options for the scan channel between the serial thread and the publisher
+#[derive(clap::Args, Debug, Clone)]
+pub struct ScanQueueArgs {
+    /// Number of scans buffered between the lidar thread and the publisher
+    #[clap(long, default_value_t = 10, value_parser = clap::value_parser!(u32).range(1..))]
+    pub scan_queue_capacity: u32,
+
+    /// Behavior when the scan queue is full
+    ///
+    /// drop-oldest keeps the lidar spinning and discards stale scans on slow networks
+    #[clap(long, value_enum, default_value_t = BackpressurePolicy::Block)]
+    pub scan_queue_policy: BackpressurePolicy,
+}
+
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+#[error("Queue receiver closed")]
+pub struct QueueClosed;
+
+struct State<T> {
+    items: VecDeque<T>,
+    sender_closed: bool,
+    receiver_closed: bool,
+}
+
+struct Shared<T> {
+    state: Mutex<State<T>>,
+    /// wakes the async receiver
+    not_empty: Notify,
+    /// wakes a blocked sender thread
+    not_full: Condvar,
+    capacity: usize,
+    policy: BackpressurePolicy,
+    dropped: AtomicU64,
+}
+
+/// Bounded queue from a blocking producer thread to an async consumer
+pub fn scan_queue<T>(
+    capacity: usize,
+    policy: BackpressurePolicy,
+) -> (QueueSender<T>, QueueReceiver<T>) {
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State {
+            items: VecDeque::with_capacity(capacity),
+            sender_closed: false,
+            receiver_closed: false,
+        }),
+        not_empty: Notify::new(),
+        not_full: Condvar::new(),
+        capacity,
+        policy,
+        dropped: AtomicU64::new(0),
+    });
+    (
+        QueueSender {
+            shared: shared.clone(),
+        },
+        QueueReceiver { shared },
+    )
+}
+
+pub struct QueueSender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> QueueSender<T> {
+    /// Push an item applying the backpressure policy
+    ///
+    /// Returns number of old items dropped to make room.
+    /// Fails once the receiver is dropped
+    pub fn send_blocking(&self, item: T) -> Result<usize, QueueClosed> {
+        let mut dropped = 0;
+        let mut state = self.shared.state.lock().unwrap();
+        loop {
+            if state.receiver_closed {
+                return Err(QueueClosed);
+            }
+            if state.items.len() < self.shared.capacity {
+                break;
+            }
+            match self.shared.policy {
+                BackpressurePolicy::Block => {
+                    state = self.shared.not_full.wait(state).unwrap();
+                }
+                BackpressurePolicy::DropOldest => {
+                    state.items.pop_front();
+                    self.shared.dropped.fetch_add(1, Ordering::Relaxed);
+                    dropped += 1;
+                }
+            }
+        }
+        state.items.push_back(item);
+        drop(state);
+        self.shared.not_empty.notify_one();
+        Ok(dropped)
+    }
+
+    pub fn stats(&self) -> QueueStatsHandle
+    where
+        T: Send + 'static,
+    {
+        QueueStatsHandle {
+            shared: Arc::downgrade(&self.shared) as _,
+        }
+    }
+}
+
+impl<T> Drop for QueueSender<T> {
+    fn drop(&mut self) {
+        self.shared.state.lock().unwrap().sender_closed = true;
+        self.shared.not_empty.notify_one();
+    }
+}
+
+pub struct QueueReceiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> QueueReceiver<T> {
+    /// Returns `None` once the sender is dropped and the queue is drained
+    pub async fn recv(&mut self) -> Option<T> {
+        loop {
+            // registered before checking so a push between unlock and await isn't missed
+            let notified = self.shared.not_empty.notified();
+            {
+                let mut state = self.shared.state.lock().unwrap();
+                if let Some(item) = state.items.pop_front() {
+                    drop(state);
+                    self.shared.not_full.notify_one();
+                    return Some(item);
+                }
+                if state.sender_closed {
+                    return None;
+                }
+            }
+            notified.await;
+        }
+    }
+}
+
+impl<T> Drop for QueueReceiver<T> {
+    fn drop(&mut self) {
+        self.shared.state.lock().unwrap().receiver_closed = true;
+        self.shared.not_full.notify_all();
+    }
+}
+
+trait QueueMetrics: Send + Sync {
+    fn depth(&self) -> usize;
+    fn capacity(&self) -> usize;
+    fn dropped(&self) -> u64;
+}
+
+impl<T: Send> QueueMetrics for Shared<T> {
+    fn depth(&self) -> usize {
+        self.state.lock().unwrap().items.len()
+    }
+
+    fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+/// Depth and drop accounting for queues that aren't a [`scan_queue`]
+///
+/// Owners record pushes, pops and drops so the queue shows up in process stats
+pub struct QueueCounters {
+    depth: AtomicU64,
+    capacity: usize,
+    dropped: AtomicU64,
+}
+
+impl QueueCounters {
+    pub fn new(capacity: usize) -> Arc<Self> {
+        Arc::new(Self {
+            depth: AtomicU64::new(0),
+            capacity,
+            dropped: AtomicU64::new(0),
+        })
+    }
+
+    pub fn record_push(&self) {
+        self.depth.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Must follow the matching [`QueueCounters::record_push`]
+    pub fn record_pop(&self) {
+        self.depth.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub fn record_drop(&self) {
+        self.dropped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn stats_handle(self: &Arc<Self>) -> QueueStatsHandle {
+        QueueStatsHandle {
+            shared: Arc::downgrade(self) as _,
+        }
+    }
+}
+
+impl QueueMetrics for QueueCounters {
+    fn depth(&self) -> usize {
+        self.depth.load(Ordering::Relaxed) as usize
+    }
+
+    fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+/// Weak handle for reading queue statistics without keeping the queue alive
+#[derive(Clone)]
+pub struct QueueStatsHandle {
+    shared: std::sync::Weak<dyn QueueMetrics>,
+}
+
+impl QueueStatsHandle {
+    pub fn stats(&self, name: &str) -> Option<crate::rplidar::QueueStats> {
+        let shared = self.shared.upgrade()?;
+        Some(crate::rplidar::QueueStats {
+            name: name.to_owned(),
+            depth: shared.depth() as u64,
+            capacity: shared.capacity() as u64,
+            dropped: shared.dropped(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn drop_oldest_counts_dropped() {
+        let (sender, mut receiver) = scan_queue(2, BackpressurePolicy::DropOldest);
+        assert_eq!(sender.send_blocking(1), Ok(0));
+        assert_eq!(sender.send_blocking(2), Ok(0));
+        assert_eq!(sender.send_blocking(3), Ok(1));
+        assert_eq!(sender.send_blocking(4), Ok(1));
+        let stats = sender.stats().stats("scans").unwrap();
+        assert_eq!(stats.depth, 2);
+        assert_eq!(stats.capacity, 2);
+        assert_eq!(stats.dropped, 2);
+        assert_eq!(receiver.recv().await, Some(3));
+        assert_eq!(receiver.recv().await, Some(4));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn blocked_sender_wakes_on_pop() {
+        let (sender, mut receiver) = scan_queue(1, BackpressurePolicy::Block);
+        sender.send_blocking(1).unwrap();
+        let (sent_sender, sent_receiver) = std::sync::mpsc::channel();
+        let producer = std::thread::spawn(move || {
+            sender.send_blocking(2).unwrap();
+            sent_sender.send(()).unwrap();
+        });
+        assert!(sent_receiver
+            .recv_timeout(Duration::from_millis(100))
+            .is_err());
+        assert_eq!(receiver.recv().await, Some(1));
+        sent_receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+        producer.join().unwrap();
+        assert_eq!(receiver.recv().await, Some(2));
+    }
+
+    #[tokio::test]
+    async fn receiver_drains_after_sender_drops() {
+        let (sender, mut receiver) = scan_queue(4, BackpressurePolicy::Block);
+        sender.send_blocking(1).unwrap();
+        sender.send_blocking(2).unwrap();
+        drop(sender);
+        assert_eq!(receiver.recv().await, Some(1));
+        assert_eq!(receiver.recv().await, Some(2));
+        assert_eq!(receiver.recv().await, None);
+    }
+
+    #[test]
+    fn send_fails_after_receiver_drops() {
+        let (sender, receiver) = scan_queue(1, BackpressurePolicy::Block);
+        sender.send_blocking(1).unwrap();
+        let producer = std::thread::spawn(move || sender.send_blocking(2));
+        // the sender is either blocked on the full queue or sees the closed receiver
+        std::thread::sleep(Duration::from_millis(50));
+        drop(receiver);
+        assert_eq!(producer.join().unwrap(), Err(QueueClosed));
+    }
+}
@@ -0,0 +1,215 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant, SystemTime},
+};
+
+use crate::{rplidar, system_time_to_proto_time};
+
+/// Window over which the scan rate is measured
+const RATE_WINDOW: Duration = Duration::from_secs(2);
+
+/// Change of scan rate health
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScanRateAlert {
+    /// Rate stayed below the floor for the whole grace period
+    Degraded { rate: f64 },
+    /// Rate is back above the floor
+    Recovered { rate: f64 },
+}
+
+impl ScanRateAlert {
+    pub fn rate(&self) -> f64 {
+        match self {
+            ScanRateAlert::Degraded { rate } | ScanRateAlert::Recovered { rate } => *rate,
+        }
+    }
+}
+
+/// Detects scan rate staying below a floor for longer than a grace period
+pub struct ScanRateMonitor {
+    min_rate: f64,
+    grace_period: Duration,
+    scans: VecDeque<Instant>,
+    below_since: Option<Instant>,
+    degraded: bool,
+}
+
+impl ScanRateMonitor {
+    pub fn new(min_rate: f64, grace_period: Duration) -> Self {
+        Self {
+            min_rate,
+            grace_period,
+            scans: VecDeque::new(),
+            below_since: None,
+            degraded: false,
+        }
+    }
+
+    pub fn min_rate(&self) -> f64 {
+        self.min_rate
+    }
+
+    pub fn record_scan(&mut self, time: Instant) {
+        self.scans.push_back(time);
+        self.trim(time);
+    }
+
+    /// Scans per second over the measurement window
+    pub fn rate(&mut self, now: Instant) -> f64 {
+        self.trim(now);
+        self.scans.len() as f64 / RATE_WINDOW.as_secs_f64()
+    }
+
+    /// Evaluate the current rate and report transitions
+    ///
+    /// `active` should be false while the lidar is intentionally stopped
+    pub fn check(&mut self, now: Instant, active: bool) -> Option<ScanRateAlert> {
+        let rate = self.rate(now);
+
+        if !active {
+            self.below_since = None;
+            if self.degraded {
+                self.degraded = false;
+                return Some(ScanRateAlert::Recovered { rate });
+            }
+            return None;
+        }
+
+        if rate >= self.min_rate {
+            self.below_since = None;
+            if self.degraded {
+                self.degraded = false;
+                return Some(ScanRateAlert::Recovered { rate });
+            }
+            return None;
+        }
+
+        let below_since = *self.below_since.get_or_insert(now);
+        if !self.degraded && now.duration_since(below_since) >= self.grace_period {
+            self.degraded = true;
+            return Some(ScanRateAlert::Degraded { rate });
+        }
+        None
+    }
+
+    fn trim(&mut self, now: Instant) {
+        while let Some(oldest) = self.scans.front() {
+            if now.duration_since(*oldest) > RATE_WINDOW {
+                self.scans.pop_front();
+            } else {
+                break;
+            }
+        }
+    }
+}
+
+pub const ROTATION_RATE_TOPIC: &str = "rotation_rate";
+
+/// How long the rotation rate has to stay out of tolerance before warning
+const ROTATION_DEVIATION_GRACE_PERIOD: Duration = Duration::from_secs(5);
+
+/// Change of rotation rate health
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RotationRateAlert {
+    /// Rate stayed outside the tolerance for the whole grace period
+    Deviating { rate: f64, expected: f64 },
+    /// Rate is back within tolerance
+    Recovered { rate: f64, expected: f64 },
+}
+
+/// Estimates the motor rotation rate from scan timing
+///
+/// Every scan is one full rotation so the rate is the mean interval between scans
+/// in the measurement window
+pub struct RotationRateMonitor {
+    tolerance: f64,
+    scans: VecDeque<Instant>,
+    outside_since: Option<Instant>,
+    deviating: bool,
+}
+
+impl RotationRateMonitor {
+    /// `tolerance` is the allowed relative deviation from the expected rate
+    pub fn new(tolerance: f64) -> Self {
+        Self {
+            tolerance,
+            scans: VecDeque::new(),
+            outside_since: None,
+            deviating: false,
+        }
+    }
+
+    pub fn record_scan(&mut self, time: Instant) {
+        self.scans.push_back(time);
+        self.trim(time);
+    }
+
+    /// Rotations per second, `None` until two scans fall into the window
+    pub fn rate(&mut self, now: Instant) -> Option<f64> {
+        self.trim(now);
+        let (first, last) = (self.scans.front()?, self.scans.back()?);
+        let elapsed = last.duration_since(*first).as_secs_f64();
+        (elapsed > 0.0).then(|| (self.scans.len() - 1) as f64 / elapsed)
+    }
+
+    /// Compare the measured rate against `expected` and report transitions
+    ///
+    /// `active` should be false while the lidar is intentionally stopped
+    pub fn check(
+        &mut self,
+        now: Instant,
+        active: bool,
+        expected: f64,
+    ) -> Option<RotationRateAlert> {
+        let rate = self.rate(now);
+        let within_tolerance = match rate {
+            Some(rate) => ((rate - expected) / expected).abs() <= self.tolerance,
+            // no scans while running is left to the scan rate monitor
+            None => true,
+        };
+        let rate = rate.unwrap_or_default();
+
+        if !active || within_tolerance {
+            self.outside_since = None;
+            if self.deviating {
+                self.deviating = false;
+                return Some(RotationRateAlert::Recovered { rate, expected });
+            }
+            return None;
+        }
+
+        let outside_since = *self.outside_since.get_or_insert(now);
+        if !self.deviating && now.duration_since(outside_since) >= ROTATION_DEVIATION_GRACE_PERIOD {
+            self.deviating = true;
+            return Some(RotationRateAlert::Deviating { rate, expected });
+        }
+        None
+    }
+
+    fn trim(&mut self, now: Instant) {
+        while let Some(oldest) = self.scans.front() {
+            if now.duration_since(*oldest) > RATE_WINDOW {
+                self.scans.pop_front();
+            } else {
+                break;
+            }
+        }
+    }
+}
+
+impl rplidar::RotationRate {
+    pub fn new(measured_hz: f64, expected_hz: Option<f64>) -> Self {
+        let expected_hz = expected_hz.unwrap_or_default();
+        let deviation = if expected_hz > 0.0 {
+            (measured_hz - expected_hz) / expected_hz
+        } else {
+            0.0
+        };
+        Self {
+            timestamp: Some(system_time_to_proto_time(&SystemTime::now())),
+            measured_hz,
+            expected_hz,
+            deviation,
+        }
+    }
+}
@@ -0,0 +1,174 @@
+use std::{
+    fmt,
+    str::FromStr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// Time of day in UTC
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TimeOfDay {
+    seconds: u64,
+}
+
+impl TimeOfDay {
+    fn of(time: SystemTime) -> Self {
+        let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+        Self {
+            seconds: since_epoch.as_secs() % SECONDS_PER_DAY,
+        }
+    }
+
+    /// Time until the next occurrence of `self` after `now`
+    fn until(self, now: Self) -> Duration {
+        let seconds = (self.seconds + SECONDS_PER_DAY - now.seconds) % SECONDS_PER_DAY;
+        if seconds == 0 {
+            Duration::from_secs(SECONDS_PER_DAY)
+        } else {
+            Duration::from_secs(seconds)
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error("Invalid time of day {0:?}, expected HH:MM or HH:MM:SS")]
+pub struct TimeOfDayParseError(String);
+
+impl FromStr for TimeOfDay {
+    type Err = TimeOfDayParseError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let error = || TimeOfDayParseError(text.to_owned());
+        let parts = text
+            .split(':')
+            .map(|part| part.parse::<u64>().map_err(|_| error()))
+            .collect::<Result<Vec<_>, _>>()?;
+        let (hours, minutes, seconds) = match parts.as_slice() {
+            [hours, minutes] => (*hours, *minutes, 0),
+            [hours, minutes, seconds] => (*hours, *minutes, *seconds),
+            _ => return Err(error()),
+        };
+        if hours >= 24 || minutes >= 60 || seconds >= 60 {
+            return Err(error());
+        }
+        Ok(Self {
+            seconds: hours * 3600 + minutes * 60 + seconds,
+        })
+    }
+}
+
+impl fmt::Display for TimeOfDay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:02}:{:02}:{:02}",
+            self.seconds / 3600,
+            self.seconds / 60 % 60,
+            self.seconds % 60
+        )
+    }
+}
+
+/// Daily recording window
+///
+/// Windows may wrap midnight, `--start-at 22:00 --stop-at 06:00` records overnight
+#[derive(clap::Args, Debug, Clone)]
+pub struct RecordingScheduleArgs {
+    /// Start recording every day at this UTC time (HH:MM or HH:MM:SS)
+    #[clap(long, requires = "stop_at")]
+    pub start_at: Option<TimeOfDay>,
+
+    /// Stop recording every day at this UTC time (HH:MM or HH:MM:SS)
+    #[clap(long, requires = "start_at")]
+    pub stop_at: Option<TimeOfDay>,
+}
+
+impl RecordingScheduleArgs {
+    /// `None` records all the time
+    pub fn schedule(&self) -> anyhow::Result<Option<RecordingSchedule>> {
+        match (self.start_at, self.stop_at) {
+            (Some(start), Some(stop)) if start == stop => {
+                anyhow::bail!("Recording window start and stop can't be the same time")
+            }
+            (Some(start), Some(stop)) => Ok(Some(RecordingSchedule { start, stop })),
+            _ => Ok(None),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RecordingSchedule {
+    start: TimeOfDay,
+    stop: TimeOfDay,
+}
+
+impl RecordingSchedule {
+    pub fn is_active(&self, now: SystemTime) -> bool {
+        let now = TimeOfDay::of(now);
+        if self.start < self.stop {
+            self.start <= now && now < self.stop
+        } else {
+            now >= self.start || now < self.stop
+        }
+    }
+
+    /// Time until the window next opens or closes
+    pub fn until_next_change(&self, now: SystemTime) -> Duration {
+        let now = TimeOfDay::of(now);
+        self.start.until(now).min(self.stop.until(now))
+    }
+}
+
+impl fmt::Display for RecordingSchedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{} UTC", self.start, self.stop)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(text: &str) -> SystemTime {
+        // arbitrary day so the date part doesn't matter
+        UNIX_EPOCH
+            + Duration::from_secs(
+                20_000 * SECONDS_PER_DAY + text.parse::<TimeOfDay>().unwrap().seconds,
+            )
+    }
+
+    #[test]
+    fn parses_time_of_day() {
+        assert_eq!(
+            "06:30".parse::<TimeOfDay>().unwrap().seconds,
+            6 * 3600 + 30 * 60
+        );
+        assert_eq!(
+            "23:59:59".parse::<TimeOfDay>().unwrap().to_string(),
+            "23:59:59"
+        );
+        assert!("24:00".parse::<TimeOfDay>().is_err());
+        assert!("6".parse::<TimeOfDay>().is_err());
+    }
+
+    #[test]
+    fn window_wraps_midnight() {
+        let schedule = RecordingSchedule {
+            start: "22:00".parse().unwrap(),
+            stop: "06:00".parse().unwrap(),
+        };
+        assert!(schedule.is_active(at("23:00")));
+        assert!(schedule.is_active(at("02:00")));
+        assert!(!schedule.is_active(at("06:00")));
+        assert!(!schedule.is_active(at("12:00")));
+        assert_eq!(
+            schedule.until_next_change(at("21:00")),
+            Duration::from_secs(3600)
+        );
+        assert_eq!(
+            schedule.until_next_change(at("05:00")),
+            Duration::from_secs(3600)
+        );
+    }
+}
@@ -0,0 +1,69 @@
+use std::collections::HashSet;
+
+use prost::Message;
+use prost_reflect::{FileDescriptor, MessageDescriptor};
+use prost_types::{FileDescriptorProto, FileDescriptorSet};
+
+/// FileDescriptorSet with only the file of `descriptor` and its transitive dependencies
+///
+/// Dependencies come before the files importing them. Much smaller than the whole
+/// descriptor pool which is otherwise repeated in every MCAP schema and Foxglove channel
+pub fn file_descriptor_set(descriptor: &MessageDescriptor) -> FileDescriptorSet {
+    let mut visited = HashSet::new();
+    let mut files = vec![];
+    add_file(&descriptor.parent_file(), &mut visited, &mut files);
+    FileDescriptorSet { file: files }
+}
+
+/// Encoded [`file_descriptor_set`] as used for protobuf schemas
+pub fn encoded_file_descriptor_set(descriptor: &MessageDescriptor) -> Vec<u8> {
+    file_descriptor_set(descriptor).encode_to_vec()
+}
+
+fn add_file(
+    file: &FileDescriptor,
+    visited: &mut HashSet<String>,
+    files: &mut Vec<FileDescriptorProto>,
+) {
+    if !visited.insert(file.name().to_owned()) {
+        return;
+    }
+    for dependency in file.dependencies() {
+        add_file(&dependency, visited, files);
+    }
+    files.push(file.file_descriptor_proto().clone());
+}
+
+#[cfg(test)]
+mod tests {
+    use prost_reflect::{DescriptorPool, ReflectMessage};
+
+    use super::*;
+    use crate::{foxglove, rplidar};
+
+    #[test]
+    fn includes_only_dependencies() {
+        let descriptor = foxglove::LaserScan::default().descriptor();
+        let set = file_descriptor_set(&descriptor);
+        let names: Vec<_> = set.file.iter().map(|file| file.name()).collect();
+        assert_eq!(names.last(), Some(&descriptor.parent_file().name()));
+        assert!(names.contains(&"google/protobuf/timestamp.proto"));
+        assert!(names.len() < descriptor.parent_pool().files().len());
+        assert!(!names.iter().any(|name| name.contains("PointCloud")));
+
+        let pool =
+            DescriptorPool::decode(encoded_file_descriptor_set(&descriptor).as_slice()).unwrap();
+        assert!(pool.get_message_by_name("foxglove.LaserScan").is_some());
+    }
+
+    #[test]
+    fn lists_shared_dependencies_once() {
+        let descriptor = rplidar::CompactLaserScan::default().descriptor();
+        let set = file_descriptor_set(&descriptor);
+        let mut names: Vec<_> = set.file.iter().map(|file| file.name()).collect();
+        let count = names.len();
+        names.sort();
+        names.dedup();
+        assert_eq!(names.len(), count);
+    }
+}
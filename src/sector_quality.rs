@@ -0,0 +1,379 @@
+use std::{
+    collections::VecDeque,
+    f64::consts::TAU,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
+
+use prost::Message;
+use rplidar_driver::ScanPoint;
+use tracing::{error, info};
+use zenoh::{prelude::r#async::*, Session};
+
+use crate::{
+    foxglove, rplidar, scan_broadcast::ScanBroadcast, system_time_to_proto_time, ErrorWrapper,
+};
+
+pub const SECTOR_QUALITY_TOPIC: &str = "sector_quality";
+pub const SECTOR_QUALITY_GRID_TOPIC: &str = "sector_quality/grid";
+
+/// Radius of the heatmap disc drawn around the lidar
+const GRID_RADIUS: f64 = 1.0;
+/// Heatmap cell size in meters
+const GRID_CELL_SIZE: f64 = 0.05;
+/// Bytes per heatmap cell, dropout and low quality rate as float32
+const GRID_CELL_STRIDE: u32 = 8;
+
+/// Per sector dropout and low quality tracking
+///
+/// A dirty lens or a developing occlusion shows up as a sector whose dropout or low
+/// quality rate creeps up over time. Points removed by filters or masks count as dropouts
+#[derive(clap::Args, Debug, Clone, Copy)]
+pub struct SectorQualityArgs {
+    /// Publish per sector data quality on <prefix>/sector_quality and a heatmap grid
+    #[clap(long)]
+    pub sector_quality: bool,
+
+    /// Number of angular sectors
+    #[clap(long, default_value_t = 36, value_parser = clap::value_parser!(u32).range(1..=360))]
+    pub sector_count: u32,
+
+    /// Length of the rolling window in seconds
+    #[clap(long, default_value_t = 60.0)]
+    pub sector_quality_window: f64,
+
+    /// Valid points with lower quality count as low quality
+    #[clap(long, default_value_t = 10)]
+    pub low_quality_threshold: u8,
+
+    /// Seconds between published statistics
+    #[clap(long, default_value_t = 1.0)]
+    pub sector_quality_interval: f64,
+}
+
+impl SectorQualityArgs {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.sector_quality_window.is_nan() || self.sector_quality_window <= 0.0 {
+            anyhow::bail!("Sector quality window must be positive");
+        }
+        if self.sector_quality_interval.is_nan() || self.sector_quality_interval <= 0.0 {
+            anyhow::bail!("Sector quality interval must be positive");
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct SectorCounts {
+    points: u64,
+    dropouts: u64,
+    low_quality: u64,
+}
+
+impl SectorCounts {
+    fn add(&mut self, other: &SectorCounts) {
+        self.points += other.points;
+        self.dropouts += other.dropouts;
+        self.low_quality += other.low_quality;
+    }
+
+    fn subtract(&mut self, other: &SectorCounts) {
+        self.points -= other.points;
+        self.dropouts -= other.dropouts;
+        self.low_quality -= other.low_quality;
+    }
+
+    fn dropout_rate(&self) -> f64 {
+        if self.points == 0 {
+            return 0.0;
+        }
+        self.dropouts as f64 / self.points as f64
+    }
+
+    fn low_quality_rate(&self) -> f64 {
+        let valid = self.points - self.dropouts;
+        if valid == 0 {
+            return 0.0;
+        }
+        self.low_quality as f64 / valid as f64
+    }
+}
+
+/// Rolling per sector counts
+pub struct SectorQualityTracker {
+    window: Duration,
+    low_quality_threshold: u8,
+    scans: VecDeque<(Instant, Vec<SectorCounts>)>,
+    totals: Vec<SectorCounts>,
+}
+
+impl SectorQualityTracker {
+    pub fn new(sector_count: usize, window: Duration, low_quality_threshold: u8) -> Self {
+        Self {
+            window,
+            low_quality_threshold,
+            scans: VecDeque::new(),
+            totals: vec![SectorCounts::default(); sector_count],
+        }
+    }
+
+    fn sector(&self, angle: f64) -> usize {
+        let sector_count = self.totals.len();
+        ((angle.rem_euclid(TAU) / TAU * sector_count as f64) as usize).min(sector_count - 1)
+    }
+
+    pub fn record(&mut self, now: Instant, scan: &[ScanPoint]) {
+        let mut counts = vec![SectorCounts::default(); self.totals.len()];
+        for point in scan {
+            let sector = &mut counts[self.sector(point.angle() as f64)];
+            sector.points += 1;
+            if !point.is_valid() {
+                sector.dropouts += 1;
+            } else if point.quality < self.low_quality_threshold {
+                sector.low_quality += 1;
+            }
+        }
+        for (total, counts) in self.totals.iter_mut().zip(&counts) {
+            total.add(counts);
+        }
+        self.scans.push_back((now, counts));
+        self.expire(now);
+    }
+
+    fn expire(&mut self, now: Instant) {
+        while let Some((time, counts)) = self.scans.front() {
+            if now.duration_since(*time) <= self.window {
+                break;
+            }
+            for (total, counts) in self.totals.iter_mut().zip(counts) {
+                total.subtract(counts);
+            }
+            self.scans.pop_front();
+        }
+    }
+
+    pub fn stats(&self, timestamp: &SystemTime) -> rplidar::SectorQuality {
+        let sector_width = 360.0 / self.totals.len() as f64;
+        rplidar::SectorQuality {
+            timestamp: Some(system_time_to_proto_time(timestamp)),
+            window_seconds: self.window.as_secs_f64(),
+            sectors: self
+                .totals
+                .iter()
+                .enumerate()
+                .map(|(index, counts)| rplidar::SectorStats {
+                    start_angle: index as f64 * sector_width,
+                    end_angle: (index + 1) as f64 * sector_width,
+                    points: counts.points,
+                    dropout_rate: counts.dropout_rate(),
+                    low_quality_rate: counts.low_quality_rate(),
+                })
+                .collect(),
+        }
+    }
+
+    /// Disc around the lidar with each cell holding the rates of its sector
+    ///
+    /// Cells outside the disc are NaN so they aren't drawn
+    pub fn grid(
+        &self,
+        timestamp: &SystemTime,
+        frame_id: &str,
+        pose: &foxglove::Pose,
+    ) -> foxglove::Grid {
+        let cells = (2.0 * GRID_RADIUS / GRID_CELL_SIZE).round() as u32;
+        let mut data = Vec::with_capacity((cells * cells * GRID_CELL_STRIDE) as usize);
+        for row in 0..cells {
+            for column in 0..cells {
+                let x = (column as f64 + 0.5) * GRID_CELL_SIZE - GRID_RADIUS;
+                let y = (row as f64 + 0.5) * GRID_CELL_SIZE - GRID_RADIUS;
+                let (dropout_rate, low_quality_rate) = if x.hypot(y) <= GRID_RADIUS {
+                    // inverse of the point cloud projection x = d cos(-a), y = d sin(-a)
+                    let counts = &self.totals[self.sector(-y.atan2(x))];
+                    (
+                        counts.dropout_rate() as f32,
+                        counts.low_quality_rate() as f32,
+                    )
+                } else {
+                    (f32::NAN, f32::NAN)
+                };
+                data.extend_from_slice(&dropout_rate.to_le_bytes());
+                data.extend_from_slice(&low_quality_rate.to_le_bytes());
+            }
+        }
+        foxglove::Grid {
+            timestamp: Some(system_time_to_proto_time(timestamp)),
+            frame_id: frame_id.to_owned(),
+            pose: Some(offset_pose(pose, -GRID_RADIUS, -GRID_RADIUS)),
+            column_count: cells,
+            cell_size: Some(foxglove::Vector2 {
+                x: GRID_CELL_SIZE,
+                y: GRID_CELL_SIZE,
+            }),
+            row_stride: cells * GRID_CELL_STRIDE,
+            cell_stride: GRID_CELL_STRIDE,
+            fields: vec![
+                foxglove::PackedElementField {
+                    name: "dropout_rate".to_string(),
+                    offset: 0,
+                    r#type: foxglove::packed_element_field::NumericType::Float32 as i32,
+                },
+                foxglove::PackedElementField {
+                    name: "low_quality_rate".to_string(),
+                    offset: 4,
+                    r#type: foxglove::packed_element_field::NumericType::Float32 as i32,
+                },
+            ],
+            data,
+        }
+    }
+}
+
+/// `pose` moved by `(x, y)` in its own frame so the grid corner follows the lidar mounting
+fn offset_pose(pose: &foxglove::Pose, x: f64, y: f64) -> foxglove::Pose {
+    let position = pose.position.unwrap_or_default();
+    let orientation = pose.orientation.unwrap_or(foxglove::Quaternion {
+        x: 0.0,
+        y: 0.0,
+        z: 0.0,
+        w: 1.0,
+    });
+    // v + 2w(q x v) + 2q x (q x v) with v = (x, y, 0)
+    let (qx, qy, qz, qw) = (orientation.x, orientation.y, orientation.z, orientation.w);
+    let (tx, ty, tz) = (-2.0 * qz * y, 2.0 * qz * x, 2.0 * (qx * y - qy * x));
+    foxglove::Pose {
+        position: Some(foxglove::Vector3 {
+            x: position.x + x + qw * tx + (qy * tz - qz * ty),
+            y: position.y + y + qw * ty + (qz * tx - qx * tz),
+            z: position.z + qw * tz + (qx * ty - qy * tx),
+        }),
+        orientation: Some(orientation),
+    }
+}
+
+/// Track scans from `scan_broadcast` and periodically publish the sector statistics
+pub async fn start_sector_quality_publisher(
+    zenoh_session: Arc<Session>,
+    stats_topic: String,
+    grid_topic: String,
+    args: SectorQualityArgs,
+    scan_broadcast: ScanBroadcast,
+) -> anyhow::Result<()> {
+    args.validate()?;
+    let stats_publisher = zenoh_session
+        .declare_publisher(stats_topic.clone())
+        .res()
+        .await
+        .map_err(ErrorWrapper::ZenohError)?;
+    let grid_publisher = zenoh_session
+        .declare_publisher(grid_topic.clone())
+        .res()
+        .await
+        .map_err(ErrorWrapper::ZenohError)?;
+    info!(stats_topic, grid_topic, "Publishing sector quality");
+
+    let mut tracker = SectorQualityTracker::new(
+        args.sector_count as usize,
+        Duration::from_secs_f64(args.sector_quality_window),
+        args.low_quality_threshold,
+    );
+    let mut scan_subscriber = scan_broadcast.subscribe("sector_quality");
+    let mut interval = tokio::time::interval(Duration::from_secs_f64(args.sector_quality_interval));
+    tokio::spawn(async move {
+        let mut last_frame = None;
+        loop {
+            tokio::select! {
+                frame = scan_subscriber.recv() => {
+                    let Some(frame) = frame else {
+                        break;
+                    };
+                    tracker.record(frame.capture_instant, &frame.points);
+                    last_frame = Some(frame);
+                }
+                _ = interval.tick() => {
+                    let Some(frame) = &last_frame else {
+                        continue;
+                    };
+                    let now = SystemTime::now();
+                    let stats = tracker.stats(&now);
+                    if let Err(err) = stats_publisher.put(stats.encode_to_vec()).res().await {
+                        error!(?err, "Failed to publish sector quality");
+                    }
+                    let grid = tracker.grid(&now, &frame.frame_id, &frame.pose);
+                    if let Err(err) = grid_publisher.put(grid.encode_to_vec()).res().await {
+                        error!(?err, "Failed to publish sector quality grid");
+                    }
+                }
+            }
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tracker() -> SectorQualityTracker {
+        SectorQualityTracker::new(4, Duration::from_secs(10), 10)
+    }
+
+    #[test]
+    fn maps_angles_to_sectors() {
+        let tracker = tracker();
+        assert_eq!(tracker.sector(0.0), 0);
+        assert_eq!(tracker.sector(TAU / 4.0 + 0.01), 1);
+        assert_eq!(tracker.sector(TAU - 0.01), 3);
+        assert_eq!(tracker.sector(TAU), 0);
+        assert_eq!(tracker.sector(-0.01), 3);
+    }
+
+    #[test]
+    fn rates_drop_out_of_window() {
+        let mut tracker = tracker();
+        let start = Instant::now();
+        let dirty = SectorCounts {
+            points: 10,
+            dropouts: 5,
+            low_quality: 4,
+        };
+        let clean = SectorCounts {
+            points: 10,
+            dropouts: 0,
+            low_quality: 0,
+        };
+        tracker.totals[2] = dirty;
+        tracker.scans.push_back((
+            start,
+            vec![
+                SectorCounts::default(),
+                SectorCounts::default(),
+                dirty,
+                SectorCounts::default(),
+            ],
+        ));
+
+        let stats = tracker.stats(&SystemTime::now());
+        assert_eq!(stats.sectors[2].points, 10);
+        assert!((stats.sectors[2].dropout_rate - 0.5).abs() < 1e-9);
+        assert!((stats.sectors[2].low_quality_rate - 0.8).abs() < 1e-9);
+        assert_eq!(stats.sectors[2].start_angle, 180.0);
+
+        tracker.totals[2].add(&clean);
+        tracker.scans.push_back((
+            start + Duration::from_secs(11),
+            vec![
+                SectorCounts::default(),
+                SectorCounts::default(),
+                clean,
+                SectorCounts::default(),
+            ],
+        ));
+        tracker.expire(start + Duration::from_secs(11));
+        assert_eq!(tracker.totals[2], clean);
+        assert_eq!(
+            tracker.stats(&SystemTime::now()).sectors[2].dropout_rate,
+            0.0
+        );
+    }
+}
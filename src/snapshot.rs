@@ -0,0 +1,93 @@
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Context;
+use rplidar_driver::ScanPoint;
+
+use crate::projection::project;
+
+pub const SNAPSHOT_TOPIC: &str = "snapshot";
+/// Upper bound on scans aggregated into a single snapshot
+pub const MAX_SNAPSHOT_SCANS: usize = 100;
+
+/// Point in the lidar frame
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PointXYZI {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    pub intensity: f32,
+}
+
+/// Project valid points of a scan into the lidar frame
+pub fn project_scan(scan: &[ScanPoint]) -> impl Iterator<Item = PointXYZI> + '_ {
+    scan.iter().filter(|point| point.is_valid()).map(|point| {
+        let (x, y) = project(point.angle(), point.distance());
+        PointXYZI {
+            x,
+            y,
+            z: 0.0,
+            intensity: point.quality as f32,
+        }
+    })
+}
+
+/// Parse the number of scans from query parameters like `scans=10`
+///
+/// Defaults to a single scan
+pub fn requested_scan_count(parameters: &str) -> anyhow::Result<usize> {
+    let Some(value) = parameters
+        .split(';')
+        .filter_map(|parameter| parameter.split_once('='))
+        .find(|(key, _)| *key == "scans")
+        .map(|(_, value)| value)
+    else {
+        return Ok(1);
+    };
+    let scans: usize = value
+        .parse()
+        .with_context(|| format!("Invalid scan count {:?}", value))?;
+    if !(1..=MAX_SNAPSHOT_SCANS).contains(&scans) {
+        anyhow::bail!("Scan count must be between 1 and {}", MAX_SNAPSHOT_SCANS);
+    }
+    Ok(scans)
+}
+
+/// Path for a new snapshot file in `directory`
+pub fn snapshot_path(directory: &Path) -> PathBuf {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+    directory.join(format!("snapshot_{}.pcd", timestamp))
+}
+
+/// Write points as an unorganized ASCII PCD file
+pub fn write_pcd(path: &Path, points: &[PointXYZI]) -> anyhow::Result<()> {
+    let file = std::fs::File::create(path)
+        .with_context(|| format!("Failed to create snapshot file {:?}", path))?;
+    let mut writer = std::io::BufWriter::new(file);
+    writeln!(writer, "# .PCD v0.7 - Point Cloud Data file format")?;
+    writeln!(writer, "VERSION 0.7")?;
+    writeln!(writer, "FIELDS x y z intensity")?;
+    writeln!(writer, "SIZE 4 4 4 4")?;
+    writeln!(writer, "TYPE F F F F")?;
+    writeln!(writer, "COUNT 1 1 1 1")?;
+    writeln!(writer, "WIDTH {}", points.len())?;
+    writeln!(writer, "HEIGHT 1")?;
+    writeln!(writer, "VIEWPOINT 0 0 0 1 0 0 0")?;
+    writeln!(writer, "POINTS {}", points.len())?;
+    writeln!(writer, "DATA ascii")?;
+    for point in points {
+        writeln!(
+            writer,
+            "{} {} {} {}",
+            point.x, point.y, point.z, point.intensity
+        )?;
+    }
+    writer.flush()?;
+    Ok(())
+}
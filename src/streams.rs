@@ -0,0 +1,77 @@
+use std::sync::Arc;
+
+use futures::{stream, Stream};
+use prost::Message;
+use tracing::warn;
+use zenoh::{prelude::r#async::*, Session};
+
+use crate::{compression, foxglove, rplidar, ErrorWrapper};
+
+/// Decode a sample payload, decompressing it when tagged with a compressed encoding
+pub fn decode_sample<M: Message + Default>(sample: &Sample) -> anyhow::Result<M> {
+    let message = match compression::decompress(&sample.value)? {
+        Some(payload) => M::decode(payload.as_slice())?,
+        None => M::decode(sample.value.payload.contiguous().as_ref())?,
+    };
+    Ok(message)
+}
+
+/// Stream of samples on `key_expr` converted by `decode`
+///
+/// Samples that fail to decode are logged and skipped. The stream ends when the
+/// session closes
+pub async fn subscribe_with<T: Send + 'static>(
+    zenoh_session: Arc<Session>,
+    key_expr: &str,
+    decode: fn(&Sample) -> anyhow::Result<T>,
+) -> anyhow::Result<impl Stream<Item = T> + Send + 'static> {
+    let subscriber = zenoh_session
+        .declare_subscriber(key_expr)
+        .res()
+        .await
+        .map_err(ErrorWrapper::ZenohError)?;
+    Ok(stream::unfold(subscriber, move |subscriber| async move {
+        loop {
+            let sample = subscriber.recv_async().await.ok()?;
+            match decode(&sample) {
+                Ok(message) => return Some((message, subscriber)),
+                Err(err) => warn!(key_expr = %sample.key_expr, ?err, "Failed to decode sample"),
+            }
+        }
+    }))
+}
+
+/// Stream of protobuf messages of type `M` published on `key_expr`
+pub async fn subscribe_messages<M: Message + Default + 'static>(
+    zenoh_session: Arc<Session>,
+    key_expr: &str,
+) -> anyhow::Result<impl Stream<Item = M> + Send + 'static> {
+    subscribe_with(zenoh_session, key_expr, decode_sample::<M>).await
+}
+
+/// Laser scans published by the driver with the default scan format
+pub async fn subscribe_laser_scans(
+    zenoh_session: Arc<Session>,
+    key_expr: &str,
+) -> anyhow::Result<impl Stream<Item = foxglove::LaserScan> + Send + 'static> {
+    subscribe_messages(zenoh_session, key_expr).await
+}
+
+/// Compact laser scans of any compact scan format expanded into foxglove laser scans
+pub async fn subscribe_compact_laser_scans(
+    zenoh_session: Arc<Session>,
+    key_expr: &str,
+) -> anyhow::Result<impl Stream<Item = foxglove::LaserScan> + Send + 'static> {
+    subscribe_with(zenoh_session, key_expr, |sample| {
+        decode_sample::<rplidar::CompactLaserScan>(sample)?.to_laser_scan()
+    })
+    .await
+}
+
+/// Point clouds published by the driver
+pub async fn subscribe_point_clouds(
+    zenoh_session: Arc<Session>,
+    key_expr: &str,
+) -> anyhow::Result<impl Stream<Item = foxglove::PointCloud> + Send + 'static> {
+    subscribe_messages(zenoh_session, key_expr).await
+}
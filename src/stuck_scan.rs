@@ -0,0 +1,106 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+};
+
+use rplidar_driver::ScanPoint;
+
+/// Consecutive identical scans before the sensor is considered wedged
+const IDENTICAL_SCAN_LIMIT: u32 = 3;
+/// Consecutive collapsed scans before the sensor is considered wedged
+const COLLAPSED_SCAN_LIMIT: u32 = 5;
+/// Scans with fewer points than this fraction of the typical count are collapsed
+const COLLAPSE_RATIO: f64 = 0.25;
+/// Weight of a new scan in the typical point count
+const POINT_COUNT_SMOOTHING: f64 = 0.1;
+
+/// Symptom of a wedged sensor
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StuckScan {
+    /// The same scan was read repeatedly
+    Identical { scans: u32 },
+    /// Point count dropped far below the typical count
+    Collapsed { points: usize, typical_points: f64 },
+}
+
+impl std::fmt::Display for StuckScan {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StuckScan::Identical { scans } => write!(f, "{} identical scans in a row", scans),
+            StuckScan::Collapsed {
+                points,
+                typical_points,
+            } => write!(
+                f,
+                "Scan collapsed to {} points from typical {:.0}",
+                points, typical_points
+            ),
+        }
+    }
+}
+
+/// Detects a wedged sensor from consecutive scans
+#[derive(Debug, Default)]
+pub struct StuckScanDetector {
+    last_fingerprint: Option<u64>,
+    identical_scans: u32,
+    typical_points: Option<f64>,
+    collapsed_scans: u32,
+}
+
+impl StuckScanDetector {
+    /// Check a freshly read scan
+    ///
+    /// Reports a symptom once its limit is reached, after which detection starts over
+    pub fn check(&mut self, scan: &[ScanPoint]) -> Option<StuckScan> {
+        let fingerprint = fingerprint(scan);
+        if self.last_fingerprint.replace(fingerprint) == Some(fingerprint) {
+            self.identical_scans += 1;
+        } else {
+            self.identical_scans = 1;
+        }
+        if self.identical_scans >= IDENTICAL_SCAN_LIMIT {
+            let scans = self.identical_scans;
+            self.reset();
+            return Some(StuckScan::Identical { scans });
+        }
+
+        let points = scan.len();
+        match self.typical_points {
+            Some(typical_points) if (points as f64) < typical_points * COLLAPSE_RATIO => {
+                // collapsed scans are left out of the typical count
+                self.collapsed_scans += 1;
+                if self.collapsed_scans >= COLLAPSED_SCAN_LIMIT {
+                    self.reset();
+                    return Some(StuckScan::Collapsed {
+                        points,
+                        typical_points,
+                    });
+                }
+            }
+            Some(typical_points) => {
+                self.collapsed_scans = 0;
+                self.typical_points =
+                    Some(typical_points + (points as f64 - typical_points) * POINT_COUNT_SMOOTHING);
+            }
+            None => self.typical_points = Some(points as f64),
+        }
+        None
+    }
+
+    /// Start over, for example after the scan was restarted
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+fn fingerprint(scan: &[ScanPoint]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    scan.len().hash(&mut hasher);
+    for point in scan {
+        point.angle_z_q14.hash(&mut hasher);
+        point.dist_mm_q2.hash(&mut hasher);
+        point.quality.hash(&mut hasher);
+    }
+    hasher.finish()
+}
@@ -0,0 +1,42 @@
+use std::sync::Arc;
+
+use tracing::info;
+use zenoh::{
+    prelude::r#async::*,
+    publication::{MatchingListener, MatchingStatus, Publisher, PublisherDeclarations},
+};
+
+use crate::{
+    events::{self, EventPublisher},
+    rplidar, ErrorWrapper,
+};
+
+/// Log and publish an event when the first subscriber of `publisher` appears or the last one leaves
+///
+/// Changes are reported for as long as the returned listener is kept alive
+pub async fn watch_subscribers(
+    publisher: &Arc<Publisher<'static>>,
+    component: &str,
+    event_publisher: EventPublisher,
+) -> anyhow::Result<MatchingListener<'static, ()>> {
+    let key_expr = publisher.key_expr().to_string();
+    let component = component.to_owned();
+    let listener = publisher
+        .matching_listener()
+        .callback(move |status: MatchingStatus| {
+            let (kind, message) = if status.matching_subscribers() {
+                (events::SUBSCRIBER_JOINED, "First subscriber appeared")
+            } else {
+                (events::SUBSCRIBERS_LEFT, "Last subscriber left")
+            };
+            info!(key_expr, "{}", message);
+            event_publisher.publish(
+                rplidar::Event::new(&component, events::Severity::Info, kind, message)
+                    .with_value("key_expr", &key_expr),
+            );
+        })
+        .res()
+        .await
+        .map_err(ErrorWrapper::ZenohError)?;
+    Ok(listener)
+}
@@ -0,0 +1,96 @@
+use std::time::{Duration, Instant};
+
+use tracing::{info, warn};
+
+/// Reports readiness and pets the watchdog when running under systemd
+///
+/// Does nothing unless enabled and built with the `systemd` feature
+pub struct SystemdNotifier {
+    enabled: bool,
+    ready: bool,
+    watchdog_interval: Option<Duration>,
+    last_watchdog: Instant,
+}
+
+impl SystemdNotifier {
+    pub fn new(enabled: bool) -> Self {
+        if enabled && !cfg!(feature = "systemd") {
+            warn!("Built without systemd feature, systemd notifications are disabled");
+        }
+        let enabled = enabled && cfg!(feature = "systemd");
+        let watchdog_interval = if enabled { watchdog_interval() } else { None };
+        if let Some(watchdog_interval) = watchdog_interval {
+            info!(?watchdog_interval, "Systemd watchdog enabled");
+        }
+        Self {
+            enabled,
+            ready: false,
+            watchdog_interval,
+            last_watchdog: Instant::now(),
+        }
+    }
+
+    /// Interval at which the watchdog should be pet
+    ///
+    /// Half of the timeout configured by systemd
+    pub fn watchdog_interval(&self) -> Option<Duration> {
+        self.watchdog_interval
+    }
+
+    /// Mark service as ready, only the first call has an effect
+    pub fn notify_ready(&mut self) {
+        if !self.enabled || self.ready {
+            return;
+        }
+        self.ready = true;
+        info!("Notifying systemd that service is ready");
+        notify_ready();
+    }
+
+    /// Pet the watchdog if it's due
+    pub fn pet_watchdog(&mut self) {
+        let Some(watchdog_interval) = self.watchdog_interval else {
+            return;
+        };
+        if self.last_watchdog.elapsed() < watchdog_interval {
+            return;
+        }
+        self.last_watchdog = Instant::now();
+        notify_watchdog();
+    }
+}
+
+#[cfg(feature = "systemd")]
+fn watchdog_interval() -> Option<Duration> {
+    let mut usec = 0;
+    if sd_notify::watchdog_enabled(false, &mut usec) {
+        Some(Duration::from_micros(usec) / 2)
+    } else {
+        None
+    }
+}
+
+#[cfg(feature = "systemd")]
+fn notify_ready() {
+    if let Err(err) = sd_notify::notify(false, &[sd_notify::NotifyState::Ready]) {
+        warn!(?err, "Failed to notify systemd");
+    }
+}
+
+#[cfg(feature = "systemd")]
+fn notify_watchdog() {
+    if let Err(err) = sd_notify::notify(false, &[sd_notify::NotifyState::Watchdog]) {
+        warn!(?err, "Failed to pet systemd watchdog");
+    }
+}
+
+#[cfg(not(feature = "systemd"))]
+fn watchdog_interval() -> Option<Duration> {
+    None
+}
+
+#[cfg(not(feature = "systemd"))]
+fn notify_ready() {}
+
+#[cfg(not(feature = "systemd"))]
+fn notify_watchdog() {}
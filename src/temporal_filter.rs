@@ -0,0 +1,209 @@
+use std::{
+    f32::consts::TAU,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use rplidar_driver::ScanPoint;
+use tokio::sync::watch;
+use tracing::{info, warn};
+use zenoh::{prelude::r#async::*, Session};
+
+use crate::{ros::Twist, ErrorWrapper};
+
+/// Angular resolution of the smoothing state
+const SMOOTHING_BINS: usize = 1440;
+
+/// Temporal smoothing of ranges that backs off while the robot moves
+///
+/// Averaging over scans removes noise on static installs but smears everything
+/// once the lidar moves, so smoothing fades out between the stationary and moving speeds
+#[derive(clap::Args, Debug, Clone)]
+pub struct TemporalFilterArgs {
+    /// Weight of the previous range when stationary, between 0 and 1, 0 disables smoothing
+    #[clap(long, default_value_t = 0.0)]
+    pub temporal_smoothing: f32,
+
+    /// Ranges changing more than this many meters between scans aren't smoothed
+    #[clap(long, default_value_t = 0.2)]
+    pub smoothing_max_jump: f32,
+
+    /// Key expression of a ROS 2 geometry_msgs/Twist velocity in CDR, e.g. cmd_vel
+    ///
+    /// Without it the lidar is assumed to be stationary
+    #[clap(long)]
+    pub velocity_topic: Option<String>,
+
+    /// Speed in m/s up to which smoothing is fully applied
+    #[clap(long, default_value_t = 0.05)]
+    pub stationary_speed: f32,
+
+    /// Speed in m/s from which smoothing is off
+    ///
+    /// Angular velocity counts as the speed of a point 1 m away
+    #[clap(long, default_value_t = 0.5)]
+    pub moving_speed: f32,
+
+    /// Seconds after which the last velocity is stale and smoothing is off
+    #[clap(long, default_value_t = 0.5)]
+    pub velocity_timeout: f64,
+}
+
+impl TemporalFilterArgs {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if !(0.0..1.0).contains(&self.temporal_smoothing) {
+            anyhow::bail!("Temporal smoothing must be at least 0 and less than 1");
+        }
+        if self.moving_speed <= self.stationary_speed {
+            anyhow::bail!("Moving speed must be larger than stationary speed");
+        }
+        Ok(())
+    }
+
+    /// Weight of the previous range at `speed`, `None` when the speed is unknown
+    pub fn smoothing(&self, speed: Option<f32>) -> f32 {
+        let speed = match (&self.velocity_topic, speed) {
+            (None, _) => 0.0,
+            (Some(_), Some(speed)) => speed,
+            (Some(_), None) => return 0.0,
+        };
+        let moving = ((speed - self.stationary_speed)
+            / (self.moving_speed - self.stationary_speed))
+            .clamp(0.0, 1.0);
+        self.temporal_smoothing * (1.0 - moving)
+    }
+}
+
+/// Exponential smoothing of ranges per angle
+pub struct TemporalFilter {
+    ranges: Vec<f32>,
+    max_jump: f32,
+}
+
+impl TemporalFilter {
+    pub fn new(max_jump: f32) -> Self {
+        Self {
+            ranges: vec![0.0; SMOOTHING_BINS],
+            max_jump,
+        }
+    }
+
+    fn bin(angle: f32) -> usize {
+        ((angle.rem_euclid(TAU) / TAU * SMOOTHING_BINS as f32) as usize).min(SMOOTHING_BINS - 1)
+    }
+
+    /// Blend ranges with the previous scans, `smoothing` is the weight of the history
+    pub fn apply(&mut self, scan: &mut [ScanPoint], smoothing: f32) {
+        for point in scan.iter_mut() {
+            let bin = Self::bin(point.angle());
+            if !point.is_valid() {
+                self.ranges[bin] = 0.0;
+                continue;
+            }
+            let smoothed = smooth(self.ranges[bin], point.distance(), smoothing, self.max_jump);
+            self.ranges[bin] = smoothed;
+            point.dist_mm_q2 = (smoothed * 4000.0).round() as u32;
+        }
+    }
+}
+
+fn smooth(previous: f32, distance: f32, smoothing: f32, max_jump: f32) -> f32 {
+    if previous <= 0.0 || (distance - previous).abs() > max_jump {
+        return distance;
+    }
+    previous * smoothing + distance * (1.0 - smoothing)
+}
+
+/// Latest speed received on the velocity topic
+#[derive(Clone)]
+pub struct VelocityReceiver {
+    receiver: watch::Receiver<Option<(Instant, f32)>>,
+    timeout: Duration,
+}
+
+impl VelocityReceiver {
+    /// `None` before the first message or when the last one is older than the timeout
+    pub fn speed(&self) -> Option<f32> {
+        let (received, speed) = (*self.receiver.borrow())?;
+        (received.elapsed() <= self.timeout).then_some(speed)
+    }
+}
+
+/// Subscribe to `Twist` velocities on `key_expr`
+pub async fn start_velocity_subscriber(
+    zenoh_session: Arc<Session>,
+    key_expr: &str,
+    timeout: Duration,
+) -> anyhow::Result<VelocityReceiver> {
+    let subscriber = zenoh_session
+        .declare_subscriber(key_expr)
+        .res()
+        .await
+        .map_err(ErrorWrapper::ZenohError)?;
+    info!(key_expr, "Subscribed to velocity");
+
+    let (sender, receiver) = watch::channel(None);
+    tokio::spawn(async move {
+        while let Ok(sample) = subscriber.recv_async().await {
+            let payload = sample.value.payload.contiguous();
+            let twist = match Twist::from_cdr(&payload) {
+                Ok(twist) => twist,
+                Err(err) => {
+                    warn!(?err, "Failed to decode velocity");
+                    continue;
+                }
+            };
+            let speed = twist.linear[0].hypot(twist.linear[1]) + twist.angular[2].abs();
+            sender.send_replace(Some((Instant::now(), speed as f32)));
+        }
+    });
+    Ok(VelocityReceiver { receiver, timeout })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(velocity_topic: Option<&str>) -> TemporalFilterArgs {
+        TemporalFilterArgs {
+            temporal_smoothing: 0.8,
+            smoothing_max_jump: 0.2,
+            velocity_topic: velocity_topic.map(str::to_owned),
+            stationary_speed: 0.1,
+            moving_speed: 0.5,
+            velocity_timeout: 0.5,
+        }
+    }
+
+    #[test]
+    fn smoothing_follows_speed() {
+        assert_eq!(args(None).smoothing(None), 0.8);
+
+        let args = args(Some("cmd_vel"));
+        assert_eq!(args.smoothing(None), 0.0);
+        assert_eq!(args.smoothing(Some(0.0)), 0.8);
+        assert!((args.smoothing(Some(0.3)) - 0.4).abs() < 1e-6);
+        assert_eq!(args.smoothing(Some(2.0)), 0.0);
+    }
+
+    #[test]
+    fn smooths_small_changes_only() {
+        assert_eq!(smooth(0.0, 2.0, 0.8, 0.2), 2.0);
+        assert!((smooth(2.0, 2.1, 0.8, 0.2) - 2.02).abs() < 1e-6);
+        // something moved in front of the lidar
+        assert_eq!(smooth(2.0, 1.0, 0.8, 0.2), 1.0);
+        assert_eq!(smooth(2.0, 2.1, 0.0, 0.2), 2.1);
+    }
+
+    #[test]
+    fn decodes_twist() {
+        let mut payload = vec![0x00, 0x01, 0x00, 0x00];
+        for value in [0.3f64, -0.4, 0.0, 0.0, 0.0, 0.5] {
+            payload.extend_from_slice(&value.to_le_bytes());
+        }
+        let twist = Twist::from_cdr(&payload).unwrap();
+        assert_eq!(twist.linear, [0.3, -0.4, 0.0]);
+        assert_eq!(twist.angular[2], 0.5);
+        assert!(Twist::from_cdr(&payload[..20]).is_err());
+    }
+}
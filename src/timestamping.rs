@@ -0,0 +1,89 @@
+use std::time::{Instant, SystemTime};
+
+use zenoh::{config::Config, sample::Sample};
+
+/// Stamp published samples with the session's hybrid logical clock
+///
+/// Receivers on other hosts can then order and align samples by source time
+/// instead of their own receive time
+pub fn enable_timestamping(zenoh_config: &mut Config) -> anyhow::Result<()> {
+    zenoh_config
+        .insert_json5("timestamping/enabled", "true")
+        .map_err(|err| anyhow::anyhow!("Failed to enable timestamping: {:?}", err))
+}
+
+/// Source timestamp of the sample, falls back to receive time for unstamped samples
+pub fn sample_time(sample: &Sample) -> SystemTime {
+    sample
+        .timestamp
+        .as_ref()
+        .map(|timestamp| timestamp.get_time().to_system_time())
+        .unwrap_or_else(SystemTime::now)
+}
+
+/// Source of scan capture timestamps
+///
+/// The system clock can jump when NTP syncs after boot on robots without a real time clock.
+/// The monotonic clock reads the system clock once at startup and advances with the
+/// monotonic clock from there, so timestamps never jump but may drift from wall time
+#[derive(Debug, Clone, Copy)]
+pub enum CaptureClock {
+    System,
+    Monotonic {
+        anchor_time: SystemTime,
+        anchor_instant: Instant,
+    },
+}
+
+impl CaptureClock {
+    /// Monotonic clock anchored at the current system time
+    pub fn monotonic() -> Self {
+        CaptureClock::Monotonic {
+            anchor_time: SystemTime::now(),
+            anchor_instant: Instant::now(),
+        }
+    }
+
+    /// Current timestamp and the instant it was taken at
+    pub fn now(&self) -> (SystemTime, Instant) {
+        let instant = Instant::now();
+        match self {
+            CaptureClock::System => (SystemTime::now(), instant),
+            CaptureClock::Monotonic {
+                anchor_time,
+                anchor_instant,
+            } => (
+                *anchor_time + instant.duration_since(*anchor_instant),
+                instant,
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::*;
+    use crate::system_time_to_proto_time;
+
+    #[test]
+    fn monotonic_clock_advances_from_anchor() {
+        let anchor_instant = Instant::now();
+        let clock = CaptureClock::Monotonic {
+            anchor_time: UNIX_EPOCH + Duration::from_secs(1000),
+            anchor_instant,
+        };
+        let (time, instant) = clock.now();
+        assert_eq!(
+            time,
+            UNIX_EPOCH + Duration::from_secs(1000) + instant.duration_since(anchor_instant)
+        );
+    }
+
+    #[test]
+    fn clamps_times_before_epoch() {
+        let timestamp = system_time_to_proto_time(&(UNIX_EPOCH - Duration::from_secs(5)));
+        assert_eq!((timestamp.seconds, timestamp.nanos), (0, 0));
+    }
+}
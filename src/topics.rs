@@ -0,0 +1,202 @@
+use zenoh::key_expr::{keyexpr, OwnedKeyExpr};
+
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum TopicError {
+    #[error("Topic {0:?} is empty")]
+    Empty(String),
+    #[error("Topic {key_expr:?} isn't a valid key expression: {reason}")]
+    Invalid { key_expr: String, reason: String },
+}
+
+/// Builds zenoh key expressions below a common prefix
+///
+/// Leading and trailing slashes of every part are ignored and empty parts are skipped
+/// so an empty prefix publishes at the root. The result is checked to be a canonical
+/// key expression so typos in topic options fail at startup instead of at declaration
+#[derive(Debug, Clone)]
+pub struct TopicBuilder {
+    prefix: String,
+}
+
+impl TopicBuilder {
+    pub fn new(prefix: &str) -> Result<Self, TopicError> {
+        let prefix = prefix.trim_matches('/').to_owned();
+        if !prefix.is_empty() {
+            validate(&prefix)?;
+        }
+        Ok(Self { prefix })
+    }
+
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    /// `<prefix>/<topic>`
+    pub fn topic(&self, topic: &str) -> Result<String, TopicError> {
+        self.join(&[topic])
+    }
+
+    /// `<prefix>/<part>/<part>...`
+    pub fn join(&self, parts: &[&str]) -> Result<String, TopicError> {
+        let key_expr = std::iter::once(self.prefix.as_str())
+            .chain(parts.iter().map(|part| part.trim_matches('/')))
+            .filter(|part| !part.is_empty())
+            .collect::<Vec<_>>()
+            .join("/");
+        validate(&key_expr)?;
+        Ok(key_expr)
+    }
+}
+
+/// Include and exclude patterns for choosing topics
+///
+/// Patterns are key expressions so `*` matches a single chunk and `**` any number of chunks
+#[derive(clap::Args, Debug, Clone, Default)]
+pub struct TopicFilterArgs {
+    /// Only use topics matching one of these key expressions
+    #[clap(long)]
+    pub include: Vec<String>,
+
+    /// Skip topics matching one of these key expressions, applied after includes
+    #[clap(long)]
+    pub exclude: Vec<String>,
+}
+
+impl TopicFilterArgs {
+    pub fn filter(&self) -> Result<TopicFilter, TopicError> {
+        Ok(TopicFilter {
+            include: parse_patterns(&self.include)?,
+            exclude: parse_patterns(&self.exclude)?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TopicFilter {
+    include: Vec<OwnedKeyExpr>,
+    exclude: Vec<OwnedKeyExpr>,
+}
+
+impl TopicFilter {
+    /// Everything is allowed without include patterns
+    pub fn allows(&self, topic: &str) -> bool {
+        let Ok(topic) = keyexpr::new(topic) else {
+            return false;
+        };
+        let included =
+            self.include.is_empty() || self.include.iter().any(|pattern| pattern.includes(topic));
+        included && !self.exclude.iter().any(|pattern| pattern.includes(topic))
+    }
+}
+
+fn parse_patterns(patterns: &[String]) -> Result<Vec<OwnedKeyExpr>, TopicError> {
+    patterns
+        .iter()
+        .map(|pattern| {
+            validate(pattern)?;
+            Ok(keyexpr::new(pattern.as_str())
+                .expect("validated key expression")
+                .to_owned())
+        })
+        .collect()
+}
+
+fn validate(key_expr: &str) -> Result<(), TopicError> {
+    if key_expr.is_empty() {
+        return Err(TopicError::Empty(key_expr.to_owned()));
+    }
+    keyexpr::new(key_expr).map_err(|err| TopicError::Invalid {
+        key_expr: key_expr.to_owned(),
+        reason: err.to_string(),
+    })?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn joins_prefix_and_topic() {
+        let topics = TopicBuilder::new("rplidar").unwrap();
+        assert_eq!(topics.topic("laser_scan").unwrap(), "rplidar/laser_scan");
+    }
+
+    #[test]
+    fn ignores_surrounding_slashes() {
+        let topics = TopicBuilder::new("/robot/rplidar/").unwrap();
+        assert_eq!(topics.prefix(), "robot/rplidar");
+        assert_eq!(topics.topic("/scan/").unwrap(), "robot/rplidar/scan");
+    }
+
+    #[test]
+    fn empty_prefix_publishes_at_root() {
+        let topics = TopicBuilder::new("").unwrap();
+        assert_eq!(topics.topic("events").unwrap(), "events");
+        assert_eq!(
+            topics.join(&["laser_scan", "metadata"]).unwrap(),
+            "laser_scan/metadata"
+        );
+    }
+
+    #[test]
+    fn skips_empty_parts() {
+        let topics = TopicBuilder::new("rplidar").unwrap();
+        assert_eq!(
+            topics.join(&["", "config", "/set"]).unwrap(),
+            "rplidar/config/set"
+        );
+    }
+
+    #[test]
+    fn rejects_empty_topic() {
+        let topics = TopicBuilder::new("").unwrap();
+        assert!(matches!(topics.topic("/"), Err(TopicError::Empty(_))));
+    }
+
+    #[test]
+    fn filter_allows_everything_by_default() {
+        let filter = TopicFilterArgs::default().filter().unwrap();
+        assert!(filter.allows("rplidar/point_cloud"));
+    }
+
+    #[test]
+    fn filter_applies_excludes_after_includes() {
+        let filter = TopicFilterArgs {
+            include: vec!["rplidar/**".to_owned()],
+            exclude: vec!["rplidar/point_cloud".to_owned()],
+        }
+        .filter()
+        .unwrap();
+        assert!(filter.allows("rplidar/laser_scan"));
+        assert!(filter.allows("rplidar/events"));
+        assert!(!filter.allows("rplidar/point_cloud"));
+        assert!(!filter.allows("other/laser_scan"));
+    }
+
+    #[test]
+    fn filter_rejects_invalid_patterns() {
+        let args = TopicFilterArgs {
+            include: vec!["rplidar//scan".to_owned()],
+            exclude: vec![],
+        };
+        assert!(matches!(args.filter(), Err(TopicError::Invalid { .. })));
+    }
+
+    #[test]
+    fn rejects_invalid_key_expressions() {
+        assert!(matches!(
+            TopicBuilder::new("robot//rplidar"),
+            Err(TopicError::Invalid { .. })
+        ));
+        let topics = TopicBuilder::new("rplidar").unwrap();
+        assert!(matches!(
+            topics.topic("scan?raw"),
+            Err(TopicError::Invalid { .. })
+        ));
+        assert!(matches!(
+            topics.topic("scan#1"),
+            Err(TopicError::Invalid { .. })
+        ));
+    }
+}
@@ -0,0 +1,104 @@
+use std::{
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use tokio::io::AsyncReadExt;
+use tracing::{info, warn};
+
+const UPLOAD_CHUNK_SIZE: usize = 64 * 1024;
+const INITIAL_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// Options for uploading finished recordings
+#[derive(clap::Args, Debug, Clone, Default)]
+pub struct UploadArgs {
+    /// Upload finished recordings with HTTP PUT to this base URL
+    ///
+    /// The file name is appended to the URL. Works with S3 compatible stores
+    /// that accept anonymous or pre-authorized PUT requests
+    #[clap(long)]
+    pub upload_url: Option<String>,
+
+    /// Number of times a failed upload is retried
+    #[clap(long, default_value_t = 3)]
+    pub upload_retries: u32,
+
+    /// Maximum upload rate in bytes per second
+    #[clap(long, value_parser = clap::value_parser!(u64).range(1..))]
+    pub upload_bandwidth_limit: Option<u64>,
+}
+
+impl UploadArgs {
+    /// Upload file and return its remote URL
+    ///
+    /// Returns `None` when uploads aren't configured
+    pub async fn upload(&self, path: &Path) -> anyhow::Result<Option<String>> {
+        let Some(base_url) = &self.upload_url else {
+            return Ok(None);
+        };
+        let file_name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .context("Recording path has no file name")?;
+        let url = format!("{}/{}", base_url.trim_end_matches('/'), file_name);
+
+        let client = reqwest::Client::new();
+        let mut retry_delay = INITIAL_RETRY_DELAY;
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            info!(?path, url, attempt, "Uploading recording");
+            match upload_once(&client, path, &url, self.upload_bandwidth_limit).await {
+                Ok(()) => return Ok(Some(url)),
+                Err(err) if attempt <= self.upload_retries => {
+                    warn!(?err, ?retry_delay, "Upload failed, retrying");
+                    tokio::time::sleep(retry_delay).await;
+                    retry_delay *= 2;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+async fn upload_once(
+    client: &reqwest::Client,
+    path: &Path,
+    url: &str,
+    bandwidth_limit: Option<u64>,
+) -> anyhow::Result<()> {
+    let file = tokio::fs::File::open(path)
+        .await
+        .with_context(|| format!("Failed to open recording {:?}", path))?;
+    let length = file.metadata().await?.len();
+
+    let started = Instant::now();
+    let chunks = futures::stream::try_unfold((file, 0_u64), move |(mut file, sent)| async move {
+        let mut chunk = vec![0; UPLOAD_CHUNK_SIZE];
+        let read = file.read(&mut chunk).await?;
+        if read == 0 {
+            return Ok::<_, std::io::Error>(None);
+        }
+        chunk.truncate(read);
+        let sent = sent + read as u64;
+        if let Some(bandwidth_limit) = bandwidth_limit {
+            // sleep until the average rate drops back to the limit
+            let expected = Duration::try_from_secs_f64(sent as f64 / bandwidth_limit as f64)
+                .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidInput, err))?;
+            if let Some(ahead) = expected.checked_sub(started.elapsed()) {
+                tokio::time::sleep(ahead).await;
+            }
+        }
+        Ok(Some((chunk, (file, sent))))
+    });
+
+    client
+        .put(url)
+        .header(reqwest::header::CONTENT_LENGTH, length)
+        .body(reqwest::Body::wrap_stream(chunks))
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
@@ -0,0 +1,323 @@
+use std::{
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
+
+use prost::Message;
+use tracing::{error, info};
+use zenoh::{prelude::r#async::*, publication::CongestionControl, Session};
+
+use crate::{
+    config::AngleMask,
+    events::{self, EventPublisher},
+    foxglove, rplidar,
+    scan_broadcast::ScanBroadcast,
+    system_time_to_proto_time, ErrorWrapper,
+};
+
+pub const ZONE_SCENE_TOPIC: &str = "zones/scene";
+
+/// Angular step of the drawn zone outline
+const ZONE_ARC_STEP_DEGREES: f32 = 5.0;
+const ZONE_OCCUPIED_COLOR: foxglove::Color = foxglove::Color {
+    r: 1.0,
+    g: 0.0,
+    b: 0.0,
+    a: 0.4,
+};
+const ZONE_CLEAR_COLOR: foxglove::Color = foxglove::Color {
+    r: 0.0,
+    g: 1.0,
+    b: 0.0,
+    a: 0.4,
+};
+
+/// Zones watched for intrusions
+#[derive(clap::Args, Debug, Clone)]
+pub struct ZoneArgs {
+    /// Watch an angular zone for intrusions
+    ///
+    /// Format is name:start_deg:end_deg:max_distance_m, sectors can wrap around zero
+    /// (e.g. doorway:350:10:1.5)
+    #[clap(long)]
+    pub zone: Vec<Zone>,
+
+    /// Number of points within a zone needed to report it as occupied
+    #[clap(long, default_value_t = 3)]
+    pub zone_min_points: usize,
+
+    /// Seconds a zone has to stay occupied before it's reported as entered
+    #[clap(long, default_value_t = 0.2)]
+    pub zone_enter_debounce: f64,
+
+    /// Seconds a zone has to stay clear before it's reported as exited
+    #[clap(long, default_value_t = 1.0)]
+    pub zone_exit_debounce: f64,
+}
+
+impl ZoneArgs {
+    /// One debouncer per configured zone
+    pub fn debouncers(&self) -> anyhow::Result<Vec<ZoneDebouncer>> {
+        let enter = Duration::try_from_secs_f64(self.zone_enter_debounce)?;
+        let exit = Duration::try_from_secs_f64(self.zone_exit_debounce)?;
+        Ok(vec![ZoneDebouncer::new(enter, exit); self.zone.len()])
+    }
+}
+
+/// Angular sector watched for obstacles closer than `max_distance`
+#[derive(Debug, Clone)]
+pub struct Zone {
+    pub name: String,
+    pub sector: AngleMask,
+    pub max_distance: f64,
+}
+
+impl FromStr for Zone {
+    type Err = anyhow::Error;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<_> = text.split(':').collect();
+        let [name, start, end, max_distance] = parts.as_slice() else {
+            anyhow::bail!("zone must be in format name:start_deg:end_deg:max_distance_m");
+        };
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            anyhow::bail!("zone name must be alphanumeric or underscore");
+        }
+        let sector = AngleMask {
+            start: start.parse()?,
+            end: end.parse()?,
+        };
+        if !(0.0..=360.0).contains(&sector.start) || !(0.0..=360.0).contains(&sector.end) {
+            anyhow::bail!("zone angles must be between 0 and 360 degrees");
+        }
+        Ok(Self {
+            name: name.to_string(),
+            sector,
+            max_distance: max_distance.parse()?,
+        })
+    }
+}
+
+impl Zone {
+    /// Check if a return at `angle` in radians and `range` in meters is inside the zone
+    pub fn contains(&self, angle: f64, range: f64) -> bool {
+        range > 0.0 && range <= self.max_distance && self.sector.contains(angle as f32)
+    }
+
+    /// Zone drawn as a filled sector, red while occupied and green while clear
+    pub fn scene_entity(
+        &self,
+        occupied: bool,
+        timestamp: &SystemTime,
+        frame_id: &str,
+        pose: &foxglove::Pose,
+    ) -> foxglove::SceneEntity {
+        let end = if self.sector.end < self.sector.start {
+            self.sector.end + 360.0
+        } else {
+            self.sector.end
+        };
+        let steps = ((end - self.sector.start) / ZONE_ARC_STEP_DEGREES)
+            .ceil()
+            .max(1.0) as usize;
+        // same projection as the published point clouds
+        let arc = (0..=steps)
+            .map(|step| {
+                let angle = (self.sector.start
+                    + (end - self.sector.start) * step as f32 / steps as f32)
+                    .to_radians() as f64;
+                foxglove::Point3 {
+                    x: self.max_distance * (-angle).cos(),
+                    y: self.max_distance * (-angle).sin(),
+                    z: 0.0,
+                }
+            })
+            .collect::<Vec<_>>();
+        let origin = foxglove::Point3::default();
+        let points = arc
+            .windows(2)
+            .flat_map(|edge| [origin, edge[0], edge[1]])
+            .collect();
+
+        foxglove::SceneEntity {
+            timestamp: Some(system_time_to_proto_time(timestamp)),
+            frame_id: frame_id.to_owned(),
+            id: self.name.clone(),
+            frame_locked: true,
+            metadata: vec![foxglove::KeyValuePair {
+                key: "occupied".to_owned(),
+                value: occupied.to_string(),
+            }],
+            triangles: vec![foxglove::TriangleListPrimitive {
+                pose: Some(*pose),
+                points,
+                color: Some(if occupied {
+                    ZONE_OCCUPIED_COLOR
+                } else {
+                    ZONE_CLEAR_COLOR
+                }),
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+}
+
+/// Reports a zone state change only once it held for the debounce time
+///
+/// The first observation is reported right away so consumers learn the initial state
+#[derive(Debug, Clone)]
+pub struct ZoneDebouncer {
+    enter: Duration,
+    exit: Duration,
+    state: Option<bool>,
+    pending_since: Option<Instant>,
+}
+
+impl ZoneDebouncer {
+    pub fn new(enter: Duration, exit: Duration) -> Self {
+        Self {
+            enter,
+            exit,
+            state: None,
+            pending_since: None,
+        }
+    }
+
+    /// Debounced state
+    pub fn state(&self) -> Option<bool> {
+        self.state
+    }
+
+    /// Returns the new state when it changes
+    pub fn update(&mut self, occupied: bool, now: Instant) -> Option<bool> {
+        if self.state.is_none() {
+            self.state = Some(occupied);
+            return self.state;
+        }
+        if self.state == Some(occupied) {
+            self.pending_since = None;
+            return None;
+        }
+        let since = *self.pending_since.get_or_insert(now);
+        let debounce = if occupied { self.enter } else { self.exit };
+        if now.duration_since(since) < debounce {
+            return None;
+        }
+        self.pending_since = None;
+        self.state = Some(occupied);
+        self.state
+    }
+}
+
+/// Watch zones in every scan from `scan_broadcast`
+///
+/// Publishes enter and exit events and a [`foxglove::SceneUpdate`] of all zones on
+/// `scene_topic` with every scan
+pub async fn start_zone_monitor(
+    zenoh_session: Arc<Session>,
+    scene_topic: String,
+    args: ZoneArgs,
+    event_publisher: EventPublisher,
+    scan_broadcast: ScanBroadcast,
+) -> anyhow::Result<()> {
+    let mut debouncers = args.debouncers()?;
+    let scene_publisher = zenoh_session
+        .declare_publisher(scene_topic.clone())
+        .congestion_control(CongestionControl::Drop)
+        .res()
+        .await
+        .map_err(ErrorWrapper::ZenohError)?;
+    info!(scene_topic, zones = args.zone.len(), "Monitoring zones");
+
+    let mut scan_subscriber = scan_broadcast.subscribe("zones");
+    tokio::spawn(async move {
+        while let Some(frame) = scan_subscriber.recv().await {
+            let mut entities = vec![];
+            for (zone, debouncer) in args.zone.iter().zip(debouncers.iter_mut()) {
+                let points = frame
+                    .points
+                    .iter()
+                    .filter(|point| {
+                        point.is_valid()
+                            && zone.contains(point.angle() as f64, point.distance() as f64)
+                    })
+                    .count();
+                let was_known = debouncer.state().is_some();
+                if let Some(occupied) =
+                    debouncer.update(points >= args.zone_min_points, frame.capture_instant)
+                {
+                    info!(zone = %zone.name, occupied, "Zone state changed");
+                    // an initially clear zone wasn't exited
+                    if was_known || occupied {
+                        let (kind, change) = if occupied {
+                            (events::ZONE_ENTERED, "entered")
+                        } else {
+                            (events::ZONE_EXITED, "exited")
+                        };
+                        event_publisher.publish(
+                            rplidar::Event::new(
+                                "zones",
+                                events::Severity::Info,
+                                kind,
+                                &format!("Zone {} {}", zone.name, change),
+                            )
+                            .with_value("zone", &zone.name)
+                            .with_value("points", points),
+                        );
+                    }
+                }
+                entities.push(zone.scene_entity(
+                    debouncer.state().unwrap_or_default(),
+                    &frame.capture_time,
+                    &frame.frame_id,
+                    &frame.pose,
+                ));
+            }
+            let scene = foxglove::SceneUpdate {
+                deletions: vec![],
+                entities,
+            };
+            if let Err(err) = scene_publisher.put(scene.encode_to_vec()).res().await {
+                error!(?err, "Failed to publish zone scene");
+            }
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_wrapping_zone() {
+        let zone: Zone = "doorway:350:10:1.5".parse().unwrap();
+        assert!(zone.contains(355f64.to_radians(), 1.0));
+        assert!(zone.contains(5f64.to_radians(), 1.5));
+        assert!(!zone.contains(5f64.to_radians(), 2.0));
+        assert!(!zone.contains(180f64.to_radians(), 1.0));
+        assert!("door-way:0:10:1".parse::<Zone>().is_err());
+        assert!("doorway:0:400:1".parse::<Zone>().is_err());
+    }
+
+    #[test]
+    fn debounces_changes() {
+        let mut debouncer =
+            ZoneDebouncer::new(Duration::from_millis(200), Duration::from_millis(1000));
+        let start = Instant::now();
+        let at = |millis| start + Duration::from_millis(millis);
+
+        assert_eq!(debouncer.update(false, at(0)), Some(false));
+        // a single noisy scan doesn't enter
+        assert_eq!(debouncer.update(true, at(100)), None);
+        assert_eq!(debouncer.update(false, at(200)), None);
+        assert_eq!(debouncer.update(true, at(300)), None);
+        assert_eq!(debouncer.update(true, at(500)), Some(true));
+        assert_eq!(debouncer.update(false, at(600)), None);
+        assert_eq!(debouncer.update(false, at(1500)), None);
+        assert_eq!(debouncer.update(false, at(1600)), Some(false));
+        assert_eq!(debouncer.state(), Some(false));
+    }
+}
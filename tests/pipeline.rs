@@ -0,0 +1,325 @@
+//! End to end checks of the wire format
+//!
+//! Scans from a simulated lidar go through the driver's encode jobs and scan publishers
+//! on one in-process zenoh peer and are decoded by a second peer connected over localhost
+
+use std::{net::TcpListener, sync::Arc, time::Duration};
+
+use futures::{Stream, StreamExt};
+use prost::Message;
+use rplidar_driver::ScanPoint;
+use zenoh::{config::Config, prelude::r#async::*, publication::Publisher, Session};
+
+use rplidar_zenoh_driver::{
+    compression::{Compression, CompressionArgs},
+    config::MountingPose,
+    encoding::{LaserScanBatchEncoder, ScanEncoder, ScanFormat},
+    peers::StaticPeersArgs,
+    profiling::StageProfiler,
+    projection::project,
+    rplidar,
+    scan_broadcast::ScanFrame,
+    scan_publisher::{EncodeJob, ScanPublishers},
+    streams::{
+        subscribe_compact_laser_scans, subscribe_laser_scans, subscribe_messages,
+        subscribe_point_clouds,
+    },
+    system_time_to_proto_time,
+    timestamping::CaptureClock,
+    RpLidarProjectedPoint,
+};
+
+const RECEIVE_TIMEOUT: Duration = Duration::from_secs(10);
+const REPUBLISH_INTERVAL: Duration = Duration::from_millis(50);
+const FRAME_ID: &str = "laser";
+
+/// One point per degree, every tenth point has no return
+fn simulated_scan() -> ScanFrame {
+    let points = (0..360u32)
+        .map(|degree| ScanPoint {
+            angle_z_q14: (degree * 16384 / 90) as u16,
+            dist_mm_q2: if degree % 10 == 0 {
+                0
+            } else {
+                (1000 + degree) * 4
+            },
+            quality: (degree % 64) as u8,
+            flag: 0,
+        })
+        .collect();
+    let mut frame = ScanFrame::captured(points, 0, "SIMULATED".to_owned(), &CaptureClock::System);
+    frame.frame_id = FRAME_ID.to_owned();
+    frame.pose = MountingPose {
+        z: 0.2,
+        yaw: 90.0,
+        ..Default::default()
+    }
+    .to_foxglove_pose();
+    frame
+}
+
+fn peer_config() -> Config {
+    let mut config = Config::default();
+    for key in ["scouting/multicast/enabled", "scouting/gossip/enabled"] {
+        config.insert_json5(key, "false").unwrap();
+    }
+    config
+}
+
+/// Publishing and subscribing peers connected over localhost
+async fn connected_peers() -> (Arc<Session>, Arc<Session>) {
+    let port = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let endpoint: zenoh_config::EndPoint = format!("tcp/127.0.0.1:{}", port).parse().unwrap();
+
+    let mut publisher_config = peer_config();
+    publisher_config.listen.endpoints = vec![endpoint.clone()];
+    let publisher = zenoh::open(publisher_config)
+        .res()
+        .await
+        .unwrap()
+        .into_arc();
+
+    let mut subscriber_config = peer_config();
+    StaticPeersArgs {
+        static_peers: vec![endpoint],
+    }
+    .apply(&mut subscriber_config)
+    .unwrap();
+    let subscriber = zenoh::open(subscriber_config)
+        .res()
+        .await
+        .unwrap()
+        .into_arc();
+    (publisher, subscriber)
+}
+
+const NO_COMPRESSION: CompressionArgs = CompressionArgs {
+    compression: Compression::None,
+    compression_min_size: 0,
+    zstd_level: 3,
+};
+
+async fn declare_publisher(session: &Arc<Session>, key_expr: &str) -> Arc<Publisher<'static>> {
+    Arc::new(
+        session
+            .declare_publisher(key_expr.to_owned())
+            .res()
+            .await
+            .unwrap(),
+    )
+}
+
+/// Scan publishers wired like the driver's with only the given outputs enabled
+async fn scan_publishers(
+    session: &Arc<Session>,
+    laser_scan: Option<&str>,
+    point_cloud: Option<&str>,
+) -> ScanPublishers {
+    let laser_scan = match laser_scan {
+        Some(key_expr) => Some(declare_publisher(session, key_expr).await),
+        None => None,
+    };
+    let point_cloud = match point_cloud {
+        Some(key_expr) => Some(declare_publisher(session, key_expr).await),
+        None => None,
+    };
+    ScanPublishers {
+        laser_scan,
+        point_cloud,
+        ros2_laser_scan: None,
+        ros2_point_cloud: None,
+        rosbridge: None,
+        metadata: declare_publisher(session, "test/rplidar/laser_scan/metadata").await,
+        laser_scan_batch: None,
+    }
+}
+
+/// Encode and publish `frame` through the driver's path until the first message arrives
+/// on `stream`
+///
+/// Republishing covers the time the peers need to exchange subscriptions
+async fn first_published<T>(
+    mut publishers: ScanPublishers,
+    mut encoder: ScanEncoder,
+    compression: CompressionArgs,
+    frame: Arc<ScanFrame>,
+    stream: impl Stream<Item = T>,
+) -> T {
+    let publisher = tokio::spawn(async move {
+        loop {
+            let job = EncodeJob {
+                encoder,
+                frame: frame.clone(),
+                range_min: 0.0,
+                range_max: 40.0,
+                laser_scan: publishers.laser_scan.is_some(),
+                point_cloud: publishers.point_cloud.is_some(),
+                ros2_laser_scan: false,
+                ros2_point_cloud: false,
+                compression,
+                profiler: StageProfiler::new(false),
+                rosbridge: None,
+                scan_mode: None,
+                span: tracing::Span::none(),
+            };
+            let encoded = tokio::task::spawn_blocking(move || job.encode())
+                .await
+                .unwrap()
+                .unwrap();
+            encoder = encoded.encoder;
+            publishers
+                .publish(encoded.payloads, &encoded.span)
+                .await
+                .unwrap();
+            tokio::time::sleep(REPUBLISH_INTERVAL).await;
+        }
+    });
+    let mut stream = std::pin::pin!(stream);
+    let received = tokio::time::timeout(RECEIVE_TIMEOUT, stream.next())
+        .await
+        .expect("Timed out waiting for message")
+        .expect("Stream ended");
+    publisher.abort();
+    received
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn laser_scan_arrives_intact() {
+    let (publisher, subscriber) = connected_peers().await;
+    let key_expr = "test/rplidar/laser_scan";
+    let frame = Arc::new(simulated_scan());
+    let publishers = scan_publishers(&publisher, Some(key_expr), None).await;
+
+    let laser_scans = subscribe_laser_scans(subscriber, key_expr).await.unwrap();
+    let laser_scan = first_published(
+        publishers,
+        ScanEncoder::new(),
+        NO_COMPRESSION,
+        frame.clone(),
+        laser_scans,
+    )
+    .await;
+
+    assert_eq!(laser_scan.frame_id, FRAME_ID);
+    assert_eq!(
+        laser_scan.timestamp,
+        Some(system_time_to_proto_time(&frame.capture_time))
+    );
+    assert_eq!(laser_scan.pose, Some(frame.pose));
+    assert_eq!(laser_scan.start_angle, 0.0);
+    assert!((laser_scan.end_angle - 359f64.to_radians()).abs() < 1e-3);
+    assert_eq!(laser_scan.ranges.len(), 360);
+    assert_eq!(laser_scan.ranges[0], 0.0);
+    assert!((laser_scan.ranges[1] - 1.001).abs() < 1e-6);
+    assert_eq!(laser_scan.intensities[63], 63.0);
+    assert_eq!(laser_scan.valid_points().count(), 324);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn compressed_point_cloud_arrives_intact() {
+    let (publisher, subscriber) = connected_peers().await;
+    let key_expr = "test/rplidar/point_cloud";
+    let frame = Arc::new(simulated_scan());
+    let publishers = scan_publishers(&publisher, None, Some(key_expr)).await;
+    let compression = CompressionArgs {
+        compression: Compression::Zstd,
+        ..NO_COMPRESSION
+    };
+
+    let point_clouds = subscribe_point_clouds(subscriber, key_expr).await.unwrap();
+    let point_cloud = first_published(
+        publishers,
+        ScanEncoder::new(),
+        compression,
+        frame.clone(),
+        point_clouds,
+    )
+    .await;
+
+    assert_eq!(point_cloud.frame_id, FRAME_ID);
+    assert_eq!(point_cloud.pose, Some(frame.pose));
+    let points = RpLidarProjectedPoint::from_foxglove_point_cloud(&point_cloud).unwrap();
+    // points without a return aren't projected
+    assert_eq!(points.len(), 324);
+    let scan_point = &frame.points[1];
+    let (x, y) = project(scan_point.angle(), scan_point.distance());
+    assert_eq!((points[0].x, points[0].y), (x, y));
+    assert_eq!(points[0].distance, scan_point.distance());
+    assert_eq!(points[0].angle, scan_point.angle());
+    assert_eq!(points[0].quality, 1);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn delta_compact_scan_decodes_to_laser_scan() {
+    let (publisher, subscriber) = connected_peers().await;
+    let key_expr = "test/rplidar/laser_scan/compact";
+    let frame = Arc::new(simulated_scan());
+    let encoder = ScanEncoder::with_format(ScanFormat::Delta, true).with_range_resolution(5);
+    let payload = ScanEncoder::with_format(ScanFormat::Delta, true)
+        .with_range_resolution(5)
+        .encode_laser_scan(
+            &frame.capture_time,
+            &frame.frame_id,
+            &frame.pose,
+            &frame.points,
+        );
+    let foxglove_size = ScanEncoder::new()
+        .laser_scan(
+            &frame.capture_time,
+            &frame.frame_id,
+            &frame.pose,
+            &frame.points,
+        )
+        .encoded_len();
+    assert!(payload.len() * 3 < foxglove_size);
+
+    let publishers = scan_publishers(&publisher, Some(key_expr), None).await;
+    let laser_scans = subscribe_compact_laser_scans(subscriber, key_expr)
+        .await
+        .unwrap();
+    let laser_scan = first_published(
+        publishers,
+        encoder,
+        NO_COMPRESSION,
+        frame.clone(),
+        laser_scans,
+    )
+    .await;
+
+    assert_eq!(laser_scan.frame_id, FRAME_ID);
+    assert_eq!(laser_scan.ranges.len(), 360);
+    for (range, point) in laser_scan.ranges.iter().zip(&frame.points) {
+        assert!((range - point.distance() as f64).abs() < 0.0026);
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn batched_laser_scans_arrive_together() {
+    let (publisher, subscriber) = connected_peers().await;
+    let key_expr = "test/rplidar/laser_scan/batch";
+    let frame = Arc::new(simulated_scan());
+    let mut publishers = scan_publishers(&publisher, Some(key_expr), None).await;
+    publishers.laser_scan_batch = Some(LaserScanBatchEncoder::new(3));
+
+    let batches = subscribe_messages::<rplidar::LaserScanBatch>(subscriber, key_expr)
+        .await
+        .unwrap();
+    let batch = first_published(
+        publishers,
+        ScanEncoder::new(),
+        NO_COMPRESSION,
+        frame.clone(),
+        batches,
+    )
+    .await;
+
+    assert_eq!(batch.scans.len(), 3);
+    for laser_scan in &batch.scans {
+        assert_eq!(laser_scan.frame_id, FRAME_ID);
+        assert_eq!(laser_scan.ranges.len(), 360);
+    }
+}